@@ -0,0 +1,104 @@
+//! OBJ parser and builder benchmarks. Run with `cargo bench --bench obj_load`.
+//!
+//! On Unix, `cargo bench --bench obj_load -- --profile-time 10` writes a flamegraph of each
+//! benchmark to `target/criterion/<name>/profile/flamegraph.svg`. For instructions per cycle
+//! run the benchmark under `perf stat -e instructions,cycles`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use soyuz::entity::model;
+use soyuz::entity::model::files::obj::{Line, ObjectBuilder};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+/// A `size` by `size` grid of quads split into triangles, with texture coordinates and a
+/// shared normal, like an exported terrain patch.
+fn grid_obj(size: usize) -> String {
+    let mut obj = String::new();
+    for y in 0..=size {
+        for x in 0..=size {
+            let (u, v) = (x as f32 / size as f32, y as f32 / size as f32);
+            writeln!(obj, "v {} {} {}", u, (u * 7.0).sin() * 0.1, v).unwrap();
+            writeln!(obj, "vt {} {}", u, v).unwrap();
+        }
+    }
+    obj.push_str("vn 0 1 0\n");
+    let index = |x: usize, y: usize| y * (size + 1) + x + 1;
+    for y in 0..size {
+        for x in 0..size {
+            let [a, b, c, d] = [index(x, y), index(x + 1, y), index(x + 1, y + 1), index(x, y + 1)];
+            writeln!(obj, "f {0}/{0}/1 {1}/{1}/1 {2}/{2}/1", a, c, b).unwrap();
+            writeln!(obj, "f {0}/{0}/1 {1}/{1}/1 {2}/{2}/1", a, d, c).unwrap();
+        }
+    }
+    obj
+}
+
+/// Writes the grid to the temporary directory so `load_file_sync` reads a real file.
+fn fixture(name: &str, size: usize) -> (PathBuf, String) {
+    let obj = grid_obj(size);
+    let path = std::env::temp_dir().join(format!("soyuz-bench-{}.obj", name));
+    std::fs::write(&path, &obj).expect("can't write the benchmark fixture");
+    (path, obj)
+}
+
+fn load_file(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_file_sync");
+    // ~1k and ~100k faces
+    for (name, size) in [("small", 22), ("large", 224)] {
+        let (path, _) = fixture(name, size);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &path, |b, path| {
+            b.iter(|| ObjectBuilder::load_file_sync(path).unwrap().build())
+        });
+    }
+    group.finish();
+}
+
+fn process_line(c: &mut Criterion) {
+    let (_, obj) = fixture("large", 224);
+    c.bench_function("process_line", |b| {
+        b.iter(|| {
+            for line in obj.lines() {
+                black_box(Line::process_line(line).unwrap());
+            }
+        })
+    });
+}
+
+fn add_vertex(c: &mut Criterion) {
+    // Every vertex is added six times, like the corners shared between the faces of a grid
+    let vertices: Vec<model::Vertex> = (0..50_000)
+        .map(|i| model::Vertex {
+            position: [i as f32, (i % 7) as f32, (i % 13) as f32],
+            normal: [0.0, 1.0, 0.0],
+            texture_coords: [(i % 101) as f32 / 100.0, 0.5],
+        })
+        .collect();
+    c.bench_function("add_vertex", |b| {
+        b.iter(|| {
+            let mut builder = ObjectBuilder::new();
+            for _ in 0..6 {
+                for &vertex in &vertices {
+                    black_box(builder.add_vertex(vertex));
+                }
+            }
+            builder
+        })
+    });
+}
+
+#[cfg(unix)]
+fn config() -> Criterion {
+    use pprof::criterion::{Output, PProfProfiler};
+    Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)))
+}
+#[cfg(not(unix))]
+fn config() -> Criterion {
+    Criterion::default()
+}
+
+criterion_group! {
+    name = benches;
+    config = config();
+    targets = load_file, process_line, add_vertex
+}
+criterion_main!(benches);
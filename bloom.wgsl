@@ -0,0 +1,81 @@
+// Bloom: bright pass, dual Kawase down/up sampling and additive composite
+
+[[block]]
+struct Bloom {
+    threshold: f32;
+    intensity: f32;
+    padding0: f32;
+    padding1: f32;
+};
+
+[[group(0), binding(0)]]
+var src: texture_2d<f32>;
+[[group(0), binding(1)]]
+var src_sampler: sampler;
+[[group(0), binding(2)]]
+var<uniform> bloom: Bloom;
+
+// Vertex shader
+
+struct FullscreenOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] in_vertex_index: u32) -> FullscreenOutput {
+    var out: FullscreenOutput;
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Fragment shaders
+
+fn tap(uv: vec2<f32>) -> vec4<f32> {
+    return textureSample(src, src_sampler, uv);
+}
+
+fn half_texel() -> vec2<f32> {
+    return 0.5 / vec2<f32>(textureDimensions(src));
+}
+
+// Keeps the part of each pixel brighter than the threshold
+[[stage(fragment)]]
+fn fs_prefilter(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let color = tap(in.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - bloom.threshold, 0.0) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_downsample(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let h = half_texel();
+    var sum: vec4<f32> = tap(in.uv) * 4.0;
+    sum = sum + tap(in.uv - h);
+    sum = sum + tap(in.uv + h);
+    sum = sum + tap(in.uv + vec2<f32>(h.x, -h.y));
+    sum = sum + tap(in.uv - vec2<f32>(h.x, -h.y));
+    return sum / 8.0;
+}
+
+[[stage(fragment)]]
+fn fs_upsample(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let h = half_texel();
+    var sum: vec4<f32> = tap(in.uv + vec2<f32>(-h.x * 2.0, 0.0));
+    sum = sum + tap(in.uv + vec2<f32>(-h.x, h.y)) * 2.0;
+    sum = sum + tap(in.uv + vec2<f32>(0.0, h.y * 2.0));
+    sum = sum + tap(in.uv + vec2<f32>(h.x, h.y)) * 2.0;
+    sum = sum + tap(in.uv + vec2<f32>(h.x * 2.0, 0.0));
+    sum = sum + tap(in.uv + vec2<f32>(h.x, -h.y)) * 2.0;
+    sum = sum + tap(in.uv + vec2<f32>(0.0, -h.y * 2.0));
+    sum = sum + tap(in.uv + vec2<f32>(-h.x, -h.y)) * 2.0;
+    return sum / 12.0;
+}
+
+[[stage(fragment)]]
+fn fs_composite(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(tap(in.uv).rgb * bloom.intensity, 0.0);
+}
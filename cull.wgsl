@@ -0,0 +1,71 @@
+// Frustum culling of indirect draw commands, culled draws get an instance count of 0
+
+[[block]]
+struct Params {
+    // Inward facing planes, xyz is the normal and w the distance
+    planes: array<vec4<f32>, 6>;
+    draw_count: u32;
+    padding0: u32;
+    padding1: u32;
+    padding2: u32;
+};
+struct Instance {
+    model: mat4x4<f32>;
+    bounds_min: vec4<f32>;
+    bounds_max: vec4<f32>;
+};
+[[block]]
+struct Instances {
+    data: array<Instance>;
+};
+struct DrawIndexedIndirect {
+    index_count: u32;
+    instance_count: u32;
+    first_index: u32;
+    base_vertex: i32;
+    first_instance: u32;
+};
+[[block]]
+struct Draws {
+    data: array<DrawIndexedIndirect>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: Params;
+[[group(0), binding(1)]]
+var<storage, read> instances: Instances;
+[[group(0), binding(2)]]
+var<storage, read> draws: Draws;
+[[group(0), binding(3)]]
+var<storage, read_write> culled: Draws;
+
+[[stage(compute), workgroup_size(64, 1, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let i = id.x;
+    if (i >= params.draw_count) {
+        return;
+    }
+    let instance = instances.data[i];
+    let m = instance.model;
+    // World space box around the transformed local box
+    let local_center = (instance.bounds_min.xyz + instance.bounds_max.xyz) * 0.5;
+    let local_extent = (instance.bounds_max.xyz - instance.bounds_min.xyz) * 0.5;
+    let center = (m * vec4<f32>(local_center, 1.0)).xyz;
+    let extent = abs(m[0].xyz) * local_extent.x
+        + abs(m[1].xyz) * local_extent.y
+        + abs(m[2].xyz) * local_extent.z;
+
+    var visible = true;
+    for (var p = 0; p < 6; p = p + 1) {
+        let plane = params.planes[p];
+        if (dot(plane.xyz, center) + dot(abs(plane.xyz), extent) + plane.w < 0.0) {
+            visible = false;
+        }
+    }
+
+    var draw = draws.data[i];
+    if (!visible) {
+        draw.instance_count = 0u;
+    }
+    culled.data[i] = draw;
+}
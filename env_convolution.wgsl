@@ -0,0 +1,117 @@
+// Image based lighting convolutions of a cube map
+
+[[block]]
+struct Params {
+    roughness: f32;
+    sample_count: u32;
+    padding0: u32;
+    padding1: u32;
+};
+
+[[group(0), binding(0)]]
+var src: texture_cube<f32>;
+[[group(0), binding(1)]]
+var src_sampler: sampler;
+[[group(0), binding(2)]]
+var<uniform> params: Params;
+[[group(0), binding(3)]]
+var dst: texture_storage_2d_array<rgba16float, write>;
+
+let PI: f32 = 3.14159265359;
+
+// Direction through texel `uv` (in [-1, 1]) of cube face `face` (+X, -X, +Y, -Y, +Z, -Z)
+fn cube_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    if (face == 0u) {
+        return normalize(vec3<f32>(1.0, -uv.y, -uv.x));
+    } elseif (face == 1u) {
+        return normalize(vec3<f32>(-1.0, -uv.y, uv.x));
+    } elseif (face == 2u) {
+        return normalize(vec3<f32>(uv.x, 1.0, uv.y));
+    } elseif (face == 3u) {
+        return normalize(vec3<f32>(uv.x, -1.0, -uv.y));
+    } elseif (face == 4u) {
+        return normalize(vec3<f32>(uv.x, -uv.y, 1.0));
+    }
+    return normalize(vec3<f32>(-uv.x, -uv.y, -1.0));
+}
+
+// Van der Corput radical inverse, the bit masks are written out in decimal
+fn radical_inverse(i: u32) -> f32 {
+    var bits: u32 = (i << 16u) | (i >> 16u);
+    bits = ((bits & 1431655765u) << 1u) | ((bits & 2863311530u) >> 1u);
+    bits = ((bits & 858993459u) << 2u) | ((bits & 3435973836u) >> 2u);
+    bits = ((bits & 252645135u) << 4u) | ((bits & 4042322160u) >> 4u);
+    bits = ((bits & 16711935u) << 8u) | ((bits & 4278255360u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), radical_inverse(i));
+}
+
+// Rotates `v` from tangent space around +Z into the space around `n`
+fn to_world(v: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return normalize(tangent * v.x + bitangent * v.y + n * v.z);
+}
+
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return to_world(vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta), n);
+}
+
+fn texel_direction(id: vec3<u32>) -> vec3<f32> {
+    let size = textureDimensions(dst);
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+    return cube_direction(id.z, uv);
+}
+
+fn outside(id: vec3<u32>) -> bool {
+    let size = textureDimensions(dst);
+    return i32(id.x) >= size.x || i32(id.y) >= size.y;
+}
+
+// GGX specular prefilter for one mip level, assuming N = V = R
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn prefilter([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (outside(id)) {
+        return;
+    }
+    let n = texel_direction(id);
+    var color: vec3<f32> = vec3<f32>(0.0);
+    var weight: f32 = 0.0;
+    for (var i: u32 = 0u; i < params.sample_count; i = i + 1u) {
+        let h = importance_sample_ggx(hammersley(i, params.sample_count), n, params.roughness);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+        if (n_dot_l > 0.0) {
+            color = color + textureSampleLevel(src, src_sampler, l, 0.0).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+    }
+    textureStore(dst, vec2<i32>(id.xy), i32(id.z), vec4<f32>(color / max(weight, 0.0001), 1.0));
+}
+
+// Diffuse irradiance, cosine weighted so the pdf cancels the Lambert term
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn irradiance([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (outside(id)) {
+        return;
+    }
+    let n = texel_direction(id);
+    var color: vec3<f32> = vec3<f32>(0.0);
+    for (var i: u32 = 0u; i < params.sample_count; i = i + 1u) {
+        let xi = hammersley(i, params.sample_count);
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt(1.0 - xi.y);
+        let sin_theta = sqrt(xi.y);
+        let l = to_world(vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta), n);
+        color = color + textureSampleLevel(src, src_sampler, l, 0.0).rgb;
+    }
+    textureStore(dst, vec2<i32>(id.xy), i32(id.z), vec4<f32>(color / f32(params.sample_count), 1.0));
+}
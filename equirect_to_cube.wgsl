@@ -0,0 +1,52 @@
+// Projects an equirectangular image onto the six layers of a cube map
+
+[[group(0), binding(0)]]
+var src: texture_2d<f32>;
+[[group(0), binding(1)]]
+var dst: texture_storage_2d_array<rgba16float, write>;
+
+let PI: f32 = 3.14159265359;
+
+// Direction through texel `uv` (in [-1, 1]) of cube face `face` (+X, -X, +Y, -Y, +Z, -Z)
+fn cube_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    if (face == 0u) {
+        return normalize(vec3<f32>(1.0, -uv.y, -uv.x));
+    } elseif (face == 1u) {
+        return normalize(vec3<f32>(-1.0, -uv.y, uv.x));
+    } elseif (face == 2u) {
+        return normalize(vec3<f32>(uv.x, 1.0, uv.y));
+    } elseif (face == 3u) {
+        return normalize(vec3<f32>(uv.x, -1.0, -uv.y));
+    } elseif (face == 4u) {
+        return normalize(vec3<f32>(uv.x, -uv.y, 1.0));
+    }
+    return normalize(vec3<f32>(-uv.x, -uv.y, -1.0));
+}
+
+// Bilinear sample, wrapping horizontally and clamping vertically. Float textures aren't
+// filterable without an extra feature so this is done by hand.
+fn sample_equirect(uv: vec2<f32>) -> vec4<f32> {
+    let size = textureDimensions(src);
+    let coords = uv * vec2<f32>(size) - 0.5;
+    let base = floor(coords);
+    let f = coords - base;
+    let x0 = (i32(base.x) % size.x + size.x) % size.x;
+    let x1 = (x0 + 1) % size.x;
+    let y0 = clamp(i32(base.y), 0, size.y - 1);
+    let y1 = clamp(i32(base.y) + 1, 0, size.y - 1);
+    let top = mix(textureLoad(src, vec2<i32>(x0, y0), 0), textureLoad(src, vec2<i32>(x1, y0), 0), vec4<f32>(f.x));
+    let bottom = mix(textureLoad(src, vec2<i32>(x0, y1), 0), textureLoad(src, vec2<i32>(x1, y1), 0), vec4<f32>(f.x));
+    return mix(top, bottom, vec4<f32>(f.y));
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(dst);
+    if (i32(id.x) >= size.x || i32(id.y) >= size.y) {
+        return;
+    }
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+    let dir = cube_direction(id.z, uv);
+    let equirect_uv = vec2<f32>(atan2(dir.z, dir.x) / (2.0 * PI) + 0.5, acos(dir.y) / PI);
+    textureStore(dst, vec2<i32>(id.xy), i32(id.z), sample_equirect(equirect_uv));
+}
@@ -0,0 +1,24 @@
+//! Malformed OBJ files have to be errors, never panics. Run with
+//! `cargo +nightly fuzz run fuzz_obj_parse fuzz/corpus/fuzz_obj_parse`, the corpus starts with
+//! an empty file, NaN and infinite coordinates, out of range indices, whitespace-only lines and
+//! CRLF line endings.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use soyuz::entity::model::files::obj::{Line, ObjectBuilder};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        // Split on `\n` alone so a CRLF file's lines keep their `\r`
+        for line in text.split('\n') {
+            let _ = Line::process_line(line);
+        }
+    }
+    // The whole reader, including gzip detection, line numbering and building
+    if let Ok(mut builder) = ObjectBuilder::from_bytes(data) {
+        builder.generate_normals = true;
+        builder.generate_tangents = true;
+        let _ = builder.validate();
+        let _ = builder.build();
+    }
+});
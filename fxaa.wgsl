@@ -0,0 +1,195 @@
+// FXAA 3.11 quality: finds the edge through each pixel and blends across it
+
+[[block]]
+struct Fxaa {
+    texel_size: vec2<f32>;
+    // How much sub-pixel aliasing is removed, 0 keeps it sharp
+    subpixel: f32;
+    // Local contrast relative to the brightest neighbour needed to count as an edge
+    edge_threshold: f32;
+    // Absolute contrast below which dark areas are skipped
+    edge_threshold_min: f32;
+    search_steps: u32;
+    padding0: u32;
+    padding1: u32;
+};
+
+[[group(0), binding(0)]]
+var src: texture_2d<f32>;
+[[group(0), binding(1)]]
+var src_sampler: sampler;
+[[group(0), binding(2)]]
+var<uniform> fxaa: Fxaa;
+
+struct FullscreenOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] in_vertex_index: u32) -> FullscreenOutput {
+    var out: FullscreenOutput;
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Perceptual luma, the sampled colors are linear
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+fn luma_at(uv: vec2<f32>) -> f32 {
+    return luma(textureSampleLevel(src, src_sampler, uv, 0.0).rgb);
+}
+
+fn luma_offset(uv: vec2<f32>, x: f32, y: f32) -> f32 {
+    return luma_at(uv + vec2<f32>(x, y) * fxaa.texel_size);
+}
+
+// Step sizes of the FXAA 3.11 quality presets, the search speeds up further out
+fn step_size(i: u32) -> f32 {
+    if (i < 5u) {
+        return 1.0;
+    }
+    if (i == 5u) {
+        return 1.5;
+    }
+    if (i < 10u) {
+        return 2.0;
+    }
+    if (i == 10u) {
+        return 4.0;
+    }
+    return 8.0;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let uv = in.uv;
+    let color = textureSampleLevel(src, src_sampler, uv, 0.0);
+    let center = luma(color.rgb);
+    // y grows downwards in UV space
+    let n = luma_offset(uv, 0.0, -1.0);
+    let s = luma_offset(uv, 0.0, 1.0);
+    let w = luma_offset(uv, -1.0, 0.0);
+    let e = luma_offset(uv, 1.0, 0.0);
+    let luma_max = max(center, max(max(n, s), max(w, e)));
+    let luma_min = min(center, min(min(n, s), min(w, e)));
+    let range = luma_max - luma_min;
+    if (range < max(fxaa.edge_threshold_min, luma_max * fxaa.edge_threshold)) {
+        return color;
+    }
+
+    let nw = luma_offset(uv, -1.0, -1.0);
+    let ne = luma_offset(uv, 1.0, -1.0);
+    let sw = luma_offset(uv, -1.0, 1.0);
+    let se = luma_offset(uv, 1.0, 1.0);
+
+    // How much the center differs from its neighbourhood, for sub-pixel aliasing
+    let average = (2.0 * (n + s + w + e) + (nw + ne + sw + se)) / 12.0;
+    let subpixel_offset = clamp(abs(average - center) / range, 0.0, 1.0);
+    let subpixel_blend = smoothstep(0.0, 1.0, subpixel_offset);
+    let subpixel_final = subpixel_blend * subpixel_blend * fxaa.subpixel;
+
+    let edge_horizontal = abs(-2.0 * w + nw + sw) + 2.0 * abs(-2.0 * center + n + s)
+        + abs(-2.0 * e + ne + se);
+    let edge_vertical = abs(-2.0 * n + nw + ne) + 2.0 * abs(-2.0 * center + w + e)
+        + abs(-2.0 * s + sw + se);
+    let is_horizontal = edge_horizontal >= edge_vertical;
+
+    // The neighbours across the edge, the first is on the negative side
+    var luma1 = w;
+    var luma2 = e;
+    var step_length = fxaa.texel_size.x;
+    var offset = vec2<f32>(0.0, fxaa.texel_size.y);
+    if (is_horizontal) {
+        luma1 = n;
+        luma2 = s;
+        step_length = fxaa.texel_size.y;
+        offset = vec2<f32>(fxaa.texel_size.x, 0.0);
+    }
+    let gradient1 = luma1 - center;
+    let gradient2 = luma2 - center;
+    let is1_steepest = abs(gradient1) >= abs(gradient2);
+    let gradient_scaled = 0.25 * max(abs(gradient1), abs(gradient2));
+    var local_average = 0.5 * (luma2 + center);
+    if (is1_steepest) {
+        step_length = -step_length;
+        local_average = 0.5 * (luma1 + center);
+    }
+
+    // Start half a pixel towards the steeper side, on the edge itself
+    var edge_uv = uv;
+    if (is_horizontal) {
+        edge_uv.y = edge_uv.y + step_length * 0.5;
+    } else {
+        edge_uv.x = edge_uv.x + step_length * 0.5;
+    }
+
+    // Walk along the edge both ways until the contrast changes
+    var uv1 = edge_uv - offset;
+    var uv2 = edge_uv + offset;
+    var end1 = luma_at(uv1) - local_average;
+    var end2 = luma_at(uv2) - local_average;
+    var reached1 = abs(end1) >= gradient_scaled;
+    var reached2 = abs(end2) >= gradient_scaled;
+    if (!reached1) {
+        uv1 = uv1 - offset;
+    }
+    if (!reached2) {
+        uv2 = uv2 + offset;
+    }
+    for (var i: u32 = 2u; i < fxaa.search_steps; i = i + 1u) {
+        if (reached1 && reached2) {
+            break;
+        }
+        if (!reached1) {
+            end1 = luma_at(uv1) - local_average;
+            reached1 = abs(end1) >= gradient_scaled;
+        }
+        if (!reached2) {
+            end2 = luma_at(uv2) - local_average;
+            reached2 = abs(end2) >= gradient_scaled;
+        }
+        if (!reached1) {
+            uv1 = uv1 - offset * step_size(i);
+        }
+        if (!reached2) {
+            uv2 = uv2 + offset * step_size(i);
+        }
+    }
+
+    var distance1 = uv.y - uv1.y;
+    var distance2 = uv2.y - uv.y;
+    if (is_horizontal) {
+        distance1 = uv.x - uv1.x;
+        distance2 = uv2.x - uv.x;
+    }
+    let is_direction1 = distance1 < distance2;
+    let distance_final = min(distance1, distance2);
+    let edge_length = distance1 + distance2;
+    let pixel_offset = 0.5 - distance_final / edge_length;
+
+    // Only blend if the closer end goes the same way as the center, otherwise this pixel is
+    // past the end of the edge
+    var closer_end = end2;
+    if (is_direction1) {
+        closer_end = end1;
+    }
+    let is_center_smaller = center < local_average;
+    var final_offset = 0.0;
+    if ((closer_end < 0.0) != is_center_smaller) {
+        final_offset = pixel_offset;
+    }
+    final_offset = max(final_offset, subpixel_final);
+
+    var final_uv = uv;
+    if (is_horizontal) {
+        final_uv.y = final_uv.y + final_offset * step_length;
+    } else {
+        final_uv.x = final_uv.x + final_offset * step_length;
+    }
+    return textureSampleLevel(src, src_sampler, final_uv, 0.0);
+}
@@ -0,0 +1,92 @@
+// Writes the surface attributes the deferred lighting pass shades with
+
+[[block]]
+struct Camera {
+    view_proj: mat4x4<f32>;
+    // Without the TAA jitter, for the velocity
+    unjittered_view_proj: mat4x4<f32>;
+    previous_view_proj: mat4x4<f32>;
+    position: vec4<f32>;
+};
+[[block]]
+struct Material {
+    albedo: vec4<f32>;
+    metallic: f32;
+    roughness: f32;
+    ao: f32;
+    texture_flags: u32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+[[group(1), binding(0)]]
+var<uniform> material: Material;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] normal: vec3<f32>;
+    [[location(1)]] texture_coords: vec2<f32>;
+    [[location(2)]] current_position: vec4<f32>;
+    [[location(3)]] previous_position: vec4<f32>;
+    [[location(4)]] color: vec4<f32>;
+};
+
+fn transform_vertex(
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    texture_coords: vec2<f32>,
+    color: vec4<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+    out.current_position = camera.unjittered_view_proj * vec4<f32>(position, 1.0);
+    out.previous_position = camera.previous_view_proj * vec4<f32>(position, 1.0);
+    out.normal = normal;
+    out.texture_coords = texture_coords;
+    out.color = color;
+    return out;
+}
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] normal: vec3<f32>,
+    [[location(2)]] texture_coords: vec2<f32>,
+) -> VertexOutput {
+    return transform_vertex(position, normal, texture_coords, vec4<f32>(1.0));
+}
+
+// For meshes with a color buffer in slot 1
+[[stage(vertex)]]
+fn vs_colored(
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] normal: vec3<f32>,
+    [[location(2)]] texture_coords: vec2<f32>,
+    [[location(4)]] color: vec4<f32>,
+) -> VertexOutput {
+    return transform_vertex(position, normal, texture_coords, color);
+}
+
+struct GBufferOutput {
+    // rgb albedo, a roughness
+    [[location(0)]] albedo: vec4<f32>;
+    // World space normal mapped to [0, 1]
+    [[location(1)]] normal: vec4<f32>;
+    // r metallic, g ambient occlusion
+    [[location(2)]] material: vec4<f32>;
+    // Screen space motion since the last frame in UV units
+    [[location(3)]] velocity: vec4<f32>;
+};
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> GBufferOutput {
+    var out: GBufferOutput;
+    out.albedo = vec4<f32>(material.albedo.rgb * in.color.rgb, material.roughness);
+    out.normal = vec4<f32>(normalize(in.normal) * 0.5 + 0.5, 1.0);
+    out.material = vec4<f32>(material.metallic, material.ao, 0.0, 0.0);
+    let current = in.current_position.xy / in.current_position.w;
+    let previous = in.previous_position.xy / in.previous_position.w;
+    // UV y points down while NDC y points up
+    out.velocity = vec4<f32>((current - previous) * vec2<f32>(0.5, -0.5), 0.0, 0.0);
+    return out;
+}
@@ -0,0 +1,140 @@
+// GPU particles: `update` integrates and recycles them, `vs_main`/`fs_main` draw camera facing quads
+
+struct Particle {
+    // Separate floats so the layout matches the tightly packed Rust struct
+    position_x: f32;
+    position_y: f32;
+    position_z: f32;
+    velocity_x: f32;
+    velocity_y: f32;
+    velocity_z: f32;
+    lifetime: f32;
+    max_lifetime: f32;
+    color: vec4<f32>;
+};
+[[block]]
+struct Particles {
+    data: array<Particle>;
+};
+[[block]]
+struct SimParams {
+    emitter: vec3<f32>;
+    dt: f32;
+    gravity: vec3<f32>;
+    particle_count: u32;
+    color: vec4<f32>;
+    speed: f32;
+    seed: u32;
+    padding0: u32;
+    padding1: u32;
+};
+[[block]]
+struct RenderParams {
+    view_proj: mat4x4<f32>;
+    // World space camera axes, w of `right` is the particle size
+    right: vec4<f32>;
+    up: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> sim: SimParams;
+[[group(0), binding(1)]]
+var<storage, read_write> particles: Particles;
+
+fn hash(x: u32) -> u32 {
+    var h = x;
+    h = (h ^ 61u) ^ (h >> 16u);
+    h = h * 9u;
+    h = h ^ (h >> 4u);
+    h = h * 668265261u;
+    h = h ^ (h >> 15u);
+    return h;
+}
+
+fn random(x: u32) -> f32 {
+    return f32(hash(x)) / 4294967295.0;
+}
+
+[[stage(compute), workgroup_size(64, 1, 1)]]
+fn update([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let i = id.x;
+    if (i >= sim.particle_count) {
+        return;
+    }
+    var p = particles.data[i];
+    p.lifetime = p.lifetime - sim.dt;
+    if (p.lifetime <= 0.0) {
+        // Recycle at the emitter, heading up in a random direction
+        let r = hash(i * 3u + sim.seed);
+        let theta = random(r) * 6.28318530718;
+        let z = random(r + 1u);
+        let radius = sqrt(1.0 - z * z);
+        let direction = vec3<f32>(radius * cos(theta), z, radius * sin(theta));
+        p.position_x = sim.emitter.x;
+        p.position_y = sim.emitter.y;
+        p.position_z = sim.emitter.z;
+        p.velocity_x = direction.x * sim.speed;
+        p.velocity_y = direction.y * sim.speed;
+        p.velocity_z = direction.z * sim.speed;
+        p.lifetime = p.max_lifetime;
+        p.color = sim.color;
+    } else {
+        p.velocity_x = p.velocity_x + sim.gravity.x * sim.dt;
+        p.velocity_y = p.velocity_y + sim.gravity.y * sim.dt;
+        p.velocity_z = p.velocity_z + sim.gravity.z * sim.dt;
+        p.position_x = p.position_x + p.velocity_x * sim.dt;
+        p.position_y = p.position_y + p.velocity_y * sim.dt;
+        p.position_z = p.position_z + p.velocity_z * sim.dt;
+    }
+    particles.data[i] = p;
+}
+
+[[group(0), binding(0)]]
+var<uniform> camera: RenderParams;
+[[group(0), binding(1)]]
+var<storage, read> live_particles: Particles;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] corner: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[builtin(vertex_index)]] vertex_index: u32,
+    [[builtin(instance_index)]] instance_index: u32,
+) -> VertexOutput {
+    // Two triangles, corners in -1..1
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let p = live_particles.data[instance_index];
+    let corner = corners[vertex_index];
+    var out: VertexOutput;
+    out.corner = corner;
+    out.color = p.color;
+    out.color.a = out.color.a * clamp(p.lifetime / p.max_lifetime, 0.0, 1.0);
+    if (p.lifetime <= 0.0) {
+        // Degenerate, nothing is drawn
+        out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+        return out;
+    }
+    let size = camera.right.w;
+    let center = vec3<f32>(p.position_x, p.position_y, p.position_z);
+    let position = center + (camera.right.xyz * corner.x + camera.up.xyz * corner.y) * size;
+    out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Round with a soft edge
+    let falloff = clamp(1.0 - dot(in.corner, in.corner), 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * falloff);
+}
@@ -0,0 +1,104 @@
+// Metallic-roughness PBR with a Cook-Torrance specular BRDF
+
+[[block]]
+struct Camera {
+    view_proj: mat4x4<f32>;
+    position: vec4<f32>;
+};
+[[block]]
+struct Light {
+    // Direction towards the light
+    direction: vec4<f32>;
+    color: vec4<f32>;
+};
+[[block]]
+struct Material {
+    albedo: vec4<f32>;
+    metallic: f32;
+    roughness: f32;
+    ao: f32;
+    // Bit i is set when texture slot i is bound
+    texture_flags: u32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+[[group(0), binding(1)]]
+var<uniform> light: Light;
+[[group(1), binding(0)]]
+var<uniform> material: Material;
+
+let PI: f32 = 3.14159265359;
+
+// Vertex shader
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] texture_coords: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] normal: vec3<f32>,
+    [[location(2)]] texture_coords: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+    out.world_position = position;
+    out.normal = normal;
+    out.texture_coords = texture_coords;
+    return out;
+}
+
+// Fragment shader
+
+// Trowbridge-Reitz GGX normal distribution
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// Smith's method, shadowing from the light and masking towards the viewer
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    return geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let albedo = material.albedo.rgb;
+    let n = normalize(in.normal);
+    let v = normalize(camera.position.xyz - in.world_position);
+    let l = normalize(light.direction.xyz);
+    let h = normalize(v + l);
+    let n_dot_v = max(dot(n, v), 0.0);
+    let n_dot_l = max(dot(n, l), 0.0);
+
+    // Dielectrics reflect ~4% at normal incidence, metals reflect their albedo
+    let f0 = mix(vec3<f32>(0.04), albedo, vec3<f32>(material.metallic));
+    let f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+    let ndf = distribution_ggx(max(dot(n, h), 0.0), material.roughness);
+    let g = geometry_smith(n_dot_v, n_dot_l, material.roughness);
+    let specular = ndf * g * f / (4.0 * n_dot_v * n_dot_l + 0.0001);
+
+    // Energy not reflected is refracted, metals absorb all of it
+    let k_d = (vec3<f32>(1.0) - f) * (1.0 - material.metallic);
+    let radiance = light.color.rgb;
+    let lo = (k_d * albedo / PI + specular) * radiance * n_dot_l;
+    let ambient = vec3<f32>(0.03) * albedo * material.ao;
+    return vec4<f32>(ambient + lo, material.albedo.a);
+}
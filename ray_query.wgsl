@@ -0,0 +1,147 @@
+// Closest hit of many rays against a BVH, for picking lots of pixels at once
+
+[[block]]
+struct Params {
+    ray_count: u32;
+    padding0: u32;
+    padding1: u32;
+    padding2: u32;
+};
+// Leaves have a count, their triangles start at left_or_first. Other nodes have their two
+// children at left_or_first and left_or_first + 1
+struct Node {
+    min: vec3<f32>;
+    left_or_first: u32;
+    max: vec3<f32>;
+    count: u32;
+};
+[[block]]
+struct Nodes {
+    data: array<Node>;
+};
+struct Triangle {
+    a: vec4<f32>;
+    b: vec4<f32>;
+    c: vec4<f32>;
+};
+[[block]]
+struct Triangles {
+    data: array<Triangle>;
+};
+[[block]]
+struct TriangleIndices {
+    data: array<u32>;
+};
+struct Ray {
+    origin: vec3<f32>;
+    t_max: f32;
+    direction: vec3<f32>;
+    padding: u32;
+};
+[[block]]
+struct Rays {
+    data: array<Ray>;
+};
+struct Hit {
+    distance: f32;
+    // 0xffffffff when nothing was hit
+    triangle: u32;
+};
+[[block]]
+struct Hits {
+    data: array<Hit>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: Params;
+[[group(0), binding(1)]]
+var<storage, read> nodes: Nodes;
+[[group(0), binding(2)]]
+var<storage, read> triangles: Triangles;
+[[group(0), binding(3)]]
+var<storage, read> triangle_indices: TriangleIndices;
+[[group(0), binding(4)]]
+var<storage, read> rays: Rays;
+[[group(0), binding(5)]]
+var<storage, read_write> hits: Hits;
+
+// Distance to the node's box, or 1e30 if it's missed or further than t_max
+fn hit_box(origin: vec3<f32>, inv_dir: vec3<f32>, node: Node, t_max: f32) -> f32 {
+    let t0 = (node.min - origin) * inv_dir;
+    let t1 = (node.max - origin) * inv_dir;
+    let near = min(t0, t1);
+    let far = max(t0, t1);
+    let t_near = max(max(near.x, near.y), max(near.z, 0.0));
+    let t_far = min(min(far.x, far.y), min(far.z, t_max));
+    if (t_near <= t_far) {
+        return t_near;
+    }
+    return 1.0e30;
+}
+
+// Moller-Trumbore, both faces. The distance or -1 for a miss
+fn hit_triangle(origin: vec3<f32>, direction: vec3<f32>, triangle: Triangle) -> f32 {
+    let e1 = triangle.b.xyz - triangle.a.xyz;
+    let e2 = triangle.c.xyz - triangle.a.xyz;
+    let p = cross(direction, e2);
+    let det = dot(e1, p);
+    if (abs(det) < 1.0e-12) {
+        return -1.0;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - triangle.a.xyz;
+    let u = dot(s, p) * inv_det;
+    if (u < 0.0 || u > 1.0) {
+        return -1.0;
+    }
+    let q = cross(s, e1);
+    let v = dot(direction, q) * inv_det;
+    if (v < 0.0 || u + v > 1.0) {
+        return -1.0;
+    }
+    return dot(e2, q) * inv_det;
+}
+
+[[stage(compute), workgroup_size(64, 1, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let i = id.x;
+    if (i >= params.ray_count) {
+        return;
+    }
+    let ray = rays.data[i];
+    let inv_dir = vec3<f32>(1.0) / ray.direction;
+    var closest = ray.t_max;
+    var hit = 4294967295u;
+    // The BVH is built at most 64 levels deep, so the stack can't overflow
+    var stack: array<u32, 64>;
+    stack[0] = 0u;
+    var sp = 1u;
+    loop {
+        if (sp == 0u) {
+            break;
+        }
+        sp = sp - 1u;
+        let node = nodes.data[stack[sp]];
+        if (hit_box(ray.origin, inv_dir, node, closest) >= 1.0e30) {
+            continue;
+        }
+        if (node.count > 0u) {
+            let end = node.left_or_first + node.count;
+            for (var j = node.left_or_first; j < end; j = j + 1u) {
+                let t = hit_triangle(ray.origin, ray.direction, triangles.data[j]);
+                if (t > 0.0 && t < closest) {
+                    closest = t;
+                    hit = triangle_indices.data[j];
+                }
+            }
+        } else {
+            stack[sp] = node.left_or_first;
+            stack[sp + 1u] = node.left_or_first + 1u;
+            sp = sp + 2u;
+        }
+    }
+    var result: Hit;
+    result.distance = closest;
+    result.triangle = hit;
+    hits.data[i] = result;
+}
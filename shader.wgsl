@@ -1,23 +1,89 @@
+// Forward pass: the material's diffuse color and map, lit by a fixed directional light
+
+[[block]]
+struct Camera {
+    view_proj: mat4x4<f32>;
+    position: vec4<f32>;
+};
+[[block]]
+struct Entity {
+    model: mat4x4<f32>;
+    // Entity::color
+    tint: vec4<f32>;
+    // Entity::emissive, drawn unlit instead when w is 1
+    emissive: vec4<f32>;
+};
+// The start of MaterialUniform
+[[block]]
+struct Material {
+    diffuse: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+[[group(0), binding(1)]]
+var<uniform> entity: Entity;
+[[group(1), binding(0)]]
+var<uniform> material: Material;
+[[group(1), binding(1)]]
+var material_sampler: sampler;
+[[group(1), binding(2)]]
+var diffuse_map: texture_2d<f32>;
+
 // Vertex shader
 
 struct VertexOutput {
     [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] normal: vec3<f32>;
+    [[location(1)]] texture_coords: vec2<f32>;
+    [[location(2)]] color: vec4<f32>;
 };
 
-[[stage(vertex)]]
-fn vs_main(
-    [[builtin(vertex_index)]] in_vertex_index: u32,
+fn transform_vertex(
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    texture_coords: vec2<f32>,
+    color: vec4<f32>,
 ) -> VertexOutput {
     var out: VertexOutput;
-    let x = f32(1 - i32(in_vertex_index)) * 0.5;
-    let y = f32(i32(in_vertex_index & 1u) * 2 - 1) * 0.5;
-    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    out.clip_position = camera.view_proj * entity.model * vec4<f32>(position, 1.0);
+    // Right for rotations and uniform scales, the normal is normalized per fragment
+    out.normal = (entity.model * vec4<f32>(normal, 0.0)).xyz;
+    out.texture_coords = texture_coords;
+    out.color = color;
     return out;
 }
 
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] normal: vec3<f32>,
+    [[location(2)]] texture_coords: vec2<f32>,
+) -> VertexOutput {
+    return transform_vertex(position, normal, texture_coords, vec4<f32>(1.0));
+}
+
+// For meshes with a color buffer in slot 1
+[[stage(vertex)]]
+fn vs_colored(
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] normal: vec3<f32>,
+    [[location(2)]] texture_coords: vec2<f32>,
+    [[location(4)]] color: vec4<f32>,
+) -> VertexOutput {
+    return transform_vertex(position, normal, texture_coords, color);
+}
+
 // Fragment shader
 
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    return vec4<f32>(0.3, 0.2, 0.1, 1.0);
+    if (entity.emissive.w > 0.0) {
+        return vec4<f32>(entity.emissive.rgb, 1.0);
+    }
+    let texel = textureSample(diffuse_map, material_sampler, in.texture_coords);
+    let albedo = material.diffuse * texel * in.color * entity.tint;
+    let light = normalize(vec3<f32>(0.3, 1.0, 0.5));
+    let n_dot_l = max(dot(normalize(in.normal), light), 0.0);
+    return vec4<f32>(albedo.rgb * (0.2 + 0.8 * n_dot_l), albedo.a);
 }
@@ -0,0 +1,79 @@
+// Linear blend skinning of model::Vertex data (8 floats: position, normal, texture coords)
+
+[[block]]
+struct Params {
+    vertex_count: u32;
+    bone_count: u32;
+    padding0: u32;
+    padding1: u32;
+};
+[[block]]
+struct Vertices {
+    data: array<f32>;
+};
+[[block]]
+struct BoneIndices {
+    // Four u8 bone indices per vertex
+    data: array<u32>;
+};
+[[block]]
+struct BoneWeights {
+    data: array<vec4<f32>>;
+};
+[[block]]
+struct Matrices {
+    data: array<mat4x4<f32>>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: Params;
+[[group(0), binding(1)]]
+var<storage, read> rest_vertices: Vertices;
+[[group(0), binding(2)]]
+var<storage, read> bone_indices: BoneIndices;
+[[group(0), binding(3)]]
+var<storage, read> bone_weights: BoneWeights;
+// Inverse bind matrices, from model space to bone space
+[[group(0), binding(4)]]
+var<storage, read> bind_pose: Matrices;
+// Current bone to model space matrices
+[[group(0), binding(5)]]
+var<storage, read> pose: Matrices;
+[[group(0), binding(6)]]
+var<storage, read_write> posed_vertices: Vertices;
+
+let VERTEX_FLOATS: u32 = 8u;
+
+fn bone_matrix(bone: u32) -> mat4x4<f32> {
+    let b = min(bone, params.bone_count - 1u);
+    return pose.data[b] * bind_pose.data[b];
+}
+
+[[stage(compute), workgroup_size(64, 1, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let v = id.x;
+    if (v >= params.vertex_count) {
+        return;
+    }
+    let packed = bone_indices.data[v];
+    let w = bone_weights.data[v];
+    let skin = bone_matrix(packed & 255u) * w.x
+        + bone_matrix((packed >> 8u) & 255u) * w.y
+        + bone_matrix((packed >> 16u) & 255u) * w.z
+        + bone_matrix((packed >> 24u) & 255u) * w.w;
+
+    let base = v * VERTEX_FLOATS;
+    let position = vec4<f32>(rest_vertices.data[base], rest_vertices.data[base + 1u], rest_vertices.data[base + 2u], 1.0);
+    let normal = vec3<f32>(rest_vertices.data[base + 3u], rest_vertices.data[base + 4u], rest_vertices.data[base + 5u]);
+    let posed_position = skin * position;
+    let posed_normal = normalize(mat3x3<f32>(skin[0].xyz, skin[1].xyz, skin[2].xyz) * normal);
+
+    posed_vertices.data[base] = posed_position.x;
+    posed_vertices.data[base + 1u] = posed_position.y;
+    posed_vertices.data[base + 2u] = posed_position.z;
+    posed_vertices.data[base + 3u] = posed_normal.x;
+    posed_vertices.data[base + 4u] = posed_normal.y;
+    posed_vertices.data[base + 5u] = posed_normal.z;
+    posed_vertices.data[base + 6u] = rest_vertices.data[base + 6u];
+    posed_vertices.data[base + 7u] = rest_vertices.data[base + 7u];
+}
@@ -0,0 +1,38 @@
+// Vertex shader
+
+[[block]]
+struct Camera {
+    // Projection * view with the view's translation removed
+    view_proj: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] direction: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec3<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let clip = camera.view_proj * vec4<f32>(position, 1.0);
+    // z = w puts the sky on the far plane
+    out.clip_position = clip.xyww;
+    out.direction = position;
+    return out;
+}
+
+// Fragment shader
+
+[[group(0), binding(1)]]
+var t_sky: texture_cube<f32>;
+[[group(0), binding(2)]]
+var s_sky: sampler;
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return textureSample(t_sky, s_sky, in.direction);
+}
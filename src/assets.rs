@@ -0,0 +1,598 @@
+use crate::entity::model::bounds::Aabb;
+use crate::entity::model::files::mtl::{MtlCache, SharedMaterials};
+use crate::entity::model::files;
+use crate::entity::model::material::{BoundMaterial, MaterialCache, MaterialUniform};
+use crate::entity::model::mesh::Mesh;
+use crate::entity::model::object::Stats;
+use crate::entity::model::{self, LoadOptions, ModelData, Object};
+use crate::entity::transform::Transform;
+use crate::entity::Entity;
+use crate::texture::Texture;
+use cgmath::{Matrix4, SquareMatrix};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+#[cfg(feature = "hot-reload")]
+use {
+    crate::scene::Scene, notify::Watcher, std::collections::HashSet, std::sync::mpsc,
+    std::time::Duration,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Load(files::Error),
+    #[cfg(feature = "image")]
+    Texture(crate::texture::Error),
+}
+impl From<files::Error> for Error {
+    fn from(e: files::Error) -> Self {
+        Error::Load(e)
+    }
+}
+#[cfg(feature = "image")]
+impl From<crate::texture::Error> for Error {
+    fn from(e: crate::texture::Error) -> Self {
+        Error::Texture(e)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Bound materials of an MTL library by name.
+pub type MaterialLibrary = HashMap<String, Rc<BoundMaterial>>;
+
+struct MeshAsset {
+    mesh: Rc<Mesh>,
+    bounds: Aabb,
+}
+
+struct MaterialAsset {
+    materials: Rc<MaterialLibrary>,
+    gpu_bytes: u64,
+}
+
+/// Loads meshes, materials and textures once per file and hands out shared `Rc`s, so every
+/// entity of the same model draws the same buffers. Files are keyed by canonical path, so
+/// `./a.obj` and `a.obj` are the same asset.
+pub struct Assets {
+    device: Rc<wgpu::Device>,
+    queue: Rc<wgpu::Queue>,
+    meshes: HashMap<PathBuf, MeshAsset>,
+    materials: HashMap<PathBuf, MaterialAsset>,
+    #[cfg(feature = "image")]
+    textures: HashMap<PathBuf, Rc<Texture>>,
+    mtl_cache: MtlCache,
+    material_cache: MaterialCache,
+    #[cfg(feature = "hot-reload")]
+    hot_reload: Option<HotReload>,
+}
+impl Assets {
+    pub fn new(device: Rc<wgpu::Device>, queue: Rc<wgpu::Queue>) -> Self {
+        let material_cache = MaterialCache::new(&device, &queue);
+        Assets {
+            device,
+            queue,
+            meshes: HashMap::new(),
+            materials: HashMap::new(),
+            #[cfg(feature = "image")]
+            textures: HashMap::new(),
+            mtl_cache: MtlCache::new(),
+            material_cache,
+            #[cfg(feature = "hot-reload")]
+            hot_reload: None,
+        }
+    }
+    /// What the adapter supports, so materials only ask for anisotropic filtering where it
+    /// exists. Materials bound before this keep their samplers.
+    pub fn set_downlevel_flags(&mut self, flags: wgpu::DownlevelFlags) {
+        self.material_cache.fallback_textures.downlevel_flags = flags;
+    }
+    /// Layout of every material's bind group, for pipelines drawing them.
+    pub fn material_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.material_cache.layout
+    }
+    /// Bound for entities without a material.
+    pub fn fallback_material(&self) -> Rc<BoundMaterial> {
+        self.material_cache.fallback()
+    }
+    fn canonicalize(path: &Path) -> Result<PathBuf, Error> {
+        std::fs::canonicalize(path).map_err(|e| Error::Load(files::Error::in_file(path, e)))
+    }
+    fn upload_mesh(&self, path: &Path, data: ModelData) -> MeshAsset {
+        if data.objects.len() > 1 {
+            log::warn!(
+                "'{}' has {} objects, only the first is used as a mesh",
+                path.display(),
+                data.objects.len()
+            );
+        }
+        let object = data.objects.into_iter().next().unwrap_or_else(|| {
+            let (vertices, indices, submeshes) = (Vec::new(), Vec::new(), Vec::new());
+            Object::new(None, vertices, indices, submeshes, vec![], vec![], Stats::default())
+        });
+        // The file name is what shows up for the buffers in graphics debuggers
+        let label = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+        MeshAsset {
+            mesh: Rc::new(Mesh::new(&self.device, &object, Some(&label))),
+            bounds: *object.bounds(),
+        }
+    }
+    fn mesh_asset(&mut self, path: &Path) -> Result<&MeshAsset, Error> {
+        let path = Self::canonicalize(path)?;
+        if !self.meshes.contains_key(&path) {
+            let data = model::loader::load_data(&path, &LoadOptions::default())?;
+            let asset = self.upload_mesh(&path, data);
+            self.meshes.insert(path.clone(), asset);
+            #[cfg(feature = "hot-reload")]
+            self.watch(&path);
+        }
+        Ok(&self.meshes[&path])
+    }
+    /// The mesh of a model file, loaded the first time it's asked for. Files with several
+    /// objects give the first.
+    pub fn get_mesh(&mut self, path: impl AsRef<Path>) -> Result<Rc<Mesh>, Error> {
+        Ok(self.mesh_asset(path.as_ref())?.mesh.clone())
+    }
+    /// A new entity drawing the mesh of `path`, sharing its buffers with every other entity
+    /// of the same file.
+    pub fn instantiate(&mut self, path: impl AsRef<Path>) -> Result<Entity, Error> {
+        Ok(Self::entity(self.mesh_asset(path.as_ref())?))
+    }
+    /// Caches a model parsed elsewhere, e.g. by `model::load_in_background`, as the mesh of
+    /// `path` and instantiates it. Replaces what was cached for the file.
+    pub fn insert_model(
+        &mut self,
+        path: impl AsRef<Path>,
+        data: ModelData,
+    ) -> Result<Entity, Error> {
+        let path = Self::canonicalize(path.as_ref())?;
+        let asset = self.upload_mesh(&path, data);
+        let entity = Self::entity(&asset);
+        self.meshes.insert(path.clone(), asset);
+        #[cfg(feature = "hot-reload")]
+        self.watch(&path);
+        Ok(entity)
+    }
+    fn entity(asset: &MeshAsset) -> Entity {
+        Entity {
+            name: None,
+            parent: None,
+            transform: Transform::identity(),
+            mx_world: Matrix4::identity(),
+            animators: Vec::new(),
+            color: wgpu::Color::WHITE,
+            emissive: None,
+            mesh: Some(asset.mesh.clone()),
+            bounds: asset.bounds,
+            uniform_offset: 0,
+            material: None,
+            lod: None,
+            model: None,
+        }
+    }
+    /// Every material of an MTL file, bound.
+    pub fn get_materials(&mut self, path: impl AsRef<Path>) -> Result<Rc<MaterialLibrary>, Error> {
+        let path = Self::canonicalize(path.as_ref())?;
+        if let Some(asset) = self.materials.get(&path) {
+            return Ok(asset.materials.clone());
+        }
+        let library = self.mtl_cache.load_sync(&path)?;
+        let asset = self.bind_library(&library);
+        let materials = asset.materials.clone();
+        self.materials.insert(path.clone(), asset);
+        #[cfg(feature = "hot-reload")]
+        self.watch(&path);
+        Ok(materials)
+    }
+    fn bind_library(&mut self, library: &SharedMaterials) -> MaterialAsset {
+        let materials: MaterialLibrary = library
+            .iter()
+            .map(|(name, material)| {
+                let bound = self.material_cache.bind(&self.device, &self.queue, material);
+                (name.clone(), bound)
+            })
+            .collect();
+        let gpu_bytes = materials
+            .values()
+            .map(|bound| {
+                let textures: u64 = bound.textures.iter().map(Texture::gpu_bytes).sum();
+                textures + std::mem::size_of::<MaterialUniform>() as u64
+            })
+            .sum();
+        MaterialAsset {
+            materials: Rc::new(materials),
+            gpu_bytes,
+        }
+    }
+    /// A sRGB texture, see `Texture::load`.
+    #[cfg(feature = "image")]
+    pub fn get_texture(&mut self, path: impl AsRef<Path>) -> Result<Rc<Texture>, Error> {
+        let path = Self::canonicalize(path.as_ref())?;
+        if let Some(texture) = self.textures.get(&path) {
+            return Ok(texture.clone());
+        }
+        let texture = Rc::new(Texture::load(&self.device, &self.queue, &path)?);
+        self.textures.insert(path.clone(), texture.clone());
+        #[cfg(feature = "hot-reload")]
+        self.watch(&path);
+        Ok(texture)
+    }
+    /// Forgets every asset loaded from `path`. Their GPU memory is freed once the last `Rc`
+    /// handed out is dropped. Returns whether anything was cached.
+    pub fn unload(&mut self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let mesh = self.meshes.remove(&path).is_some();
+        let materials = self.materials.remove(&path).is_some();
+        self.mtl_cache.invalidate(&path);
+        #[cfg(feature = "image")]
+        let texture = self.textures.remove(&path).is_some();
+        #[cfg(not(feature = "image"))]
+        let texture = false;
+        self.material_cache.evict_unused();
+        mesh || materials || texture
+    }
+    pub fn clear(&mut self) {
+        self.meshes.clear();
+        self.materials.clear();
+        #[cfg(feature = "image")]
+        self.textures.clear();
+        self.mtl_cache.clear();
+        self.material_cache.evict_unused();
+    }
+    /// Memory of the cached buffers and textures on the GPU. Materials shared between
+    /// libraries are counted once per library.
+    pub fn total_gpu_bytes(&self) -> u64 {
+        let meshes: u64 = self.meshes.values().map(|asset| asset.mesh.gpu_bytes()).sum();
+        let materials: u64 = self.materials.values().map(|asset| asset.gpu_bytes).sum();
+        #[cfg(feature = "image")]
+        let textures: u64 = self.textures.values().map(|t| t.gpu_bytes()).sum();
+        #[cfg(not(feature = "image"))]
+        let textures = 0;
+        meshes + materials + textures
+    }
+}
+
+/// A cached mesh in `Assets`' debug output.
+#[derive(Debug)]
+#[allow(dead_code)]
+struct MeshSummary<'a> {
+    label: &'a str,
+    vertices: u32,
+    indices: u32,
+    gpu_bytes: u64,
+}
+
+/// Lists the cached meshes largest first, for finding the ones taking up memory.
+impl std::fmt::Debug for Assets {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut meshes: Vec<MeshSummary> = self
+            .meshes
+            .values()
+            .map(|asset| MeshSummary {
+                label: asset.mesh.label().unwrap_or_default(),
+                vertices: asset.mesh.vertex_count(),
+                indices: asset.mesh.index_count(),
+                gpu_bytes: asset.mesh.gpu_bytes(),
+            })
+            .collect();
+        meshes.sort_by(|a, b| b.gpu_bytes.cmp(&a.gpu_bytes).then(a.label.cmp(b.label)));
+        let mut f = f.debug_struct("Assets");
+        f.field("meshes", &meshes);
+        f.field("materials", &self.materials.len());
+        #[cfg(feature = "image")]
+        f.field("textures", &self.textures.len());
+        f.field("total_gpu_bytes", &self.total_gpu_bytes()).finish()
+    }
+}
+
+/// A reload running on another thread.
+#[cfg(feature = "hot-reload")]
+enum Reload {
+    Mesh(model::ModelLoadHandle),
+    Materials(mpsc::Receiver<Result<SharedMaterials, files::Error>>),
+    #[cfg(feature = "image")]
+    Texture(mpsc::Receiver<Result<image::DynamicImage, crate::texture::Error>>),
+}
+
+#[cfg(feature = "hot-reload")]
+struct HotReload {
+    watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::DebouncedEvent>,
+    /// Directories being watched. Editors often save by renaming a temporary file over the
+    /// old one, which a watch on the file itself wouldn't survive.
+    directories: HashSet<PathBuf>,
+    reloads: HashMap<PathBuf, Reload>,
+}
+
+/// Reloading assets when their files change, for iterating on them in other programs.
+#[cfg(feature = "hot-reload")]
+impl Assets {
+    /// Starts watching every cached file and the ones loaded later. Events for the same file
+    /// within `debounce` of each other cause a single reload.
+    pub fn enable_hot_reload(&mut self, debounce: Duration) -> notify::Result<()> {
+        let (sender, events) = mpsc::channel();
+        self.hot_reload = Some(HotReload {
+            watcher: notify::watcher(sender, debounce)?,
+            events,
+            directories: HashSet::new(),
+            reloads: HashMap::new(),
+        });
+        let paths = self.meshes.keys().chain(self.materials.keys());
+        let paths: Vec<PathBuf> = paths.cloned().collect();
+        #[cfg(feature = "image")]
+        let paths: Vec<PathBuf> = paths.into_iter().chain(self.textures.keys().cloned()).collect();
+        for path in paths {
+            self.watch(&path);
+        }
+        Ok(())
+    }
+    fn watch(&mut self, path: &Path) {
+        let hot_reload = match &mut self.hot_reload {
+            Some(hot_reload) => hot_reload,
+            None => return,
+        };
+        let directory = match path.parent() {
+            Some(directory) => directory.to_path_buf(),
+            None => return,
+        };
+        if hot_reload.directories.contains(&directory) {
+            return;
+        }
+        match hot_reload.watcher.watch(&directory, notify::RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                hot_reload.directories.insert(directory);
+            }
+            Err(e) => log::warn!("can't watch '{}': {}", directory.display(), e),
+        }
+    }
+    /// Starts reloading changed files and swaps in the ones that finished, updating the
+    /// entities of `scene` that used the old buffers or materials. Call it once a frame.
+    /// A file that fails to load, often because it's still being written, keeps its previous
+    /// version until it changes again. Returns how many assets were swapped.
+    pub fn poll_reloads(&mut self, scene: &mut Scene) -> usize {
+        let hot_reload = match &mut self.hot_reload {
+            Some(hot_reload) => hot_reload,
+            None => return 0,
+        };
+        let mut changed = Vec::new();
+        while let Ok(event) = hot_reload.events.try_recv() {
+            match event {
+                notify::DebouncedEvent::Create(path)
+                | notify::DebouncedEvent::Write(path)
+                | notify::DebouncedEvent::Rename(_, path) => changed.push(path),
+                notify::DebouncedEvent::Error(e, path) => {
+                    log::warn!("watching {:?}: {}", path, e)
+                }
+                _ => {}
+            }
+        }
+        for path in changed {
+            // A newer change replaces a reload still running for the path
+            let reload = if self.meshes.contains_key(&path) {
+                Reload::Mesh(model::load_in_background(&path, LoadOptions::default()))
+            } else if self.materials.contains_key(&path) {
+                let (sender, receiver) = mpsc::channel();
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let library = files::mtl::MtlLibrary::load_file_sync(&path);
+                    let _ = sender.send(library.map(|library| library.build_shared()));
+                });
+                Reload::Materials(receiver)
+            } else {
+                #[cfg(feature = "image")]
+                if self.textures.contains_key(&path) {
+                    let (sender, receiver) = mpsc::channel();
+                    let image_path = path.clone();
+                    std::thread::spawn(move || {
+                        let image = image::open(&image_path)
+                            .map_err(|e| crate::texture::Error::Image(Some(image_path), e));
+                        let _ = sender.send(image);
+                    });
+                    hot_reload.reloads.insert(path, Reload::Texture(receiver));
+                }
+                continue;
+            };
+            hot_reload.reloads.insert(path, reload);
+        }
+        let mut swapped = 0;
+        let paths: Vec<PathBuf> = hot_reload.reloads.keys().cloned().collect();
+        for path in paths {
+            if let Some(done) = self.finish_reload(&path, scene) {
+                if let Some(hot_reload) = &mut self.hot_reload {
+                    hot_reload.reloads.remove(&path);
+                }
+                if done {
+                    log::info!("reloaded '{}'", path.display());
+                    scene.mark_dirty();
+                    swapped += 1;
+                }
+            }
+        }
+        swapped
+    }
+    /// `None` while the reload is running, otherwise whether it succeeded and was swapped in.
+    fn finish_reload(&mut self, path: &Path, scene: &mut Scene) -> Option<bool> {
+        let reload = self.hot_reload.as_mut()?.reloads.get_mut(path)?;
+        let failed = |e: &dyn std::fmt::Display| {
+            log::warn!("reloading '{}' failed, keeping the old version: {}", path.display(), e);
+            Some(false)
+        };
+        match reload {
+            Reload::Mesh(handle) => match handle.try_take()? {
+                Ok(data) => {
+                    let asset = self.upload_mesh(path, data);
+                    if let Some(old) = self.meshes.insert(path.to_path_buf(), asset) {
+                        let new = &self.meshes[path];
+                        for entity in &mut scene.entities {
+                            let uses_old = entity.mesh.as_ref().map_or(false, |mesh| {
+                                Rc::ptr_eq(mesh, &old.mesh)
+                            });
+                            if uses_old {
+                                entity.mesh = Some(new.mesh.clone());
+                                entity.bounds = new.bounds;
+                            }
+                        }
+                    }
+                    Some(true)
+                }
+                Err(e) => failed(&e),
+            },
+            Reload::Materials(receiver) => match receiver.try_recv().ok()? {
+                Ok(library) => {
+                    self.mtl_cache.invalidate(path);
+                    let asset = self.bind_library(&library);
+                    if let Some(old) = self.materials.insert(path.to_path_buf(), asset) {
+                        let new = &self.materials[path].materials;
+                        for entity in &mut scene.entities {
+                            let material = match &mut entity.material {
+                                Some(material) => material,
+                                None => continue,
+                            };
+                            let name = old
+                                .materials
+                                .iter()
+                                .find(|(_, bound)| Rc::ptr_eq(bound, material))
+                                .map(|(name, _)| name);
+                            if let Some(bound) = name.and_then(|name| new.get(name)) {
+                                *material = bound.clone();
+                            }
+                        }
+                    }
+                    self.material_cache.evict_unused();
+                    Some(true)
+                }
+                Err(e) => failed(&e),
+            },
+            #[cfg(feature = "image")]
+            Reload::Texture(receiver) => match receiver.try_recv().ok()? {
+                Ok(image) => {
+                    let label = path.to_string_lossy();
+                    let texture = Texture::from_image(
+                        &self.device,
+                        &self.queue,
+                        &image,
+                        Texture::FORMAT,
+                        Some(&label),
+                    );
+                    self.textures.insert(path.to_path_buf(), Rc::new(texture));
+                    Some(true)
+                }
+                Err(e) => failed(&e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OBJ: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+    const MTL: &str = "newmtl red\nKd 1 0 0\n";
+
+    /// A directory with `crate.obj` and `crate.mtl`, removed when dropped.
+    struct Files(PathBuf);
+    impl Files {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("soyuz-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("crate.obj"), OBJ).unwrap();
+            std::fs::write(dir.join("crate.mtl"), MTL).unwrap();
+            Files(dir)
+        }
+    }
+    impl Drop for Files {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn assets() -> Option<Assets> {
+        let (device, queue) = crate::testing::device()?;
+        Some(Assets::new(Rc::new(device), Rc::new(queue)))
+    }
+
+    #[test]
+    fn entities_share_buffers() {
+        let mut assets = match assets() {
+            Some(assets) => assets,
+            None => return,
+        };
+        let files = Files::new("assets-share");
+        let a = assets.instantiate(files.0.join("crate.obj")).unwrap();
+        let b = assets.instantiate(files.0.join(".").join("crate.obj")).unwrap();
+        let (a, b) = (a.mesh.unwrap(), b.mesh.unwrap());
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(Rc::ptr_eq(&a, &assets.get_mesh(files.0.join("crate.obj")).unwrap()));
+        assert_eq!(assets.total_gpu_bytes(), a.gpu_bytes());
+
+        let materials = assets.get_materials(files.0.join("crate.mtl")).unwrap();
+        assert!(materials.contains_key("red"));
+        let again = assets.get_materials(files.0.join(".").join("crate.mtl")).unwrap();
+        assert!(Rc::ptr_eq(&materials, &again));
+        assert!(assets.total_gpu_bytes() > a.gpu_bytes());
+    }
+
+    #[test]
+    fn unload_and_clear() {
+        let mut assets = match assets() {
+            Some(assets) => assets,
+            None => return,
+        };
+        let files = Files::new("assets-unload");
+        let path = files.0.join("crate.obj");
+        let mesh = assets.get_mesh(&path).unwrap();
+        assert!(assets.unload(files.0.join(".").join("crate.obj")));
+        assert!(!assets.unload(&path));
+        assert!(!Rc::ptr_eq(&mesh, &assets.get_mesh(&path).unwrap()));
+
+        assets.get_materials(files.0.join("crate.mtl")).unwrap();
+        assets.clear();
+        assert_eq!(assets.total_gpu_bytes(), 0);
+    }
+
+    #[test]
+    fn missing_files() {
+        let mut assets = match assets() {
+            Some(assets) => assets,
+            None => return,
+        };
+        let files = Files::new("assets-missing");
+        let missing = files.0.join("barrel.obj");
+        assert!(matches!(assets.get_mesh(&missing), Err(Error::Load(_))));
+        assert!(matches!(assets.get_materials(&missing), Err(Error::Load(_))));
+        assert!(!assets.unload(&missing));
+    }
+
+    #[test]
+    fn debug_lists_meshes_largest_first() {
+        let mut assets = match assets() {
+            Some(assets) => assets,
+            None => return,
+        };
+        let files = Files::new("assets-debug");
+        let quad = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nf 1 3 4\n";
+        std::fs::write(files.0.join("quad.obj"), quad).unwrap();
+        let small = assets.get_mesh(files.0.join("crate.obj")).unwrap();
+        let large = assets.get_mesh(files.0.join("quad.obj")).unwrap();
+        assert!(large.gpu_bytes() > small.gpu_bytes());
+        assert_eq!(
+            assets.total_gpu_bytes(),
+            small.gpu_bytes() + large.gpu_bytes()
+        );
+
+        let debug = format!("{:?}", assets);
+        let (quad, cube) = (debug.find("\"quad.obj\""), debug.find("\"crate.obj\""));
+        assert!(quad.unwrap() < cube.unwrap(), "{}", debug);
+        // Labelled with the file name alone
+        assert!(!debug.contains(&*files.0.to_string_lossy()), "{}", debug);
+    }
+}
@@ -0,0 +1,288 @@
+use wgpu::util::DeviceExt;
+
+/// Format of the HDR color buffer bloom is composited onto, and of the blur chain.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    threshold: f32,
+    intensity: f32,
+    _padding: [f32; 2],
+}
+
+const ADDITIVE: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
+/// Bloom using a bright pass followed by a dual Kawase blur. Level `0` of the chain is half the
+/// surface resolution and every further level halves it again.
+pub struct BloomPass {
+    uniform: BloomUniform,
+    uniform_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    prefilter_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    upsample_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    iterations: u32,
+    levels: Vec<wgpu::TextureView>,
+    /// `level_bind_groups[i]` samples `levels[i]`.
+    level_bind_groups: Vec<wgpu::BindGroup>,
+}
+impl BloomPass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        threshold: f32,
+        intensity: f32,
+        iterations: u32,
+    ) -> BloomPass {
+        let uniform = BloomUniform {
+            threshold,
+            intensity,
+            _padding: [0.0; 2],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Bloom Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Bloom Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../bloom.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |label, entry_point, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[wgpu::ColorTargetState {
+                        format: HDR_FORMAT,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+            })
+        };
+        let prefilter_pipeline = pipeline("Bloom Prefilter Pipeline", "fs_prefilter", None);
+        let downsample_pipeline = pipeline("Bloom Downsample Pipeline", "fs_downsample", None);
+        // Upsampling adds onto the level that was downsampled into it
+        let upsample_pipeline = pipeline("Bloom Upsample Pipeline", "fs_upsample", Some(ADDITIVE));
+        let composite_pipeline =
+            pipeline("Bloom Composite Pipeline", "fs_composite", Some(ADDITIVE));
+        let mut bloom = BloomPass {
+            uniform,
+            uniform_buffer,
+            sampler,
+            bind_group_layout,
+            prefilter_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+            iterations,
+            levels: Vec::new(),
+            level_bind_groups: Vec::new(),
+        };
+        bloom.resize(device, config);
+        bloom
+    }
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.levels = (0..=self.iterations)
+            .map(|level| {
+                device
+                    .create_texture(&wgpu::TextureDescriptor {
+                        label: Some("Bloom Texture"),
+                        size: wgpu::Extent3d {
+                            width: (config.width >> (level + 1)).max(1),
+                            height: (config.height >> (level + 1)).max(1),
+                            depth_or_array_layers: 1,
+                        },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: HDR_FORMAT,
+                        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                            | wgpu::TextureUsages::TEXTURE_BINDING,
+                    })
+                    .create_view(&wgpu::TextureViewDescriptor::default())
+            })
+            .collect();
+        self.level_bind_groups = self
+            .levels
+            .iter()
+            .map(|view| self.bind_group(device, view))
+            .collect();
+    }
+    fn bind_group(&self, device: &wgpu::Device, view: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+    pub fn set_threshold(&mut self, queue: &wgpu::Queue, threshold: f32) {
+        self.uniform.threshold = threshold;
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+    /// Strength of the composite, cheap enough to change every frame.
+    pub fn set_intensity(&mut self, queue: &wgpu::Queue, intensity: f32) {
+        self.uniform.intensity = intensity;
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+    /// Blooms `hdr_view` in place, it must be a `HDR_FORMAT` texture with `RENDER_ATTACHMENT`
+    /// and `TEXTURE_BINDING` usage.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr_view: &wgpu::TextureView,
+    ) {
+        let hdr_bind_group = self.bind_group(device, hdr_view);
+        fullscreen_pass(
+            encoder,
+            "Bloom Prefilter Pass",
+            &self.prefilter_pipeline,
+            &hdr_bind_group,
+            &self.levels[0],
+            true,
+        );
+        for level in 1..self.levels.len() {
+            fullscreen_pass(
+                encoder,
+                "Bloom Downsample Pass",
+                &self.downsample_pipeline,
+                &self.level_bind_groups[level - 1],
+                &self.levels[level],
+                true,
+            );
+        }
+        for level in (1..self.levels.len()).rev() {
+            fullscreen_pass(
+                encoder,
+                "Bloom Upsample Pass",
+                &self.upsample_pipeline,
+                &self.level_bind_groups[level],
+                &self.levels[level - 1],
+                false,
+            );
+        }
+        fullscreen_pass(
+            encoder,
+            "Bloom Composite Pass",
+            &self.composite_pipeline,
+            &self.level_bind_groups[0],
+            hdr_view,
+            false,
+        );
+    }
+}
+
+fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    target: &wgpu::TextureView,
+    clear: bool,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: if clear {
+                    wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+                } else {
+                    wgpu::LoadOp::Load
+                },
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
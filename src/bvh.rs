@@ -0,0 +1,580 @@
+use crate::entity::model::bounds::Aabb;
+use crate::entity::model::Vertex;
+use crate::readback::BufferReadback;
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+const WORKGROUP_SIZE: u32 = 64;
+/// Buckets per axis that the surface area heuristic tries splits between.
+const BINS: usize = 12;
+/// Nodes with this few triangles aren't split.
+const MAX_LEAF_SIZE: usize = 2;
+/// Levels of the tree, the size of the traversal stack in `ray_query.wgsl`.
+const MAX_DEPTH: usize = 64;
+
+/// A node as stored on the GPU. Leaves have a `count`, their triangles start at
+/// `left_or_first`. Other nodes have their children at `left_or_first` and the one after it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BvhNode {
+    pub min: [f32; 3],
+    pub left_or_first: u32,
+    pub max: [f32; 3],
+    pub count: u32,
+}
+impl BvhNode {
+    fn new(bounds: &Aabb, left_or_first: usize, count: usize) -> Self {
+        BvhNode {
+            min: bounds.min.into(),
+            max: bounds.max.into(),
+            left_or_first: left_or_first as u32,
+            count: count as u32,
+        }
+    }
+    /// Distance along the ray to the box, if it's hit before `t_max`.
+    fn hit(&self, origin: Vector3<f32>, inv_dir: Vector3<f32>, t_max: f32) -> Option<f32> {
+        let (mut near, mut far) = (0.0f32, t_max);
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - origin[axis]) * inv_dir[axis];
+            let t1 = (self.max[axis] - origin[axis]) * inv_dir[axis];
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        if near <= far {
+            Some(near)
+        } else {
+            None
+        }
+    }
+}
+
+fn surface_area(bounds: &Aabb) -> f32 {
+    let d = bounds.max - bounds.min;
+    2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+}
+
+/// Möller-Trumbore, hitting both faces. The distance in lengths of `direction`.
+fn hit_triangle(
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    t: &[Vector3<f32>; 3],
+) -> Option<f32> {
+    let (e1, e2) = (t[1] - t[0], t[2] - t[0]);
+    let p = direction.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - t[0];
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = e2.dot(q) * inv_det;
+    if distance > 0.0 {
+        Some(distance)
+    } else {
+        None
+    }
+}
+
+struct Builder {
+    triangles: Vec<[Vector3<f32>; 3]>,
+    centroids: Vec<Point3<f32>>,
+    /// Triangle indices, reordered so every leaf's triangles are contiguous.
+    order: Vec<u32>,
+    nodes: Vec<BvhNode>,
+}
+impl Builder {
+    fn bounds(&self, first: usize, count: usize) -> (Aabb, Aabb) {
+        let mut bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for &i in &self.order[first..first + count] {
+            for &p in &self.triangles[i as usize] {
+                bounds.add_point(Point3::from_vec(p));
+            }
+            centroid_bounds.add_point(self.centroids[i as usize]);
+        }
+        (bounds, centroid_bounds)
+    }
+    /// The cheapest split by the surface area heuristic as an axis and position along it,
+    /// `None` when keeping the node a leaf is cheaper.
+    fn best_split(
+        &self,
+        first: usize,
+        count: usize,
+        bounds: &Aabb,
+        centroids: &Aabb,
+    ) -> Option<(usize, f32)> {
+        let mut best = (surface_area(bounds) * count as f32, None);
+        for axis in 0..3 {
+            let (min, extent) = (centroids.min[axis], centroids.max[axis] - centroids.min[axis]);
+            if extent <= 0.0 {
+                continue;
+            }
+            let mut bins = [(Aabb::empty(), 0usize); BINS];
+            for &i in &self.order[first..first + count] {
+                let offset = (self.centroids[i as usize][axis] - min) / extent;
+                let bin = &mut bins[((offset * BINS as f32) as usize).min(BINS - 1)];
+                for &p in &self.triangles[i as usize] {
+                    bin.0.add_point(Point3::from_vec(p));
+                }
+                bin.1 += 1;
+            }
+            // Cost of everything left of each split, then added to everything right of it
+            let mut costs = [0.0; BINS - 1];
+            let (mut left, mut left_count) = (Aabb::empty(), 0);
+            for (split, bin) in bins[..BINS - 1].iter().enumerate() {
+                left = left.union(&bin.0);
+                left_count += bin.1;
+                if left_count > 0 {
+                    costs[split] = surface_area(&left) * left_count as f32;
+                }
+            }
+            let (mut right, mut right_count) = (Aabb::empty(), 0);
+            for (split, bin) in bins[1..].iter().enumerate().rev() {
+                right = right.union(&bin.0);
+                right_count += bin.1;
+                if right_count > 0 {
+                    costs[split] += surface_area(&right) * right_count as f32;
+                }
+            }
+            for (split, &cost) in costs.iter().enumerate() {
+                if cost < best.0 {
+                    let position = min + extent * (split + 1) as f32 / BINS as f32;
+                    best = (cost, Some((axis, position)));
+                }
+            }
+        }
+        best.1
+    }
+    fn subdivide(&mut self, node: usize, first: usize, count: usize, depth: usize) {
+        let (bounds, centroids) = self.bounds(first, count);
+        self.nodes[node] = BvhNode::new(&bounds, first, count);
+        if count <= MAX_LEAF_SIZE || depth + 1 >= MAX_DEPTH {
+            return;
+        }
+        let (axis, position) = match self.best_split(first, count, &bounds, &centroids) {
+            Some(split) => split,
+            None => return,
+        };
+        let mut left_count = 0;
+        for i in first..first + count {
+            if self.centroids[self.order[i] as usize][axis] < position {
+                self.order.swap(i, first + left_count);
+                left_count += 1;
+            }
+        }
+        if left_count == 0 || left_count == count {
+            return;
+        }
+        let left = self.nodes.len();
+        self.nodes.extend([BvhNode::default(); 2]);
+        self.nodes[node] = BvhNode::new(&bounds, left, 0);
+        self.subdivide(left, first, left_count, depth + 1);
+        self.subdivide(left + 1, first + left_count, count - left_count, depth + 1);
+    }
+}
+
+/// A bounding volume hierarchy over the triangles of a mesh, for picking with rays.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// Positions of each triangle, in the order leaves reference them.
+    triangles: Vec<[Vector3<f32>; 3]>,
+    /// Index of each of `triangles` in the mesh it was built from.
+    triangle_indices: Vec<u32>,
+}
+impl Bvh {
+    /// Splits by the surface area heuristic, binned. `indices` is a triangle list.
+    pub fn build(vertices: &[Vertex], indices: &[u32]) -> Bvh {
+        let position = |i: u32| Vector3::from(vertices[i as usize].position);
+        let triangles: Vec<[Vector3<f32>; 3]> = indices
+            .chunks_exact(3)
+            .map(|t| [position(t[0]), position(t[1]), position(t[2])])
+            .collect();
+        let centroids = triangles
+            .iter()
+            .map(|t| Point3::from_vec((t[0] + t[1] + t[2]) / 3.0))
+            .collect();
+        let mut builder = Builder {
+            order: (0..triangles.len() as u32).collect(),
+            triangles,
+            centroids,
+            nodes: Vec::new(),
+        };
+        if !builder.triangles.is_empty() {
+            builder.nodes.push(BvhNode::default());
+            builder.subdivide(0, 0, builder.triangles.len(), 0);
+        }
+        Bvh {
+            triangles: builder.order.iter().map(|&i| builder.triangles[i as usize]).collect(),
+            triangle_indices: builder.order,
+            nodes: builder.nodes,
+        }
+    }
+    pub fn nodes(&self) -> &[BvhNode] {
+        &self.nodes
+    }
+    /// The closest triangle hit as its distance in lengths of `direction` and its index in the
+    /// mesh, that is its first index divided by 3. Both faces are hit.
+    pub fn intersect_ray(&self, origin: [f32; 3], direction: [f32; 3]) -> Option<(f32, u32)> {
+        let (origin, direction) = (Vector3::from(origin), Vector3::from(direction));
+        let inv_dir = Vector3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+        let mut closest: Option<(f32, u32)> = None;
+        let mut stack = Vec::with_capacity(MAX_DEPTH);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            let t_max = closest.map_or(f32::INFINITY, |(t, _)| t);
+            if node.hit(origin, inv_dir, t_max).is_none() {
+                continue;
+            }
+            let first = node.left_or_first as usize;
+            if node.count > 0 {
+                for j in first..first + node.count as usize {
+                    match hit_triangle(origin, direction, &self.triangles[j]) {
+                        Some(t) if closest.map_or(true, |(closest, _)| t < closest) => {
+                            closest = Some((t, self.triangle_indices[j]));
+                        }
+                        _ => {}
+                    }
+                }
+            } else {
+                // The nearer child goes on top so it's visited first and shortens the ray
+                let near = |child: usize| {
+                    self.nodes[child].hit(origin, inv_dir, t_max).unwrap_or(f32::INFINITY)
+                };
+                if near(first) < near(first + 1) {
+                    stack.extend([first + 1, first]);
+                } else {
+                    stack.extend([first, first + 1]);
+                }
+            }
+        }
+        closest
+    }
+    /// Copies the tree to the GPU for `GpuBvh::pick`.
+    pub fn upload(&self, device: &wgpu::Device) -> GpuBvh {
+        GpuBvh::new(device, self)
+    }
+}
+
+/// A ray for `GpuBvh::pick`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuRay {
+    pub origin: [f32; 3],
+    /// Hits further than this are ignored.
+    pub t_max: f32,
+    pub direction: [f32; 3],
+    _padding: u32,
+}
+impl GpuRay {
+    pub fn new(origin: [f32; 3], direction: [f32; 3]) -> Self {
+        GpuRay {
+            origin,
+            t_max: f32::MAX,
+            direction,
+            _padding: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuHit {
+    distance: f32,
+    triangle: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct RayQueryParams {
+    ray_count: u32,
+    _padding: [u32; 3],
+}
+
+/// A `Bvh` on the GPU, for intersecting thousands of rays at once, e.g. every pixel under a
+/// decal.
+pub struct GpuBvh {
+    node_buffer: wgpu::Buffer,
+    triangle_buffer: wgpu::Buffer,
+    triangle_index_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+impl GpuBvh {
+    pub fn new(device: &wgpu::Device, bvh: &Bvh) -> GpuBvh {
+        // Empty buffers can't be bound, an empty tree gets a root box that nothing hits
+        let mut nodes = bvh.nodes.clone();
+        if nodes.is_empty() {
+            nodes.push(BvhNode::new(&Aabb::empty(), 0, 0));
+        }
+        let mut triangles: Vec<[[f32; 4]; 3]> = bvh
+            .triangles
+            .iter()
+            .map(|t| t.map(|p| p.extend(1.0).into()))
+            .collect();
+        let mut triangle_indices = bvh.triangle_indices.clone();
+        if triangles.is_empty() {
+            triangles.push([[0.0; 4]; 3]);
+            triangle_indices.push(0);
+        }
+        let node_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("BVH Node Buffer"),
+            contents: bytemuck::cast_slice(&nodes),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let triangle_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("BVH Triangle Buffer"),
+            contents: bytemuck::cast_slice(&triangles),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let triangle_index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("BVH Triangle Index Buffer"),
+            contents: bytemuck::cast_slice(&triangle_indices),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Ray Query Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, true),
+                storage(4, true),
+                storage(5, false),
+            ],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Ray Query Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../ray_query.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Ray Query Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Ray Query Pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "main",
+        });
+        GpuBvh {
+            node_buffer,
+            triangle_buffer,
+            triangle_index_buffer,
+            bind_group_layout,
+            pipeline,
+        }
+    }
+    /// Intersects every ray, giving what `Bvh::intersect_ray` would for each.
+    pub async fn pick(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rays: &[GpuRay],
+    ) -> Result<Vec<Option<(f32, u32)>>, wgpu::BufferAsyncError> {
+        if rays.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ray_count = rays.len() as u32;
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ray Query Params Buffer"),
+            contents: bytemuck::cast_slice(&[RayQueryParams {
+                ray_count,
+                _padding: [0; 3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let ray_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ray Query Ray Buffer"),
+            contents: bytemuck::cast_slice(rays),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let hits_size = (rays.len() * std::mem::size_of::<GpuHit>()) as u64;
+        let hit_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Ray Query Hit Buffer"),
+            size: hits_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let buffers = [
+            &params_buffer,
+            &self.node_buffer,
+            &self.triangle_buffer,
+            &self.triangle_index_buffer,
+            &ray_buffer,
+            &hit_buffer,
+        ];
+        let entries: Vec<wgpu::BindGroupEntry> = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ray Query Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Ray Query Encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Ray Query Pass"),
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch((ray_count + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE, 1, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        let hits: Vec<GpuHit> = BufferReadback::new(device, hits_size)
+            .read(device, queue, &hit_buffer)
+            .await?;
+        Ok(hits
+            .into_iter()
+            .map(|hit| match hit.triangle {
+                u32::MAX => None,
+                triangle => Some((hit.distance, triangle)),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::model::primitives;
+    use crate::entity::model::raycast::{self, Faces};
+    use cgmath::{Matrix4, SquareMatrix};
+
+    /// Rays at a sphere from all around, some of them missing it.
+    fn rays() -> Vec<([f32; 3], [f32; 3])> {
+        let mut rays = Vec::new();
+        for i in 0..16 {
+            for j in 0..16 {
+                let (x, y) = (i as f32 * 0.173 - 1.3, j as f32 * 0.161 - 1.2);
+                rays.push(([x, y, 5.0], [0.0, 0.0, -1.0]));
+                rays.push(([5.0, x, y], [-2.0, 0.1, 0.05]));
+            }
+        }
+        rays
+    }
+
+    fn brute_force(
+        vertices: &[Vertex],
+        indices: &[u32],
+        (origin, direction): ([f32; 3], [f32; 3]),
+    ) -> Option<raycast::Hit> {
+        let (origin, direction) = (origin.into(), direction.into());
+        let identity = Matrix4::identity();
+        raycast::intersect_ray(origin, direction, vertices, indices, &identity, Faces::Both)
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let (vertices, indices) = primitives::uv_sphere(1.0, 12, 16);
+        let bvh = Bvh::build(&vertices, &indices);
+        let (mut hits, mut misses) = (0, 0);
+        for ray in rays() {
+            match (bvh.intersect_ray(ray.0, ray.1), brute_force(&vertices, &indices, ray)) {
+                (Some((distance, triangle)), Some(expected)) => {
+                    assert!((distance - expected.distance).abs() < 1e-4, "{:?}", ray);
+                    // Rays through an edge can hit either triangle at the same distance
+                    let corners = &indices[3 * triangle as usize..][..3];
+                    let alone = brute_force(&vertices, corners, ray).unwrap();
+                    assert!((alone.distance - distance).abs() < 1e-4, "{:?}", ray);
+                    hits += 1;
+                }
+                (None, None) => misses += 1,
+                (found, expected) => panic!("{:?}: {:?} vs {:?}", ray, found, expected),
+            }
+        }
+        assert!(hits > 0 && misses > 0);
+    }
+
+    #[test]
+    fn nodes_bound_their_triangles() {
+        let (vertices, indices) = primitives::uv_sphere(1.0, 12, 16);
+        let bvh = Bvh::build(&vertices, &indices);
+        let contains = |node: &BvhNode, p: [f32; 3]| {
+            (0..3).all(|a| node.min[a] <= p[a] && p[a] <= node.max[a])
+        };
+        let mut leaf_triangles = 0;
+        for node in bvh.nodes() {
+            let first = node.left_or_first as usize;
+            if node.count > 0 {
+                assert!(node.count as usize <= MAX_LEAF_SIZE);
+                for triangle in &bvh.triangles[first..first + node.count as usize] {
+                    assert!(triangle.iter().all(|&p| contains(node, p.into())));
+                }
+                leaf_triangles += node.count as usize;
+            } else {
+                for child in &bvh.nodes()[first..first + 2] {
+                    assert!(contains(node, child.min) && contains(node, child.max));
+                }
+            }
+        }
+        assert_eq!(leaf_triangles, indices.len() / 3);
+    }
+
+    #[test]
+    fn empty_mesh_hits_nothing() {
+        let bvh = Bvh::build(&[], &[]);
+        assert!(bvh.nodes().is_empty());
+        assert_eq!(bvh.intersect_ray([0.0; 3], [0.0, 0.0, 1.0]), None);
+    }
+
+    #[test]
+    fn gpu_picks_like_the_cpu() {
+        let (device, queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let (vertices, indices) = primitives::uv_sphere(1.0, 12, 16);
+        let bvh = Bvh::build(&vertices, &indices);
+        let rays = rays();
+        let gpu_rays: Vec<GpuRay> = rays.iter().map(|&(o, d)| GpuRay::new(o, d)).collect();
+        let gpu = bvh.upload(&device);
+        let picked = pollster::block_on(gpu.pick(&device, &queue, &gpu_rays)).unwrap();
+        for (ray, picked) in rays.into_iter().zip(picked) {
+            let expected = bvh.intersect_ray(ray.0, ray.1);
+            assert_eq!(picked.is_some(), expected.is_some(), "{:?}", ray);
+            if let (Some(picked), Some(expected)) = (picked, expected) {
+                assert!((picked.0 - expected.0).abs() < 1e-3, "{:?}", ray);
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::entity::model::bounds::Aabb;
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Vector2, Vector3};
+
+/// cgmath builds OpenGL projections with depth in `-1..1`, wgpu's clip space has `0..1`.
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+/// A half line, `direction` isn't necessarily normalized so distances along it are in its
+/// lengths.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+impl Ray {
+    pub fn at(&self, t: f32) -> Point3<f32> {
+        self.origin + self.direction * t
+    }
+    /// The ray in the space `transform` maps to. Distances along it stay the same.
+    pub fn transform(&self, transform: Matrix4<f32>) -> Ray {
+        Ray {
+            origin: Point3::from_homogeneous(transform * self.origin.to_homogeneous()),
+            direction: (transform * self.direction.extend(0.0)).truncate(),
+        }
+    }
+    /// Distance to where the ray enters `bounds`, 0 when it starts inside.
+    pub fn intersect_aabb(&self, bounds: &Aabb) -> Option<f32> {
+        if bounds.is_empty() {
+            return None;
+        }
+        let (mut near, mut far) = (0.0f32, f32::INFINITY);
+        for axis in 0..3 {
+            let inv_dir = 1.0 / self.direction[axis];
+            let t0 = (bounds.min[axis] - self.origin[axis]) * inv_dir;
+            let t1 = (bounds.max[axis] - self.origin[axis]) * inv_dir;
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        if near <= far {
+            Some(near)
+        } else {
+            None
+        }
+    }
+}
+
+/// A perspective camera looking from `eye` at `target`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Camera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    /// Width over height of the viewport.
+    pub aspect: f32,
+    /// Vertical field of view.
+    pub fovy: Rad<f32>,
+    pub znear: f32,
+    pub zfar: f32,
+}
+impl Camera {
+    pub fn new(aspect: f32) -> Self {
+        Camera {
+            eye: Point3::new(0.0, 1.0, 2.0),
+            target: Point3::origin(),
+            up: Vector3::unit_y(),
+            aspect,
+            fovy: Rad(std::f32::consts::FRAC_PI_4),
+            znear: 0.1,
+            zfar: 100.0,
+        }
+    }
+    pub fn view(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+    pub fn projection(&self) -> Matrix4<f32> {
+        OPENGL_TO_WGPU_MATRIX * cgmath::perspective(self.fovy, self.aspect, self.znear, self.zfar)
+    }
+    pub fn view_proj(&self) -> Matrix4<f32> {
+        self.projection() * self.view()
+    }
+    /// Moves the eye back along its current direction until `bounds` fits the view, looking
+    /// at its center. The clip planes are moved to fit it too.
+    pub fn frame(&mut self, bounds: &Aabb) {
+        if bounds.is_empty() {
+            return;
+        }
+        let center = bounds.min.midpoint(bounds.max);
+        let radius = (bounds.max - bounds.min).magnitude() * 0.5;
+        let half_fovy = self.fovy.0 * 0.5;
+        let half_fovx = (half_fovy.tan() * self.aspect).atan();
+        let half_fov = half_fovy.min(half_fovx);
+        // Far enough that the bounding sphere touches the narrower side of the frustum
+        let distance = radius.max(1e-3) / half_fov.sin();
+        let direction = self.eye - self.target;
+        let direction = if direction.magnitude2() > 0.0 {
+            direction.normalize()
+        } else {
+            Vector3::unit_z()
+        };
+        self.target = center;
+        self.eye = center + direction * distance;
+        self.znear = (distance - radius).max(distance * 1e-3);
+        self.zfar = distance + radius * 2.0;
+    }
+    /// The ray through `ndc`, `-1..1` with y up, from the near plane towards the far plane.
+    /// The direction is normalized.
+    pub fn screen_to_ray(&self, ndc: Vector2<f32>) -> Ray {
+        let inverse = self.view_proj().invert().unwrap_or_else(Matrix4::identity);
+        let unproject = |depth: f32| {
+            Point3::from_homogeneous(inverse * ndc.extend(depth).extend(1.0))
+        };
+        let (near, far) = (unproject(0.0), unproject(1.0));
+        Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+}
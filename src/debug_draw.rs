@@ -0,0 +1,195 @@
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4};
+
+const CIRCLE_SEGMENTS: usize = 24;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct DebugVertex {
+    /// World space until `flush` transforms it to clip space.
+    position: [f32; 4],
+    color: [f32; 4],
+}
+
+/// Immediate mode line drawing for one frame's worth of debug shapes. Shapes are collected on
+/// the CPU and drawn by `flush`, which uploads them into a buffer that only lives until the
+/// next flush.
+pub struct DebugDraw {
+    vertices: Vec<DebugVertex>,
+    view_proj: Matrix4<f32>,
+    pipeline: wgpu::RenderPipeline,
+    buffer: Option<wgpu::Buffer>,
+}
+impl DebugDraw {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> DebugDraw {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Debug Draw Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../debug_draw.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug Draw Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Debug Draw Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<DebugVertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &[
+                        // Position
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            shader_location: 0,
+                            format: wgpu::VertexFormat::Float32x4,
+                        },
+                        // Color
+                        wgpu::VertexAttribute {
+                            offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                            shader_location: 1,
+                            format: wgpu::VertexFormat::Float32x4,
+                        },
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+        });
+        DebugDraw {
+            vertices: Vec::new(),
+            view_proj: Matrix4::identity(),
+            pipeline,
+            buffer: None,
+        }
+    }
+    /// Camera used by the next `flush`.
+    pub fn set_view_proj(&mut self, view_proj: Matrix4<f32>) {
+        self.view_proj = view_proj;
+    }
+    pub fn line(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 4]) {
+        for p in [from, to] {
+            self.vertices.push(DebugVertex {
+                position: [p.x, p.y, p.z, 1.0],
+                color,
+            });
+        }
+    }
+    /// Three axis aligned circles.
+    pub fn sphere(&mut self, center: Point3<f32>, radius: f32, color: [f32; 4]) {
+        let axes = [
+            (Vector3::unit_x(), Vector3::unit_y()),
+            (Vector3::unit_x(), Vector3::unit_z()),
+            (Vector3::unit_y(), Vector3::unit_z()),
+        ];
+        for (u, v) in axes {
+            self.circle(center, u * radius, v * radius, color);
+        }
+    }
+    fn circle(&mut self, center: Point3<f32>, u: Vector3<f32>, v: Vector3<f32>, color: [f32; 4]) {
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + u * angle.cos() + v * angle.sin()
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+    pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, color: [f32; 4]) {
+        let corner = |i: usize| {
+            Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        // Each edge joins two corners differing in exactly one bit
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+    /// A line with a four pronged head at `to`.
+    pub fn arrow(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 4]) {
+        self.line(from, to, color);
+        let shaft = to - from;
+        let length = shaft.magnitude();
+        if length <= f32::EPSILON {
+            return;
+        }
+        let direction = shaft / length;
+        let helper = if direction.y.abs() < 0.99 {
+            Vector3::unit_y()
+        } else {
+            Vector3::unit_x()
+        };
+        let side = direction.cross(helper).normalize();
+        let up = direction.cross(side);
+        let head = length * 0.2;
+        for offset in [side, -side, up, -up] {
+            self.line(to, to - direction * head + offset * head * 0.5, color);
+        }
+    }
+    /// Draws everything collected since the last flush and clears it. The vertex buffer is
+    /// kept alive until the next flush because the pass borrows it.
+    pub fn flush<'a>(
+        &'a mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        render_pass: &mut wgpu::RenderPass<'a>,
+    ) {
+        if self.vertices.is_empty() {
+            self.buffer = None;
+            return;
+        }
+        for vertex in self.vertices.iter_mut() {
+            vertex.position = (self.view_proj * Vector4::from(vertex.position)).into();
+        }
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug Draw Vertex Buffer"),
+            size: (self.vertices.len() * std::mem::size_of::<DebugVertex>())
+                as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&buffer, 0, bytemuck::cast_slice(&self.vertices));
+        let vertex_count = self.vertices.len() as u32;
+        self.vertices.clear();
+        self.buffer = Some(buffer);
+
+        let this: &'a DebugDraw = self;
+        if let Some(buffer) = &this.buffer {
+            render_pass.set_pipeline(&this.pipeline);
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            render_pass.draw(0..vertex_count, 0..1);
+        }
+    }
+}
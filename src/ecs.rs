@@ -0,0 +1,241 @@
+//! `Entity`'s fields as `hecs` components, for games that keep their scene in a `hecs::World`
+//! rather than `Scene::entities`. Components have to be `Send + Sync`, which the `Rc`s holding
+//! meshes and materials aren't, so entities get handles into `RenderResources` instead.
+//!
+//! ```ignore
+//! let mut world = hecs::World::new();
+//! let mut resources = RenderResources::new();
+//! let id = ecs::spawn_entity(&mut world, &mut resources, entity);
+//! // Each frame
+//! ecs::update(&mut world, dt);
+//! ecs::draw(&world, &resources, &mut pass, 1, |pass, id, transform| {
+//!     // Per object uniforms
+//! });
+//! ```
+
+use crate::entity::animation::Animator;
+use crate::entity::model::bounds::Aabb;
+use crate::entity::model::material::BoundMaterial;
+use crate::entity::model::mesh::Mesh;
+use crate::entity::transform::Transform;
+use crate::entity::Entity;
+use std::rc::Rc;
+
+/// `Entity::mx_world`, refreshed from the `Transform` by `update`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct WorldMatrix(pub cgmath::Matrix4<f32>);
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Name(pub String);
+
+/// `Entity::animators`, run by `update`.
+pub struct Animators(pub Vec<Box<dyn Animator>>);
+
+/// `Entity::bounds`, in local space.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Bounds(pub Aabb);
+
+/// `Entity::color` and `Entity::emissive`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Tint {
+    pub color: wgpu::Color,
+    pub emissive: Option<wgpu::Color>,
+}
+
+/// `Entity::uniform_offset`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct UniformOffset(pub wgpu::DynamicOffset);
+
+/// A mesh in `RenderResources`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct MeshHandle(pub usize);
+
+/// A material in `RenderResources`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct MaterialHandle(pub usize);
+
+/// The GPU data the handles refer to. Adding the same `Rc` twice gives the same handle, so
+/// entities sharing a mesh keep sharing it.
+#[derive(Default)]
+pub struct RenderResources {
+    meshes: Vec<Rc<Mesh>>,
+    materials: Vec<Rc<BoundMaterial>>,
+}
+impl RenderResources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add_mesh(&mut self, mesh: Rc<Mesh>) -> MeshHandle {
+        MeshHandle(add(&mut self.meshes, mesh))
+    }
+    pub fn add_material(&mut self, material: Rc<BoundMaterial>) -> MaterialHandle {
+        MaterialHandle(add(&mut self.materials, material))
+    }
+    pub fn mesh(&self, handle: MeshHandle) -> Option<&Rc<Mesh>> {
+        self.meshes.get(handle.0)
+    }
+    pub fn material(&self, handle: MaterialHandle) -> Option<&Rc<BoundMaterial>> {
+        self.materials.get(handle.0)
+    }
+}
+fn add<T>(items: &mut Vec<Rc<T>>, item: Rc<T>) -> usize {
+    match items.iter().position(|other| Rc::ptr_eq(other, &item)) {
+        Some(i) => i,
+        None => {
+            items.push(item);
+            items.len() - 1
+        }
+    }
+}
+
+/// Spawns `entity` with its fields as separate components, the mesh and material added to
+/// `resources`. Optional fields that aren't set get no component. `parent` indexes
+/// `Scene::entities` so it's dropped, and so are `lod` and `model`, with a warning.
+pub fn spawn_entity(
+    world: &mut hecs::World,
+    resources: &mut RenderResources,
+    entity: Entity,
+) -> hecs::Entity {
+    if entity.lod.is_some() || entity.model.is_some() {
+        log::warn!(
+            "{:?}: LODs and models aren't components, dropping them",
+            entity.name
+        );
+    }
+    let mut builder = hecs::EntityBuilder::new();
+    builder.add(entity.transform);
+    builder.add(WorldMatrix(entity.mx_world));
+    builder.add(Bounds(entity.bounds));
+    builder.add(Tint {
+        color: entity.color,
+        emissive: entity.emissive,
+    });
+    builder.add(UniformOffset(entity.uniform_offset));
+    if let Some(name) = entity.name {
+        builder.add(Name(name));
+    }
+    if !entity.animators.is_empty() {
+        builder.add(Animators(entity.animators));
+    }
+    if let Some(mesh) = entity.mesh {
+        builder.add(resources.add_mesh(mesh));
+    }
+    if let Some(material) = entity.material {
+        builder.add(resources.add_material(material));
+    }
+    world.spawn(builder.build())
+}
+
+/// `Entity::update` for every entity: runs the animators and refreshes `WorldMatrix`.
+pub fn update(world: &mut hecs::World, dt: f32) {
+    for (_, (transform, animators)) in world.query_mut::<(&mut Transform, &Animators)>() {
+        for animator in &animators.0 {
+            animator.update(transform, dt);
+        }
+    }
+    for (_, (transform, matrix)) in world.query_mut::<(&Transform, &mut WorldMatrix)>() {
+        matrix.0 = transform.to_matrix();
+    }
+}
+
+/// Draws every entity with a `Transform`, a mesh and a material, binding the material to
+/// group `material_group`. `bind_object` is called before each draw to set per object state
+/// such as the model uniform's offset. The pipeline and the other groups have to be set.
+/// `State` only draws `Scene::entities`, so this is for applications recording their own
+/// passes over a `hecs::World`.
+pub fn draw<'a>(
+    world: &hecs::World,
+    resources: &'a RenderResources,
+    pass: &mut wgpu::RenderPass<'a>,
+    material_group: u32,
+    mut bind_object: impl FnMut(&mut wgpu::RenderPass<'a>, hecs::Entity, &Transform),
+) {
+    let mut query = world.query::<(&Transform, &MeshHandle, &MaterialHandle)>();
+    for (id, (transform, &mesh, &material)) in query.iter() {
+        let (mesh, material) = match (resources.mesh(mesh), resources.material(material)) {
+            (Some(mesh), Some(material)) => (mesh, material),
+            _ => continue,
+        };
+        pass.set_bind_group(material_group, &material.bind_group, &[]);
+        bind_object(pass, id, transform);
+        mesh.draw(pass, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::animation::OscillatePosition;
+    use cgmath::{SquareMatrix, Vector3};
+
+    fn entity(name: Option<&str>, mesh: Option<Rc<Mesh>>) -> Entity {
+        Entity {
+            name: name.map(str::to_string),
+            parent: None,
+            transform: Transform::identity(),
+            mx_world: cgmath::Matrix4::identity(),
+            animators: Vec::new(),
+            color: wgpu::Color::WHITE,
+            emissive: None,
+            mesh,
+            bounds: Aabb::empty(),
+            uniform_offset: 0,
+            material: None,
+            lod: None,
+            model: None,
+        }
+    }
+
+    #[test]
+    fn update_runs_animators_and_refreshes_world_matrix() {
+        let mut world = hecs::World::new();
+        // A quarter of a period in, the offset is the whole amplitude
+        let animator = OscillatePosition::new(2.0, 0.25, Vector3::unit_x());
+        let id = world.spawn((
+            Transform::identity(),
+            WorldMatrix(cgmath::Matrix4::identity()),
+            Animators(vec![Box::new(animator)]),
+        ));
+        update(&mut world, 1.0);
+        let translation = world.get::<Transform>(id).unwrap().translation;
+        assert!((translation.x - 2.0).abs() < 1e-5, "{:?}", translation);
+        let matrix = world.get::<WorldMatrix>(id).unwrap().0;
+        assert!((matrix.w.x - 2.0).abs() < 1e-5, "{:?}", matrix);
+    }
+
+    #[test]
+    fn spawn_entity_adds_only_set_fields() {
+        let mut world = hecs::World::new();
+        let mut resources = RenderResources::new();
+        let entity = entity(Some("Empty"), None);
+        let id = spawn_entity(&mut world, &mut resources, entity);
+        assert_eq!(world.get::<Name>(id).unwrap().0, "Empty");
+        assert!(world.get::<Transform>(id).is_ok());
+        assert!(world.get::<Animators>(id).is_err());
+        assert!(world.get::<MeshHandle>(id).is_err());
+    }
+
+    #[test]
+    fn shared_meshes_get_one_handle() {
+        let (device, _queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut world = hecs::World::new();
+        let mut resources = RenderResources::new();
+        let plane = Rc::new(Mesh::plane(&device, 1.0, 1.0, 1));
+        let other = Rc::new(Mesh::plane(&device, 2.0, 2.0, 1));
+        let ids: Vec<_> = [plane.clone(), plane.clone(), other]
+            .into_iter()
+            .map(|mesh| spawn_entity(&mut world, &mut resources, entity(None, Some(mesh))))
+            .collect();
+        let handles: Vec<MeshHandle> = ids
+            .iter()
+            .map(|&id| *world.get::<MeshHandle>(id).unwrap())
+            .collect();
+        assert_eq!(handles, [MeshHandle(0), MeshHandle(0), MeshHandle(1)]);
+        assert!(Rc::ptr_eq(resources.mesh(handles[0]).unwrap(), &plane));
+        assert!(world.get::<Name>(ids[0]).is_err());
+        assert!(resources.mesh(MeshHandle(2)).is_none());
+    }
+}
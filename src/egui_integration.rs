@@ -0,0 +1,92 @@
+use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
+use std::rc::Rc;
+use winit::window::Window;
+
+/// Draws egui over the frame, for developer tools. `egui_wgpu` needs a newer wgpu, so the
+/// painting goes through `egui_wgpu_backend`; input goes through `egui_winit`.
+///
+/// Each frame: `begin_frame`, build the UI with the returned context, then `end_frame` after
+/// the scene was recorded so the UI is drawn on top.
+pub struct EguiRenderer {
+    device: Rc<wgpu::Device>,
+    queue: Rc<wgpu::Queue>,
+    context: egui::CtxRef,
+    winit_state: egui_winit::State,
+    render_pass: RenderPass,
+    width: u32,
+    height: u32,
+    /// Cursor and clipboard requests of the last frame, applied by the next `begin_frame`
+    /// since that has the window.
+    output: Option<egui::Output>,
+    frame_begun: bool,
+}
+impl EguiRenderer {
+    pub fn new(
+        device: Rc<wgpu::Device>,
+        queue: Rc<wgpu::Queue>,
+        config: &wgpu::SurfaceConfiguration,
+        window: &Window,
+    ) -> Self {
+        let render_pass = RenderPass::new(&device, config.format, 1);
+        EguiRenderer {
+            device,
+            queue,
+            context: egui::CtxRef::default(),
+            winit_state: egui_winit::State::new(window),
+            render_pass,
+            width: config.width,
+            height: config.height,
+            output: None,
+            frame_begun: false,
+        }
+    }
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+    }
+    /// Passes a window event to egui. Returns whether egui used it, then it shouldn't also
+    /// move the camera or the like.
+    pub fn on_event(&mut self, event: &winit::event::WindowEvent) -> bool {
+        self.winit_state.on_event(&self.context, event)
+    }
+    pub fn pixels_per_point(&self) -> f32 {
+        self.winit_state.pixels_per_point()
+    }
+    /// Starts a frame with the input gathered since the last one.
+    pub fn begin_frame(&mut self, window: &Window) -> egui::CtxRef {
+        if let Some(output) = self.output.take() {
+            self.winit_state.handle_output(window, &self.context, output);
+        }
+        let input = self.winit_state.take_egui_input(window);
+        self.context.begin_frame(input);
+        self.frame_begun = true;
+        self.context.clone()
+    }
+    /// Uploads the frame's font texture and meshes and draws them to `view`. Does nothing
+    /// without a `begin_frame`.
+    pub fn end_frame(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        pixels_per_point: f32,
+    ) {
+        if !std::mem::take(&mut self.frame_begun) {
+            return;
+        }
+        let (output, shapes) = self.context.end_frame();
+        self.output = Some(output);
+        let meshes = self.context.tessellate(shapes);
+        let screen = ScreenDescriptor {
+            physical_width: self.width,
+            physical_height: self.height,
+            scale_factor: pixels_per_point,
+        };
+        let (device, queue) = (&self.device, &self.queue);
+        self.render_pass.update_texture(device, queue, &self.context.texture());
+        self.render_pass.update_user_textures(device, queue);
+        self.render_pass.update_buffers(device, queue, &meshes, &screen);
+        if let Err(e) = self.render_pass.execute(encoder, view, &meshes, &screen, None) {
+            log::error!("egui: {}", e);
+        }
+    }
+}
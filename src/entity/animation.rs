@@ -0,0 +1,188 @@
+use crate::entity::transform::Transform;
+use crate::scene::Scene;
+use cgmath::{InnerSpace, Quaternion, Rad, Rotation3, Vector3};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Changes an entity's transform over time. `dt` is in seconds.
+pub trait Animator: Send + Sync {
+    fn update(&self, transform: &mut Transform, dt: f32);
+}
+
+/// Spins around `axis` at `speed` radians per second.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ConstantRotation {
+    pub axis: Vector3<f32>,
+    pub speed: f32,
+}
+impl Animator for ConstantRotation {
+    fn update(&self, transform: &mut Transform, dt: f32) {
+        let step = Quaternion::from_axis_angle(self.axis.normalize(), Rad(self.speed * dt));
+        transform.rotation = step * transform.rotation;
+    }
+}
+
+/// Moves back and forth along `axis` by up to `amplitude`, `frequency` times per second.
+#[derive(Debug)]
+pub struct OscillatePosition {
+    pub amplitude: f32,
+    pub frequency: f32,
+    pub axis: Vector3<f32>,
+    /// Seconds since the animation started, as `f32` bits so `update` can take `&self`.
+    elapsed: AtomicU32,
+}
+impl OscillatePosition {
+    pub fn new(amplitude: f32, frequency: f32, axis: Vector3<f32>) -> Self {
+        OscillatePosition {
+            amplitude,
+            frequency,
+            axis,
+            elapsed: AtomicU32::new(0f32.to_bits()),
+        }
+    }
+    fn offset(&self, t: f32) -> f32 {
+        self.amplitude * (std::f32::consts::TAU * self.frequency * t).sin()
+    }
+}
+impl Animator for OscillatePosition {
+    fn update(&self, transform: &mut Transform, dt: f32) {
+        let before = f32::from_bits(self.elapsed.load(Ordering::Relaxed));
+        let after = before + dt;
+        self.elapsed.store(after.to_bits(), Ordering::Relaxed);
+        // Only apply the change so other animators can move the entity too
+        transform.translation += self.axis.normalize() * (self.offset(after) - self.offset(before));
+    }
+}
+
+/// How values between two keyframes are found.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Interpolation {
+    /// Hold the previous keyframe's value.
+    Step,
+    /// Lerp vectors, slerp rotations.
+    Linear,
+}
+
+/// Values of one property at increasing `times`, in seconds.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Keyframes<T> {
+    pub times: Vec<f32>,
+    pub values: Vec<T>,
+    pub interpolation: Interpolation,
+}
+impl<T: Copy> Keyframes<T> {
+    pub fn duration(&self) -> f32 {
+        self.times.last().copied().unwrap_or(0.0)
+    }
+    /// Value at `time`, clamped to the first and last keyframes.
+    pub fn sample(&self, time: f32, interpolate: impl Fn(T, T, f32) -> T) -> Option<T> {
+        let last = self.times.len().min(self.values.len()).checked_sub(1)?;
+        let next = self.times[..=last].partition_point(|&t| t <= time);
+        if next == 0 {
+            return Some(self.values[0]);
+        }
+        if next > last {
+            return Some(self.values[last]);
+        }
+        let (start, end) = (self.times[next - 1], self.times[next]);
+        let previous = self.values[next - 1];
+        match self.interpolation {
+            Interpolation::Step => Some(previous),
+            Interpolation::Linear => {
+                let t = if end > start {
+                    (time - start) / (end - start)
+                } else {
+                    0.0
+                };
+                Some(interpolate(previous, self.values[next], t))
+            }
+        }
+    }
+}
+
+/// Keyframes moving a single node.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AnimChannel {
+    /// Index of the animated node, see `AnimationPlayer::update`.
+    pub node: usize,
+    pub translation: Option<Keyframes<Vector3<f32>>>,
+    pub rotation: Option<Keyframes<Quaternion<f32>>>,
+    pub scale: Option<Keyframes<Vector3<f32>>>,
+}
+impl AnimChannel {
+    pub fn new(node: usize) -> Self {
+        AnimChannel {
+            node,
+            translation: None,
+            rotation: None,
+            scale: None,
+        }
+    }
+    pub fn duration(&self) -> f32 {
+        let translation = self.translation.as_ref().map_or(0.0, Keyframes::duration);
+        let rotation = self.rotation.as_ref().map_or(0.0, Keyframes::duration);
+        let scale = self.scale.as_ref().map_or(0.0, Keyframes::duration);
+        translation.max(rotation).max(scale)
+    }
+    /// Overwrites the animated properties of `transform` with their values at `time`.
+    pub fn apply(&self, time: f32, transform: &mut Transform) {
+        let lerp = |a: Vector3<f32>, b: Vector3<f32>, t| a + (b - a) * t;
+        if let Some(translation) = self.translation.as_ref().and_then(|k| k.sample(time, lerp)) {
+            transform.translation = translation;
+        }
+        if let Some(rotation) = self
+            .rotation
+            .as_ref()
+            .and_then(|k| k.sample(time, |a: Quaternion<f32>, b, t| a.slerp(b, t)))
+        {
+            transform.rotation = rotation;
+        }
+        if let Some(scale) = self.scale.as_ref().and_then(|k| k.sample(time, lerp)) {
+            transform.scale = scale;
+        }
+    }
+}
+
+/// A named animation of several nodes.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    pub channels: Vec<AnimChannel>,
+    /// Seconds, the time of the last keyframe.
+    pub duration: f32,
+}
+
+/// Plays an `AnimationClip` on the entities of a scene.
+#[derive(Clone, Debug)]
+pub struct AnimationPlayer {
+    pub clip: AnimationClip,
+    /// Seconds into the clip.
+    pub time: f32,
+    /// Start over after the last keyframe instead of holding it.
+    pub looping: bool,
+}
+impl AnimationPlayer {
+    pub fn new(clip: AnimationClip) -> Self {
+        AnimationPlayer {
+            clip,
+            time: 0.0,
+            looping: true,
+        }
+    }
+    /// Advances by `dt` seconds and poses the animated entities. A channel's node indexes
+    /// `scene.entities`, channels of missing entities are skipped.
+    pub fn update(&mut self, dt: f32, scene: &mut Scene) {
+        self.time += dt;
+        if self.clip.duration > 0.0 {
+            self.time = if self.looping {
+                self.time.rem_euclid(self.clip.duration)
+            } else {
+                self.time.min(self.clip.duration)
+            };
+        }
+        for channel in &self.clip.channels {
+            if let Some(entity) = scene.entities.get_mut(channel.node) {
+                channel.apply(self.time, &mut entity.transform);
+            }
+        }
+    }
+}
@@ -1,15 +1,83 @@
+pub mod animation;
 pub mod model;
+pub mod transform;
 
-
+use crate::entity::model::bounds::Aabb;
+use crate::entity::model::mesh::Mesh;
+use crate::entity::model::Model;
+use crate::lod::LodMesh;
+use animation::Animator;
+use cgmath::{InnerSpace, Point3};
+use model::material::BoundMaterial;
 use std::rc::Rc;
+use transform::Transform;
 
 pub struct Entity {
+    pub name: Option<String>,
+    /// Index of the parent in `Scene::entities`, `transform` is relative to it.
+    pub parent: Option<usize>,
+    pub transform: Transform,
+    /// `transform` as a matrix, refreshed by `update`. Entities with a parent get the parent's
+    /// matrix applied by `Scene::update`.
     pub mx_world: cgmath::Matrix4<f32>,
-    pub rotation_speed: f32,
+    pub animators: Vec<Box<dyn Animator>>,
+    /// Debug tint, e.g. to highlight a selected entity. The surface color comes from
+    /// `material`; this is not a material color and isn't used for lighting.
     pub color: wgpu::Color,
-    pub vertex_buf: Rc<wgpu::Buffer>,
-    pub index_buf: Rc<wgpu::Buffer>,
-    pub index_format: wgpu::IndexFormat,
-    pub index_count: usize,
+    /// Color of unlit materials. When set it's drawn instead of `color`.
+    pub emissive: Option<wgpu::Color>,
+    /// `None` for entities without geometry, e.g. transform-only parents.
+    pub mesh: Option<Rc<Mesh>>,
+    /// Around the vertices of `mesh`, in local space. Empty for entities without geometry,
+    /// which `State::pick` never hits.
+    pub bounds: Aabb,
     pub uniform_offset: wgpu::DynamicOffset,
+    pub material: Option<Rc<BoundMaterial>>,
+    /// Drawn instead of `mesh`, at the detail for the camera distance.
+    pub lod: Option<Rc<LodMesh>>,
+    /// Drawn instead of `mesh` and `material`, with its own materials.
+    pub model: Option<Rc<Model>>,
+}
+impl Entity {
+    /// Runs the animators and refreshes `mx_world`.
+    pub fn update(&mut self, dt: f32) {
+        for animator in &self.animators {
+            animator.update(&mut self.transform, dt);
+        }
+        self.mx_world = self.transform.to_matrix();
+    }
+    /// `bounds` in world space, as of the last `update`. Empty for entities without geometry.
+    pub fn world_bounds(&self) -> Aabb {
+        self.bounds.transform(&self.mx_world)
+    }
+    /// World space origin, as of the last `update`.
+    pub fn position(&self) -> Point3<f32> {
+        Point3::from_homogeneous(self.mx_world.w)
+    }
+    /// The LOD level to draw when seen from `eye`, `None` without a `lod`.
+    pub fn select_lod(&self, eye: Point3<f32>) -> Option<&Mesh> {
+        let distance = (self.position() - eye).magnitude();
+        self.lod.as_ref().map(|lod| lod.select_lod(distance))
+    }
+    /// Index in the `lod`'s levels of `select_lod`.
+    pub fn select_lod_level(&self, eye: Point3<f32>) -> Option<usize> {
+        let distance = (self.position() - eye).magnitude();
+        self.lod.as_ref().map(|lod| lod.select_level(distance))
+    }
+    /// Binds the mesh's vertex buffer to slot 0, the colors if there are any to slot 1 and the
+    /// index buffer. Entities with colors need a pipeline with `Mesh::COLOR_LAYOUT`. Does
+    /// nothing without a mesh.
+    pub fn set_buffers<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        if let Some(mesh) = &self.mesh {
+            mesh.set_buffers(pass);
+        }
+    }
+    /// Indices to draw after `set_buffers`, 0 without a mesh.
+    pub fn index_count(&self) -> u32 {
+        self.mesh.as_ref().map_or(0, |mesh| mesh.index_count())
+    }
+    /// Whether the material needs a blended pass. Entities without a material are opaque.
+    pub fn is_transparent(&self) -> bool {
+        self.material.as_ref().map_or(false, |m| m.is_transparent())
+    }
 }
@@ -0,0 +1,151 @@
+use cgmath::{EuclideanSpace, Matrix4, Point3, Transform, Vector3};
+
+/// Axis-aligned bounding box. An empty box has `min > max` on every axis so adding the first
+/// point snaps it to that point.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Aabb {
+        Aabb { min, max }
+    }
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+    pub fn add_point(&mut self, p: Point3<f32>) {
+        self.min = Point3::new(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z));
+        self.max = Point3::new(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z));
+    }
+    /// The box around both, empty boxes don't grow it.
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let (a, b) = (self, other);
+        Aabb {
+            min: Point3::new(a.min.x.min(b.min.x), a.min.y.min(b.min.y), a.min.z.min(b.min.z)),
+            max: Point3::new(a.max.x.max(b.max.x), a.max.y.max(b.max.y), a.max.z.max(b.max.z)),
+        }
+    }
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Aabb {
+        let mut aabb = Aabb::empty();
+        for p in points {
+            aabb.add_point(p);
+        }
+        aabb
+    }
+    /// Meaningless for empty boxes.
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+    /// Half the size on each axis, from `center` to `max`. Zero for empty boxes.
+    pub fn extent(&self) -> Vector3<f32> {
+        if self.is_empty() {
+            return Vector3::new(0.0, 0.0, 0.0);
+        }
+        (self.max - self.min) * 0.5
+    }
+    /// The eight corners, bit 0 of the index picks `max.x` over `min.x`, bit 1 y and bit 2 z.
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+        let mut corners = [min; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            *corner = Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+        }
+        corners
+    }
+    /// The box around this one after `matrix`, e.g. from local to world space. It fits the
+    /// transformed corners, so it's bigger than the contents when there's rotation. Empty
+    /// boxes stay empty.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        Aabb::from_points(self.corners().iter().map(|&p| matrix.transform_point(p)))
+    }
+}
+impl Default for Aabb {
+    fn default() -> Self {
+        Aabb::empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, InnerSpace};
+
+    fn assert_near(a: &Aabb, b: &Aabb) {
+        let near = |p: Point3<f32>, q: Point3<f32>| (p - q).magnitude() < 1e-5;
+        assert!(near(a.min, b.min) && near(a.max, b.max), "{:?} != {:?}", a, b);
+    }
+
+    fn unit_cube() -> Aabb {
+        Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn rotation_grows_the_box() {
+        let rotated = unit_cube().transform(&Matrix4::from_angle_z(Deg(45.0)));
+        let diagonal = 2.0f32.sqrt();
+        let expected = Aabb::new(
+            Point3::new(-diagonal, -diagonal, -1.0),
+            Point3::new(diagonal, diagonal, 1.0),
+        );
+        assert_near(&rotated, &expected);
+        assert!((rotated.extent() - Vector3::new(diagonal, diagonal, 1.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn quarter_turns_keep_the_size() {
+        let aabb = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 1.0, 3.0));
+        // X goes to -Z and Z to X
+        let rotated = aabb.transform(&Matrix4::from_angle_y(Deg(90.0)));
+        let expected = Aabb::new(Point3::new(0.0, 0.0, -2.0), Point3::new(3.0, 1.0, 0.0));
+        assert_near(&rotated, &expected);
+
+        let matrix = Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0))
+            * Matrix4::from_angle_x(Deg(180.0))
+            * Matrix4::from_scale(2.0);
+        let expected = Aabb::new(Point3::new(1.0, 0.0, -3.0), Point3::new(5.0, 2.0, 3.0));
+        assert_near(&aabb.transform(&matrix), &expected);
+    }
+
+    #[test]
+    fn center_extent_and_union() {
+        let a = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 4.0, 6.0));
+        assert_eq!(a.center(), Point3::new(1.0, 2.0, 3.0));
+        assert_eq!(a.extent(), Vector3::new(1.0, 2.0, 3.0));
+        let b = Aabb::new(Point3::new(-1.0, 1.0, 1.0), Point3::new(1.0, 5.0, 2.0));
+        let union = a.union(&b);
+        assert_eq!(union, Aabb::new(Point3::new(-1.0, 0.0, 0.0), Point3::new(2.0, 5.0, 6.0)));
+        assert_eq!(a.union(&Aabb::empty()), a);
+        assert_eq!(Aabb::empty().union(&b), b);
+        assert_eq!(Aabb::from_points([a.min, a.max]), a);
+        let corners = a.corners();
+        assert_eq!((corners[0], corners[7]), (a.min, a.max));
+        assert_eq!(corners[5], Point3::new(2.0, 0.0, 6.0));
+    }
+
+    #[test]
+    fn empty_boxes_stay_empty() {
+        let empty = Aabb::default();
+        assert!(empty.is_empty());
+        assert_eq!(empty.extent(), Vector3::new(0.0, 0.0, 0.0));
+        let moved = empty.transform(&Matrix4::from_translation(Vector3::new(1.0, 0.0, 0.0)));
+        assert!(moved.is_empty());
+        let mut point = empty;
+        point.add_point(Point3::new(1.0, 2.0, 3.0));
+        assert!(!point.is_empty());
+        assert_eq!(point.min, point.max);
+    }
+}
@@ -0,0 +1,472 @@
+use crate::entity::model::files;
+use crate::entity::model::object::{Stats, SubMesh};
+use crate::entity::model::{Object, Vertex};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// Start of every cache file.
+pub const MAGIC: [u8; 4] = *b"SOYM";
+/// Bumped whenever the layout changes, older caches are regenerated.
+pub const VERSION: u32 = 2;
+/// Version 1 had no flags and was never compressed, it's still read.
+const VERSION_UNFLAGGED: u32 = 1;
+/// Header flag for a body compressed with `Compression::Lz4`.
+#[cfg(feature = "lz4")]
+const FLAG_LZ4: u32 = 1;
+
+/// How the body of a cache file, everything after the header, is stored. Float vertex data
+/// barely compresses on its own, so scanned meshes can come out bigger than their OBJ.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Compression {
+    None,
+    /// LZ4 block compression, `level` 0 is the fast default and 1 to 12 are increasingly
+    /// slow high compression levels.
+    #[cfg(feature = "lz4")]
+    Lz4 { level: u32 },
+}
+impl Default for Compression {
+    #[cfg(feature = "lz4")]
+    fn default() -> Self {
+        Compression::Lz4 { level: 0 }
+    }
+    #[cfg(not(feature = "lz4"))]
+    fn default() -> Self {
+        Compression::None
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    IO(std::io::Error),
+    /// Not a cache file.
+    BadMagic,
+    /// Written by another version of the format.
+    Version(u32),
+    /// The file ended early.
+    Truncated,
+    InvalidUtf8,
+    /// A submesh or index is out of range.
+    Corrupt,
+    /// The body is compressed with a method this build doesn't have.
+    UnsupportedCompression(u32),
+}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::IO(e)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Marks a missing name or material.
+const NONE: u32 = u32::MAX;
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+impl Writer {
+    fn u32(&mut self, x: u32) {
+        self.bytes.extend_from_slice(&x.to_le_bytes());
+    }
+    fn u64(&mut self, x: u64) {
+        self.bytes.extend_from_slice(&x.to_le_bytes());
+    }
+    fn f32(&mut self, x: f32) {
+        self.bytes.extend_from_slice(&x.to_le_bytes());
+    }
+    fn str(&mut self, s: Option<&str>) {
+        match s {
+            Some(s) => {
+                self.u32(s.len() as u32);
+                self.bytes.extend_from_slice(s.as_bytes());
+            }
+            None => self.u32(NONE),
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if n > self.bytes.len() {
+            return Err(Error::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn u32(&mut self) -> Result<u32, Error> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    fn u64(&mut self) -> Result<u64, Error> {
+        let (low, high) = (self.u32()? as u64, self.u32()? as u64);
+        Ok(low | high << 32)
+    }
+    fn f32(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+    /// A count of items at least `item_size` bytes each, checked against the remaining bytes
+    /// so a corrupt count can't allocate huge vectors.
+    fn count(&mut self, item_size: usize) -> Result<usize, Error> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(item_size) > self.bytes.len() {
+            return Err(Error::Truncated);
+        }
+        Ok(count)
+    }
+    fn str(&mut self) -> Result<Option<String>, Error> {
+        let len = self.u32()?;
+        if len == NONE {
+            return Ok(None);
+        }
+        let bytes = self.take(len as usize)?;
+        let s = std::str::from_utf8(bytes).map_err(|_| Error::InvalidUtf8)?;
+        Ok(Some(s.to_string()))
+    }
+}
+
+/// Serializes the object uncompressed, see `to_bytes_with`.
+pub fn to_bytes(object: &Object) -> Vec<u8> {
+    to_bytes_with(object, Compression::None)
+}
+
+/// Serializes the object, little endian. Bounds aren't stored as `Object::new` recomputes them.
+pub fn to_bytes_with(object: &Object, compression: Compression) -> Vec<u8> {
+    let body = body_bytes(object);
+    let mut w = Writer {
+        bytes: Vec::with_capacity(body.len() + 12),
+    };
+    w.bytes.extend_from_slice(&MAGIC);
+    w.u32(VERSION);
+    match compression {
+        Compression::None => {
+            w.u32(0);
+            w.bytes.extend_from_slice(&body);
+        }
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 { level } => {
+            let mode = match level {
+                0 => lz4::block::CompressionMode::DEFAULT,
+                level => lz4::block::CompressionMode::HIGHCOMPRESSION(level.min(12) as i32),
+            };
+            // Only fails for bodies over 2GB, which are left uncompressed.
+            match lz4::block::compress(&body, Some(mode), true) {
+                Ok(compressed) => {
+                    w.u32(FLAG_LZ4);
+                    w.bytes.extend_from_slice(&compressed);
+                }
+                Err(_) => {
+                    w.u32(0);
+                    w.bytes.extend_from_slice(&body);
+                }
+            }
+        }
+    }
+    w.bytes
+}
+
+fn body_bytes(object: &Object) -> Vec<u8> {
+    let mut w = Writer { bytes: Vec::new() };
+    w.str(object.name());
+    w.u32(object.vertices().len() as u32);
+    for vertex in object.vertices() {
+        for &x in vertex.position.iter().chain(&vertex.normal).chain(&vertex.texture_coords) {
+            w.f32(x);
+        }
+    }
+    w.u32(object.indices().len() as u32);
+    for &index in object.indices() {
+        w.u32(index);
+    }
+    w.u32(object.submeshes().len() as u32);
+    for submesh in object.submeshes() {
+        w.str(submesh.name.as_deref());
+        w.u32(submesh.material.map_or(NONE, |m| m as u32));
+        w.u32(submesh.indices.start);
+        w.u32(submesh.indices.end);
+    }
+    w.u32(object.materials().len() as u32);
+    for material in object.materials() {
+        w.str(Some(material));
+    }
+    w.u32(object.material_libraries().len() as u32);
+    for library in object.material_libraries() {
+        w.str(Some(&library.to_string_lossy()));
+    }
+    match object.colors() {
+        Some(colors) => {
+            w.u32(1);
+            for &x in colors.iter().flatten() {
+                w.f32(x);
+            }
+        }
+        None => w.u32(0),
+    }
+    let stats = object.stats();
+    for &x in &[
+        stats.positions,
+        stats.normals,
+        stats.texture_coords,
+        stats.vertices,
+        stats.triangles,
+        stats.duplicate_faces,
+        stats.removed_vertices,
+    ] {
+        w.u64(x as u64);
+    }
+    w.bytes
+}
+
+/// Reverses `to_bytes_with`, validating every count and range. Compression is read from the
+/// header.
+pub fn from_bytes(bytes: &[u8]) -> Result<Object, Error> {
+    let mut r = Reader { bytes };
+    if r.take(MAGIC.len()).map_err(|_| Error::BadMagic)? != MAGIC {
+        return Err(Error::BadMagic);
+    }
+    let flags = match r.u32()? {
+        VERSION => r.u32()?,
+        VERSION_UNFLAGGED => 0,
+        version => return Err(Error::Version(version)),
+    };
+    match flags {
+        0 => body_from_bytes(r),
+        #[cfg(feature = "lz4")]
+        FLAG_LZ4 => {
+            let size = r.u32()? as usize;
+            // LZ4 can't expand data more than 255 times, so a bigger size is corrupt and would
+            // only allocate a huge buffer.
+            if size > r.bytes.len().saturating_mul(255) {
+                return Err(Error::Corrupt);
+            }
+            let body = lz4::block::decompress(r.bytes, Some(size as i32))
+                .map_err(|_| Error::Corrupt)?;
+            body_from_bytes(Reader { bytes: &body })
+        }
+        flags => Err(Error::UnsupportedCompression(flags)),
+    }
+}
+
+fn body_from_bytes(mut r: Reader) -> Result<Object, Error> {
+    let name = r.str()?;
+    let vertex_count = r.count(std::mem::size_of::<Vertex>())?;
+    let mut vertices = Vec::with_capacity(vertex_count);
+    for _ in 0..vertex_count {
+        let mut floats = [0.0; 8];
+        for x in floats.iter_mut() {
+            *x = r.f32()?;
+        }
+        let [px, py, pz, nx, ny, nz, u, v] = floats;
+        vertices.push(Vertex {
+            position: [px, py, pz],
+            normal: [nx, ny, nz],
+            texture_coords: [u, v],
+        });
+    }
+    let index_count = r.count(4)?;
+    let mut indices = Vec::with_capacity(index_count);
+    for _ in 0..index_count {
+        let index = r.u32()?;
+        if index as usize >= vertex_count {
+            return Err(Error::Corrupt);
+        }
+        indices.push(index);
+    }
+    let submesh_count = r.count(16)?;
+    let mut submeshes = Vec::with_capacity(submesh_count);
+    for _ in 0..submesh_count {
+        let name = r.str()?;
+        let material = r.u32()?;
+        let (start, end) = (r.u32()?, r.u32()?);
+        if start > end || end as usize > index_count {
+            return Err(Error::Corrupt);
+        }
+        submeshes.push(SubMesh {
+            name,
+            material: if material == NONE { None } else { Some(material as usize) },
+            indices: start..end,
+        });
+    }
+    let material_count = r.count(4)?;
+    let mut materials = Vec::with_capacity(material_count);
+    for _ in 0..material_count {
+        materials.push(r.str()?.ok_or(Error::Corrupt)?);
+    }
+    if submeshes.iter().any(|s| s.material.map_or(false, |m| m >= material_count)) {
+        return Err(Error::Corrupt);
+    }
+    let library_count = r.count(4)?;
+    let mut material_libraries = Vec::with_capacity(library_count);
+    for _ in 0..library_count {
+        material_libraries.push(PathBuf::from(r.str()?.ok_or(Error::Corrupt)?));
+    }
+    let colors = match r.u32()? {
+        0 => None,
+        _ => {
+            let mut colors = Vec::with_capacity(vertex_count);
+            for _ in 0..vertex_count {
+                colors.push([r.f32()?, r.f32()?, r.f32()?, r.f32()?]);
+            }
+            Some(colors)
+        }
+    };
+    let mut stats = [0; 7];
+    for x in stats.iter_mut() {
+        *x = r.u64()? as usize;
+    }
+    let [positions, normals, texture_coords, vertex_stat, triangles, duplicates, removed] = stats;
+    let stats = Stats {
+        positions,
+        normals,
+        texture_coords,
+        vertices: vertex_stat,
+        triangles,
+        duplicate_faces: duplicates,
+        removed_vertices: removed,
+    };
+    let object = Object::new(
+        name,
+        vertices,
+        indices,
+        submeshes,
+        materials,
+        material_libraries,
+        stats,
+    );
+    Ok(match colors {
+        Some(colors) => object.with_colors(colors),
+        None => object,
+    })
+}
+
+pub fn write(
+    path: impl AsRef<Path>,
+    object: &Object,
+    compression: Compression,
+) -> Result<(), Error> {
+    std::fs::write(path, to_bytes_with(object, compression))?;
+    Ok(())
+}
+
+pub fn read(path: impl AsRef<Path>) -> Result<Object, Error> {
+    from_bytes(&std::fs::read(path)?)
+}
+
+/// Where `load_or_cache` keeps the cache of `source`, next to it with `.cache` appended.
+pub fn cache_path(source: &Path) -> PathBuf {
+    let mut path = source.as_os_str().to_owned();
+    path.push(".cache");
+    PathBuf::from(path)
+}
+
+/// Loads the cache of `obj_path` if it's at least as new as the OBJ, otherwise parses the OBJ
+/// and rewrites the cache with the default compression. Stale, corrupt and other-version
+/// caches are logged and replaced.
+pub fn load_or_cache(obj_path: impl AsRef<Path>) -> Result<Object, files::Error> {
+    let obj_path = obj_path.as_ref();
+    let cache = cache_path(obj_path);
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let source_modified = modified(obj_path);
+    let fresh = match (modified(&cache), source_modified) {
+        (Some(cached), Some(source)) => cached >= source,
+        _ => false,
+    };
+    if fresh {
+        match read(&cache) {
+            Ok(object) => return Ok(object),
+            Err(e) => log::warn!("ignoring mesh cache '{}': {}", cache.display(), e),
+        }
+    }
+    let object = files::obj::ObjectBuilder::load_file_sync(obj_path)?.build();
+    if let Err(e) = write(&cache, &object, Compression::default()) {
+        log::warn!("can't write mesh cache '{}': {}", cache.display(), e);
+    }
+    Ok(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::model::primitives;
+
+    /// A sphere split in two submeshes with materials and colors, every field set.
+    fn fixture() -> Object {
+        let (vertices, indices) = primitives::uv_sphere(1.0, 32, 64);
+        let half = (indices.len() / 6 * 3) as u32;
+        let submeshes = vec![
+            SubMesh {
+                name: Some("Top".to_string()),
+                material: Some(0),
+                indices: 0..half,
+            },
+            SubMesh {
+                name: None,
+                material: None,
+                indices: half..indices.len() as u32,
+            },
+        ];
+        let colors = vertices.iter().map(|v| [v.position[0], 0.5, 0.25, 1.0]).collect();
+        let stats = Stats {
+            vertices: vertices.len(),
+            triangles: indices.len() / 3,
+            ..Stats::default()
+        };
+        let materials = vec!["Shiny Red".to_string()];
+        let libraries = vec![PathBuf::from("sphere.mtl")];
+        let name = Some("Sphere".to_string());
+        Object::new(name, vertices, indices, submeshes, materials, libraries, stats)
+            .with_colors(colors)
+    }
+
+    #[test]
+    fn round_trip_is_bit_identical() {
+        let object = fixture();
+        let bytes = to_bytes(&object);
+        assert_eq!(to_bytes(&from_bytes(&bytes).unwrap()), bytes);
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn compressed_round_trip_is_bit_identical() {
+        let object = fixture();
+        let raw = to_bytes(&object);
+        for level in [0, 9] {
+            let compressed = to_bytes_with(&object, Compression::Lz4 { level });
+            assert_eq!(to_bytes(&from_bytes(&compressed).unwrap()), raw);
+            // The indices' high bytes are all zero, so even the fast level shrinks them
+            assert!(compressed.len() < raw.len(), "level {}", level);
+        }
+    }
+
+    #[test]
+    fn reads_unflagged_caches() {
+        let bytes = to_bytes(&fixture());
+        // Version 1 had no flags after the version
+        let mut old = MAGIC.to_vec();
+        old.extend(VERSION_UNFLAGGED.to_le_bytes());
+        old.extend(&bytes[12..]);
+        assert_eq!(to_bytes(&from_bytes(&old).unwrap()), bytes);
+    }
+
+    #[test]
+    fn rejects_bad_headers_and_bodies() {
+        let bytes = to_bytes(&fixture());
+        assert!(matches!(from_bytes(b"SOY"), Err(Error::BadMagic)));
+        let mut version = bytes.clone();
+        version[4] = 99;
+        assert!(matches!(from_bytes(&version), Err(Error::Version(99))));
+        let mut flags = bytes.clone();
+        flags[8] = 0x80;
+        assert!(matches!(from_bytes(&flags), Err(Error::UnsupportedCompression(0x80))));
+        assert!(matches!(from_bytes(&bytes[..bytes.len() - 1]), Err(Error::Truncated)));
+    }
+}
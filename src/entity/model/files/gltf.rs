@@ -0,0 +1,736 @@
+use crate::entity::animation::{AnimChannel, AnimationClip, Interpolation, Keyframes};
+use crate::entity::model;
+use crate::entity::model::bounds::Aabb;
+use crate::entity::model::files;
+use crate::entity::model::material::{AlphaMode, ImageData, Material, TextureRef};
+use crate::entity::model::mesh::Mesh;
+use crate::entity::transform::Transform;
+use crate::texture::SamplerConfig;
+use crate::entity::Entity;
+use cgmath::{Matrix4, Quaternion, SquareMatrix, Vector3};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum Error {
+    Gltf(gltf::Error),
+    /// A channel's sampler has no input times or output values.
+    MissingKeyframes,
+    /// A primitive without a `POSITION` attribute.
+    MissingPositions,
+    /// Only triangle lists are supported.
+    UnsupportedMode(gltf::mesh::Mode),
+    /// An index past the end of the primitive's vertices.
+    InvalidIndex,
+}
+impl From<gltf::Error> for Error {
+    fn from(e: gltf::Error) -> Self {
+        Error::Gltf(e)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+type Imported = (gltf::Document, Vec<gltf::buffer::Data>, Vec<gltf::image::Data>);
+
+/// `gltf::import` with the error tagged with the file.
+fn import(filename: &Path) -> Result<Imported, files::Error> {
+    gltf::import(filename).map_err(|e| files::Error::in_file(filename, Error::from(e)))
+}
+
+fn keyframes<T>(
+    times: Vec<f32>,
+    values: Vec<T>,
+    interpolation: Interpolation,
+    cubic: bool,
+) -> Keyframes<T> {
+    Keyframes {
+        times,
+        values: if cubic {
+            // Each keyframe is stored as (in tangent, value, out tangent)
+            values.into_iter().skip(1).step_by(3).collect()
+        } else {
+            values
+        },
+        interpolation,
+    }
+}
+
+/// Reads the animations of a glTF document.
+pub struct GltfAnimation;
+impl GltfAnimation {
+    /// Imports `filename` along with its buffers and returns every animation in it.
+    pub fn load_file_sync(
+        filename: impl AsRef<Path>,
+    ) -> Result<Vec<AnimationClip>, files::Error> {
+        let filename = filename.as_ref();
+        let (document, buffers, _) = import(filename)?;
+        Self::read_document(&document, &buffers).map_err(|e| files::Error::in_file(filename, e))
+    }
+    pub fn read_document(
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Vec<AnimationClip>, Error> {
+        document
+            .animations()
+            .map(|animation| Self::read_animation(&animation, buffers))
+            .collect()
+    }
+    /// Channels targeting the same node are merged into a single `AnimChannel`. Morph target
+    /// weights are skipped.
+    pub fn read_animation(
+        animation: &gltf::Animation,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<AnimationClip, Error> {
+        let mut channels: Vec<AnimChannel> = Vec::new();
+        for channel in animation.channels() {
+            let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|d| &d.0[..]));
+            let times: Vec<f32> = reader.read_inputs().ok_or(Error::MissingKeyframes)?.collect();
+            let outputs = reader.read_outputs().ok_or(Error::MissingKeyframes)?;
+            let (interpolation, cubic) = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => (Interpolation::Step, false),
+                gltf::animation::Interpolation::Linear => (Interpolation::Linear, false),
+                // Tangents are dropped and the values interpolated linearly
+                gltf::animation::Interpolation::CubicSpline => (Interpolation::Linear, true),
+            };
+            let node = channel.target().node().index();
+            let index = match channels.iter().position(|c| c.node == node) {
+                Some(index) => index,
+                None => {
+                    channels.push(AnimChannel::new(node));
+                    channels.len() - 1
+                }
+            };
+            let target = &mut channels[index];
+            use gltf::animation::util::ReadOutputs;
+            match outputs {
+                ReadOutputs::Translations(values) => {
+                    let values = values.map(Vector3::from).collect();
+                    target.translation = Some(keyframes(times, values, interpolation, cubic))
+                }
+                ReadOutputs::Rotations(values) => {
+                    let values = values
+                        .into_f32()
+                        .map(|[x, y, z, w]| Quaternion::new(w, x, y, z))
+                        .collect();
+                    target.rotation = Some(keyframes(times, values, interpolation, cubic))
+                }
+                ReadOutputs::Scales(values) => {
+                    let values = values.map(Vector3::from).collect();
+                    target.scale = Some(keyframes(times, values, interpolation, cubic))
+                }
+                ReadOutputs::MorphTargetWeights(_) => {}
+            }
+        }
+        let duration = channels.iter().map(AnimChannel::duration).fold(0.0, f32::max);
+        Ok(AnimationClip {
+            name: animation
+                .name()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("animation {}", animation.index())),
+            channels,
+            duration,
+        })
+    }
+}
+
+/// Reads the meshes of a glTF document, each primitive becomes its own `Object`.
+pub struct GltfMesh;
+impl GltfMesh {
+    /// Imports a `.gltf` with its `.bin` buffers, or a `.glb`, and returns every primitive.
+    pub fn load_file_sync(
+        filename: impl AsRef<Path>,
+    ) -> Result<Vec<model::Object>, files::Error> {
+        let filename = filename.as_ref();
+        let (document, buffers, _) = import(filename)?;
+        Self::read_document(&document, &buffers).map_err(|e| files::Error::in_file(filename, e))
+    }
+    pub fn read_document(
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Vec<model::Object>, Error> {
+        let mut objects = Vec::new();
+        for mesh in document.meshes() {
+            for primitive in mesh.primitives() {
+                objects.push(Self::read_primitive(&mesh, &primitive, buffers)?);
+            }
+        }
+        Ok(objects)
+    }
+    /// Sparse accessors are applied and normalized integer texture coordinates converted to
+    /// floats by the reader. Missing normals are left zeroed and missing indices are generated.
+    pub fn read_primitive(
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<model::Object, Error> {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            return Err(Error::UnsupportedMode(primitive.mode()));
+        }
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|d| &d.0[..]));
+        let mut vertices: Vec<model::Vertex> = reader
+            .read_positions()
+            .ok_or(Error::MissingPositions)?
+            .map(|position| model::Vertex {
+                position,
+                ..Default::default()
+            })
+            .collect();
+        if let Some(normals) = reader.read_normals() {
+            for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                vertex.normal = normal;
+            }
+        }
+        if let Some(texture_coords) = reader.read_tex_coords(0) {
+            for (vertex, uv) in vertices.iter_mut().zip(texture_coords.into_f32()) {
+                vertex.texture_coords = uv;
+            }
+        }
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+        if indices.iter().any(|&i| i as usize >= vertices.len()) {
+            return Err(Error::InvalidIndex);
+        }
+        let materials: Vec<String> = material_name(&primitive.material()).into_iter().collect();
+        let stats = model::object::Stats {
+            positions: vertices.len(),
+            normals: vertices.len(),
+            texture_coords: vertices.len(),
+            vertices: vertices.len(),
+            triangles: indices.len() / 3,
+            duplicate_faces: 0,
+            removed_vertices: 0,
+        };
+        let submeshes = vec![model::object::SubMesh {
+            name: None,
+            material: if materials.is_empty() { None } else { Some(0) },
+            indices: 0..indices.len() as u32,
+        }];
+        let name = match mesh.name() {
+            Some(name) => format!("{} {}", name, primitive.index()),
+            None => format!("mesh {} {}", mesh.index(), primitive.index()),
+        };
+        Ok(model::Object::new(
+            Some(name),
+            vertices,
+            indices,
+            submeshes,
+            materials,
+            vec![],
+            stats,
+        ))
+    }
+}
+
+/// The name materials are looked up by, `None` for glTF's default material. Unnamed materials
+/// are named after their index.
+fn material_name(material: &gltf::Material) -> Option<String> {
+    let index = material.index()?;
+    Some(match material.name() {
+        Some(name) => name.to_string(),
+        None => format!("material {}", index),
+    })
+}
+
+/// Expands decoded glTF image data to RGBA8, 16 bit channels keep their high byte.
+fn to_rgba8(image: &gltf::image::Data) -> ImageData {
+    use gltf::image::Format;
+    let (channels, bytes, bgr) = match image.format {
+        Format::R8 => (1, 1, false),
+        Format::R8G8 => (2, 1, false),
+        Format::R8G8B8 => (3, 1, false),
+        Format::R8G8B8A8 => (4, 1, false),
+        Format::B8G8R8 => (3, 1, true),
+        Format::B8G8R8A8 => (4, 1, true),
+        Format::R16 => (1, 2, false),
+        Format::R16G16 => (2, 2, false),
+        Format::R16G16B16 => (3, 2, false),
+        Format::R16G16B16A16 => (4, 2, false),
+    };
+    let mut rgba = Vec::with_capacity(image.width as usize * image.height as usize * 4);
+    for pixel in image.pixels.chunks_exact(channels * bytes) {
+        // Little endian, the high byte is last
+        let channel = |i: usize| pixel[i * bytes + bytes - 1];
+        let [mut r, g, mut b, a] = match channels {
+            1 => [channel(0), channel(0), channel(0), 255],
+            2 => [channel(0), channel(1), 0, 255],
+            3 => [channel(0), channel(1), channel(2), 255],
+            _ => [channel(0), channel(1), channel(2), channel(3)],
+        };
+        if bgr {
+            std::mem::swap(&mut r, &mut b);
+        }
+        rgba.extend_from_slice(&[r, g, b, a]);
+    }
+    ImageData {
+        width: image.width,
+        height: image.height,
+        rgba,
+    }
+}
+
+/// Reads the metallic-roughness materials of a glTF document into `Material`s named like the
+/// materials of `GltfMesh` objects.
+pub struct GltfMaterial;
+impl GltfMaterial {
+    /// Imports `filename` along with its buffers and images, embedded or not, and returns every
+    /// material in it.
+    pub fn load_file_sync(filename: impl AsRef<Path>) -> Result<Vec<Material>, files::Error> {
+        let filename = filename.as_ref();
+        let (document, _, images) = import(filename)?;
+        Ok(Self::read_document(&document, &images, filename))
+    }
+    /// `filename` is only used to name the textures, the pixels come from `images`.
+    pub fn read_document(
+        document: &gltf::Document,
+        images: &[gltf::image::Data],
+        filename: &Path,
+    ) -> Vec<Material> {
+        let base_dir = filename.parent().unwrap_or_else(|| Path::new(""));
+        let textures: Vec<(PathBuf, Arc<ImageData>)> = document
+            .images()
+            .zip(images)
+            .map(|(image, data)| {
+                let path = match image.source() {
+                    gltf::image::Source::Uri { uri, .. } => base_dir.join(uri),
+                    gltf::image::Source::View { .. } => {
+                        PathBuf::from(format!("{}#image{}", filename.display(), image.index()))
+                    }
+                };
+                (path, Arc::new(to_rgba8(data)))
+            })
+            .collect();
+        document
+            .materials()
+            .map(|material| Self::read_material(&material, &textures))
+            .collect()
+    }
+    /// `textures` are the document's images by index. Base color and emissive textures are
+    /// sampled as sRGB and the rest as linear by `Material::bind`.
+    pub fn read_material(
+        material: &gltf::Material,
+        textures: &[(PathBuf, Arc<ImageData>)],
+    ) -> Material {
+        let texture_ref = |texture: gltf::Texture| {
+            let (path, image) = textures.get(texture.source().index())?;
+            Some(TextureRef {
+                image: Some(image.clone()),
+                ..TextureRef::new(path.clone())
+            })
+        };
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, a] = pbr.base_color_factor();
+        let metallic_roughness =
+            pbr.metallic_roughness_texture().and_then(|info| texture_ref(info.texture()));
+        let alpha_mode = match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask {
+                cutoff: material.alpha_cutoff(),
+            },
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        };
+        let normal_texture = material.normal_texture();
+        let base_color = pbr.base_color_texture();
+        let sampler = base_color.as_ref().map_or_else(SamplerConfig::default, |info| {
+            Self::read_sampler(&info.texture().sampler())
+        });
+        Material {
+            diffuse: [r, g, b],
+            ambient: [r, g, b],
+            // glTF ignores the alpha of opaque materials
+            alpha: if alpha_mode == AlphaMode::Opaque { 1.0 } else { a },
+            emissive: material.emissive_factor(),
+            roughness: Some(pbr.roughness_factor()),
+            metallic: Some(pbr.metallic_factor()),
+            diffuse_map: base_color.and_then(|info| texture_ref(info.texture())),
+            // Roughness is in green and metallic in blue of the same texture
+            roughness_map: metallic_roughness.clone(),
+            metallic_map: metallic_roughness,
+            normal_map: normal_texture.as_ref().and_then(|info| texture_ref(info.texture())),
+            bump_multiplier: normal_texture.as_ref().map_or(1.0, |info| info.scale()),
+            emissive_map: material.emissive_texture().and_then(|info| texture_ref(info.texture())),
+            alpha_mode,
+            double_sided: material.double_sided(),
+            sampler,
+            ..Material::new(material_name(material).unwrap_or_default())
+        }
+    }
+    /// Filters left out by the file are linear, like most viewers.
+    fn read_sampler(sampler: &gltf::texture::Sampler) -> SamplerConfig {
+        use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+        let address_mode = |mode| match mode {
+            WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+            WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+            WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+        };
+        let (nearest, linear) = (wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear);
+        let filter_mag = match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => nearest,
+            Some(MagFilter::Linear) | None => linear,
+        };
+        let (filter_min, filter_mip) = match sampler.min_filter() {
+            Some(MinFilter::Nearest) | Some(MinFilter::NearestMipmapNearest) => (nearest, nearest),
+            Some(MinFilter::Linear) | Some(MinFilter::LinearMipmapNearest) => (linear, nearest),
+            Some(MinFilter::NearestMipmapLinear) => (nearest, linear),
+            Some(MinFilter::LinearMipmapLinear) | None => (linear, linear),
+        };
+        SamplerConfig {
+            address_mode_u: address_mode(sampler.wrap_s()),
+            address_mode_v: address_mode(sampler.wrap_t()),
+            filter_min,
+            filter_mag,
+            filter_mip,
+            ..SamplerConfig::default()
+        }
+    }
+}
+
+/// GPU buffers of one primitive, shared by every node using its mesh.
+#[derive(Clone)]
+struct PrimitiveBuffers {
+    mesh: Option<Rc<Mesh>>,
+    bounds: Aabb,
+}
+
+/// Turns the node tree of a glTF scene into entities.
+pub struct GltfScene;
+impl GltfScene {
+    /// Imports `filename` and instantiates its default scene, or the first one if it has no
+    /// default.
+    pub fn load_file_sync(
+        device: &wgpu::Device,
+        filename: impl AsRef<Path>,
+    ) -> Result<Vec<Entity>, files::Error> {
+        let filename = filename.as_ref();
+        let (document, buffers, _) = import(filename)?;
+        Self::read_document(device, &document, &buffers)
+            .map_err(|e| files::Error::in_file(filename, e))
+    }
+    /// Entity `i` is node `i`, so `AnimationPlayer` channels target the right entities when the
+    /// entities are the start of `Scene::entities`. Nodes outside the scene are kept without
+    /// geometry to preserve that. A mesh's first primitive is drawn by its node's entity and
+    /// every further primitive by a child entity appended after the nodes. Nodes without a
+    /// mesh are transform-only parents without one. Materials aren't bound, resolve
+    /// them through `GltfMaterial` and a `MaterialCache`.
+    pub fn read_document(
+        device: &wgpu::Device,
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Vec<Entity>, Error> {
+        let empty = PrimitiveBuffers {
+            mesh: None,
+            bounds: Aabb::empty(),
+        };
+        let mut entities: Vec<Entity> = document
+            .nodes()
+            .map(|node| {
+                let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+                let transform = Transform {
+                    translation: translation.into(),
+                    rotation: Quaternion::new(w, x, y, z),
+                    scale: scale.into(),
+                };
+                Entity {
+                    name: node.name().map(str::to_string),
+                    mx_world: transform.to_matrix(),
+                    ..Self::entity(&empty, transform)
+                }
+            })
+            .collect();
+        for node in document.nodes() {
+            for child in node.children() {
+                entities[child.index()].parent = Some(node.index());
+            }
+        }
+        let scene = match document.default_scene().or_else(|| document.scenes().next()) {
+            Some(scene) => scene,
+            None => return Ok(entities),
+        };
+        let mut uploaded: HashMap<(usize, usize), PrimitiveBuffers> = HashMap::new();
+        let mut stack: Vec<gltf::Node> = scene.nodes().collect();
+        while let Some(node) = stack.pop() {
+            stack.extend(node.children());
+            let mesh = match node.mesh() {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+            for primitive in mesh.primitives() {
+                let key = (mesh.index(), primitive.index());
+                let primitive_buffers = match uploaded.get(&key) {
+                    Some(primitive_buffers) => primitive_buffers.clone(),
+                    None => {
+                        let object = GltfMesh::read_primitive(&mesh, &primitive, buffers)?;
+                        let primitive_buffers = PrimitiveBuffers {
+                            mesh: Some(Rc::new(Mesh::new(device, &object, object.name()))),
+                            bounds: *object.bounds(),
+                        };
+                        uploaded.insert(key, primitive_buffers.clone());
+                        primitive_buffers
+                    }
+                };
+                if primitive.index() == 0 {
+                    let entity = &mut entities[node.index()];
+                    entity.mesh = primitive_buffers.mesh;
+                    entity.bounds = primitive_buffers.bounds;
+                } else {
+                    let name = entities[node.index()].name.clone();
+                    entities.push(Entity {
+                        name,
+                        parent: Some(node.index()),
+                        ..Self::entity(&primitive_buffers, Transform::identity())
+                    });
+                }
+            }
+        }
+        // World matrices for the first frame, `Scene::update` keeps them current
+        let locals: Vec<Matrix4<f32>> = entities.iter().map(|e| e.mx_world).collect();
+        for i in 0..entities.len() {
+            let (mut world, mut parent) = (locals[i], entities[i].parent);
+            while let Some(p) = parent {
+                world = locals[p] * world;
+                parent = entities[p].parent;
+            }
+            entities[i].mx_world = world;
+        }
+        Ok(entities)
+    }
+    fn entity(buffers: &PrimitiveBuffers, transform: Transform) -> Entity {
+        Entity {
+            name: None,
+            parent: None,
+            transform,
+            mx_world: Matrix4::identity(),
+            animators: Vec::new(),
+            color: wgpu::Color::WHITE,
+            emissive: None,
+            mesh: buffers.mesh.clone(),
+            bounds: buffers.bounds,
+            uniform_offset: 0,
+            material: None,
+            lod: None,
+            model: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A GLB with `json` as its JSON chunk and `bin` as its binary chunk, left out if empty.
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let padded = |len: usize| (len + 3) / 4 * 4;
+        let (json_len, bin_len) = (padded(json.len()), padded(bin.len()));
+        let bin_chunk = if bin.is_empty() { 0 } else { 8 + bin_len };
+        let mut glb = Vec::new();
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((12 + 8 + json_len + bin_chunk) as u32).to_le_bytes());
+        glb.extend_from_slice(&(json_len as u32).to_le_bytes());
+        glb.extend_from_slice(b"JSON");
+        glb.extend_from_slice(json.as_bytes());
+        glb.resize(glb.len() + json_len - json.len(), b' ');
+        if !bin.is_empty() {
+            glb.extend_from_slice(&(bin_len as u32).to_le_bytes());
+            glb.extend_from_slice(b"BIN\0");
+            glb.extend_from_slice(bin);
+            glb.resize(glb.len() + bin_len - bin.len(), 0);
+        }
+        glb
+    }
+
+    /// One triangle twice, indexed with u8 UVs and not indexed with u16 UVs and a sparse
+    /// accessor moving its last position.
+    const TRIANGLES: &str = r#"{
+        "asset": {"version": "2.0"},
+        "buffers": [{"byteLength": 84}],
+        "bufferViews": [
+            {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+            {"buffer": 0, "byteOffset": 36, "byteLength": 6},
+            {"buffer": 0, "byteOffset": 44, "byteLength": 12, "byteStride": 4},
+            {"buffer": 0, "byteOffset": 56, "byteLength": 12},
+            {"buffer": 0, "byteOffset": 68, "byteLength": 2},
+            {"buffer": 0, "byteOffset": 72, "byteLength": 12}
+        ],
+        "accessors": [
+            {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+             "min": [0, 0, 0], "max": [1, 1, 0]},
+            {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"},
+            {"bufferView": 2, "componentType": 5121, "normalized": true, "count": 3,
+             "type": "VEC2"},
+            {"bufferView": 3, "componentType": 5123, "normalized": true, "count": 3,
+             "type": "VEC2"},
+            {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+             "min": [0, 0, 0], "max": [1, 1, 5],
+             "sparse": {"count": 1, "indices": {"bufferView": 4, "componentType": 5123},
+                        "values": {"bufferView": 5}}}
+        ],
+        "meshes": [{"name": "Triangle", "primitives": [
+            {"attributes": {"POSITION": 0, "TEXCOORD_0": 2}, "indices": 1},
+            {"attributes": {"POSITION": 4, "TEXCOORD_0": 3}}
+        ]}]
+    }"#;
+
+    fn triangles_bin() -> Vec<u8> {
+        let mut bin = Vec::new();
+        let positions: [f32; 9] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        bin.extend_from_slice(bytemuck::cast_slice(&positions));
+        // Indices, padded to 4 bytes
+        bin.extend_from_slice(bytemuck::cast_slice(&[0u16, 1, 2, 0]));
+        // u8 UVs, each padded to 4 bytes
+        bin.extend_from_slice(&[0, 0, 0, 0, 255, 0, 0, 0, 0, 255, 0, 0]);
+        bin.extend_from_slice(bytemuck::cast_slice(&[0u16, 0, 65535, 0, 65535, 65535]));
+        // The sparse index, padded, and its value
+        bin.extend_from_slice(bytemuck::cast_slice(&[2u16, 0]));
+        bin.extend_from_slice(bytemuck::cast_slice(&[0.0f32, 0.0, 5.0]));
+        bin
+    }
+
+    #[test]
+    fn indexed_and_generated_indices() {
+        let (document, buffers, _) = gltf::import_slice(&glb(TRIANGLES, &triangles_bin())).unwrap();
+        let objects = GltfMesh::read_document(&document, &buffers).unwrap();
+        let attributes = |object: &model::Object| {
+            let vertices = object.vertices().iter();
+            let attributes = vertices.map(|v| (v.position, v.texture_coords));
+            attributes.collect::<Vec<_>>()
+        };
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].name(), Some("Triangle 0"));
+        assert_eq!(objects[0].indices(), [0, 1, 2]);
+        // Normalized u8s become 0 to 1
+        let expected = [
+            ([0.0, 0.0, 0.0], [0.0, 0.0]),
+            ([1.0, 0.0, 0.0], [1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [0.0, 1.0]),
+        ];
+        assert_eq!(attributes(&objects[0]), expected);
+        // The file has no normals
+        assert!(objects[0].vertices().iter().all(|v| v.normal == [0.0; 3]));
+
+        assert_eq!(objects[1].name(), Some("Triangle 1"));
+        assert_eq!(objects[1].indices(), [0, 1, 2]);
+        let expected = [
+            ([0.0, 0.0, 0.0], [0.0, 0.0]),
+            ([1.0, 0.0, 0.0], [1.0, 0.0]),
+            ([0.0, 0.0, 5.0], [1.0, 1.0]),
+        ];
+        assert_eq!(attributes(&objects[1]), expected);
+        assert_eq!(objects[1].bounds().max, cgmath::Point3::new(1.0, 0.0, 5.0));
+    }
+
+    #[test]
+    fn missing_buffers_are_errors() {
+        // The JSON declares a buffer the GLB doesn't have
+        let without_bin = glb(TRIANGLES, &[]);
+        assert!(matches!(
+            gltf::import_slice(&without_bin),
+            Err(gltf::Error::MissingBlob)
+        ));
+        // Reading without the buffers finds no positions
+        let gltf = gltf::Gltf::from_slice(&without_bin).unwrap();
+        let result = GltfMesh::read_document(&gltf.document, &[]);
+        assert!(matches!(result, Err(Error::MissingPositions)));
+
+        let dir = std::env::temp_dir().join(format!("soyuz-gltf-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let external = r#""byteLength": 84, "uri": "triangle.bin"}"#;
+        let json = TRIANGLES.replace(r#""byteLength": 84}"#, external);
+        std::fs::write(dir.join("triangle.gltf"), json).unwrap();
+        let result = GltfMesh::load_file_sync(dir.join("triangle.gltf"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn every_texture_slot_is_bound() {
+        // One 1×1 PNG per texture, each a different color
+        let colors: [[u8; 4]; 4] = [
+            [255, 0, 0, 255],
+            [0, 128, 255, 255],
+            [128, 128, 255, 255],
+            [255, 255, 0, 255],
+        ];
+        let (mut bin, mut views) = (Vec::new(), Vec::new());
+        for color in colors {
+            let (mut png, format) = (Vec::new(), image::ImageOutputFormat::Png);
+            image::RgbaImage::from_pixel(1, 1, image::Rgba(color))
+                .write_to(&mut std::io::Cursor::new(&mut png), format)
+                .unwrap();
+            views.push(format!(
+                r#"{{"buffer": 0, "byteOffset": {}, "byteLength": {}}}"#,
+                bin.len(),
+                png.len()
+            ));
+            bin.extend_from_slice(&png);
+            bin.resize((bin.len() + 3) / 4 * 4, 0);
+        }
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "buffers": [{{"byteLength": {}}}],
+                "bufferViews": [{}],
+                "images": [
+                    {{"bufferView": 0, "mimeType": "image/png"}},
+                    {{"bufferView": 1, "mimeType": "image/png"}},
+                    {{"bufferView": 2, "mimeType": "image/png"}},
+                    {{"bufferView": 3, "mimeType": "image/png"}}
+                ],
+                "textures": [{{"source": 0}}, {{"source": 1}}, {{"source": 2}}, {{"source": 3}}],
+                "materials": [{{
+                    "name": "Helmet",
+                    "pbrMetallicRoughness": {{
+                        "baseColorTexture": {{"index": 0}},
+                        "metallicRoughnessTexture": {{"index": 1}}
+                    }},
+                    "normalTexture": {{"index": 2, "scale": 0.5}},
+                    "emissiveTexture": {{"index": 3}},
+                    "emissiveFactor": [1, 1, 1],
+                    "doubleSided": true
+                }}]
+            }}"#,
+            bin.len(),
+            views.join(", ")
+        );
+        let (document, _, images) = gltf::import_slice(&glb(&json, &bin)).unwrap();
+        let materials = GltfMaterial::read_document(&document, &images, Path::new("helmet.glb"));
+        let helmet = &materials[0];
+        let slots = [
+            (&helmet.diffuse_map, 0),
+            (&helmet.metallic_map, 1),
+            (&helmet.roughness_map, 1),
+            (&helmet.normal_map, 2),
+            (&helmet.emissive_map, 3),
+        ];
+        for (map, image) in slots {
+            let map = map.as_ref().unwrap();
+            assert_eq!(map.path, Path::new(&format!("helmet.glb#image{}", image)));
+            assert_eq!(map.image.as_ref().unwrap().rgba, colors[image]);
+        }
+        assert_eq!(helmet.bump_multiplier, 0.5);
+        assert_eq!(helmet.cull_mode(), None);
+
+        let (device, queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let layout = Material::bind_group_layout(&device);
+        let fallback = crate::entity::model::material::FallbackTextures::new(&device, &queue);
+        let bound = helmet.clone().bind(&device, &queue, &layout, &fallback);
+        // Base color, emissive, normal and the packed metallic-roughness texture, none of them
+        // replaced by a fallback
+        assert_eq!(bound.textures.len(), 4);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::entity::model::files::obj::ObjectBuilder;
+use crate::entity::model::files::source::AssetSource;
+use crate::entity::model::files::{self, Format};
+use crate::entity::model::loader::{self, ModelData};
+use reqwest::Url;
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+/// Size of the pipe between a download and the OBJ parser.
+const STREAM_BUFFER: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum Error {
+    Request(reqwest::Error),
+    /// The server answered with an error status.
+    Status(reqwest::StatusCode),
+    /// The body is larger than `HttpOptions::max_bytes`.
+    TooLarge { limit: u64 },
+    /// A reference couldn't be joined onto the base URL.
+    InvalidUrl(String),
+    /// Writing to the parser failed, it stopped reading.
+    IO(std::io::Error),
+}
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Request(e)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::IO(e)
+    }
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Request(e) => Some(e),
+            Error::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct HttpOptions {
+    /// For each request, from connecting until the body is read.
+    pub timeout: Duration,
+    /// Bodies larger than this fail with `Error::TooLarge`.
+    pub max_bytes: u64,
+    pub max_redirects: usize,
+}
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            timeout: Duration::from_secs(30),
+            max_bytes: 256 * 1024 * 1024,
+            max_redirects: 10,
+        }
+    }
+}
+
+/// Runs `future` to completion from synchronous code, inside the runtime if there is one.
+/// `AssetSource::read` is synchronous while reqwest is async.
+fn block_on<F: Future>(future: F) -> std::io::Result<F::Output> {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => Ok(tokio::task::block_in_place(|| handle.block_on(future))),
+        Err(_) => Ok(tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(future)),
+    }
+}
+
+/// Writes the body of `response` into `writer` as it arrives. Dropping the writer at the end
+/// closes the reading side.
+async fn pipe(
+    mut response: reqwest::Response,
+    mut writer: tokio::io::DuplexStream,
+    limit: u64,
+) -> Result<(), Error> {
+    let mut read = 0;
+    while let Some(chunk) = response.chunk().await? {
+        read += chunk.len() as u64;
+        if read > limit {
+            return Err(Error::TooLarge { limit });
+        }
+        writer.write_all(&chunk).await?;
+    }
+    Ok(())
+}
+
+/// Loads a model from a URL, and the files it references relative to it. References are
+/// resolved like links in a web page, `textures/crate.png` next to
+/// `https://example.com/models/crate.obj` is `https://example.com/models/textures/crate.png`.
+/// Redirects are followed.
+///
+/// ```ignore
+/// let url = "https://assets.example.com/crate.obj";
+/// let source = HttpSource::new(url, HttpOptions::default())?;
+/// let data = source.load_model().await?;
+/// let materials = MaterialCache::with_source(&device, &queue, Rc::new(source));
+/// ```
+pub struct HttpSource {
+    client: reqwest::Client,
+    url: Url,
+    options: HttpOptions,
+}
+impl HttpSource {
+    pub fn new(url: &str, options: HttpOptions) -> Result<Self, files::Error> {
+        let in_url = |e: Error| files::Error::in_url(url, e);
+        let parsed = Url::parse(url).map_err(|e| in_url(Error::InvalidUrl(e.to_string())))?;
+        let client = reqwest::Client::builder()
+            .timeout(options.timeout)
+            .redirect(reqwest::redirect::Policy::limited(options.max_redirects))
+            .build()
+            .map_err(|e| in_url(e.into()))?;
+        Ok(HttpSource {
+            client,
+            url: parsed,
+            options,
+        })
+    }
+    /// The URL of the model, which references are relative to.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+    /// `path` relative to the model's URL.
+    pub fn resolve(&self, path: &Path) -> Result<Url, Error> {
+        // Paths from Windows authored files may use backslashes
+        let reference = path.to_string_lossy().replace('\\', "/");
+        self.url.join(&reference).map_err(|e| Error::InvalidUrl(e.to_string()))
+    }
+    async fn get(&self, url: Url) -> Result<reqwest::Response, Error> {
+        let response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::Status(response.status()));
+        }
+        let limit = self.options.max_bytes;
+        match response.content_length() {
+            Some(length) if length > limit => Err(Error::TooLarge { limit }),
+            _ => Ok(response),
+        }
+    }
+    /// The whole body at `url`.
+    pub async fn download(&self, url: Url) -> Result<Vec<u8>, Error> {
+        let limit = self.options.max_bytes;
+        let mut response = self.get(url).await?;
+        let mut body = Vec::with_capacity(response.content_length().unwrap_or(0) as usize);
+        // The content length header is only a hint, the limit is checked as chunks arrive
+        while let Some(chunk) = response.chunk().await? {
+            if (body.len() + chunk.len()) as u64 > limit {
+                return Err(Error::TooLarge { limit });
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+    /// Downloads and parses the model. OBJ files are parsed while they download, other
+    /// formats are read into memory first. The format is picked by the URL's extension, or
+    /// by the first bytes.
+    pub async fn load_model(&self) -> Result<ModelData, files::Error> {
+        let in_url = |e: files::Error| files::Error::in_url(self.url.as_str(), e);
+        let name_hint = self.url.path_segments().and_then(|s| s.last()).unwrap_or_default();
+        if Format::from_path(Path::new(name_hint)) != Some(Format::Obj) {
+            let bytes = self.download(self.url.clone()).await.map_err(|e| in_url(e.into()))?;
+            return loader::load_from_bytes(name_hint, &bytes).map_err(in_url);
+        }
+        let response = self.get(self.url.clone()).await.map_err(|e| in_url(e.into()))?;
+        let (writer, reader) = tokio::io::duplex(STREAM_BUFFER);
+        let download = pipe(response, writer, self.options.max_bytes);
+        let mut builder = ObjectBuilder::new();
+        let parse = builder.read_async(tokio::io::BufReader::new(reader));
+        let (downloaded, parsed) = tokio::join!(download, parse);
+        match (downloaded, parsed) {
+            // The parser stopped reading, its error is the cause
+            (Err(Error::IO(_)), Err(e)) => return Err(in_url(e.into())),
+            (Err(e), _) => return Err(in_url(e.into())),
+            (Ok(()), parsed) => parsed.map_err(|e| in_url(e.into()))?,
+        }
+        let name = Path::new(name_hint)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(ModelData::new(name, Format::Obj, vec![builder.build()]))
+    }
+}
+impl AssetSource for HttpSource {
+    /// Blocks until the download is done. Inside a tokio runtime this needs the
+    /// multi-threaded scheduler.
+    fn read(&self, path: &Path) -> std::io::Result<Cow<[u8]>> {
+        let to_io = |e: String| std::io::Error::new(std::io::ErrorKind::Other, e);
+        let url = self.resolve(path).map_err(|e| to_io(e.to_string()))?;
+        let described = url.to_string();
+        block_on(self.download(url))?
+            .map(Cow::Owned)
+            .map_err(|e| to_io(format!("{}: {}", described, e)))
+    }
+}
@@ -1 +1,183 @@
-pub mod obj;
\ No newline at end of file
+#[cfg(feature = "gltf")]
+pub mod gltf;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod mtl;
+pub mod obj;
+pub mod ply;
+pub mod source;
+pub mod stl;
+
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// An error from any of the loaders. The public loading functions return this, wrapped in
+/// `InFile` when they were given a path, while the parsers keep their format's own error.
+#[derive(Debug)]
+pub enum Error {
+    IO(std::io::Error),
+    Obj(obj::Error),
+    Mtl(mtl::Error),
+    Stl(stl::Error),
+    Ply(ply::Error),
+    #[cfg(feature = "gltf")]
+    Gltf(gltf::Error),
+    #[cfg(feature = "http")]
+    Http(http::Error),
+    /// Neither the extension nor the contents match a supported format, or support for the
+    /// format isn't enabled.
+    UnsupportedFormat { extension: Option<String> },
+    /// The file that caused the error.
+    InFile(PathBuf, Box<Error>),
+    /// The URL of the file that caused the error.
+    InUrl(String, Box<Error>),
+}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::IO(e)
+    }
+}
+impl From<obj::Error> for Error {
+    fn from(e: obj::Error) -> Self {
+        Error::Obj(e)
+    }
+}
+impl From<mtl::Error> for Error {
+    fn from(e: mtl::Error) -> Self {
+        Error::Mtl(e)
+    }
+}
+impl From<stl::Error> for Error {
+    fn from(e: stl::Error) -> Self {
+        Error::Stl(e)
+    }
+}
+impl From<ply::Error> for Error {
+    fn from(e: ply::Error) -> Self {
+        Error::Ply(e)
+    }
+}
+#[cfg(feature = "gltf")]
+impl From<gltf::Error> for Error {
+    fn from(e: gltf::Error) -> Self {
+        Error::Gltf(e)
+    }
+}
+#[cfg(feature = "http")]
+impl From<http::Error> for Error {
+    fn from(e: http::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+impl Error {
+    /// `e` in the file at `path`.
+    pub fn in_file(path: impl Into<PathBuf>, e: impl Into<Error>) -> Error {
+        Error::InFile(path.into(), Box::new(e.into()))
+    }
+    /// `e` in the file downloaded from `url`.
+    pub fn in_url(url: impl Into<String>, e: impl Into<Error>) -> Error {
+        Error::InUrl(url.into(), Box::new(e.into()))
+    }
+    /// The file the error happened in, if known.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::InFile(path, _) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+            Error::Obj(e) => Some(e),
+            Error::Mtl(e) => Some(e),
+            Error::Stl(e) => Some(e),
+            Error::Ply(e) => Some(e),
+            #[cfg(feature = "gltf")]
+            Error::Gltf(e) => Some(e),
+            #[cfg(feature = "http")]
+            Error::Http(e) => Some(e),
+            Error::UnsupportedFormat { .. } => None,
+            Error::InFile(_, e) | Error::InUrl(_, e) => Some(e.as_ref()),
+        }
+    }
+}
+
+/// The model file formats there are loaders for.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Format {
+    Obj,
+    Stl,
+    Ply,
+    /// `.gltf` with its buffers or `.glb`.
+    Gltf,
+}
+impl Format {
+    /// By extension, case insensitive. `.obj.gz` counts as OBJ.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let mut extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        if extension == "gz" {
+            let stem = Path::new(path.file_stem()?);
+            extension = stem.extension()?.to_str()?.to_ascii_lowercase();
+        }
+        match extension.as_str() {
+            "obj" => Some(Format::Obj),
+            "stl" => Some(Format::Stl),
+            "ply" => Some(Format::Ply),
+            "gltf" | "glb" => Some(Format::Gltf),
+            _ => None,
+        }
+    }
+    /// By the first bytes of the file, for files without a known extension. Gzipped data is
+    /// taken to be OBJ.
+    pub fn sniff(bytes: &[u8]) -> Option<Format> {
+        let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+        let text = &bytes[start..];
+        if bytes.starts_with(b"ply") {
+            Some(Format::Ply)
+        } else if bytes.starts_with(b"glTF") || text.starts_with(b"{") {
+            Some(Format::Gltf)
+        } else if stl::is_stl(bytes) {
+            Some(Format::Stl)
+        } else if bytes.starts_with(&obj::GZIP_MAGIC) || Self::looks_like_obj(text) {
+            // Only OBJ is read compressed
+            Some(Format::Obj)
+        } else {
+            None
+        }
+    }
+    /// The first statement is one an OBJ file could start with.
+    fn looks_like_obj(text: &[u8]) -> bool {
+        let first_line = text.split(|&b| b == b'\n').next().unwrap_or_default();
+        let tag = first_line.split(|b| b.is_ascii_whitespace()).next().unwrap_or_default();
+        let tags: [&[u8]; 8] = [b"#", b"v", b"vn", b"vt", b"f", b"o", b"g", b"mtllib"];
+        tags.contains(&tag) || first_line.starts_with(b"#")
+    }
+    /// From the extension, or by sniffing the contents if it's missing or unknown.
+    pub fn detect(path: &Path) -> Result<Format, Error> {
+        if let Some(format) = Self::from_path(path) {
+            return Ok(format);
+        }
+        let mut head = [0; 512];
+        let mut file = std::fs::File::open(path)?;
+        let read = std::io::Read::read(&mut file, &mut head)?;
+        let head = &head[..read];
+        let format = match Self::sniff(head) {
+            // Binary STL is only recognized by its size
+            None => Self::sniff(&std::fs::read(path)?),
+            format => format,
+        };
+        format.ok_or_else(|| Error::UnsupportedFormat {
+            extension: path.extension().map(|e| e.to_string_lossy().into_owned()),
+        })
+    }
+}
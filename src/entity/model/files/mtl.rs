@@ -0,0 +1,1073 @@
+use crate::entity::model::files;
+use crate::entity::model::files::source::AssetSource;
+use crate::entity::model::material::{IlluminationModel, MapOptions, TextureRef};
+use crate::entity::model::{Material, Object};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+use std::num::{ParseFloatError, ParseIntError};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncBufReadExt;
+
+#[derive(Debug)]
+pub enum Error {
+    IO(std::io::Error),
+    MissingTag,
+    UnrecognizedTag,
+    ParseIntError(ParseIntError),
+    ParseFloatError(ParseFloatError),
+    MissingNumber,
+    /// A material property came before any `newmtl`.
+    MissingNewMtl,
+    /// A `map_*` statement without a file name.
+    MissingPath,
+    /// `1` based line number of the line that caused the error.
+    AtLine(usize, Box<Error>),
+}
+impl From<ParseIntError> for Error {
+    fn from(e: ParseIntError) -> Self {
+        Error::ParseIntError(e)
+    }
+}
+impl From<ParseFloatError> for Error {
+    fn from(e: ParseFloatError) -> Self {
+        Error::ParseFloatError(e)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::IO(e)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// `r [g b]`, a single value is used for all three channels.
+fn parse_color(s: &str) -> Result<[f32; 3], Error> {
+    let mut nums = s.split_whitespace();
+    let r = nums.next().ok_or(Error::MissingNumber)?.parse()?;
+    let g = nums.next().map(|g| g.parse()).transpose()?.unwrap_or(r);
+    let b = nums.next().map(|b| b.parse()).transpose()?.unwrap_or(r);
+    Ok([r, g, b])
+}
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum MapKind {
+    Ambient,
+    Diffuse,
+    Specular,
+    Shininess,
+    Dissolve,
+    Bump,
+    Normal,
+    /// `map_Pr`
+    Roughness,
+    /// `map_Pm`
+    Metallic,
+    /// `map_Ke`
+    Emissive,
+}
+
+/// The file name of a `map_*` statement and the options in front of it.
+#[derive(Clone, PartialOrd, PartialEq, Debug)]
+pub struct TextureMap<'a> {
+    pub path: Cow<'a, str>,
+    /// `-bm`, scales the values of a bump map.
+    pub bump_multiplier: Option<f32>,
+    pub options: MapOptions,
+}
+
+/// Splits off the first whitespace separated token.
+fn next_token(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    s.split_once(char::is_whitespace).unwrap_or((s, ""))
+}
+
+/// Takes up to `max` numbers off the front of `rest`, always leaving a token for the file name.
+fn parse_numbers<'a>(rest: &mut &'a str, max: usize) -> Vec<f32> {
+    let mut numbers = Vec::with_capacity(max);
+    while numbers.len() < max {
+        let (token, after) = next_token(*rest);
+        match token.parse() {
+            Ok(number) if !after.trim().is_empty() => {
+                numbers.push(number);
+                *rest = after;
+            }
+            _ => break,
+        }
+    }
+    numbers
+}
+
+/// Like `parse_numbers` with missing values taken from `default`.
+fn parse_vector(rest: &mut &str, default: [f32; 3]) -> Result<[f32; 3], Error> {
+    let numbers = parse_numbers(rest, 3);
+    if numbers.is_empty() {
+        return Err(Error::MissingNumber);
+    }
+    let mut vector = default;
+    vector[..numbers.len()].copy_from_slice(&numbers);
+    Ok(vector)
+}
+
+impl<'a> TextureMap<'a> {
+    pub fn to_static(self) -> TextureMap<'static> {
+        TextureMap {
+            path: Cow::Owned(self.path.into_owned()),
+            bump_multiplier: self.bump_multiplier,
+            options: self.options,
+        }
+    }
+    /// Parses `[options] filename`, the file name may contain spaces. Options this doesn't
+    /// know are skipped along with their arguments.
+    pub fn parse(s: &'a str) -> Result<Self, Error> {
+        let mut rest = s.trim();
+        let mut bump_multiplier = None;
+        let mut options = MapOptions::default();
+        while rest.starts_with('-') {
+            let (flag, after) = next_token(rest);
+            rest = after;
+            match flag {
+                "-bm" => match parse_numbers(&mut rest, 1)[..] {
+                    [multiplier] => bump_multiplier = Some(multiplier),
+                    _ => return Err(Error::MissingNumber),
+                },
+                "-o" => options.offset = parse_vector(&mut rest, [0.0; 3])?,
+                "-s" => options.scale = parse_vector(&mut rest, [1.0; 3])?,
+                "-mm" => match parse_numbers(&mut rest, 2)[..] {
+                    [base, gain] => options.mm = Some((base, gain)),
+                    _ => return Err(Error::MissingNumber),
+                },
+                "-clamp" => {
+                    let (value, after) = next_token(rest);
+                    options.clamp = value == "on";
+                    rest = after;
+                }
+                _ => {
+                    // `-blendu on`, `-t 1 1 1`, `-imfchan r` and so on
+                    if parse_numbers(&mut rest, 3).is_empty() {
+                        let (value, after) = next_token(rest);
+                        if !after.trim().is_empty() && !value.starts_with('-') {
+                            rest = after;
+                        }
+                    }
+                }
+            }
+            rest = rest.trim_start();
+        }
+        if rest.is_empty() {
+            return Err(Error::MissingPath);
+        }
+        Ok(TextureMap {
+            path: Cow::Borrowed(rest),
+            bump_multiplier,
+            options,
+        })
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq, Debug)]
+pub enum Line<'a> {
+    NewMtl(Cow<'a, str>),
+    Ambient([f32; 3]),
+    Diffuse([f32; 3]),
+    Specular([f32; 3]),
+    Emissive([f32; 3]),
+    Shininess(f32),
+    Dissolve(f32),
+    Transparency(f32),
+    OpticalDensity(f32),
+    Illumination(IlluminationModel),
+    /// `Pr`, from the PBR extension.
+    Roughness(f32),
+    /// `Pm`
+    Metallic(f32),
+    /// `Ps`
+    Sheen(f32),
+    /// `aniso`
+    Anisotropy(f32),
+    Map(MapKind, TextureMap<'a>),
+    Comment(Cow<'a, str>),
+}
+impl<'a> Line<'a> {
+    pub fn to_static(self) -> Line<'static> {
+        match self {
+            Line::NewMtl(name) => Line::NewMtl(Cow::Owned(name.into_owned())),
+            Line::Map(kind, map) => Line::Map(kind, map.to_static()),
+            Line::Comment(comment) => Line::Comment(Cow::Owned(comment.into_owned())),
+
+            Line::Ambient(c) => Line::Ambient(c),
+            Line::Diffuse(c) => Line::Diffuse(c),
+            Line::Specular(c) => Line::Specular(c),
+            Line::Emissive(c) => Line::Emissive(c),
+            Line::Shininess(x) => Line::Shininess(x),
+            Line::Dissolve(x) => Line::Dissolve(x),
+            Line::Transparency(x) => Line::Transparency(x),
+            Line::OpticalDensity(x) => Line::OpticalDensity(x),
+            Line::Illumination(x) => Line::Illumination(x),
+            Line::Roughness(x) => Line::Roughness(x),
+            Line::Metallic(x) => Line::Metallic(x),
+            Line::Sheen(x) => Line::Sheen(x),
+            Line::Anisotropy(x) => Line::Anisotropy(x),
+        }
+    }
+    pub fn process_line(line: &'a str) -> Result<Self, Error> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Err(Error::MissingTag);
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            return Ok(Line::Comment(Cow::Borrowed(comment.trim_start())));
+        }
+        // Exporters separate with tabs too. A tag on its own fails parsing what's after it.
+        let (tag, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim_start();
+        match tag {
+            "newmtl" => Ok(Line::NewMtl(Cow::Borrowed(rest))),
+
+            "Ka" => Ok(Line::Ambient(parse_color(rest)?)),
+            "Kd" => Ok(Line::Diffuse(parse_color(rest)?)),
+            "Ks" => Ok(Line::Specular(parse_color(rest)?)),
+            "Ke" => Ok(Line::Emissive(parse_color(rest)?)),
+            "Ns" => Ok(Line::Shininess(rest.parse()?)),
+            "d" => Ok(Line::Dissolve(rest.parse()?)),
+            "Tr" => Ok(Line::Transparency(rest.parse()?)),
+            "Ni" => Ok(Line::OpticalDensity(rest.parse()?)),
+            "illum" => Ok(Line::Illumination(rest.parse::<u32>()?.into())),
+            "Pr" => Ok(Line::Roughness(rest.parse()?)),
+            "Pm" => Ok(Line::Metallic(rest.parse()?)),
+            "Ps" => Ok(Line::Sheen(rest.parse()?)),
+            "aniso" => Ok(Line::Anisotropy(rest.parse()?)),
+
+            "map_Ka" => Ok(Line::Map(MapKind::Ambient, TextureMap::parse(rest)?)),
+            "map_Kd" => Ok(Line::Map(MapKind::Diffuse, TextureMap::parse(rest)?)),
+            "map_Ks" => Ok(Line::Map(MapKind::Specular, TextureMap::parse(rest)?)),
+            "map_Ns" => Ok(Line::Map(MapKind::Shininess, TextureMap::parse(rest)?)),
+            "map_d" => Ok(Line::Map(MapKind::Dissolve, TextureMap::parse(rest)?)),
+            "map_Bump" | "map_bump" | "bump" => {
+                Ok(Line::Map(MapKind::Bump, TextureMap::parse(rest)?))
+            }
+            "norm" | "map_Norm" => Ok(Line::Map(MapKind::Normal, TextureMap::parse(rest)?)),
+            "map_Pr" => Ok(Line::Map(MapKind::Roughness, TextureMap::parse(rest)?)),
+            "map_Pm" => Ok(Line::Map(MapKind::Metallic, TextureMap::parse(rest)?)),
+            "map_Ke" => Ok(Line::Map(MapKind::Emissive, TextureMap::parse(rest)?)),
+            _ => Err(Error::UnrecognizedTag),
+        }
+    }
+}
+
+/// How to read `Tr`, exporters disagree on it.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TrMode {
+    /// `Tr` is the transparency, `alpha = 1 - Tr`. What the spec and most exporters use.
+    Transparency,
+    /// `Tr` is the same as `d`.
+    Dissolve,
+}
+impl Default for TrMode {
+    fn default() -> Self {
+        TrMode::Transparency
+    }
+}
+
+/// Collects the materials of one or more MTL files.
+pub struct MtlLibrary {
+    pub materials: HashMap<String, Material>,
+    pub tr_mode: TrMode,
+    current: Option<Material>,
+    /// The current material has a `d`, which wins over `Tr` no matter the order.
+    has_dissolve: bool,
+    /// Directory of the file being read, relative map paths are resolved against it.
+    base_dir: Option<PathBuf>,
+}
+impl MtlLibrary {
+    pub fn new() -> Self {
+        MtlLibrary {
+            materials: HashMap::new(),
+            tr_mode: TrMode::default(),
+            current: None,
+            has_dissolve: false,
+            base_dir: None,
+        }
+    }
+    fn finish_current(&mut self) {
+        if let Some(material) = self.current.take() {
+            self.materials.insert(material.name.clone(), material);
+        }
+    }
+    pub fn process_line(&mut self, line: Line) -> Result<(), Error> {
+        if let Line::NewMtl(name) = &line {
+            self.finish_current();
+            self.current = Some(Material::new(name.to_string()));
+            self.has_dissolve = false;
+            return Ok(());
+        }
+        if let Line::Comment(_) = &line {
+            return Ok(());
+        }
+        let material = self.current.as_mut().ok_or(Error::MissingNewMtl)?;
+        match line {
+            Line::Ambient(c) => material.ambient = c,
+            Line::Diffuse(c) => material.diffuse = c,
+            Line::Specular(c) => material.specular = c,
+            Line::Emissive(c) => material.emissive = c,
+            Line::Shininess(x) => material.shininess = x,
+            Line::Dissolve(x) => {
+                material.alpha = x;
+                self.has_dissolve = true;
+            }
+            Line::Transparency(_) if self.has_dissolve => {}
+            Line::Transparency(x) => {
+                material.alpha = match self.tr_mode {
+                    TrMode::Transparency => 1.0 - x,
+                    TrMode::Dissolve => x,
+                }
+            }
+            Line::OpticalDensity(x) => material.optical_density = x,
+            Line::Illumination(x) => material.illumination = x,
+            Line::Roughness(x) => material.roughness = Some(x),
+            Line::Metallic(x) => material.metallic = Some(x),
+            Line::Sheen(x) => material.sheen = Some(x),
+            Line::Anisotropy(x) => material.anisotropy = Some(x),
+            Line::Map(kind, map) => {
+                if let Some(multiplier) = map.bump_multiplier {
+                    material.bump_multiplier = multiplier;
+                }
+                // Every map shares the material's sampler, the diffuse map's matters most
+                if kind == MapKind::Diffuse && map.options.clamp {
+                    let clamp = wgpu::AddressMode::ClampToEdge;
+                    material.sampler = material.sampler.with_address_mode(clamp);
+                }
+                let path = PathBuf::from(map.path.into_owned());
+                let path = Some(TextureRef {
+                    path: match &self.base_dir {
+                        Some(base_dir) if path.is_relative() => base_dir.join(path),
+                        _ => path,
+                    },
+                    options: map.options,
+                    image: None,
+                });
+                match kind {
+                    MapKind::Ambient => material.ambient_map = path,
+                    MapKind::Diffuse => material.diffuse_map = path,
+                    MapKind::Specular => material.specular_map = path,
+                    MapKind::Shininess => material.shininess_map = path,
+                    MapKind::Dissolve => material.dissolve_map = path,
+                    MapKind::Bump => material.bump_map = path,
+                    MapKind::Normal => material.normal_map = path,
+                    MapKind::Roughness => material.roughness_map = path,
+                    MapKind::Metallic => material.metallic_map = path,
+                    MapKind::Emissive => material.emissive_map = path,
+                }
+            }
+            Line::NewMtl(_) | Line::Comment(_) => unreachable!("handled above"),
+        }
+        Ok(())
+    }
+    pub fn build(mut self) -> HashMap<String, Material> {
+        self.finish_current();
+        self.materials
+    }
+    /// Like `build` but with the materials behind `Arc`s, as handed out by `MtlCache`.
+    pub fn build_shared(self) -> SharedMaterials {
+        self.build()
+            .into_iter()
+            .map(|(name, material)| (name, Arc::new(material)))
+            .collect()
+    }
+    /// Parses a library that's already in memory. Map paths are kept as written.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut library = Self::new();
+        library.read_lines(bytes)?;
+        Ok(library)
+    }
+    pub async fn load_file(filename: impl AsRef<std::path::Path>) -> Result<Self, files::Error> {
+        let mut library = Self::new();
+        library.read_file(filename).await?;
+        Ok(library)
+    }
+    pub async fn read_file(
+        &mut self,
+        filename: impl AsRef<std::path::Path>,
+    ) -> Result<(), files::Error> {
+        let filename = filename.as_ref();
+        self.read_file_async(filename)
+            .await
+            .map_err(|e| files::Error::in_file(filename, e))
+    }
+    async fn read_file_async(&mut self, filename: &std::path::Path) -> Result<(), Error> {
+        self.base_dir = filename.parent().map(PathBuf::from);
+        let file = tokio::fs::File::open(filename).await?;
+        let mut lines = tokio::io::BufReader::new(file).lines();
+        let mut number = 0;
+        while let Some(line) = lines.next_line().await? {
+            number += 1;
+            self.read_line(number, &line)?;
+        }
+        self.finish_current();
+        Ok(())
+    }
+    pub fn load_file_sync(filename: impl AsRef<std::path::Path>) -> Result<Self, files::Error> {
+        let mut library = Self::new();
+        library.read_file_sync(filename)?;
+        Ok(library)
+    }
+    pub fn read_file_sync(
+        &mut self,
+        filename: impl AsRef<std::path::Path>,
+    ) -> Result<(), files::Error> {
+        let filename = filename.as_ref();
+        self.base_dir = filename.parent().map(PathBuf::from);
+        std::fs::File::open(filename)
+            .map_err(Error::from)
+            .and_then(|file| self.read_lines(std::io::BufReader::new(file)))
+            .map_err(|e| files::Error::in_file(filename, e))
+    }
+    pub fn read_lines(&mut self, reader: impl BufRead) -> Result<(), Error> {
+        for (index, line) in reader.lines().enumerate() {
+            self.read_line(index + 1, &line?)?;
+        }
+        self.finish_current();
+        Ok(())
+    }
+    fn read_line(&mut self, number: usize, line: &str) -> Result<(), Error> {
+        // MTL files separate materials with blank lines
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        match Line::process_line(line) {
+            // `Tf`, `disp`, `refl` and the like, which nothing uses yet
+            Err(Error::UnrecognizedTag) => {
+                let line = line.trim();
+                log::warn!("line {}: skipping unsupported statement '{}'", number, line);
+                Ok(())
+            }
+            line => line
+                .and_then(|line| self.process_line(line))
+                .map_err(|e| Error::AtLine(number, Box::new(e))),
+        }
+    }
+}
+
+/// `path` joined onto the working directory unless it's already absolute.
+fn absolute_path(path: &Path) -> Option<PathBuf> {
+    if path.is_absolute() {
+        Some(path.to_path_buf())
+    } else {
+        std::env::current_dir().ok().map(|cwd| cwd.join(path))
+    }
+}
+
+/// `path` relative to `dir`, walking up with `..` where needed. Relative paths are taken to be
+/// relative to the working directory, like the loaders do.
+fn relative_path(path: &Path, dir: Option<&Path>) -> PathBuf {
+    let (absolute, dir) = match dir.and_then(absolute_path).zip(absolute_path(path)) {
+        Some((dir, absolute)) => (absolute, dir),
+        None => return path.to_path_buf(),
+    };
+    let mut path_components = absolute.components().peekable();
+    let mut dir_components = dir.components().peekable();
+    // Different prefixes (Windows drives) can't be made relative
+    if path_components.peek() != dir_components.peek() {
+        return path.to_path_buf();
+    }
+    while path_components.peek().is_some() && path_components.peek() == dir_components.peek() {
+        path_components.next();
+        dir_components.next();
+    }
+    dir_components
+        .map(|_| Component::ParentDir)
+        .chain(path_components)
+        .collect()
+}
+
+fn write_color(out: &mut impl Write, tag: &str, [r, g, b]: [f32; 3]) -> std::io::Result<()> {
+    writeln!(out, "{} {} {} {}", tag, r, g, b)
+}
+
+/// Writes the options that differ from the defaults, each followed by a space.
+fn write_map_options(out: &mut impl Write, options: &MapOptions) -> std::io::Result<()> {
+    let default = MapOptions::default();
+    if options.offset != default.offset {
+        let [u, v, w] = options.offset;
+        write!(out, "-o {} {} {} ", u, v, w)?;
+    }
+    if options.scale != default.scale {
+        let [u, v, w] = options.scale;
+        write!(out, "-s {} {} {} ", u, v, w)?;
+    }
+    if options.clamp {
+        write!(out, "-clamp on ")?;
+    }
+    if let Some((base, gain)) = options.mm {
+        write!(out, "-mm {} {} ", base, gain)?;
+    }
+    Ok(())
+}
+
+/// Writes `material` as a `newmtl` block. Values equal to `Material::new`'s defaults are left
+/// out and map paths are written relative to `dir`, the directory the file is written to.
+/// Floats are written with as many digits as needed to read them back exactly.
+pub fn write_material(
+    out: &mut impl Write,
+    material: &Material,
+    dir: Option<&Path>,
+) -> std::io::Result<()> {
+    let default = Material::new(material.name.clone());
+    writeln!(out, "newmtl {}", material.name)?;
+    let colors = [
+        ("Ka", material.ambient, default.ambient),
+        ("Kd", material.diffuse, default.diffuse),
+        ("Ks", material.specular, default.specular),
+        ("Ke", material.emissive, default.emissive),
+    ];
+    for (tag, color, default) in colors {
+        if color != default {
+            write_color(out, tag, color)?;
+        }
+    }
+    let values = [
+        ("Ns", material.shininess, default.shininess),
+        ("d", material.alpha, default.alpha),
+        ("Ni", material.optical_density, default.optical_density),
+    ];
+    for (tag, value, default) in values {
+        if value != default {
+            writeln!(out, "{} {}", tag, value)?;
+        }
+    }
+    if material.illumination != default.illumination {
+        writeln!(out, "illum {}", u32::from(material.illumination))?;
+    }
+    let optional = [
+        ("Pr", material.roughness),
+        ("Pm", material.metallic),
+        ("Ps", material.sheen),
+        ("aniso", material.anisotropy),
+    ];
+    for (tag, value) in optional {
+        if let Some(value) = value {
+            writeln!(out, "{} {}", tag, value)?;
+        }
+    }
+    let maps = [
+        ("map_Ka", &material.ambient_map),
+        ("map_Kd", &material.diffuse_map),
+        ("map_Ks", &material.specular_map),
+        ("map_Ns", &material.shininess_map),
+        ("map_d", &material.dissolve_map),
+        ("map_Bump", &material.bump_map),
+        ("norm", &material.normal_map),
+        ("map_Pr", &material.roughness_map),
+        ("map_Pm", &material.metallic_map),
+        ("map_Ke", &material.emissive_map),
+    ];
+    for (tag, map) in maps {
+        if let Some(map) = map {
+            write!(out, "{} ", tag)?;
+            if tag == "map_Bump" && material.bump_multiplier != default.bump_multiplier {
+                write!(out, "-bm {} ", material.bump_multiplier)?;
+            }
+            write_map_options(out, &map.options)?;
+            writeln!(out, "{}", relative_path(&map.path, dir).display())?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes every material with a blank line between them, see `write_material`.
+pub fn write_mtl<'a>(
+    out: &mut impl Write,
+    materials: impl IntoIterator<Item = &'a Material>,
+    dir: Option<&Path>,
+) -> std::io::Result<()> {
+    for (i, material) in materials.into_iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        write_material(out, material, dir)?;
+    }
+    Ok(())
+}
+
+impl MtlLibrary {
+    /// Writes the materials sorted by name so the output is the same every time.
+    pub fn write(&self, out: &mut impl Write, dir: Option<&Path>) -> std::io::Result<()> {
+        let mut materials: Vec<&Material> = self.materials.values().collect();
+        materials.sort_by(|a, b| a.name.cmp(&b.name));
+        write_mtl(out, materials, dir)
+    }
+    /// Writes the library to `filename` with map paths relative to its directory.
+    pub fn save_sync(&self, filename: impl AsRef<Path>) -> std::io::Result<()> {
+        let filename = filename.as_ref();
+        let mut out = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        self.write(&mut out, filename.parent())?;
+        out.flush()
+    }
+}
+
+/// Materials by name, shared between everything using the same MTL file.
+pub type SharedMaterials = HashMap<String, Arc<Material>>;
+
+/// The key of `path` in `MtlCache`, canonical if it's on disk and as given otherwise.
+fn cache_key(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Parsed MTL files by canonical path, so objects referencing the same library get the same
+/// `Arc<Material>`s. Entries stay until they're invalidated.
+#[derive(Default)]
+pub struct MtlCache {
+    libraries: Mutex<HashMap<PathBuf, Arc<SharedMaterials>>>,
+}
+impl MtlCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn cached(&self, path: &Path) -> Option<Arc<SharedMaterials>> {
+        self.libraries.lock().unwrap().get(path).cloned()
+    }
+    /// Keeps the first library inserted for `path` if two loads raced.
+    fn insert(&self, path: PathBuf, materials: SharedMaterials) -> Arc<SharedMaterials> {
+        let mut libraries = self.libraries.lock().unwrap();
+        libraries.entry(path).or_insert_with(|| Arc::new(materials)).clone()
+    }
+    /// The materials of `filename`, parsing it unless it's cached.
+    pub async fn load(
+        &self,
+        filename: impl AsRef<Path>,
+    ) -> Result<Arc<SharedMaterials>, files::Error> {
+        let filename = filename.as_ref();
+        let path = tokio::fs::canonicalize(filename)
+            .await
+            .map_err(|e| files::Error::in_file(filename, e))?;
+        if let Some(materials) = self.cached(&path) {
+            return Ok(materials);
+        }
+        let materials = MtlLibrary::load_file(&path).await?.build_shared();
+        Ok(self.insert(path, materials))
+    }
+    pub fn load_sync(
+        &self,
+        filename: impl AsRef<Path>,
+    ) -> Result<Arc<SharedMaterials>, files::Error> {
+        let filename = filename.as_ref();
+        let path =
+            std::fs::canonicalize(filename).map_err(|e| files::Error::in_file(filename, e))?;
+        if let Some(materials) = self.cached(&path) {
+            return Ok(materials);
+        }
+        let materials = MtlLibrary::load_file_sync(&path)?.build_shared();
+        Ok(self.insert(path, materials))
+    }
+    /// The materials of `filename` read through `source`, e.g. embedded files or an
+    /// `AssetResolver`. Map paths are relative to the library's directory as `source` sees it.
+    /// Paths that aren't on disk are keyed as given since the source may not be the filesystem.
+    pub fn load_from(
+        &self,
+        source: &dyn AssetSource,
+        filename: impl AsRef<Path>,
+    ) -> Result<Arc<SharedMaterials>, files::Error> {
+        let filename = filename.as_ref();
+        let key = cache_key(filename);
+        if let Some(materials) = self.cached(&key) {
+            return Ok(materials);
+        }
+        let bytes = source.read(filename).map_err(|e| files::Error::in_file(filename, e))?;
+        let mut library = MtlLibrary::new();
+        library.base_dir = filename.parent().map(PathBuf::from);
+        library.read_lines(&bytes[..]).map_err(|e| files::Error::in_file(filename, e))?;
+        Ok(self.insert(key, library.build_shared()))
+    }
+    /// `load_for_sync` through `source`.
+    pub fn load_for_from(
+        &self,
+        source: &dyn AssetSource,
+        object: &Object,
+    ) -> Result<SharedMaterials, files::Error> {
+        let mut materials = SharedMaterials::new();
+        for library in object.material_libraries() {
+            materials.extend(self.load_from(source, library)?.as_ref().clone());
+        }
+        Ok(materials)
+    }
+    /// Every material of the object's `mtllib`s. Later libraries win when names clash.
+    pub async fn load_for(&self, object: &Object) -> Result<SharedMaterials, files::Error> {
+        let mut materials = SharedMaterials::new();
+        for library in object.material_libraries() {
+            materials.extend(self.load(library).await?.as_ref().clone());
+        }
+        Ok(materials)
+    }
+    pub fn load_for_sync(&self, object: &Object) -> Result<SharedMaterials, files::Error> {
+        let mut materials = SharedMaterials::new();
+        for library in object.material_libraries() {
+            materials.extend(self.load_sync(library)?.as_ref().clone());
+        }
+        Ok(materials)
+    }
+    /// Drops the cached library so the next load parses it again. Materials already handed out
+    /// stay alive. Returns whether it was cached.
+    pub fn invalidate(&self, filename: impl AsRef<Path>) -> bool {
+        let key = cache_key(filename.as_ref());
+        self.libraries.lock().unwrap().remove(&key).is_some()
+    }
+    pub fn clear(&self) {
+        self.libraries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::model::files::source::{EmbeddedFiles, FileSystem};
+    use crate::texture::SamplerConfig;
+
+    fn read(source: &str, base_dir: &str) -> MtlLibrary {
+        let mut library = MtlLibrary::new();
+        library.base_dir = Some(PathBuf::from(base_dir));
+        library.read_lines(source.as_bytes()).unwrap();
+        library
+    }
+
+    const FIXTURE: &str = "\
+# Three materials
+newmtl Brick
+Ka 0.2 0.2 0.2
+Kd 0.6 0.3 0.2
+Ks 0.1 0.1 0.1
+Ns 12
+illum 2
+map_Kd brick.png
+bump -bm 0.5 brick_h.png
+
+newmtl Glass
+Kd 0.9
+Ks 1 1 1
+Ns 250
+d 0.25
+Ni 1.5
+illum 1
+
+newmtl Light
+Ke 4 4 3
+illum 0
+Pr 0.4
+Pm 0
+map_Ke -s 2 2 glow.png
+";
+
+    #[test]
+    fn fixture_parses_into_the_expected_materials() {
+        let materials = read(FIXTURE, "models").build();
+        let brick = Material {
+            ambient: [0.2; 3],
+            diffuse: [0.6, 0.3, 0.2],
+            specular: [0.1; 3],
+            shininess: 12.0,
+            illumination: IlluminationModel::Specular,
+            diffuse_map: Some(TextureRef::new("models/brick.png")),
+            bump_map: Some(TextureRef::new("models/brick_h.png")),
+            bump_multiplier: 0.5,
+            ..Material::new("Brick")
+        };
+        let glass = Material {
+            diffuse: [0.9; 3],
+            specular: [1.0; 3],
+            shininess: 250.0,
+            alpha: 0.25,
+            optical_density: 1.5,
+            illumination: IlluminationModel::Diffuse,
+            ..Material::new("Glass")
+        };
+        let light = Material {
+            emissive: [4.0, 4.0, 3.0],
+            illumination: IlluminationModel::ColorOnly,
+            roughness: Some(0.4),
+            metallic: Some(0.0),
+            emissive_map: Some(TextureRef {
+                options: MapOptions {
+                    scale: [2.0, 2.0, 1.0],
+                    ..MapOptions::default()
+                },
+                ..TextureRef::new("models/glow.png")
+            }),
+            ..Material::new("Light")
+        };
+        let expected: HashMap<String, Material> = [brick, glass, light]
+            .into_iter()
+            .map(|material| (material.name.clone(), material))
+            .collect();
+        assert_eq!(materials, expected);
+    }
+
+    #[test]
+    fn unsupported_statements_are_skipped() {
+        assert!(matches!(
+            Line::process_line("Tf 1 1 1"),
+            Err(Error::UnrecognizedTag)
+        ));
+        let source = "newmtl Mirror\nKd 1 1 1\nTf 1 1 1\nPc 0.5\nPcr 0.1\n\
+                      disp height.png\ndecal stamp.png\nrefl -type sphere env.png\nNs 500\n";
+        let materials = read(source, "").build();
+        let expected = Material {
+            diffuse: [1.0; 3],
+            shininess: 500.0,
+            ..Material::new("Mirror")
+        };
+        assert_eq!(materials.len(), 1);
+        assert_eq!(materials["Mirror"], expected);
+        // Known statements that don't parse are still errors
+        let mut library = MtlLibrary::new();
+        let error = library.read_lines("newmtl Broken\nKd red\n".as_bytes());
+        assert!(matches!(error, Err(Error::AtLine(2, _))));
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(Line::process_line("#").unwrap(), Line::Comment(Cow::Borrowed("")));
+        let expected = Line::Comment(Cow::Borrowed("Blender MTL"));
+        assert_eq!(Line::process_line("#Blender MTL").unwrap(), expected);
+        assert_eq!(Line::process_line("# Blender MTL").unwrap(), expected);
+        let library = read("#\nnewmtl Red\n#\nKd 1 0 0\n", "");
+        assert_eq!(library.materials["Red"].diffuse, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn clamped_diffuse_maps_clamp_the_sampler() {
+        let library = read(
+            "newmtl Decal\nmap_Kd -clamp on decal.png\n\
+             newmtl Tiled\nmap_Kd tiles.png\nmap_Bump -clamp on bump.png\n",
+            "",
+        );
+        let clamp = wgpu::AddressMode::ClampToEdge;
+        let decal = &library.materials["Decal"].sampler;
+        assert_eq!((decal.address_mode_u, decal.address_mode_v), (clamp, clamp));
+        // Only the diffuse map decides
+        assert_eq!(library.materials["Tiled"].sampler, SamplerConfig::default());
+    }
+
+    #[test]
+    fn tab_separated() {
+        assert_eq!(Line::process_line("Kd\t1 1 1").unwrap(), Line::Diffuse([1.0; 3]));
+        assert_eq!(Line::process_line("Ns\t\t10").unwrap(), Line::Shininess(10.0));
+        let library = read("newmtl\tBlue\nKd\t0 0 1\n", "");
+        assert_eq!(library.materials["Blue"].diffuse, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn map_options() {
+        let map = TextureMap::parse("-s 2 3 -o 0.5 -mm 0.1 0.8 -clamp on -bm 0.25 my tex.png");
+        let map = map.unwrap();
+        assert_eq!(map.path, "my tex.png");
+        assert_eq!(map.bump_multiplier, Some(0.25));
+        let expected = MapOptions {
+            offset: [0.5, 0.0, 0.0],
+            scale: [2.0, 3.0, 1.0],
+            clamp: true,
+            mm: Some((0.1, 0.8)),
+        };
+        assert_eq!(map.options, expected);
+        let transform = [[2.0, 0.0, 0.5], [0.0, 3.0, 0.0]];
+        assert_eq!(map.options.uv_transform(), transform);
+        // A number is only an argument if a file name still follows it
+        let map = TextureMap::parse("-s 2 2 2.png").unwrap();
+        assert_eq!(map.path, "2.png");
+        assert_eq!(map.options.scale, [2.0, 2.0, 1.0]);
+        let short = TextureMap::parse("-s 2");
+        assert!(matches!(short, Err(Error::MissingNumber)));
+        let short = TextureMap::parse("-mm 1 wood.png");
+        assert!(matches!(short, Err(Error::MissingNumber)));
+        let no_path = TextureMap::parse("-clamp on");
+        assert!(matches!(no_path, Err(Error::MissingPath)));
+    }
+
+    #[test]
+    fn unknown_map_options_are_skipped_with_their_arguments() {
+        let map = TextureMap::parse("-blendu off -t 1 1 1 -imfchan r -o 1 2 3 -cc wood.png");
+        let map = map.unwrap();
+        assert_eq!(map.path, "wood.png");
+        assert_eq!(map.options.offset, [1.0, 2.0, 3.0]);
+        assert_eq!(map.options.scale, [1.0; 3]);
+    }
+
+    #[test]
+    fn illumination_models() {
+        let other = Line::Illumination(IlluminationModel::Other(300));
+        assert_eq!(Line::process_line("illum 300").unwrap(), other);
+        let source = "newmtl A\nillum 0\nnewmtl B\nillum 1\nnewmtl C\nillum 10\n";
+        let library = read(source, "");
+        let flags = |name: &str| library.materials[name].illumination_flags();
+        assert_eq!(flags("A"), Material::UNLIT);
+        assert_eq!(flags("B"), Material::NO_SPECULAR);
+        // Unsupported models are lit like `illum 2`
+        let unsupported = library.materials["C"].illumination;
+        assert_eq!(unsupported, IlluminationModel::Other(10));
+        assert_eq!(flags("C"), 0);
+    }
+
+    #[test]
+    fn cache_shares_libraries_until_invalidated() {
+        let source = EmbeddedFiles::new([("lib.mtl", &b"newmtl Red\nKd 1 0 0\n"[..])]);
+        let cache = MtlCache::new();
+        let first = cache.load_from(&source, "lib.mtl").unwrap();
+        let second = cache.load_from(&source, "lib.mtl").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(Arc::ptr_eq(&first["Red"], &second["Red"]));
+        assert!(cache.invalidate("lib.mtl"));
+        assert!(!cache.invalidate("lib.mtl"));
+        let reloaded = cache.load_from(&source, "lib.mtl").unwrap();
+        assert!(!Arc::ptr_eq(&first, &reloaded));
+    }
+
+    #[test]
+    fn objects_sharing_a_library_share_its_materials() {
+        use crate::entity::model::files::obj::ObjectBuilder;
+        let obj = b"mtllib shared.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl Red\nf 1 2 3\n";
+        let mtl = b"newmtl Red\nKd 1 0 0\nnewmtl Blue\nKd 0 0 1\n";
+        let source = EmbeddedFiles::new([
+            ("models/a.obj", &obj[..]),
+            ("models/b.obj", &obj[..]),
+            ("models/shared.mtl", &mtl[..]),
+        ]);
+        let cache = MtlCache::new();
+        let load = |filename| {
+            let object = ObjectBuilder::load_from(&source, filename).unwrap().build();
+            cache.load_for_from(&source, &object).unwrap()
+        };
+        let (a, b) = (load("models/a.obj"), load("models/b.obj"));
+        assert_eq!(a.len(), 2);
+        for name in ["Red", "Blue"] {
+            assert!(Arc::ptr_eq(&a[name], &b[name]), "{}", name);
+        }
+        assert_eq!(a["Red"].diffuse, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn cache_keys_files_by_canonical_path() {
+        let dir = std::env::temp_dir().join(format!("soyuz-mtl-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let filename = dir.join("lib.mtl");
+        std::fs::write(&filename, "newmtl Red\nKd 1 0 0\n").unwrap();
+        let cache = MtlCache::new();
+        let first = cache.load_from(&FileSystem, &filename).unwrap();
+        let second = cache.load_sync(dir.join(".").join("lib.mtl")).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(cache.invalidate(dir.join("..").join(dir.file_name().unwrap()).join("lib.mtl")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bump_map_with_multiplier() {
+        let map = TextureMap::parse("-bm 0.3 brick_n.png").unwrap();
+        assert_eq!(map.path, "brick_n.png");
+        assert_eq!(map.bump_multiplier, Some(0.3));
+        let library = read("newmtl Brick\nmap_bump -bm 0.3 brick_n.png\n", "");
+        let brick = &library.materials["Brick"];
+        assert_eq!(brick.bump_map.as_ref().unwrap().path, Path::new("brick_n.png"));
+        assert_eq!(brick.bump_multiplier, 0.3);
+        assert_eq!(brick.map_flags(), Material::BUMP_MAP | Material::BUMP_AS_HEIGHT);
+    }
+
+    #[test]
+    fn bump_and_normal_maps() {
+        let source = "newmtl Brick\nbump -s 2 2 brick h.png\nnorm brick_n.png\n";
+        let brick = &read(source, "textures").materials["Brick"];
+        let bump = brick.bump_map.as_ref().unwrap();
+        assert_eq!(bump.path, Path::new("textures/brick h.png"));
+        assert_eq!(bump.options.scale, [2.0, 2.0, 1.0]);
+        assert_eq!(brick.bump_multiplier, 1.0);
+        let normal = brick.normal_map.as_ref().unwrap();
+        assert_eq!(normal.path, Path::new("textures/brick_n.png"));
+        assert_eq!(brick.map_flags(), Material::BUMP_MAP | Material::NORMAL_MAP);
+        assert!(TextureMap::parse("-bm brick_n.png").is_err());
+    }
+
+    #[test]
+    fn relative_path_of_relative_paths() {
+        let path = Path::new("assets/textures/wood.png");
+        let expected = Path::new("textures/wood.png");
+        assert_eq!(relative_path(path, Some(Path::new("assets"))), expected);
+        let expected = Path::new("../assets/textures/wood.png");
+        assert_eq!(relative_path(path, Some(Path::new("models"))), expected);
+        assert_eq!(relative_path(path, None), path);
+    }
+
+    #[test]
+    fn written_libraries_parse_back_equal() {
+        let map = |path: &str, options| TextureRef {
+            options,
+            ..TextureRef::new(Path::new("out/textures").join(path))
+        };
+        let tiled = MapOptions {
+            offset: [0.5, 0.25, 0.0],
+            scale: [2.0, 3.0, 1.0],
+            clamp: true,
+            mm: Some((0.1, 0.9)),
+        };
+        let painted = Material {
+            ambient: [0.1, 0.2, 0.3],
+            diffuse: [0.1234567, 0.5, 1.0 / 3.0],
+            specular: [0.9; 3],
+            emissive: [0.0, 0.1, 0.0],
+            shininess: 96.07843,
+            alpha: 0.7,
+            optical_density: 1.45,
+            illumination: IlluminationModel::Diffuse,
+            diffuse_map: Some(map("paint.png", tiled)),
+            specular_map: Some(map("paint_s.png", MapOptions::default())),
+            bump_map: Some(map("paint h.png", MapOptions::default())),
+            bump_multiplier: 0.75,
+            // What `-clamp on` on the diffuse map reads back as
+            sampler: SamplerConfig::default().with_address_mode(wgpu::AddressMode::ClampToEdge),
+            ..Material::new("Painted")
+        };
+        let metal = Material {
+            illumination: IlluminationModel::ColorOnly,
+            roughness: Some(0.35),
+            metallic: Some(1.0),
+            sheen: Some(0.2),
+            anisotropy: Some(0.5),
+            ambient_map: Some(map("metal_a.png", MapOptions::default())),
+            shininess_map: Some(map("metal_ns.png", MapOptions::default())),
+            dissolve_map: Some(map("metal_d.png", MapOptions::default())),
+            normal_map: Some(map("metal_n.png", MapOptions::default())),
+            roughness_map: Some(map("metal_r.png", MapOptions::default())),
+            metallic_map: Some(map("metal_m.png", MapOptions::default())),
+            emissive_map: Some(map("metal_e.png", MapOptions::default())),
+            ..Material::new("Metal")
+        };
+        let mut library = MtlLibrary::new();
+        for material in [painted, metal, Material::new("Plain")] {
+            library.materials.insert(material.name.clone(), material);
+        }
+
+        let mut saved = Vec::new();
+        library.write(&mut saved, Some(Path::new("out"))).unwrap();
+        let text = std::str::from_utf8(&saved).unwrap();
+        assert!(text.contains("map_Kd -o 0.5 0.25 0 -s 2 3 1 -clamp on -mm 0.1 0.9 "));
+        // Defaults are left out
+        assert!(text.ends_with("\n\nnewmtl Plain\n"), "{}", text);
+        let reloaded = read(text, "out");
+        assert_eq!(reloaded.materials, library.materials, "{}", text);
+    }
+
+    #[test]
+    fn relative_map_survives_save_and_parse() {
+        let library = read("newmtl Wood\nmap_Kd wood.png\n", "assets");
+        let mut saved = Vec::new();
+        library.write(&mut saved, Some(Path::new("assets"))).unwrap();
+        assert!(String::from_utf8_lossy(&saved).contains("map_Kd wood.png"));
+        let reloaded = read(std::str::from_utf8(&saved).unwrap(), "assets");
+        let map = reloaded.materials["Wood"].diffuse_map.as_ref().unwrap();
+        assert_eq!(map.path, Path::new("assets/wood.png"));
+    }
+}
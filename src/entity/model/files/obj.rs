@@ -1,11 +1,30 @@
 use crate::entity::model;
+use crate::entity::model::files;
+use crate::entity::model::files::source::AssetSource;
+use cgmath::{InnerSpace, Vector3, Zero};
 use crate::entity::model::files::obj::Error::MissingTag;
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::num::{NonZeroU32, ParseFloatError, ParseIntError};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::pin::Pin;
 use std::str::FromStr;
+use std::task::{Context, Poll};
+use std::io::{BufRead, Read, Write};
 use tokio::io::AsyncBufReadExt;
 
+/// `line` without the `\r` a Windows line ending leaves when lines are split on `\n`, which
+/// would otherwise end up in the last number and fail to parse.
+pub fn strip_cr(line: &str) -> &str {
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// Color of vertices without one in a file where others have one.
+const WHITE: [f32; 4] = [1.0; 4];
+
 #[derive(Debug)]
 pub enum Error {
     IO(std::io::Error),
@@ -17,6 +36,10 @@ pub enum Error {
     MissingNormal,
     MissingTextureCoord,
     InvalidIndex,
+    /// The file is compressed but support for its compression isn't enabled.
+    UnsupportedCompression,
+    /// `1` based line number of the line that caused the error.
+    AtLine(usize, Box<Error>),
 }
 impl From<ParseIntError> for Error {
     fn from(e: ParseIntError) -> Self {
@@ -48,11 +71,15 @@ pub struct VertexIndices {
     pub normal: Option<NonZeroU32>,
 }
 impl FromStr for VertexIndices {
-    type Err = std::num::ParseIntError;
+    type Err = Error;
 
     fn from_str(indices_str: &str) -> Result<Self, Self::Err> {
         let mut indices = [0; 3];
         for (index, s) in indices_str.split('/').enumerate() {
+            // `v/vt/vn` is the most there can be
+            if index >= indices.len() {
+                return Err(Error::InvalidIndex);
+            }
             if !s.is_empty() {
                 indices[index] = s.parse()?;
             }
@@ -70,6 +97,8 @@ pub struct Vertex {
     pub y: f32,
     pub z: f32,
     pub w: f32,
+    /// From `v x y z r g b`, an extension written by e.g. MeshLab. Always `None` for normals.
+    pub color: Option<[f32; 3]>,
 }
 impl FromStr for Vertex {
     type Err = Error;
@@ -79,8 +108,13 @@ impl FromStr for Vertex {
         let x = nums.next().ok_or(Error::MissingNumber)?.parse()?;
         let y = nums.next().ok_or(Error::MissingNumber)?.parse()?;
         let z = nums.next().ok_or(Error::MissingNumber)?.parse()?;
-        let w = nums.next().map(|w| w.parse()).transpose()?.unwrap_or(1f32);
-        Ok(Vertex { x, y, z, w })
+        let fourth = nums.next().map(|w| w.parse()).transpose()?;
+        // Six numbers are a position and a color, there's no `w` then
+        let (w, color) = match (fourth, nums.next(), nums.next()) {
+            (Some(r), Some(g), Some(b)) => (1f32, Some([r, g.parse()?, b.parse()?])),
+            _ => (fourth.unwrap_or(1f32), None),
+        };
+        Ok(Vertex { x, y, z, w, color })
     }
 }
 #[derive(Copy, Clone, PartialOrd, PartialEq, Debug, Default)]
@@ -135,9 +169,19 @@ impl<'a> Line<'a> {
         }
     }
     pub fn process_line(line: &'a str) -> Result<Self, Error> {
-        let (tag, rest) = line.split_once(' ').ok_or(MissingTag)?;
+        // Strip a Windows line ending's CR and any other trailing whitespace so names with
+        // internal spaces are kept whole and `usemtl Red\r` and `usemtl Red` are the same.
+        let line = strip_cr(line).trim_end();
+        if line.is_empty() {
+            return Err(MissingTag);
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            return Ok(Line::Comment(Cow::Borrowed(comment.trim_start())));
+        }
+        // A tag on its own has nothing after it, which is an error for every tag with numbers
+        let (tag, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim_start();
         match tag {
-            "#" => Ok(Line::Comment(Cow::Borrowed(rest))),
             "o" => Ok(Line::Name(Cow::Borrowed(rest))),
             "usemtl" => Ok(Line::UseMtl(Cow::Borrowed(rest))),
             "mtllib" => Ok(Line::MtlLib(Cow::Borrowed(rest))),
@@ -169,6 +213,99 @@ impl<'a> Line<'a> {
     }
 }
 
+/// How far through a file the reader is. For compressed files this counts compressed bytes
+/// since the decompressed size isn't known up front.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct Progress {
+    pub bytes_read: u64,
+    pub total_bytes: u64,
+}
+struct ProgressReader<R, F> {
+    inner: R,
+    progress: Progress,
+    callback: F,
+}
+impl<R: Read, F: FnMut(Progress)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.progress.bytes_read += read as u64;
+        (self.callback)(self.progress);
+        Ok(read)
+    }
+}
+impl<R, F> tokio::io::AsyncRead for ProgressReader<R, F>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    F: FnMut(Progress) + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = tokio::io::AsyncRead::poll_read(Pin::new(&mut this.inner), cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            this.progress.bytes_read += (buf.filled().len() - before) as u64;
+            (this.callback)(this.progress);
+        }
+        result
+    }
+}
+fn is_gzip(filename: &std::path::Path, start: &[u8]) -> bool {
+    filename.extension().map_or(false, |ext| ext == "gz") || start.starts_with(&GZIP_MAGIC)
+}
+
+/// Which repeated faces the builder drops.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum DuplicateFaces {
+    Keep,
+    /// Drop faces using the same vertices in the same winding as an earlier face. Always safe.
+    DropSameWinding,
+    /// Also drop faces that are an earlier face reversed. Double sided sheets are sometimes
+    /// modeled like this on purpose.
+    DropAnyWinding,
+}
+impl Default for DuplicateFaces {
+    fn default() -> Self {
+        DuplicateFaces::Keep
+    }
+}
+/// Rotates the triangle so its smallest index comes first, which keeps the winding.
+fn canonical_winding([a, b, c]: [u32; 3]) -> [u32; 3] {
+    if a <= b && a <= c {
+        [a, b, c]
+    } else if b <= a && b <= c {
+        [b, c, a]
+    } else {
+        [c, a, b]
+    }
+}
+
+/// A contiguous range of `mesh_indices` sharing the same group and material.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Hash)]
+pub struct SubMesh {
+    pub group: Option<String>,
+    pub material: Option<String>,
+    pub indices: Range<u32>,
+}
+
+/// The bits of a vertex and its color, so vertices can be hashed. `-0.0` is stored as `0.0`
+/// since they compare equal.
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+struct VertexKey([u32; 12]);
+impl VertexKey {
+    fn new(v: &model::Vertex, color: [f32; 4]) -> Self {
+        let mut bits = [0; 12];
+        let floats = v.position.iter().chain(&v.normal).chain(&v.texture_coords).chain(&color);
+        for (bits, x) in bits.iter_mut().zip(floats) {
+            *bits = (x + 0.0).to_bits();
+        }
+        VertexKey(bits)
+    }
+}
+
 pub struct ObjectBuilder {
     pub vertices: Vec<Vertex>,
     pub normals: Vec<Vertex>,
@@ -176,18 +313,113 @@ pub struct ObjectBuilder {
     pub indices: Vec<VertexIndices>,
 
     pub mesh_vertices: Vec<model::Vertex>,
+    /// RGBA parallel to `mesh_vertices`, empty until a face uses a vertex with a color.
+    pub mesh_colors: Vec<[f32; 4]>,
     pub mesh_indices: Vec<u32>,
+
+    pub name: Option<String>,
+    pub submeshes: Vec<SubMesh>,
+    /// `mtllib` files, relative paths are resolved against the OBJ file's directory.
+    pub material_libraries: Vec<PathBuf>,
+
+    /// Fill in normals for vertices that the file didn't give one when building.
+    pub generate_normals: bool,
+    /// Generate tangents from the texture coordinates when building, for normal mapping.
+    pub generate_tangents: bool,
+    /// Reorder triangles and vertices for the GPU's vertex caches when building, see
+    /// `optimize::optimize_indices`. Off by default since it changes the order of the faces.
+    pub optimize_vertex_cache: bool,
+    pub duplicate_faces: DuplicateFaces,
+
+    seen_faces: HashSet<[u32; 3]>,
+    /// Index in `mesh_vertices` of each vertex and color added so far.
+    vertex_lookup: HashMap<VertexKey, u32>,
+    removed_faces: usize,
+    /// Directory of the file being read.
+    base_dir: Option<PathBuf>,
 }
 impl ObjectBuilder {
     pub fn new() -> Self {
+        Self::with_capacity(0, 0)
+    }
+    /// Preallocates for about `positions` vertices and `faces` triangles.
+    pub fn with_capacity(positions: usize, faces: usize) -> Self {
         ObjectBuilder {
-            vertices: vec![],
-            normals: vec![],
-            texture_coords: vec![],
+            vertices: Vec::with_capacity(positions),
+            normals: Vec::with_capacity(positions),
+            texture_coords: Vec::with_capacity(positions),
             indices: vec![],
-            mesh_vertices: vec![],
-            mesh_indices: vec![],
+            mesh_vertices: Vec::with_capacity(positions),
+            mesh_colors: Vec::new(),
+            mesh_indices: Vec::with_capacity(faces * 3),
+            name: None,
+            submeshes: vec![],
+            material_libraries: vec![],
+            generate_normals: false,
+            generate_tangents: false,
+            optimize_vertex_cache: false,
+            duplicate_faces: DuplicateFaces::default(),
+            seen_faces: HashSet::new(),
+            vertex_lookup: HashMap::with_capacity(positions),
+            removed_faces: 0,
+            base_dir: None,
+        }
+    }
+    /// Guesses the capacities from the size of an OBJ file. A typical mesh has about as many
+    /// `v`, `vt` and `vn` lines as positions and twice as many `f` lines, at ~32 bytes a line.
+    pub fn with_file_size(bytes: u64) -> Self {
+        let lines = (bytes / 32) as usize;
+        Self::with_capacity(lines / 5, lines * 2 / 5)
+    }
+    /// Resets the builder for the next file while keeping its allocations. `generate_normals`,
+    /// `generate_tangents`, `optimize_vertex_cache` and `duplicate_faces` are settings and are
+    /// kept as well.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.normals.clear();
+        self.texture_coords.clear();
+        self.indices.clear();
+        self.mesh_vertices.clear();
+        self.mesh_colors.clear();
+        self.vertex_lookup.clear();
+        self.mesh_indices.clear();
+        self.name = None;
+        self.submeshes.clear();
+        self.material_libraries.clear();
+        self.seen_faces.clear();
+        self.removed_faces = 0;
+        self.base_dir = None;
+    }
+    fn current_submesh(&self) -> Option<&SubMesh> {
+        self.submeshes.last()
+    }
+    /// Starts a new submesh if `group` or `material` differ from the current one. Names are
+    /// compared as whole strings so `Shiny Red` and `Shiny Blue` are different materials.
+    fn split_submesh(&mut self, group: Option<&str>, material: Option<&str>) {
+        let start = self.mesh_indices.len() as u32;
+        if let Some(current) = self.submeshes.last_mut() {
+            if current.group.as_deref() == group && current.material.as_deref() == material {
+                return;
+            }
+            if current.indices.is_empty() {
+                current.group = group.map(String::from);
+                current.material = material.map(String::from);
+                return;
+            }
         }
+        self.submeshes.push(SubMesh {
+            group: group.map(String::from),
+            material: material.map(String::from),
+            indices: start..start,
+        })
+    }
+    pub fn set_group(&mut self, group: &str) {
+        let material = self.current_submesh().and_then(|s| s.material.clone());
+        self.split_submesh(Some(group), material.as_deref())
+    }
+    pub fn set_material(&mut self, material: &str) {
+        let group = self.current_submesh().and_then(|s| s.group.clone());
+        self.split_submesh(group.as_deref(), Some(material))
     }
     pub fn handle_face(
         &mut self,
@@ -204,41 +436,90 @@ impl ObjectBuilder {
         {
             return Err(Error::MissingNormal);
         }
+        let [c1, c2, c3] = [v1, v2, v3].map(|v| self.vertex_color(v));
         let v1 = self.get_vertex(v1).ok_or(Error::InvalidIndex)?;
         let v2 = self.get_vertex(v2).ok_or(Error::InvalidIndex)?;
         let v3 = self.get_vertex(v3).ok_or(Error::InvalidIndex)?;
 
-        let v1_i = self.add_vertex(v1);
-        let v2_i = self.add_vertex(v2);
-        let v3_i = self.add_vertex(v3);
+        let v1_i = self.add_colored_vertex(v1, c1);
+        let v2_i = self.add_colored_vertex(v2, c2);
+        let v3_i = self.add_colored_vertex(v3, c3);
 
+        if self.is_duplicate_face([v1_i, v2_i, v3_i]) {
+            self.removed_faces += 1;
+            return Ok(());
+        }
+        // Faces before any `g` or `usemtl` go in a submesh without either, opened before the
+        // indices are added so it starts at the first of them
+        if self.submeshes.is_empty() {
+            self.split_submesh(None, None);
+        }
         self.mesh_indices.push(v1_i);
         self.mesh_indices.push(v2_i);
         self.mesh_indices.push(v3_i);
+        let end = self.mesh_indices.len() as u32;
+        if let Some(current) = self.submeshes.last_mut() {
+            current.indices.end = end;
+        }
         Ok(())
     }
+    /// Checks `face` against the faces seen so far according to `duplicate_faces`, remembering
+    /// it if it's new.
+    fn is_duplicate_face(&mut self, [a, b, c]: [u32; 3]) -> bool {
+        let drop_reversed = match self.duplicate_faces {
+            DuplicateFaces::Keep => return false,
+            DuplicateFaces::DropSameWinding => false,
+            DuplicateFaces::DropAnyWinding => true,
+        };
+        let face = canonical_winding([a, b, c]);
+        if self.seen_faces.contains(&face)
+            || (drop_reversed && self.seen_faces.contains(&canonical_winding([a, c, b])))
+        {
+            return true;
+        }
+        self.seen_faces.insert(face);
+        false
+    }
+    /// Returns the index of `v` in `mesh_vertices`, appending it if it's new. Vertices are
+    /// numbered in order of first use so the output is deterministic. Lookups are hashed, so
+    /// vertices pushed to `mesh_vertices` directly aren't found.
     pub fn add_vertex(&mut self, v: model::Vertex) -> u32 {
-        let existing_pos = self.mesh_vertices.iter().position(|vi| vi == &v);
-        let pos = match existing_pos {
-            Some(pos) => pos,
-            None => {
-                let pos = self.mesh_vertices.len();
-                self.mesh_vertices.push(v);
-                pos
+        self.add_colored_vertex(v, None)
+    }
+    /// Like `add_vertex`, vertices only match if their colors do as well. Vertices without a
+    /// color are white once any vertex has one.
+    pub fn add_colored_vertex(&mut self, v: model::Vertex, color: Option<[f32; 4]>) -> u32 {
+        if color.is_some() && self.mesh_colors.len() < self.mesh_vertices.len() {
+            self.mesh_colors.resize(self.mesh_vertices.len(), WHITE);
+        }
+        // Uncolored vertices are white, so they keep matching after the first color
+        let color = color.unwrap_or(WHITE);
+        let next = self.mesh_vertices.len() as u32;
+        let pos = *self.vertex_lookup.entry(VertexKey::new(&v, color)).or_insert(next);
+        if pos == next {
+            self.mesh_vertices.push(v);
+            if !self.mesh_colors.is_empty() {
+                self.mesh_colors.push(color);
             }
-        };
-        pos as u32
+        }
+        pos
+    }
+    /// The color of the `v` line `v` refers to, if it has one.
+    fn vertex_color(&self, v: VertexIndices) -> Option<[f32; 4]> {
+        let [r, g, b] = self.vertices.get(v.position.checked_sub(1)? as usize)?.color?;
+        Some([r, g, b, 1.0])
     }
+    /// The vertex `v` refers to, `None` if an index is out of range. OBJ indices start at 1.
     pub fn get_vertex(&self, v: VertexIndices) -> Option<model::Vertex> {
         let default_vertex = Vertex::default();
         let default_tc = TextureCoords::default();
-        let vertex: &Vertex = self.vertices.get(v.position as usize)?;
+        let vertex: &Vertex = self.vertices.get(v.position.checked_sub(1)? as usize)?;
         let normal: &Vertex = match v.normal {
-            Some(ni) => self.normals.get(ni.get() as usize)?,
+            Some(ni) => self.normals.get(ni.get() as usize - 1)?,
             None => &default_vertex,
         };
         let texture_coords: &TextureCoords = match v.texture_coords {
-            Some(ti) => self.texture_coords.get(ti.get() as usize)?,
+            Some(ti) => self.texture_coords.get(ti.get() as usize - 1)?,
             None => &default_tc,
         };
         Some(model::Vertex {
@@ -254,31 +535,709 @@ impl ObjectBuilder {
             Line::TextureCoords(tc) => self.texture_coords.push(tc),
             Line::Face(v1, v2, v3) => self.handle_face(v1, v2, v3)?,
 
-            Line::Point(_) => todo!("handle obj point"),
-            Line::Line(_, _) => todo!("handle obj line"),
-            Line::SmoothingGroup(_) => todo!("handle obj smoothing group"),
-            Line::Group(_) => todo!("handle obj group"),
-            Line::UseMtl(_) => todo!("handle obj usemtl"),
-            Line::MtlLib(_) => todo!("handle obj mtllib"),
-            Line::Name(_) => todo!("handle obj name"),
-            Line::Comment(_) => todo!("handle obj comment"),
+            // Only triangles are drawn, and normals come from the file or `generate_normals`
+            Line::Point(_) | Line::Line(_, _) | Line::SmoothingGroup(_) => {}
+            Line::Group(group) => self.set_group(&group),
+            Line::UseMtl(material) => self.set_material(&material),
+            Line::MtlLib(library) => {
+                let path = PathBuf::from(library.into_owned());
+                self.material_libraries.push(match &self.base_dir {
+                    Some(base_dir) if path.is_relative() => base_dir.join(path),
+                    _ => path,
+                });
+            }
+            Line::Name(name) => self.name = Some(name.into_owned()),
+            Line::Comment(_) => {}
         }
         Ok(())
     }
-    pub async fn load_file(filename: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+    /// Drops `mesh_vertices` that no face refers to and rewrites `mesh_indices` to match. The
+    /// remaining vertices are kept in order of first use. Returns how many were removed.
+    pub fn compact(&mut self) -> usize {
+        const UNUSED: u32 = u32::MAX;
+        let mut remap = vec![UNUSED; self.mesh_vertices.len()];
+        let mut vertices = Vec::with_capacity(self.mesh_vertices.len());
+        let mut colors = Vec::with_capacity(self.mesh_colors.len());
+        for index in self.mesh_indices.iter_mut() {
+            let new_index = &mut remap[*index as usize];
+            if *new_index == UNUSED {
+                *new_index = vertices.len() as u32;
+                vertices.push(self.mesh_vertices[*index as usize]);
+                if let Some(&color) = self.mesh_colors.get(*index as usize) {
+                    colors.push(color);
+                }
+            }
+            *index = *new_index;
+        }
+        let removed = self.mesh_vertices.len() - vertices.len();
+        self.mesh_vertices = vertices;
+        self.mesh_colors = colors;
+        // Adding faces after compacting has to find the new indices
+        self.vertex_lookup.clear();
+        for (i, v) in self.mesh_vertices.iter().enumerate() {
+            let color = self.mesh_colors.get(i).copied().unwrap_or(WHITE);
+            self.vertex_lookup.insert(VertexKey::new(v, color), i as u32);
+        }
+        removed
+    }
+    /// Sets missing normals to the area weighted average of the faces sharing the vertex.
+    fn compute_normals(&mut self) {
+        let mut accumulated = vec![Vector3::<f32>::zero(); self.mesh_vertices.len()];
+        for triangle in self.mesh_indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]]
+                .map(|i| Vector3::from(self.mesh_vertices[i as usize].position));
+            let normal = (b - a).cross(c - a);
+            for &i in triangle {
+                accumulated[i as usize] += normal;
+            }
+        }
+        for (vertex, normal) in self.mesh_vertices.iter_mut().zip(accumulated) {
+            if vertex.normal == [0.0; 3] && normal.magnitude2() > 0.0 {
+                vertex.normal = normal.normalize().into();
+            }
+        }
+    }
+    /// Reorders the triangles within each submesh for the post-transform cache, then the
+    /// vertices by first use for fetch locality.
+    fn optimize_vertex_cache(&mut self) {
+        let vertex_count = self.mesh_vertices.len();
+        if self.submeshes.is_empty() {
+            model::optimize::optimize_indices(&mut self.mesh_indices, vertex_count);
+        }
+        for submesh in &self.submeshes {
+            let range = submesh.indices.start as usize..submesh.indices.end as usize;
+            model::optimize::optimize_indices(&mut self.mesh_indices[range], vertex_count);
+        }
+        // Keeps the vertices in order of first use, which is what the fetch wants
+        self.compact();
+    }
+    /// Checks the mesh so far, see `validate::validate`.
+    pub fn validate(&self) -> Vec<model::ValidationIssue> {
+        model::validate(&self.mesh_vertices, &self.mesh_indices)
+    }
+    /// The mesh so far reduced to about `target_ratio` of its triangles, see
+    /// `simplify::simplify`.
+    pub fn simplify(&self, target_ratio: f32) -> (Vec<model::Vertex>, Vec<u32>) {
+        model::simplify::simplify(&self.mesh_vertices, &self.mesh_indices, target_ratio)
+    }
+    pub fn build(mut self) -> model::Object {
+        self.finish(false)
+    }
+    /// Like `build` but copies the mesh data out and clears the builder so its allocations can
+    /// be reused for the next file.
+    pub fn build_and_clear(&mut self) -> model::Object {
+        self.finish(true)
+    }
+    fn finish(&mut self, reuse: bool) -> model::Object {
+        let removed_vertices = self.compact();
+        if self.generate_normals {
+            self.compute_normals();
+        }
+        if self.optimize_vertex_cache {
+            self.optimize_vertex_cache();
+        }
+        let stats = model::object::Stats {
+            positions: self.vertices.len(),
+            normals: self.normals.len(),
+            texture_coords: self.texture_coords.len(),
+            vertices: self.mesh_vertices.len(),
+            triangles: self.mesh_indices.len() / 3,
+            duplicate_faces: self.removed_faces,
+            removed_vertices,
+        };
+        let mut materials: Vec<String> = Vec::new();
+        let submeshes = self
+            .submeshes
+            .drain(..)
+            .filter(|submesh| !submesh.indices.is_empty())
+            .map(|submesh| {
+                let material = submesh.material.map(|name| {
+                    match materials.iter().position(|m| m == &name) {
+                        Some(i) => i,
+                        None => {
+                            materials.push(name);
+                            materials.len() - 1
+                        }
+                    }
+                });
+                model::object::SubMesh {
+                    name: submesh.group,
+                    material,
+                    indices: submesh.indices,
+                }
+            })
+            .collect();
+        let (vertices, indices, colors) = if reuse {
+            (
+                self.mesh_vertices.clone(),
+                self.mesh_indices.clone(),
+                self.mesh_colors.clone(),
+            )
+        } else {
+            (
+                std::mem::take(&mut self.mesh_vertices),
+                std::mem::take(&mut self.mesh_indices),
+                std::mem::take(&mut self.mesh_colors),
+            )
+        };
+        let object = model::Object::new(
+            self.name.take(),
+            vertices,
+            indices,
+            submeshes,
+            materials,
+            std::mem::take(&mut self.material_libraries),
+            stats,
+        );
+        let object = if colors.is_empty() {
+            object
+        } else {
+            object.with_colors(colors)
+        };
+        self.clear();
+        if self.generate_tangents {
+            object.with_tangents()
+        } else {
+            object
+        }
+    }
+    /// Parses a file that's already in memory, e.g. from `include_bytes!`. Gzip compressed
+    /// bytes are detected by their magic bytes. `mtllib` paths are kept as written, for
+    /// loading through an `AssetSource`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut obj = Self::new();
+        obj.read_bytes(bytes)?;
+        Ok(obj)
+    }
+    fn read_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            self.read_gzip(bytes)
+        } else {
+            self.read_lines(bytes)
+        }
+    }
+    /// Reads `filename` through `source`, e.g. an `AssetResolver` or `EmbeddedFiles`. The
+    /// `mtllib` paths are relative to the file's directory as `source` sees it, so load them
+    /// through the same source with `MtlCache::load_for_from`.
+    pub fn load_from(
+        source: &dyn AssetSource,
+        filename: impl AsRef<std::path::Path>,
+    ) -> Result<Self, files::Error> {
+        let filename = filename.as_ref();
+        let bytes = source.read(filename).map_err(|e| files::Error::in_file(filename, e))?;
         let mut obj = Self::new();
+        obj.base_dir = filename.parent().map(PathBuf::from);
+        obj.read_bytes(&bytes).map_err(|e| files::Error::in_file(filename, e))?;
+        Ok(obj)
+    }
+    /// Reads `filename` from the filesystem. Use `load_from` with an `AssetResolver` to look
+    /// it up in several directories instead.
+    pub async fn load_file(filename: impl AsRef<std::path::Path>) -> Result<Self, files::Error> {
+        let mut obj = Self::new();
+        obj.read_file(filename).await?;
+        Ok(obj)
+    }
+    /// Parses the file into this builder. Parsing the same file into a cleared builder always
+    /// produces the same `mesh_vertices` and `mesh_indices`. Gzip compressed files are detected
+    /// by their `.gz` extension or magic bytes.
+    pub async fn read_file(
+        &mut self,
+        filename: impl AsRef<std::path::Path>,
+    ) -> Result<(), files::Error> {
+        self.read_file_with_progress(filename, |_| {}).await
+    }
+    /// `read_file` reporting how much of the file has been read, see `read_file_sync`.
+    pub async fn read_file_with_progress(
+        &mut self,
+        filename: impl AsRef<std::path::Path>,
+        progress: impl FnMut(Progress) + Unpin,
+    ) -> Result<(), files::Error> {
+        let filename = filename.as_ref();
+        self.read_file_async(filename, progress)
+            .await
+            .map_err(|e| files::Error::in_file(filename, e))
+    }
+    async fn read_file_async(
+        &mut self,
+        filename: &std::path::Path,
+        progress: impl FnMut(Progress) + Unpin,
+    ) -> Result<(), Error> {
+        self.base_dir = filename.parent().map(PathBuf::from);
         let file = tokio::fs::File::open(filename).await?;
-        let file = tokio::io::BufReader::new(file);
-        let mut lines = file.lines();
+        let total_bytes = file.metadata().await?.len();
+        let mut reader = tokio::io::BufReader::new(ProgressReader {
+            inner: file,
+            progress: Progress {
+                bytes_read: 0,
+                total_bytes,
+            },
+            callback: progress,
+        });
+        if is_gzip(filename, reader.fill_buf().await?) {
+            return self.read_gzip_async(reader).await;
+        }
+        self.read_lines_async(reader).await
+    }
+    /// Parses lines as they arrive from `reader`, e.g. a download, so the whole file never has
+    /// to be in memory. Gzip compressed data is detected by its magic bytes and decompressed
+    /// as it arrives too.
+    pub async fn read_async(
+        &mut self,
+        mut reader: impl tokio::io::AsyncBufRead + Unpin,
+    ) -> Result<(), Error> {
+        if reader.fill_buf().await?.starts_with(&GZIP_MAGIC) {
+            return self.read_gzip_async(reader).await;
+        }
+        self.read_lines_async(reader).await
+    }
+    /// flate2 only decodes synchronously, so compressed chunks are pushed through a decoder as
+    /// they arrive and the complete lines decoded so far are parsed after each.
+    #[cfg(feature = "gzip")]
+    async fn read_gzip_async(
+        &mut self,
+        mut reader: impl tokio::io::AsyncBufRead + Unpin,
+    ) -> Result<(), Error> {
+        let mut decoder = flate2::write::GzDecoder::new(Vec::new());
+        let mut number = 0;
         loop {
-            let result = lines.next_line().await;
-            let result = result?;
-            let actual_line = match result {
-                Some(line) => Line::process_line(&line)?.to_static(),
-                None => break,
-            };
-            obj.process_line(actual_line)?;
+            let chunk = reader.fill_buf().await?;
+            if chunk.is_empty() {
+                break;
+            }
+            decoder.write_all(chunk)?;
+            let read = chunk.len();
+            reader.consume(read);
+            let decoded = decoder.get_mut();
+            if let Some(end) = decoded.iter().rposition(|&b| b == b'\n') {
+                number = self.read_lines_from(&decoded[..=end], number)?;
+                decoded.drain(..=end);
+            }
         }
+        let rest = decoder.finish()?;
+        self.read_lines_from(&rest[..], number).map(|_| ())
+    }
+    #[cfg(not(feature = "gzip"))]
+    async fn read_gzip_async(
+        &mut self,
+        _reader: impl tokio::io::AsyncBufRead + Unpin,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedCompression)
+    }
+    async fn read_lines_async(
+        &mut self,
+        reader: impl tokio::io::AsyncBufRead + Unpin,
+    ) -> Result<(), Error> {
+        let mut lines = reader.lines();
+        let mut number = 0;
+        while let Some(line) = lines.next_line().await? {
+            number += 1;
+            self.read_line(number, &line)?;
+        }
+        Ok(())
+    }
+    pub fn load_file_sync(filename: impl AsRef<std::path::Path>) -> Result<Self, files::Error> {
+        let mut obj = Self::new();
+        obj.read_file_sync(filename, |_| {})?;
         Ok(obj)
     }
+    /// Blocking version of `read_file` that reports how much of the file has been read.
+    pub fn read_file_sync(
+        &mut self,
+        filename: impl AsRef<std::path::Path>,
+        progress: impl FnMut(Progress),
+    ) -> Result<(), files::Error> {
+        let filename = filename.as_ref();
+        self.read_file_blocking(filename, progress)
+            .map_err(|e| files::Error::in_file(filename, e))
+    }
+    fn read_file_blocking(
+        &mut self,
+        filename: &std::path::Path,
+        progress: impl FnMut(Progress),
+    ) -> Result<(), Error> {
+        self.base_dir = filename.parent().map(PathBuf::from);
+        let file = std::fs::File::open(filename)?;
+        let total_bytes = file.metadata()?.len();
+        let mut reader = std::io::BufReader::new(ProgressReader {
+            inner: file,
+            progress: Progress {
+                bytes_read: 0,
+                total_bytes,
+            },
+            callback: progress,
+        });
+        if is_gzip(filename, reader.fill_buf()?) {
+            self.read_gzip(reader)
+        } else {
+            self.read_lines(reader)
+        }
+    }
+    #[cfg(feature = "gzip")]
+    fn read_gzip(&mut self, reader: impl BufRead) -> Result<(), Error> {
+        self.read_lines(std::io::BufReader::new(flate2::bufread::GzDecoder::new(reader)))
+    }
+    #[cfg(not(feature = "gzip"))]
+    fn read_gzip(&mut self, _reader: impl BufRead) -> Result<(), Error> {
+        Err(Error::UnsupportedCompression)
+    }
+    pub fn read_lines(&mut self, reader: impl BufRead) -> Result<(), Error> {
+        self.read_lines_from(reader, 0).map(|_| ())
+    }
+    /// Numbers the lines from `number + 1` on and returns the last one's number.
+    fn read_lines_from(&mut self, reader: impl BufRead, mut number: usize) -> Result<usize, Error> {
+        for line in reader.lines() {
+            number += 1;
+            self.read_line(number, &line?)?;
+        }
+        Ok(number)
+    }
+    fn read_line(&mut self, number: usize, line: &str) -> Result<(), Error> {
+        let line = strip_cr(line);
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        Line::process_line(line)
+            .and_then(|line| self.process_line(line))
+            .map_err(|e| Error::AtLine(number, Box::new(e)))
+    }
+}
+
+/// Writes a triangle list as an OBJ file that `ObjectBuilder` reads back to the same
+/// `mesh_vertices` and `mesh_indices`, as long as `vertices` has no duplicates. Every vertex
+/// gets its own `v`, `vt` and `vn` line and faces use `v/vt/vn` indices starting at 1. Floats
+/// are written with as many digits as needed to read them back exactly, normals with at least
+/// 6 decimals since some importers renormalize short ones badly.
+///
+/// With `materials`, each pair is the `.mtl` file a material is in and the material, written
+/// with `mtl::write_mtl`. The files are referenced with `mtllib` and each submesh's faces
+/// follow a `g` with its name and a `usemtl` for its material, an index into `materials`.
+/// Without submeshes every face is written as one group without a material.
+pub fn write_obj(
+    out: &mut impl Write,
+    vertices: &[model::Vertex],
+    indices: &[u32],
+    submeshes: &[model::object::SubMesh],
+    materials: Option<&[(String, model::Material)]>,
+) -> std::io::Result<()> {
+    let materials = materials.unwrap_or_default();
+    let mut libraries: Vec<&str> = Vec::new();
+    for (library, _) in materials {
+        if !libraries.contains(&library.as_str()) {
+            libraries.push(library);
+            writeln!(out, "mtllib {}", library)?;
+        }
+    }
+    for vertex in vertices {
+        let [x, y, z] = vertex.position;
+        writeln!(out, "v {} {} {}", x, y, z)?;
+    }
+    for vertex in vertices {
+        let [u, v] = vertex.texture_coords;
+        writeln!(out, "vt {} {}", u, v)?;
+    }
+    for vertex in vertices {
+        let [x, y, z] = vertex.normal.map(normal_component);
+        writeln!(out, "vn {} {} {}", x, y, z)?;
+    }
+    let write_faces = |out: &mut dyn Write, indices: &[u32]| -> std::io::Result<()> {
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i + 1);
+            writeln!(out, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", a, b, c)?;
+        }
+        Ok(())
+    };
+    if submeshes.is_empty() {
+        return write_faces(out, indices);
+    }
+    for submesh in submeshes {
+        if let Some(name) = &submesh.name {
+            writeln!(out, "g {}", name)?;
+        }
+        if let Some((_, material)) = submesh.material.and_then(|i| materials.get(i)) {
+            writeln!(out, "usemtl {}", material.name)?;
+        }
+        let range = submesh.indices.start as usize..submesh.indices.end as usize;
+        write_faces(out, indices.get(range).unwrap_or_default())?;
+    }
+    Ok(())
+}
+
+/// `x` with 6 decimals if that's exact and the shortest exact form otherwise, which then has
+/// more.
+fn normal_component(x: f32) -> String {
+    let fixed = format!("{:.6}", x);
+    if fixed.parse::<f32>() == Ok(x) {
+        fixed
+    } else {
+        x.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(source: &str) -> ObjectBuilder {
+        let mut builder = ObjectBuilder::new();
+        builder.read_lines(source.as_bytes()).unwrap();
+        builder
+    }
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+    const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nf 1 3 4\n";
+
+    #[test]
+    fn first_face_without_group_is_in_a_submesh() {
+        let builder = read(&format!("{}f 1 2 3\n", TRIANGLE));
+        assert_eq!(builder.submeshes.len(), 1);
+        assert_eq!(builder.submeshes[0].indices, 0..3);
+        let object = builder.build();
+        assert_eq!(object.submeshes()[0].indices, 0..3);
+        assert_eq!(object.indices().len(), 3);
+    }
+
+    #[test]
+    fn faces_before_and_after_usemtl() {
+        let builder = read(&format!("{}f 1 2 3\nusemtl Red\nf 1 3 2\n", TRIANGLE));
+        let ranges: Vec<_> = builder.submeshes.iter().map(|s| s.indices.clone()).collect();
+        assert_eq!(ranges, vec![0..3, 3..6]);
+        assert_eq!(builder.submeshes[1].material.as_deref(), Some("Red"));
+    }
+
+    #[test]
+    fn names_keep_internal_spaces() {
+        let builder = read(&format!("{}g Left Wing\nusemtl Shiny Red\nf 1 2 3\n", TRIANGLE));
+        assert_eq!(builder.submeshes[0].group.as_deref(), Some("Left Wing"));
+        assert_eq!(builder.submeshes[0].material.as_deref(), Some("Shiny Red"));
+    }
+
+    #[test]
+    fn trailing_cr_is_the_same_material() {
+        assert_eq!(
+            Line::process_line("usemtl Red\r").unwrap(),
+            Line::UseMtl(Cow::Borrowed("Red"))
+        );
+        let builder = read(&format!("{}usemtl Red\r\nf 1 2 3\nusemtl Red\nf 1 3 2\n", TRIANGLE));
+        assert_eq!(builder.submeshes.len(), 1);
+        assert_eq!(builder.submeshes[0].indices, 0..6);
+    }
+
+    #[test]
+    fn cleared_builder_parses_the_same_again() {
+        let mut builder = read(QUAD);
+        let vertices = model::Vertex::as_bytes(&builder.mesh_vertices).to_vec();
+        let indices = builder.mesh_indices.clone();
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+        let capacity = builder.mesh_vertices.capacity();
+        builder.clear();
+        assert!(builder.mesh_vertices.is_empty() && builder.submeshes.is_empty());
+        assert_eq!(builder.mesh_vertices.capacity(), capacity);
+        builder.read_lines(QUAD.as_bytes()).unwrap();
+        assert_eq!(model::Vertex::as_bytes(&builder.mesh_vertices), &vertices[..]);
+        assert_eq!(builder.mesh_indices, indices);
+    }
+
+    #[test]
+    fn build_and_clear_keeps_settings() {
+        let mut builder = ObjectBuilder::with_capacity(4, 2);
+        builder.generate_normals = true;
+        builder.read_lines(QUAD.as_bytes()).unwrap();
+        let first = builder.build_and_clear();
+        assert!(builder.mesh_indices.is_empty() && builder.vertices.is_empty());
+        assert!(builder.generate_normals);
+        builder.read_lines(QUAD.as_bytes()).unwrap();
+        let second = builder.build_and_clear();
+        assert_eq!(first.vertices(), second.vertices());
+        assert_eq!(first.indices(), second.indices());
+        assert_eq!(first.vertices()[0].normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn duplicate_faces_by_winding() {
+        // The first face again, rotated, and then reversed
+        let source = format!("{}f 1 2 3\nf 2 3 1\nf 3 2 1\n", TRIANGLE);
+        let build = |duplicate_faces| {
+            let mut builder = ObjectBuilder::new();
+            builder.duplicate_faces = duplicate_faces;
+            builder.read_lines(source.as_bytes()).unwrap();
+            builder.build()
+        };
+        let kept = build(DuplicateFaces::Keep);
+        assert_eq!(kept.indices().len(), 9);
+        assert_eq!(kept.stats().duplicate_faces, 0);
+        let same = build(DuplicateFaces::DropSameWinding);
+        assert_eq!(same.indices(), [0, 1, 2, 2, 1, 0]);
+        assert_eq!(same.stats().duplicate_faces, 1);
+        let any = build(DuplicateFaces::DropAnyWinding);
+        assert_eq!(any.indices(), [0, 1, 2]);
+        assert_eq!(any.stats().duplicate_faces, 2);
+        assert_eq!(any.submeshes()[0].indices, 0..3);
+    }
+
+    #[test]
+    fn exported_cube_reads_back() {
+        let (vertices, indices) = model::primitives::cube(2.0);
+        let submesh = |name: &str, material, indices| model::object::SubMesh {
+            name: Some(name.to_string()),
+            material: Some(material),
+            indices,
+        };
+        let submeshes = [submesh("Front", 0, 0..18), submesh("Back", 1, 18..36)];
+        let library = String::from("cube.mtl");
+        let materials = [
+            (library.clone(), model::Material::new("Red")),
+            (library, model::Material::new("Blue")),
+        ];
+        let mut out = Vec::new();
+        write_obj(&mut out, &vertices, &indices, &submeshes, Some(&materials)).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.matches("mtllib").count(), 1);
+        // The first face's normal, with 6 decimals
+        assert!(text.lines().any(|line| line == "vn 1.000000 0.000000 0.000000"));
+        assert!(text.lines().any(|line| line == "f 1/1/1 2/2/2 3/3/3"));
+        assert!(!text.contains(" 0/"));
+
+        let builder = read(&text);
+        assert_eq!(builder.material_libraries, [PathBuf::from("cube.mtl")]);
+        let object = builder.build();
+        assert_eq!(object.vertices().len(), vertices.len());
+        assert_eq!(object.indices().len(), indices.len());
+        assert_eq!(object.materials(), ["Red", "Blue"]);
+        let names: Vec<_> = object.submeshes().iter().map(|s| s.name.as_deref()).collect();
+        assert_eq!(names, [Some("Front"), Some("Back")]);
+
+        let mut mtl = Vec::new();
+        files::mtl::write_mtl(&mut mtl, materials.iter().map(|(_, m)| m), None).unwrap();
+        let mtl = String::from_utf8(mtl).unwrap();
+        assert!(mtl.contains("newmtl Red") && mtl.contains("newmtl Blue"));
+    }
+
+    const TEXTURED: &str = "mtllib quad.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\n\
+        vt 1 1\nvt 0 1\nvn 0 0 1\ng Quad\nusemtl Red\nf 1/1/1 2/2/1 3/3/1\nf 1/1/1 3/3/1 4/4/1\n";
+
+    fn assert_same_mesh(a: &ObjectBuilder, b: &ObjectBuilder) {
+        let bytes = |vertices: &[model::Vertex]| model::Vertex::as_bytes(vertices).to_vec();
+        assert_eq!(bytes(&a.mesh_vertices), bytes(&b.mesh_vertices));
+        assert_eq!(a.mesh_indices, b.mesh_indices);
+        assert_eq!(a.submeshes, b.submeshes);
+        assert_eq!(a.material_libraries, b.material_libraries);
+    }
+
+    #[test]
+    fn strip_cr_only_strips_one() {
+        assert_eq!(strip_cr("f 1 2 3\r"), "f 1 2 3");
+        assert_eq!(strip_cr("f 1 2 3"), "f 1 2 3");
+        assert_eq!(strip_cr("\r\r"), "\r");
+    }
+
+    #[test]
+    fn crlf_reads_like_lf() {
+        let crlf = TEXTURED.replace('\n', "\r\n");
+        assert_same_mesh(&read(&crlf), &read(TEXTURED));
+        // Without a final LF the last line's CR isn't removed by `lines`
+        assert_same_mesh(&read(crlf.trim_end_matches('\n')), &read(TEXTURED));
+        // Lines split on LF alone keep their CR up to `process_line`
+        for (crlf, lf) in crlf.split('\n').zip(TEXTURED.split('\n')) {
+            if lf.is_empty() {
+                continue;
+            }
+            assert_eq!(Line::process_line(crlf).unwrap(), Line::process_line(lf).unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn crlf_reads_like_lf_async() {
+        let crlf = TEXTURED.replace('\n', "\r\n");
+        let mut builder = ObjectBuilder::new();
+        builder.read_async(crlf.trim_end_matches('\n').as_bytes()).await.unwrap();
+        assert_same_mesh(&builder, &read(TEXTURED));
+    }
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod gzip_tests {
+    use super::*;
+
+    const CUBE_SIDE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\n\
+                             vt 0 1\nvn 0 0 1\ng Side\nf 1/1/1 2/2/1 3/3/1\nf 1/1/1 3/3/1 4/4/1\n";
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn plain() -> ObjectBuilder {
+        ObjectBuilder::from_bytes(CUBE_SIDE.as_bytes()).unwrap()
+    }
+
+    fn assert_same_mesh(a: &ObjectBuilder, b: &ObjectBuilder) {
+        assert_eq!(a.mesh_vertices, b.mesh_vertices);
+        assert_eq!(a.mesh_indices, b.mesh_indices);
+    }
+
+    /// Written to the temp directory, removed again on drop.
+    struct TempFile(std::path::PathBuf);
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> Self {
+            let name = format!("soyuz-{}-{}", std::process::id(), name);
+            let path = std::env::temp_dir().join(name);
+            std::fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn compressed_bytes_match_plain() {
+        let compressed = ObjectBuilder::from_bytes(&gzip(CUBE_SIDE.as_bytes())).unwrap();
+        assert_same_mesh(&compressed, &plain());
+    }
+
+    #[test]
+    fn compressed_file_matches_plain_with_compressed_progress() {
+        let compressed = gzip(CUBE_SIDE.as_bytes());
+        let file = TempFile::new("sync.obj.gz", &compressed);
+        let mut builder = ObjectBuilder::new();
+        let mut last = Progress::default();
+        builder.read_file_sync(&file.0, |progress| last = progress).unwrap();
+        assert_same_mesh(&builder, &plain());
+        assert_eq!(last.bytes_read, compressed.len() as u64);
+        assert_eq!(last.total_bytes, compressed.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn compressed_file_matches_plain_async() {
+        let compressed = gzip(CUBE_SIDE.as_bytes());
+        let file = TempFile::new("async.obj.gz", &compressed);
+        let mut builder = ObjectBuilder::new();
+        let mut last = Progress::default();
+        builder
+            .read_file_with_progress(&file.0, |progress| last = progress)
+            .await
+            .unwrap();
+        assert_same_mesh(&builder, &plain());
+        assert_eq!(last.bytes_read, compressed.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn lines_split_between_chunks() {
+        let compressed = gzip(CUBE_SIDE.as_bytes());
+        let mut builder = ObjectBuilder::new();
+        let reader = tokio::io::BufReader::with_capacity(3, &compressed[..]);
+        builder.read_async(reader).await.unwrap();
+        assert_same_mesh(&builder, &plain());
+    }
+
+    #[tokio::test]
+    async fn errors_keep_line_numbers() {
+        let compressed = gzip(b"v 0 0 0\nv 1 0 0\nv x 0 0\n");
+        let reader = tokio::io::BufReader::with_capacity(4, &compressed[..]);
+        let error = ObjectBuilder::new().read_async(reader).await.unwrap_err();
+        assert!(matches!(error, Error::AtLine(3, _)), "{:?}", error);
+    }
 }
@@ -0,0 +1,541 @@
+use crate::entity::model;
+use crate::entity::model::files;
+use std::fmt::{Display, Formatter};
+use std::num::{ParseFloatError, ParseIntError};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    IO(std::io::Error),
+    ParseIntError(ParseIntError),
+    ParseFloatError(ParseFloatError),
+    /// The file doesn't start with `ply`.
+    NotPly,
+    /// The header has no `end_header`.
+    MissingEndHeader,
+    MissingFormat,
+    /// Only `ascii` and `binary_little_endian` are supported.
+    UnsupportedFormat(String),
+    UnrecognizedTag,
+    UnknownType(String),
+    /// A `property` line before any `element`.
+    PropertyWithoutElement,
+    MissingNumber,
+    /// The data ended before every element was read.
+    UnexpectedEnd,
+    /// The vertex element has no `x`, `y` or `z`.
+    MissingPosition,
+    InvalidIndex,
+    /// `1` based line number of the header or ASCII data line that caused the error.
+    AtLine(usize, Box<Error>),
+    /// Offset into the binary data of the element that caused the error.
+    AtByte(usize, Box<Error>),
+}
+impl From<ParseIntError> for Error {
+    fn from(e: ParseIntError) -> Self {
+        Error::ParseIntError(e)
+    }
+}
+impl From<ParseFloatError> for Error {
+    fn from(e: ParseFloatError) -> Self {
+        Error::ParseFloatError(e)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::IO(e)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Format {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self, Error> {
+        match name {
+            "char" | "int8" => Ok(ScalarType::I8),
+            "uchar" | "uint8" => Ok(ScalarType::U8),
+            "short" | "int16" => Ok(ScalarType::I16),
+            "ushort" | "uint16" => Ok(ScalarType::U16),
+            "int" | "int32" => Ok(ScalarType::I32),
+            "uint" | "uint32" => Ok(ScalarType::U32),
+            "float" | "float32" => Ok(ScalarType::F32),
+            "double" | "float64" => Ok(ScalarType::F64),
+            _ => Err(Error::UnknownType(name.to_string())),
+        }
+    }
+    pub fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+    /// Largest value of the integer types, colors stored as integers are divided by it.
+    fn max(self) -> f64 {
+        match self {
+            ScalarType::I8 => i8::MAX as f64,
+            ScalarType::U8 => u8::MAX as f64,
+            ScalarType::I16 => i16::MAX as f64,
+            ScalarType::U16 => u16::MAX as f64,
+            ScalarType::I32 => i32::MAX as f64,
+            ScalarType::U32 => u32::MAX as f64,
+            ScalarType::F32 | ScalarType::F64 => 1.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PropertyKind {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Property {
+    pub name: String,
+    pub kind: PropertyKind,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Element {
+    pub name: String,
+    pub count: usize,
+    pub properties: Vec<Property>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Header {
+    pub format: Format,
+    pub elements: Vec<Element>,
+}
+impl Header {
+    /// Parses the header lines up to and including `end_header`. Returns the header and the
+    /// byte offset the data starts at.
+    pub fn parse(bytes: &[u8]) -> Result<(Header, usize), Error> {
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+        let mut offset = 0;
+        for number in 1.. {
+            let end = bytes[offset..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .ok_or(Error::MissingEndHeader)?;
+            let line = String::from_utf8_lossy(&bytes[offset..offset + end]);
+            offset += end + 1;
+            let line = line.trim();
+            let at_line = |e| Error::AtLine(number, Box::new(e));
+            if number == 1 {
+                if line != "ply" {
+                    return Err(at_line(Error::NotPly));
+                }
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("format") => {
+                    format = Some(match words.next() {
+                        Some("ascii") => Format::Ascii,
+                        Some("binary_little_endian") => Format::BinaryLittleEndian,
+                        other => {
+                            let format = other.unwrap_or_default().to_string();
+                            return Err(at_line(Error::UnsupportedFormat(format)));
+                        }
+                    })
+                }
+                Some("element") => {
+                    let name = words.next().ok_or(at_line(Error::MissingNumber))?;
+                    let count = words.next().ok_or(at_line(Error::MissingNumber))?;
+                    elements.push(Element {
+                        name: name.to_string(),
+                        count: count.parse().map_err(|e| at_line(Error::from(e)))?,
+                        properties: Vec::new(),
+                    });
+                }
+                Some("property") => {
+                    let words: Vec<&str> = words.collect();
+                    let property = match words[..] {
+                        ["list", count, item, name] => Property {
+                            name: name.to_string(),
+                            kind: PropertyKind::List {
+                                count: ScalarType::parse(count).map_err(at_line)?,
+                                item: ScalarType::parse(item).map_err(at_line)?,
+                            },
+                        },
+                        [ty, name] => Property {
+                            name: name.to_string(),
+                            kind: PropertyKind::Scalar(ScalarType::parse(ty).map_err(at_line)?),
+                        },
+                        _ => return Err(at_line(Error::UnrecognizedTag)),
+                    };
+                    elements
+                        .last_mut()
+                        .ok_or(at_line(Error::PropertyWithoutElement))?
+                        .properties
+                        .push(property);
+                }
+                Some("end_header") => {
+                    let format = format.ok_or(Error::MissingFormat)?;
+                    return Ok((Header { format, elements }, offset));
+                }
+                Some("comment") | Some("obj_info") | None => {}
+                Some(_) => return Err(at_line(Error::UnrecognizedTag)),
+            }
+        }
+        unreachable!("the loop only ends by returning")
+    }
+}
+
+/// Where element values are read from.
+trait Source {
+    /// Called before each element instance.
+    fn begin(&mut self) -> Result<(), Error>;
+    fn read(&mut self, ty: ScalarType) -> Result<f64, Error>;
+    /// Wraps `e` with the position of the current element.
+    fn locate(&self, e: Error) -> Error;
+}
+
+/// One element instance per line.
+struct AsciiSource<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    words: std::str::SplitWhitespace<'a>,
+    /// Line number of the current element.
+    number: usize,
+}
+impl<'a> Source for AsciiSource<'a> {
+    fn begin(&mut self) -> Result<(), Error> {
+        loop {
+            let (index, line) = self.lines.next().ok_or(Error::UnexpectedEnd)?;
+            if !line.trim().is_empty() {
+                self.number = index + 1;
+                self.words = line.split_whitespace();
+                return Ok(());
+            }
+        }
+    }
+    fn read(&mut self, ty: ScalarType) -> Result<f64, Error> {
+        let word = self.words.next().ok_or(Error::MissingNumber)?;
+        Ok(match ty {
+            ScalarType::F32 | ScalarType::F64 => word.parse()?,
+            _ => word.parse::<i64>()? as f64,
+        })
+    }
+    fn locate(&self, e: Error) -> Error {
+        Error::AtLine(self.number, Box::new(e))
+    }
+}
+
+struct BinarySource<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    /// Offset of the current element.
+    start: usize,
+}
+impl<'a> Source for BinarySource<'a> {
+    fn begin(&mut self) -> Result<(), Error> {
+        self.start = self.offset;
+        Ok(())
+    }
+    fn read(&mut self, ty: ScalarType) -> Result<f64, Error> {
+        let end = self.offset + ty.size();
+        let bytes = self.bytes.get(self.offset..end).ok_or(Error::UnexpectedEnd)?;
+        self.offset = end;
+        let array = |bytes: &[u8]| -> [u8; 8] {
+            let mut array = [0; 8];
+            array[..bytes.len()].copy_from_slice(bytes);
+            array
+        };
+        let [b0, b1, b2, b3, ..] = array(bytes);
+        Ok(match ty {
+            ScalarType::I8 => b0 as i8 as f64,
+            ScalarType::U8 => b0 as f64,
+            ScalarType::I16 => i16::from_le_bytes([b0, b1]) as f64,
+            ScalarType::U16 => u16::from_le_bytes([b0, b1]) as f64,
+            ScalarType::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::F64 => f64::from_le_bytes(array(bytes)),
+        })
+    }
+    fn locate(&self, e: Error) -> Error {
+        Error::AtByte(self.start, Box::new(e))
+    }
+}
+
+/// Reads a property, lists are read into `list` and give their length.
+fn read_property(
+    source: &mut impl Source,
+    kind: PropertyKind,
+    list: &mut Vec<f64>,
+) -> Result<f64, Error> {
+    match kind {
+        PropertyKind::Scalar(ty) => source.read(ty),
+        PropertyKind::List { count, item } => {
+            let count = source.read(count)? as usize;
+            list.clear();
+            for _ in 0..count {
+                list.push(source.read(item)?);
+            }
+            Ok(count as f64)
+        }
+    }
+}
+
+/// Vertices, triangles and colors read from a PLY file.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct PlyMesh {
+    pub vertices: Vec<model::Vertex>,
+    pub indices: Vec<u32>,
+    /// From `red green blue [alpha]`, `None` if the vertices have no colors.
+    pub colors: Option<Vec<[f32; 4]>>,
+}
+
+fn read_data(header: &Header, source: &mut impl Source) -> Result<PlyMesh, Error> {
+    let mut mesh = PlyMesh::default();
+    let mut list = Vec::new();
+    for element in &header.elements {
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        if is_vertex {
+            let has = |name| element.properties.iter().any(|p| p.name == name);
+            if !(has("x") && has("y") && has("z")) {
+                return Err(Error::MissingPosition);
+            }
+            if has("red") || has("green") || has("blue") {
+                mesh.colors = Some(Vec::with_capacity(element.count));
+            }
+            mesh.vertices.reserve(element.count);
+        }
+        for _ in 0..element.count {
+            source.begin()?;
+            let mut vertex = model::Vertex::default();
+            let mut color = [1.0; 4];
+            let mut read = || -> Result<(), Error> {
+                for property in &element.properties {
+                    let value = read_property(source, property.kind, &mut list)?;
+                    let name = property.name.as_str();
+                    if is_face && (name == "vertex_indices" || name == "vertex_index") {
+                        // Fan triangulation of the polygon
+                        for i in 1..list.len().saturating_sub(1) {
+                            for &index in &[list[0], list[i], list[i + 1]] {
+                                mesh.indices.push(index as u32);
+                            }
+                        }
+                    }
+                    if !is_vertex {
+                        continue;
+                    }
+                    let value32 = value as f32;
+                    match name {
+                        "x" => vertex.position[0] = value32,
+                        "y" => vertex.position[1] = value32,
+                        "z" => vertex.position[2] = value32,
+                        "nx" => vertex.normal[0] = value32,
+                        "ny" => vertex.normal[1] = value32,
+                        "nz" => vertex.normal[2] = value32,
+                        "u" | "s" | "texture_u" => vertex.texture_coords[0] = value32,
+                        "v" | "t" | "texture_v" => vertex.texture_coords[1] = value32,
+                        channel @ ("red" | "green" | "blue" | "alpha") => {
+                            let i = ["red", "green", "blue", "alpha"]
+                                .iter()
+                                .position(|&c| c == channel)
+                                .unwrap_or_default();
+                            if let PropertyKind::Scalar(ty) = property.kind {
+                                color[i] = (value / ty.max()) as f32;
+                            }
+                        }
+                        // Anything else has been read past and is ignored
+                        _ => {}
+                    }
+                }
+                Ok(())
+            };
+            read().map_err(|e| source.locate(e))?;
+            if is_vertex {
+                mesh.vertices.push(vertex);
+                if let Some(colors) = &mut mesh.colors {
+                    colors.push(color);
+                }
+            }
+        }
+    }
+    if mesh.indices.iter().any(|&i| i as usize >= mesh.vertices.len()) {
+        return Err(Error::InvalidIndex);
+    }
+    Ok(mesh)
+}
+
+/// Loads ASCII and binary little endian PLY files.
+pub struct PlyLoader;
+impl PlyLoader {
+    pub fn load_path(filename: impl AsRef<Path>) -> Result<model::Object, files::Error> {
+        let filename = filename.as_ref();
+        let bytes = std::fs::read(filename).map_err(|e| files::Error::in_file(filename, e))?;
+        let name = filename.file_stem().map(|stem| stem.to_string_lossy().into_owned());
+        let mesh = Self::read_bytes(&bytes).map_err(|e| files::Error::in_file(filename, e))?;
+        Ok(Self::build(mesh, name))
+    }
+    pub fn read_bytes(bytes: &[u8]) -> Result<PlyMesh, Error> {
+        let (header, offset) = Header::parse(bytes)?;
+        let data = &bytes[offset..];
+        match header.format {
+            Format::Ascii => {
+                let text = String::from_utf8_lossy(data);
+                // Header lines come first in the file
+                let header_lines = bytes[..offset].iter().filter(|&&b| b == b'\n').count();
+                let mut source = AsciiSource {
+                    lines: text.lines().enumerate(),
+                    words: "".split_whitespace(),
+                    number: 0,
+                };
+                read_data(&header, &mut source).map_err(|e| match e {
+                    Error::AtLine(number, e) => Error::AtLine(number + header_lines, e),
+                    e => e,
+                })
+            }
+            Format::BinaryLittleEndian => {
+                let mut source = BinarySource {
+                    bytes: data,
+                    offset: 0,
+                    start: 0,
+                };
+                read_data(&header, &mut source)
+            }
+        }
+    }
+    pub fn build(mesh: PlyMesh, name: Option<String>) -> model::Object {
+        let stats = model::object::Stats {
+            positions: mesh.vertices.len(),
+            normals: mesh.vertices.len(),
+            texture_coords: mesh.vertices.len(),
+            vertices: mesh.vertices.len(),
+            triangles: mesh.indices.len() / 3,
+            duplicate_faces: 0,
+            removed_vertices: 0,
+        };
+        let submeshes = vec![model::object::SubMesh {
+            name: None,
+            material: None,
+            indices: 0..mesh.indices.len() as u32,
+        }];
+        let object =
+            model::Object::new(name, mesh.vertices, mesh.indices, submeshes, vec![], vec![], stats);
+        match mesh.colors {
+            Some(colors) => object.with_colors(colors),
+            None => object,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "ply
+format ascii 1.0
+comment a unit square
+element vertex 4
+property float x
+property float y
+property float z
+property double confidence
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 0.5 255 0 0
+1 0 0 0.5 0 255 0
+1 1 0 0.5 0 0 255
+0 1 0 0.5 255 255 255
+4 0 1 2 3
+";
+
+    /// `ASCII` as binary little endian.
+    fn binary() -> Vec<u8> {
+        let header = ASCII.split("end_header\n").next().unwrap();
+        let mut bytes = format!("{}end_header\n", header)
+            .replacen("format ascii", "format binary_little_endian", 1)
+            .into_bytes();
+        let vertices = [([0.0f32, 0.0], [255u8, 0, 0]), ([1.0, 0.0], [0, 255, 0])];
+        let vertices = vertices.iter().chain(&[([1.0, 1.0], [0, 0, 255]), ([0.0, 1.0], [255; 3])]);
+        for ([x, y], color) in vertices {
+            for coordinate in [*x, *y, 0.0] {
+                bytes.extend(coordinate.to_le_bytes());
+            }
+            bytes.extend(0.5f64.to_le_bytes());
+            bytes.extend(color);
+        }
+        bytes.push(4);
+        for index in 0..4i32 {
+            bytes.extend(index.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii_square() {
+        let mesh = PlyLoader::read_bytes(ASCII.as_bytes()).unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.vertices[2].position, [1.0, 1.0, 0.0]);
+        // The quad is split into a fan
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+        let colors = mesh.colors.unwrap();
+        assert_eq!(colors[0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(colors[3], [1.0; 4]);
+    }
+
+    #[test]
+    fn binary_matches_ascii() {
+        let binary = PlyLoader::read_bytes(&binary()).unwrap();
+        assert_eq!(binary, PlyLoader::read_bytes(ASCII.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn errors_are_located() {
+        let text = ASCII.replacen("1 1 0 0.5", "1 one 0 0.5", 1);
+        let error = PlyLoader::read_bytes(text.as_bytes()).unwrap_err();
+        assert!(matches!(error, Error::AtLine(17, _)), "{:?}", error);
+        let mut bytes = binary();
+        bytes.truncate(bytes.len() - 2);
+        let error = PlyLoader::read_bytes(&bytes).unwrap_err();
+        // The face starts after four vertices of 3 floats, a double and 3 bytes
+        assert!(matches!(error, Error::AtByte(92, _)), "{:?}", error);
+        let text = ASCII.replacen("4 0 1 2 3", "3 0 1 4", 1);
+        let error = PlyLoader::read_bytes(text.as_bytes()).unwrap_err();
+        assert!(matches!(error, Error::InvalidIndex), "{:?}", error);
+    }
+
+    #[test]
+    fn header_errors() {
+        let error = Header::parse(b"ply\nformat binary_big_endian 1.0\nend_header\n").unwrap_err();
+        assert!(matches!(error, Error::AtLine(2, _)), "{:?}", error);
+        let error = Header::parse(b"ply\nproperty float x\n").unwrap_err();
+        assert!(matches!(error, Error::AtLine(2, _)), "{:?}", error);
+        assert!(matches!(Header::parse(b"obj\n"), Err(Error::AtLine(1, _))));
+    }
+}
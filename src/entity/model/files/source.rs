@@ -0,0 +1,144 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// Where files referenced by other files come from, like the MTL libraries of an OBJ and the
+/// maps of a material. The default is the filesystem; `EmbeddedFiles` serves files compiled
+/// into the binary.
+pub trait AssetSource {
+    /// The contents of `path`, as referenced by the file that needs it.
+    fn read(&self, path: &Path) -> std::io::Result<Cow<[u8]>>;
+}
+
+/// Reads files from disk, relative paths are relative to the working directory.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct FileSystem;
+impl AssetSource for FileSystem {
+    fn read(&self, path: &Path) -> std::io::Result<Cow<[u8]>> {
+        std::fs::read(path).map(Cow::Owned)
+    }
+}
+
+/// Looks files up in several directories and reads the first match, e.g. a mod overlay, then
+/// DLC, then the base assets, so an overlay can replace single files. References stay
+/// relative to the file that makes them, so an OBJ read with `ObjectBuilder::load_from` has
+/// its `mtllib`s and their maps looked up in every directory too, through the same resolver
+/// given to `MtlCache::load_for_from` and `MaterialCache::with_source`.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct AssetResolver {
+    /// Earlier directories win.
+    pub search_paths: Vec<PathBuf>,
+}
+impl AssetResolver {
+    pub fn new(search_paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        AssetResolver {
+            search_paths: search_paths.into_iter().map(Into::into).collect(),
+        }
+    }
+    /// `relative` in the first search path that has it as a file. `..` can't leave the search
+    /// paths. Absolute paths are used as they are.
+    pub fn resolve(&self, relative: &Path) -> Option<PathBuf> {
+        if relative.is_absolute() {
+            return Some(relative.to_path_buf()).filter(|path| path.is_file());
+        }
+        let relative = normalize(relative);
+        self.search_paths.iter().map(|dir| dir.join(&relative)).find(|path| path.is_file())
+    }
+}
+impl AssetSource for AssetResolver {
+    fn read(&self, path: &Path) -> std::io::Result<Cow<[u8]>> {
+        match self.resolve(path) {
+            Some(resolved) => std::fs::read(resolved).map(Cow::Owned),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("'{}' isn't in any search path", path.display()),
+            )),
+        }
+    }
+}
+
+/// Drops `.` components and applies `..` ones, so `./a/../b.mtl` and `b.mtl` are the same file.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Files from `include_bytes!` by path, for shipping a single executable:
+///
+/// ```ignore
+/// let files = EmbeddedFiles::new([
+///     ("crate.mtl", &include_bytes!("../assets/crate.mtl")[..]),
+///     ("crate.png", &include_bytes!("../assets/crate.png")[..]),
+/// ]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EmbeddedFiles {
+    files: HashMap<PathBuf, &'static [u8]>,
+}
+impl EmbeddedFiles {
+    pub fn new(files: impl IntoIterator<Item = (&'static str, &'static [u8])>) -> Self {
+        EmbeddedFiles {
+            files: files
+                .into_iter()
+                .map(|(path, bytes)| (normalize(Path::new(path)), bytes))
+                .collect(),
+        }
+    }
+    pub fn insert(&mut self, path: impl AsRef<Path>, bytes: &'static [u8]) {
+        self.files.insert(normalize(path.as_ref()), bytes);
+    }
+}
+impl AssetSource for EmbeddedFiles {
+    fn read(&self, path: &Path) -> std::io::Result<Cow<[u8]>> {
+        match self.files.get(&normalize(path)) {
+            Some(bytes) => Ok(Cow::Borrowed(bytes)),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("'{}' isn't embedded", path.display()),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn earlier_search_paths_win() {
+        let dir = std::env::temp_dir().join(format!("soyuz-source-{}", std::process::id()));
+        let (overlay, base) = (dir.join("overlay"), dir.join("base"));
+        std::fs::create_dir_all(overlay.join("textures")).unwrap();
+        std::fs::create_dir_all(base.join("textures")).unwrap();
+        std::fs::write(base.join("textures/a.png"), "base a").unwrap();
+        std::fs::write(base.join("textures/b.png"), "base b").unwrap();
+        std::fs::write(overlay.join("textures/a.png"), "overlay a").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        let resolver = AssetResolver::new([&overlay, &base]);
+        let read = |path: &str| resolver.read(Path::new(path)).unwrap().into_owned();
+        // The overlay only replaces the file it has
+        assert_eq!(read("textures/a.png"), b"overlay a");
+        assert_eq!(read("textures/b.png"), b"base b");
+        assert_eq!(read("./textures/../textures/b.png"), b"base b");
+        // Directories aren't files
+        assert_eq!(resolver.resolve(Path::new("textures")), None);
+        let missing = resolver.read(Path::new("c.png")).unwrap_err();
+        assert_eq!(missing.kind(), std::io::ErrorKind::NotFound);
+        // `..` stops at the search path instead of reaching `dir`
+        for escaping in ["../secret.txt", "textures/../../../secret.txt"] {
+            assert_eq!(resolver.resolve(Path::new(escaping)), None);
+        }
+        let absolute = dir.join("secret.txt");
+        assert_eq!(resolver.resolve(&absolute), Some(absolute));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,344 @@
+use crate::entity::model;
+use crate::entity::model::files;
+use cgmath::{InnerSpace, Vector3};
+use std::fmt::{Display, Formatter};
+use std::num::ParseFloatError;
+use std::path::Path;
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+#[derive(Debug)]
+pub enum Error {
+    IO(std::io::Error),
+    ParseFloatError(ParseFloatError),
+    MissingNumber,
+    /// A different keyword was found where this one was expected.
+    Expected(&'static str),
+    /// A facet's loop had this many vertices instead of 3.
+    VertexCount(usize),
+    /// The file ended inside a `solid`.
+    UnexpectedEnd,
+    /// A binary file shorter than its triangle count says.
+    Truncated,
+    /// `1` based line number of the line that caused the error.
+    AtLine(usize, Box<Error>),
+}
+impl From<ParseFloatError> for Error {
+    fn from(e: ParseFloatError) -> Self {
+        Error::ParseFloatError(e)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::IO(e)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A triangle as stored in an STL file.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Facet {
+    pub normal: [f32; 3],
+    pub vertices: [[f32; 3]; 3],
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum StlFormat {
+    Binary,
+    Ascii,
+}
+
+/// Whether `bytes` are certainly an STL file, for telling formats apart. Unlike
+/// `detect_format` this doesn't assume binary when unsure.
+pub fn is_stl(bytes: &[u8]) -> bool {
+    let binary_size = bytes.len() >= HEADER_SIZE + 4 && {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        count
+            .checked_mul(TRIANGLE_SIZE)
+            .and_then(|size| size.checked_add(HEADER_SIZE + 4))
+            == Some(bytes.len())
+    };
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+    let ascii = bytes[start..].starts_with(b"solid") && bytes.windows(5).any(|w| w == b"facet");
+    binary_size || ascii
+}
+
+/// Binary files can start with `solid` too, so a file is only taken as binary when its size
+/// matches the triangle count in its header, and as ASCII when it has `facet` keywords.
+pub fn detect_format(bytes: &[u8]) -> StlFormat {
+    if bytes.len() >= HEADER_SIZE + 4 {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if count
+            .checked_mul(TRIANGLE_SIZE)
+            .and_then(|size| size.checked_add(HEADER_SIZE + 4))
+            == Some(bytes.len())
+        {
+            return StlFormat::Binary;
+        }
+    }
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+    let starts_with_solid = bytes[start..].starts_with(b"solid");
+    let has_facet = bytes.windows(5).any(|window| window == b"facet");
+    if starts_with_solid && (has_facet || bytes.len() < HEADER_SIZE + 4) {
+        StlFormat::Ascii
+    } else {
+        StlFormat::Binary
+    }
+}
+
+fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+fn read_vector(bytes: &[u8], offset: usize) -> [f32; 3] {
+    [read_f32(bytes, offset), read_f32(bytes, offset + 4), read_f32(bytes, offset + 8)]
+}
+
+pub fn parse_binary(bytes: &[u8]) -> Result<Vec<Facet>, Error> {
+    if bytes.len() < HEADER_SIZE + 4 {
+        return Err(Error::Truncated);
+    }
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    let triangles = &bytes[HEADER_SIZE + 4..];
+    if triangles.len() / TRIANGLE_SIZE < count {
+        return Err(Error::Truncated);
+    }
+    Ok(triangles
+        .chunks_exact(TRIANGLE_SIZE)
+        .take(count)
+        .map(|triangle| Facet {
+            normal: read_vector(triangle, 0),
+            vertices: [
+                read_vector(triangle, 12),
+                read_vector(triangle, 24),
+                read_vector(triangle, 36),
+            ],
+        })
+        .collect())
+}
+
+fn parse_numbers(s: &str) -> Result<[f32; 3], Error> {
+    let mut nums = s.split_whitespace().map(|n| n.parse::<f32>());
+    let mut next = || nums.next().ok_or(Error::MissingNumber)?.map_err(Error::from);
+    Ok([next()?, next()?, next()?])
+}
+
+/// Non blank lines of an ASCII file, split into keyword and arguments.
+struct AsciiLines<'a> {
+    lines: std::iter::Peekable<Box<dyn Iterator<Item = (usize, &'a str)> + 'a>>,
+    /// Number of the last line read, for errors at the end of the file.
+    number: usize,
+}
+impl<'a> AsciiLines<'a> {
+    fn new(text: &'a str) -> Self {
+        let lines: Box<dyn Iterator<Item = (usize, &'a str)>> = Box::new(
+            text.lines()
+                .enumerate()
+                .map(|(index, line)| (index + 1, line.trim()))
+                .filter(|(_, line)| !line.is_empty()),
+        );
+        AsciiLines {
+            lines: lines.peekable(),
+            number: 0,
+        }
+    }
+    fn error(&self, e: Error) -> Error {
+        Error::AtLine(self.number, Box::new(e))
+    }
+    fn peek_keyword(&mut self) -> Option<&'a str> {
+        self.lines
+            .peek()
+            .map(|(_, line)| line.split_whitespace().next().unwrap_or(""))
+    }
+    /// Reads the next line, which has to start with `keyword`, and returns its arguments.
+    fn expect(&mut self, keyword: &'static str) -> Result<&'a str, Error> {
+        let (number, line) = self.lines.next().ok_or_else(|| self.error(Error::UnexpectedEnd))?;
+        self.number = number;
+        match line.split_once(char::is_whitespace).unwrap_or((line, "")) {
+            (tag, rest) if tag == keyword => Ok(rest.trim()),
+            _ => Err(self.error(Error::Expected(keyword))),
+        }
+    }
+}
+
+/// Parses every `solid` of an ASCII file.
+pub fn parse_ascii(text: &str) -> Result<Vec<Facet>, Error> {
+    let mut lines = AsciiLines::new(text);
+    let mut facets = Vec::new();
+    while lines.peek_keyword().is_some() {
+        lines.expect("solid")?;
+        while lines.peek_keyword() == Some("facet") {
+            let rest = lines.expect("facet")?;
+            let normal = rest
+                .strip_prefix("normal")
+                .ok_or(Error::Expected("normal"))
+                .and_then(parse_numbers)
+                .map_err(|e| lines.error(e))?;
+            if lines.expect("outer")? != "loop" {
+                return Err(lines.error(Error::Expected("loop")));
+            }
+            let mut vertices = Vec::with_capacity(3);
+            while lines.peek_keyword() == Some("vertex") {
+                let rest = lines.expect("vertex")?;
+                vertices.push(parse_numbers(rest).map_err(|e| lines.error(e))?);
+            }
+            let vertices: [[f32; 3]; 3] = match vertices[..] {
+                [a, b, c] => [a, b, c],
+                _ => return Err(lines.error(Error::VertexCount(vertices.len()))),
+            };
+            lines.expect("endloop")?;
+            lines.expect("endfacet")?;
+            facets.push(Facet { normal, vertices });
+        }
+        lines.expect("endsolid")?;
+    }
+    Ok(facets)
+}
+
+/// Loads binary and ASCII STL files into `model::Object`s.
+pub struct StlLoader;
+impl StlLoader {
+    /// Reads `filename`, detecting whether it's binary or ASCII.
+    pub fn load_path(filename: impl AsRef<Path>) -> Result<model::Object, files::Error> {
+        let filename = filename.as_ref();
+        let bytes = std::fs::read(filename).map_err(|e| files::Error::in_file(filename, e))?;
+        let name = filename.file_stem().map(|stem| stem.to_string_lossy().into_owned());
+        Self::load_bytes(&bytes, name).map_err(|e| files::Error::in_file(filename, e))
+    }
+    pub fn load_bytes(bytes: &[u8], name: Option<String>) -> Result<model::Object, Error> {
+        let facets = match detect_format(bytes) {
+            StlFormat::Binary => parse_binary(bytes)?,
+            StlFormat::Ascii => parse_ascii(&String::from_utf8_lossy(bytes))?,
+        };
+        Ok(Self::build(facets, name))
+    }
+    /// Every facet gets its own three vertices with the facet normal, STL has no shared
+    /// vertices. Zero normals are recomputed from the winding.
+    pub fn build(facets: Vec<Facet>, name: Option<String>) -> model::Object {
+        let mut vertices = Vec::with_capacity(facets.len() * 3);
+        for facet in &facets {
+            let [a, b, c] = facet.vertices.map(Vector3::from);
+            let normal = if facet.normal == [0.0; 3] {
+                let cross = (b - a).cross(c - a);
+                if cross.magnitude2() > 0.0 {
+                    cross.normalize().into()
+                } else {
+                    [0.0; 3]
+                }
+            } else {
+                facet.normal
+            };
+            vertices.extend(facet.vertices.iter().map(|&position| model::Vertex {
+                position,
+                normal,
+                texture_coords: [0.0; 2],
+            }));
+        }
+        let indices: Vec<u32> = (0..vertices.len() as u32).collect();
+        let stats = model::object::Stats {
+            positions: vertices.len(),
+            normals: facets.len(),
+            texture_coords: 0,
+            vertices: vertices.len(),
+            triangles: facets.len(),
+            duplicate_faces: 0,
+            removed_vertices: 0,
+        };
+        let submeshes = vec![model::object::SubMesh {
+            name: None,
+            material: None,
+            indices: 0..indices.len() as u32,
+        }];
+        model::Object::new(name, vertices, indices, submeshes, vec![], vec![], stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "solid square
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 1 1 0
+    endloop
+  endfacet
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 1 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid square
+";
+
+    /// `facets` as a binary file, the header padded to 80 bytes.
+    fn binary(header: &[u8], facets: &[Facet]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(HEADER_SIZE, 0);
+        bytes.extend((facets.len() as u32).to_le_bytes());
+        for facet in facets {
+            let floats = facet.normal.iter().chain(facet.vertices.iter().flatten());
+            bytes.extend(floats.flat_map(|x| x.to_le_bytes()));
+            bytes.extend([0, 0]);
+        }
+        bytes
+    }
+
+    fn error_line(error: Error) -> (usize, Error) {
+        match error {
+            Error::AtLine(line, e) => (line, *e),
+            e => panic!("no line number: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn binary_and_ascii_load_the_same() {
+        let facets = parse_ascii(ASCII).unwrap();
+        assert_eq!(facets.len(), 2);
+        // Binary files starting with `solid` are still binary when the size matches
+        let bytes = binary(b"solid square", &facets);
+        assert_eq!(detect_format(&bytes), StlFormat::Binary);
+        assert_eq!(detect_format(ASCII.as_bytes()), StlFormat::Ascii);
+        let from_binary = StlLoader::load_bytes(&bytes, None).unwrap();
+        let from_ascii = StlLoader::load_bytes(ASCII.as_bytes(), None).unwrap();
+        assert_eq!(from_binary.vertices(), from_ascii.vertices());
+        assert_eq!(from_binary.indices(), from_ascii.indices());
+        // The second facet's zero normal comes from its winding
+        assert_eq!(from_ascii.vertices()[3].normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let mut bytes = binary(b"", &parse_ascii(ASCII).unwrap());
+        bytes.truncate(bytes.len() - 1);
+        assert!(matches!(parse_binary(&bytes), Err(Error::Truncated)));
+    }
+
+    #[test]
+    fn missing_endloop() {
+        let text = ASCII.replacen("    endloop\n", "", 1);
+        let (line, error) = error_line(parse_ascii(&text).unwrap_err());
+        assert_eq!(line, 7);
+        assert!(matches!(error, Error::Expected("endloop")), "{:?}", error);
+    }
+
+    #[test]
+    fn wrong_vertex_count() {
+        let text = ASCII.replacen("      vertex 1 1 0\n", "", 1);
+        let (line, error) = error_line(parse_ascii(&text).unwrap_err());
+        assert_eq!(line, 5);
+        assert!(matches!(error, Error::VertexCount(2)), "{:?}", error);
+    }
+}
@@ -0,0 +1,498 @@
+use crate::entity::model::bounds::Aabb;
+use crate::entity::model::files::mtl::MtlCache;
+use crate::entity::model::files::obj::Progress;
+use crate::entity::model::files::{self, Format};
+use crate::entity::model::material::{BoundMaterial, MaterialCache};
+use crate::entity::model::mesh::Mesh;
+use crate::entity::model::object::Stats;
+use crate::entity::model::Object;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+
+/// Options for `load`.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct LoadOptions {
+    /// Skips detection, for files whose extension and contents are misleading.
+    pub format: Option<Format>,
+}
+
+/// A model file loaded through `load`, whatever its format. Submeshes and material names are
+/// on the objects, `meshes[i]` is `objects[i]` uploaded.
+pub struct LoadedModel {
+    pub name: String,
+    pub format: Format,
+    pub objects: Vec<Object>,
+    pub meshes: Vec<Mesh>,
+    /// Around every object.
+    pub bounds: Aabb,
+    /// Summed over the objects.
+    pub stats: Stats,
+}
+
+/// A range of one of a `Model`'s meshes drawn with one of its materials.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct SubMesh {
+    /// Index into `Model::meshes`.
+    pub mesh: usize,
+    pub indices: Range<u32>,
+    /// Index into `Model::materials`.
+    pub material: usize,
+}
+
+/// Meshes with their materials bound, ready to draw. Entities share it through an `Rc`.
+pub struct Model {
+    pub name: String,
+    pub meshes: Vec<Mesh>,
+    /// In draw order, grouped by mesh.
+    pub submeshes: Vec<SubMesh>,
+    /// Each material once, however many submeshes use it.
+    pub materials: Vec<Rc<BoundMaterial>>,
+    pub bounds: Aabb,
+}
+impl Model {
+    /// Binds each submesh's material to group `material_group` and draws its range. The
+    /// pipeline and the other groups have to be set already.
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, material_group: u32) {
+        let mut bound_mesh = None;
+        let mut bound_material = None;
+        for submesh in &self.submeshes {
+            if submesh.indices.is_empty() {
+                continue;
+            }
+            if bound_mesh != Some(submesh.mesh) {
+                self.meshes[submesh.mesh].set_buffers(pass);
+                bound_mesh = Some(submesh.mesh);
+            }
+            // Consecutive submeshes often share a material
+            if bound_material != Some(submesh.material) {
+                let material = &self.materials[submesh.material];
+                pass.set_bind_group(material_group, &material.bind_group, &[]);
+                bound_material = Some(submesh.material);
+            }
+            pass.draw_indexed(submesh.indices.clone(), 0, 0..1);
+        }
+    }
+    /// Draws LOD `level` of each mesh, see `Mesh::with_lods`. Simplified levels span the
+    /// whole mesh, so they're drawn with the material of its first submesh. Level 0 is `draw`.
+    pub fn draw_lod<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        material_group: u32,
+        level: usize,
+    ) {
+        if level == 0 {
+            self.draw(pass, material_group);
+            return;
+        }
+        for (i, mesh) in self.meshes.iter().enumerate() {
+            let material = match self.submeshes.iter().find(|s| s.mesh == i) {
+                Some(submesh) => &self.materials[submesh.material],
+                None => continue,
+            };
+            pass.set_bind_group(material_group, &material.bind_group, &[]);
+            mesh.draw_submesh(pass, mesh.lod(level), 0..1);
+        }
+    }
+}
+
+/// The CPU side of a `LoadedModel`, parsed but not uploaded yet. Can be sent between threads.
+pub struct ModelData {
+    pub name: String,
+    pub format: Format,
+    pub objects: Vec<Object>,
+    pub bounds: Aabb,
+    pub stats: Stats,
+}
+impl ModelData {
+    /// Sums the bounds and stats of `objects`.
+    pub fn new(name: String, format: Format, objects: Vec<Object>) -> ModelData {
+        let mut bounds = Aabb::empty();
+        let mut stats = Stats::default();
+        for object in &objects {
+            bounds = bounds.union(object.bounds());
+            let s = object.stats();
+            stats.positions += s.positions;
+            stats.normals += s.normals;
+            stats.texture_coords += s.texture_coords;
+            stats.vertices += s.vertices;
+            stats.triangles += s.triangles;
+            stats.duplicate_faces += s.duplicate_faces;
+            stats.removed_vertices += s.removed_vertices;
+        }
+        ModelData {
+            name,
+            format,
+            objects,
+            bounds,
+            stats,
+        }
+    }
+    /// Creates a `Mesh` per object.
+    pub fn upload(self, device: &wgpu::Device) -> LoadedModel {
+        let name = self.name;
+        let meshes = self
+            .objects
+            .iter()
+            .map(|object| Mesh::new(device, object, Some(object.name().unwrap_or(&name))))
+            .collect();
+        LoadedModel {
+            name,
+            format: self.format,
+            objects: self.objects,
+            meshes,
+            bounds: self.bounds,
+            stats: self.stats,
+        }
+    }
+    /// Uploads the objects and binds their materials into a `Model`. MTL files are looked up
+    /// in `mtl_cache`, ones that fail to load are logged and their submeshes get the fallback
+    /// material.
+    pub fn into_model(
+        self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mtl_cache: &MtlCache,
+        material_cache: &mut MaterialCache,
+    ) -> Model {
+        let mut meshes = Vec::with_capacity(self.objects.len());
+        let mut submeshes = Vec::new();
+        let mut materials: Vec<Rc<BoundMaterial>> = Vec::new();
+        for (mesh, object) in self.objects.iter().enumerate() {
+            let library = mtl_cache.load_for_sync(object).unwrap_or_else(|e| {
+                log::warn!("can't load the materials of '{}': {}", self.name, e);
+                Default::default()
+            });
+            let bound = material_cache.resolve(device, queue, &library, object);
+            let mut material_index = |material: Rc<BoundMaterial>| {
+                match materials.iter().position(|m| Rc::ptr_eq(m, &material)) {
+                    Some(i) => i,
+                    None => {
+                        materials.push(material);
+                        materials.len() - 1
+                    }
+                }
+            };
+            if object.submeshes().is_empty() && !object.indices().is_empty() {
+                submeshes.push(SubMesh {
+                    mesh,
+                    indices: 0..object.indices().len() as u32,
+                    material: material_index(material_cache.fallback()),
+                });
+            }
+            for (submesh, material) in object.submeshes().iter().zip(bound) {
+                submeshes.push(SubMesh {
+                    mesh,
+                    indices: submesh.indices.clone(),
+                    material: material_index(material),
+                });
+            }
+            meshes.push(Mesh::new(device, object, Some(object.name().unwrap_or(&self.name))));
+        }
+        Model {
+            name: self.name,
+            meshes,
+            submeshes,
+            materials,
+            bounds: self.bounds,
+        }
+    }
+}
+
+/// Loads an OBJ, STL, PLY or glTF file and uploads its meshes. The format is picked by
+/// extension, or by the file's first bytes when the extension is missing or unknown.
+pub fn load(
+    path: impl AsRef<Path>,
+    device: &wgpu::Device,
+    options: &LoadOptions,
+) -> Result<LoadedModel, files::Error> {
+    Ok(parse(path.as_ref(), options, |_| {})?.upload(device))
+}
+
+/// The parsing half of `load`, which doesn't need the device.
+pub fn load_data(path: impl AsRef<Path>, options: &LoadOptions) -> Result<ModelData, files::Error> {
+    parse(path.as_ref(), options, |_| {})
+}
+
+fn parse(
+    path: &Path,
+    options: &LoadOptions,
+    progress: impl FnMut(Progress),
+) -> Result<ModelData, files::Error> {
+    load_objects(path, options, progress)
+        .map(|(format, objects)| {
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            ModelData::new(name, format, objects)
+        })
+        .map_err(|e| match e {
+            e @ files::Error::InFile(..) => e,
+            e => files::Error::in_file(path, e),
+        })
+}
+
+/// Parses a model that's already in memory, like one embedded with `include_bytes!`. The format
+/// is picked by the extension of `name_hint`, or by the first bytes. glTF has to be a `.glb` or
+/// a `.gltf` with embedded buffers since there's nothing to resolve URIs against. Materials and
+/// textures referenced by OBJ files can be read with an `EmbeddedFiles` source.
+pub fn load_from_bytes(name_hint: &str, bytes: &[u8]) -> Result<ModelData, files::Error> {
+    let hint = Path::new(name_hint);
+    let format = Format::from_path(hint)
+        .or_else(|| Format::sniff(bytes))
+        .ok_or_else(|| files::Error::UnsupportedFormat {
+            extension: hint.extension().map(|e| e.to_string_lossy().into_owned()),
+        })
+        .map_err(|e| files::Error::in_file(hint, e))?;
+    let stem = hint.file_stem().map(|stem| stem.to_string_lossy().into_owned());
+    let objects = parse_bytes(format, bytes, stem.clone())
+        .map_err(|e| files::Error::in_file(hint, e))?;
+    Ok(ModelData::new(stem.unwrap_or_default(), format, objects))
+}
+
+fn parse_bytes(
+    format: Format,
+    bytes: &[u8],
+    name: Option<String>,
+) -> Result<Vec<Object>, files::Error> {
+    Ok(match format {
+        Format::Obj => vec![files::obj::ObjectBuilder::from_bytes(bytes)?.build()],
+        Format::Stl => vec![files::stl::StlLoader::load_bytes(bytes, name)?],
+        Format::Ply => {
+            let mesh = files::ply::PlyLoader::read_bytes(bytes)?;
+            vec![files::ply::PlyLoader::build(mesh, name)]
+        }
+        #[cfg(feature = "gltf")]
+        Format::Gltf => {
+            let (document, buffers, _) =
+                gltf::import_slice(bytes).map_err(files::gltf::Error::from)?;
+            files::gltf::GltfMesh::read_document(&document, &buffers)?
+        }
+        #[cfg(not(feature = "gltf"))]
+        Format::Gltf => return Err(files::Error::UnsupportedFormat { extension: None }),
+    })
+}
+
+/// A model being parsed on another thread, see `load_in_background`.
+pub struct ModelLoadHandle {
+    path: PathBuf,
+    bytes_read: Arc<AtomicU64>,
+    total_bytes: Arc<AtomicU64>,
+    result: mpsc::Receiver<Result<ModelData, files::Error>>,
+    taken: bool,
+}
+impl ModelLoadHandle {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// How much of the file has been parsed. Only OBJ files report as they go, other formats
+    /// jump to done at the end.
+    pub fn progress(&self) -> Progress {
+        Progress {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            total_bytes: self.total_bytes.load(Ordering::Relaxed),
+        }
+    }
+    /// The parsed model or error once the load is done, `None` while it's running and after
+    /// the result was taken. Doesn't block, so it can be polled every frame.
+    pub fn try_take(&mut self) -> Option<Result<ModelData, files::Error>> {
+        if self.taken {
+            return None;
+        }
+        let result = match self.result.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            // The thread panicked
+            Err(mpsc::TryRecvError::Disconnected) => Err(files::Error::in_file(
+                &self.path,
+                std::io::Error::new(std::io::ErrorKind::Other, "loader thread panicked"),
+            )),
+        };
+        self.taken = true;
+        Some(result)
+    }
+}
+
+/// Parses the model on its own thread so the event loop keeps running. Poll the handle each
+/// frame and `upload` the result on the thread that owns the device:
+///
+/// ```ignore
+/// if let Some(result) = handle.try_take() {
+///     match result {
+///         Ok(data) => model = data.upload(&device),
+///         Err(e) => log::error!("{}", e),
+///     }
+/// }
+/// ```
+pub fn load_in_background(path: impl Into<PathBuf>, options: LoadOptions) -> ModelLoadHandle {
+    let path = path.into();
+    let total = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    let bytes_read = Arc::new(AtomicU64::new(0));
+    let total_bytes = Arc::new(AtomicU64::new(total));
+    let (sender, result) = mpsc::channel();
+    {
+        let path = path.clone();
+        let (bytes_read, total_bytes) = (bytes_read.clone(), total_bytes.clone());
+        std::thread::spawn(move || {
+            let loaded = parse(&path, &options, |progress| {
+                bytes_read.store(progress.bytes_read, Ordering::Relaxed);
+                total_bytes.store(progress.total_bytes, Ordering::Relaxed);
+            });
+            bytes_read.store(total_bytes.load(Ordering::Relaxed), Ordering::Relaxed);
+            // The handle may have been dropped, then nobody wants the model
+            let _ = sender.send(loaded);
+        });
+    }
+    ModelLoadHandle {
+        path,
+        bytes_read,
+        total_bytes,
+        result,
+        taken: false,
+    }
+}
+
+fn load_objects(
+    path: &Path,
+    options: &LoadOptions,
+    progress: impl FnMut(Progress),
+) -> Result<(Format, Vec<Object>), files::Error> {
+    let format = match options.format {
+        Some(format) => format,
+        None => Format::detect(path)?,
+    };
+    let objects = match format {
+        Format::Obj => {
+            let mut builder = files::obj::ObjectBuilder::new();
+            builder.read_file_sync(path, progress)?;
+            vec![builder.build()]
+        }
+        Format::Stl => vec![files::stl::StlLoader::load_path(path)?],
+        Format::Ply => vec![files::ply::PlyLoader::load_path(path)?],
+        #[cfg(feature = "gltf")]
+        Format::Gltf => files::gltf::GltfMesh::load_file_sync(path)?,
+        #[cfg(not(feature = "gltf"))]
+        Format::Gltf => {
+            return Err(files::Error::UnsupportedFormat {
+                extension: path.extension().map(|e| e.to_string_lossy().into_owned()),
+            })
+        }
+    };
+    Ok((format, objects))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Point3;
+    use std::time::{Duration, Instant};
+
+    /// The same triangle in each format.
+    const OBJ: &str = "v 0 0 0\nv 2 0 0\nv 0 1 0\nf 1 2 3\n";
+    const STL: &str = "solid triangle\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\n\
+                       vertex 2 0 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid triangle\n";
+    const PLY: &str = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\n\
+                       property float y\nproperty float z\nelement face 1\n\
+                       property list uchar int vertex_indices\nend_header\n0 0 0\n2 0 0\n\
+                       0 1 0\n3 0 1 2\n";
+    const FIXTURES: [(&str, &str, Format); 3] = [
+        ("triangle.obj", OBJ, Format::Obj),
+        ("triangle.stl", STL, Format::Stl),
+        ("triangle.ply", PLY, Format::Ply),
+    ];
+
+    fn assert_triangle(data: &ModelData, format: Format) {
+        assert_eq!(data.format, format);
+        assert_eq!(data.name, "triangle");
+        assert_eq!(data.objects.len(), 1);
+        assert_eq!(data.stats.triangles, 1);
+        assert_eq!(data.bounds.min, Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(data.bounds.max, Point3::new(2.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn every_format_from_bytes() {
+        for (name, source, format) in FIXTURES {
+            assert_triangle(&load_from_bytes(name, source.as_bytes()).unwrap(), format);
+        }
+    }
+
+    #[test]
+    fn every_format_from_files_without_extensions() {
+        let dir = std::env::temp_dir().join(format!("soyuz-loader-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (_, source, format) in FIXTURES {
+            let path = dir.join("triangle");
+            std::fs::write(&path, source).unwrap();
+            assert_triangle(&load_data(&path, &LoadOptions::default()).unwrap(), format);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Polls the handle like a frame loop would until the load is done.
+    fn poll(handle: &mut ModelLoadHandle) -> Result<ModelData, files::Error> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Some(result) = handle.try_take() {
+                return result;
+            }
+            let progress = handle.progress();
+            assert!(progress.bytes_read <= progress.total_bytes);
+            if Instant::now() > deadline {
+                panic!("{:?} never loaded", handle.path());
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn background_loads_can_be_polled() {
+        let dir = std::env::temp_dir().join(format!("soyuz-background-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("triangle.obj");
+        std::fs::write(&path, OBJ).unwrap();
+        let mut handle = load_in_background(&path, LoadOptions::default());
+        assert_eq!(handle.path(), path);
+        assert_triangle(&poll(&mut handle).unwrap(), Format::Obj);
+        let progress = handle.progress();
+        let size = OBJ.len() as u64;
+        assert_eq!((progress.bytes_read, progress.total_bytes), (size, size));
+        // The result is handed out once
+        assert!(handle.try_take().is_none());
+        let mut missing = load_in_background(dir.join("missing.obj"), LoadOptions::default());
+        assert!(poll(&mut missing).is_err());
+        std::fs::write(&path, "v 0 x 0\n").unwrap();
+        let mut invalid = load_in_background(&path, LoadOptions::default());
+        assert!(poll(&mut invalid).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn formats_by_extension() {
+        let format = |path: &str| Format::from_path(Path::new(path));
+        assert_eq!(format("a/b.OBJ"), Some(Format::Obj));
+        assert_eq!(format("scan.obj.gz"), Some(Format::Obj));
+        assert_eq!(format("scene.glb"), Some(Format::Gltf));
+        assert_eq!(format("notes.txt"), None);
+        assert_eq!(format("archive.tar.gz"), None);
+    }
+
+    #[test]
+    fn unknown_formats() {
+        let error = load_from_bytes("notes.txt", b"hello").unwrap_err();
+        match error {
+            files::Error::InFile(path, e) => {
+                assert_eq!(path, Path::new("notes.txt"));
+                let extension = match *e {
+                    files::Error::UnsupportedFormat { extension } => extension,
+                    e => panic!("not an unsupported format: {:?}", e),
+                };
+                assert_eq!(extension.as_deref(), Some("txt"));
+            }
+            e => panic!("not in a file: {:?}", e),
+        }
+    }
+}
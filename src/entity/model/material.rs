@@ -0,0 +1,760 @@
+use crate::entity::model::files::source::{AssetSource, FileSystem};
+use crate::entity::model::Object;
+use crate::texture::{SamplerConfig, Texture};
+use memoffset::offset_of;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+
+/// The `illum` statement of an MTL file, how the material should be lit.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum IlluminationModel {
+    /// `illum 0`, the diffuse color without any lighting.
+    ColorOnly,
+    /// `illum 1`, diffuse lighting only.
+    Diffuse,
+    /// `illum 2`, diffuse and specular lighting.
+    Specular,
+    /// Any other model, reflection, refraction and so on. Not supported yet so shaded like
+    /// `Specular`.
+    Other(u32),
+}
+impl IlluminationModel {
+    /// The model the shader actually uses, unsupported models fall back to `Specular`.
+    pub fn supported(self) -> IlluminationModel {
+        match self {
+            IlluminationModel::Other(illum) => {
+                log::warn!("unsupported illumination model {}, using 2", illum);
+                IlluminationModel::Specular
+            }
+            model => model,
+        }
+    }
+}
+impl From<u32> for IlluminationModel {
+    fn from(illum: u32) -> Self {
+        match illum {
+            0 => IlluminationModel::ColorOnly,
+            1 => IlluminationModel::Diffuse,
+            2 => IlluminationModel::Specular,
+            _ => IlluminationModel::Other(illum),
+        }
+    }
+}
+impl From<IlluminationModel> for u32 {
+    fn from(model: IlluminationModel) -> Self {
+        match model {
+            IlluminationModel::ColorOnly => 0,
+            IlluminationModel::Diffuse => 1,
+            IlluminationModel::Specular => 2,
+            IlluminationModel::Other(illum) => illum,
+        }
+    }
+}
+
+/// Options in front of the file name of a `map_*` statement.
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct MapOptions {
+    /// `-o u v w`, added to the texture coordinates.
+    pub offset: [f32; 3],
+    /// `-s u v w`, multiplies the texture coordinates before the offset is added.
+    pub scale: [f32; 3],
+    /// `-clamp on`, clamp the texture coordinates instead of repeating the texture.
+    pub clamp: bool,
+    /// `-mm base gain`, remaps the texture values to `base + value * gain`.
+    pub mm: Option<(f32, f32)>,
+}
+impl MapOptions {
+    /// Row major 2×3 matrix taking `(u, v, 1)` to the transformed texture coordinates.
+    pub fn uv_transform(&self) -> [[f32; 3]; 2] {
+        [
+            [self.scale[0], 0.0, self.offset[0]],
+            [0.0, self.scale[1], self.offset[1]],
+        ]
+    }
+}
+impl Default for MapOptions {
+    fn default() -> Self {
+        MapOptions {
+            offset: [0.0; 3],
+            scale: [1.0; 3],
+            clamp: false,
+            mm: None,
+        }
+    }
+}
+
+/// Decoded RGBA8 pixels, for textures that don't come from their own file like the images
+/// embedded in a GLB.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+/// A texture a material refers to, `path` is absolute or relative to the working directory.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TextureRef {
+    pub path: PathBuf,
+    pub options: MapOptions,
+    /// Already decoded pixels, used instead of reading `path`.
+    pub image: Option<Arc<ImageData>>,
+}
+impl TextureRef {
+    pub fn new(path: impl Into<PathBuf>) -> TextureRef {
+        TextureRef {
+            path: path.into(),
+            options: MapOptions::default(),
+            image: None,
+        }
+    }
+}
+
+/// How the alpha of a material is used, from glTF's `alphaMode`.
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub enum AlphaMode {
+    /// Blended only when `alpha` is below `Material::OPAQUE_THRESHOLD`, MTL files don't say.
+    Opaque,
+    /// Fragments with an alpha below `cutoff` are discarded, the rest are opaque.
+    Mask { cutoff: f32 },
+    /// Always blended.
+    Blend,
+}
+impl Default for AlphaMode {
+    fn default() -> Self {
+        AlphaMode::Opaque
+    }
+}
+
+/// A material as described by a Wavefront MTL file.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Material {
+    pub name: String,
+    /// `Ka`
+    pub ambient: [f32; 3],
+    /// `Kd`
+    pub diffuse: [f32; 3],
+    /// `Ks`
+    pub specular: [f32; 3],
+    /// `Ke`
+    pub emissive: [f32; 3],
+    /// `Ns`, the specular exponent.
+    pub shininess: f32,
+    /// `d`, or `Tr` depending on `MtlLibrary::tr_mode`. `1` is fully opaque.
+    pub alpha: f32,
+    /// `Ni`
+    pub optical_density: f32,
+    /// `illum`
+    pub illumination: IlluminationModel,
+    /// `Pr`, only set by exporters using the PBR extension.
+    pub roughness: Option<f32>,
+    /// `Pm`
+    pub metallic: Option<f32>,
+    /// `Ps`
+    pub sheen: Option<f32>,
+    /// `aniso`
+    pub anisotropy: Option<f32>,
+
+    pub ambient_map: Option<TextureRef>,
+    pub diffuse_map: Option<TextureRef>,
+    pub specular_map: Option<TextureRef>,
+    pub shininess_map: Option<TextureRef>,
+    /// `map_d`, recorded but not sampled yet.
+    pub dissolve_map: Option<TextureRef>,
+    /// Height map, from `bump` or `map_Bump`.
+    pub bump_map: Option<TextureRef>,
+    /// Tangent space normal map, from `norm`.
+    pub normal_map: Option<TextureRef>,
+    /// `map_Pr`
+    pub roughness_map: Option<TextureRef>,
+    /// `map_Pm`
+    pub metallic_map: Option<TextureRef>,
+    /// `map_Ke`, glow added on top of the lit color.
+    pub emissive_map: Option<TextureRef>,
+    /// `-bm` of the bump map.
+    pub bump_multiplier: f32,
+    pub alpha_mode: AlphaMode,
+    /// Both faces are drawn, MTL materials are always single sided.
+    pub double_sided: bool,
+    /// Shared by all the maps. Clamped for MTL diffuse maps with `-clamp on`, from the base
+    /// color texture's sampler for glTF.
+    pub sampler: SamplerConfig,
+}
+impl Material {
+    pub fn new(name: impl Into<String>) -> Material {
+        Material {
+            name: name.into(),
+            ambient: [1.0; 3],
+            diffuse: [0.8; 3],
+            specular: [0.0; 3],
+            emissive: [0.0; 3],
+            shininess: 0.0,
+            alpha: 1.0,
+            optical_density: 1.0,
+            illumination: IlluminationModel::Specular,
+            roughness: None,
+            metallic: None,
+            sheen: None,
+            anisotropy: None,
+            ambient_map: None,
+            diffuse_map: None,
+            specular_map: None,
+            shininess_map: None,
+            dissolve_map: None,
+            bump_map: None,
+            normal_map: None,
+            roughness_map: None,
+            metallic_map: None,
+            emissive_map: None,
+            bump_multiplier: 1.0,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+            sampler: SamplerConfig::default(),
+        }
+    }
+}
+impl Material {
+    /// Name of `Material::fallback`, not a valid `newmtl` name as it contains spaces.
+    pub const FALLBACK_NAME: &'static str = "default material";
+    /// Used for faces without a `usemtl` and for materials the MTL files don't define. Mid-gray
+    /// and without maps.
+    pub fn fallback() -> Material {
+        Material {
+            diffuse: [0.5; 3],
+            ..Material::new(Self::FALLBACK_NAME)
+        }
+    }
+}
+impl Material {
+    /// Materials with a lower `alpha` need blending.
+    pub const OPAQUE_THRESHOLD: f32 = 0.99;
+    /// Whether the material has to be drawn in a blended pass, sorted back to front.
+    pub fn is_transparent(&self) -> bool {
+        match self.alpha_mode {
+            AlphaMode::Opaque => self.alpha < Self::OPAQUE_THRESHOLD || self.dissolve_map.is_some(),
+            AlphaMode::Mask { .. } => false,
+            AlphaMode::Blend => true,
+        }
+    }
+    /// Face culling for the material's pipeline.
+    pub fn cull_mode(&self) -> Option<wgpu::Face> {
+        if self.double_sided {
+            None
+        } else {
+            Some(wgpu::Face::Back)
+        }
+    }
+}
+impl Default for Material {
+    fn default() -> Self {
+        Material::new("")
+    }
+}
+
+/// `Material` laid out for a WGSL uniform buffer:
+/// ```wgsl
+/// struct Material {
+///     diffuse: vec4<f32>; // rgb, alpha
+///     specular: vec3<f32>;
+///     shininess: f32;
+///     flags: u32;
+///     bump_multiplier: f32;
+///     roughness: f32;
+///     metallic: f32;
+///     emissive: vec3<f32>;
+///     sheen: f32;
+///     anisotropy: f32;
+///     alpha_cutoff: f32; // with ALPHA_MASK
+///     uv_transform: mat2x4<f32>; // rows of the 2×3 matrix, padded
+/// };
+/// ```
+/// The PBR values default to `roughness = 1` and `metallic = sheen = anisotropy = 0` when the
+/// file doesn't set them, `PBR` in `flags` tells whether it did.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub diffuse: [f32; 4],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub flags: u32,
+    pub bump_multiplier: f32,
+    pub roughness: f32,
+    pub metallic: f32,
+    pub emissive: [f32; 3],
+    pub sheen: f32,
+    pub anisotropy: f32,
+    pub alpha_cutoff: f32,
+    pub _padding: [u32; 2],
+    /// `Material::uv_transform`, each row padded to a `vec4`.
+    pub uv_transform: [[f32; 4]; 2],
+}
+const _: () = assert!(std::mem::size_of::<MaterialUniform>() == 112);
+// The offsets WGSL gives the same fields, with `vec3`s and the `mat2x4` aligned to 16 bytes
+const _: () = assert!(offset_of!(MaterialUniform, flags) == 32);
+const _: () = assert!(offset_of!(MaterialUniform, emissive) == 48);
+const _: () = assert!(offset_of!(MaterialUniform, sheen) == 60);
+const _: () = assert!(offset_of!(MaterialUniform, uv_transform) == 80);
+
+impl Material {
+    pub const AMBIENT_MAP: u32 = 1 << 0;
+    pub const DIFFUSE_MAP: u32 = 1 << 1;
+    pub const SPECULAR_MAP: u32 = 1 << 2;
+    pub const SHININESS_MAP: u32 = 1 << 3;
+    pub const DISSOLVE_MAP: u32 = 1 << 4;
+    pub const BUMP_MAP: u32 = 1 << 5;
+    pub const NORMAL_MAP: u32 = 1 << 6;
+    /// There's a bump map but no normal map, the shader has to derive normals from the heights.
+    pub const BUMP_AS_HEIGHT: u32 = 1 << 7;
+    /// `illum 0`, output the diffuse color without lighting.
+    pub const UNLIT: u32 = 1 << 8;
+    /// `illum 1`, leave out the specular term.
+    pub const NO_SPECULAR: u32 = 1 << 9;
+    /// `Pr` or `Pm` was given, shade with the metallic-roughness model.
+    pub const PBR: u32 = 1 << 10;
+    pub const ROUGHNESS_MAP: u32 = 1 << 11;
+    pub const METALLIC_MAP: u32 = 1 << 12;
+    pub const EMISSIVE_MAP: u32 = 1 << 13;
+    /// `AlphaMode::Mask`, discard fragments below `alpha_cutoff`.
+    pub const ALPHA_MASK: u32 = 1 << 14;
+
+    /// Which maps are present, as `*_MAP` bits.
+    pub fn map_flags(&self) -> u32 {
+        let flags = [
+            (&self.ambient_map, Self::AMBIENT_MAP),
+            (&self.diffuse_map, Self::DIFFUSE_MAP),
+            (&self.specular_map, Self::SPECULAR_MAP),
+            (&self.shininess_map, Self::SHININESS_MAP),
+            (&self.dissolve_map, Self::DISSOLVE_MAP),
+            (&self.bump_map, Self::BUMP_MAP),
+            (&self.normal_map, Self::NORMAL_MAP),
+            (&self.roughness_map, Self::ROUGHNESS_MAP),
+            (&self.metallic_map, Self::METALLIC_MAP),
+            (&self.emissive_map, Self::EMISSIVE_MAP),
+        ]
+        .iter()
+        .filter(|(map, _)| map.is_some())
+        .fold(0, |flags, (_, flag)| flags | flag);
+        if self.bump_map.is_some() && self.normal_map.is_none() {
+            flags | Self::BUMP_AS_HEIGHT
+        } else {
+            flags
+        }
+    }
+    /// Shading bits for the illumination model, `UNLIT` or `NO_SPECULAR`.
+    pub fn illumination_flags(&self) -> u32 {
+        match self.illumination.supported() {
+            IlluminationModel::ColorOnly => Self::UNLIT,
+            IlluminationModel::Diffuse => Self::NO_SPECULAR,
+            _ => 0,
+        }
+    }
+    /// UV transform of the diffuse map, which all maps are sampled with.
+    pub fn uv_transform(&self) -> [[f32; 3]; 2] {
+        self.diffuse_map
+            .as_ref()
+            .map(|map| map.options)
+            .unwrap_or_default()
+            .uv_transform()
+    }
+    pub fn to_uniform(&self) -> MaterialUniform {
+        let [[a, b, c], [d, e, f]] = self.uv_transform();
+        let [r, g, b] = self.diffuse;
+        let mut flags = self.map_flags() | self.illumination_flags();
+        if self.roughness.is_some() || self.metallic.is_some() {
+            flags |= Self::PBR;
+        }
+        let alpha_cutoff = match self.alpha_mode {
+            AlphaMode::Mask { cutoff } => {
+                flags |= Self::ALPHA_MASK;
+                cutoff
+            }
+            _ => 0.0,
+        };
+        MaterialUniform {
+            diffuse: [r, g, b, self.alpha],
+            // Zeroed as well so shaders ignoring the flags still drop the highlight
+            specular: if flags & Self::NO_SPECULAR != 0 {
+                [0.0; 3]
+            } else {
+                self.specular
+            },
+            shininess: self.shininess,
+            flags,
+            bump_multiplier: self.bump_multiplier,
+            roughness: self.roughness.unwrap_or(1.0),
+            metallic: self.metallic.unwrap_or(0.0),
+            emissive: self.emissive,
+            sheen: self.sheen.unwrap_or(0.0),
+            anisotropy: self.anisotropy.unwrap_or(0.0),
+            alpha_cutoff,
+            _padding: [0; 2],
+            uv_transform: [[a, b, c, 0.0], [d, e, f, 0.0]],
+        }
+    }
+    /// Layout with the material uniform at binding 0, a sampler at 1, the diffuse, ambient
+    /// and emissive maps at 2 to 4, the normal map at 5 and the roughness map at 6, visible to
+    /// the fragment stage. The roughness map is glTF's packed metallic-roughness texture when
+    /// both maps are the same, roughness in green and metallic in blue. Missing maps are bound
+    /// to `FallbackTextures` so every material fits it.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                texture(2),
+                texture(3),
+                texture(4),
+                texture(5),
+                texture(6),
+            ],
+        })
+    }
+    /// Uploads `map` in `format`, sRGB for colors and linear for data. Failures are logged and
+    /// give `None` so the fallback is bound instead.
+    fn load_map(
+        source: &dyn AssetSource,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        map: &Option<TextureRef>,
+        format: wgpu::TextureFormat,
+    ) -> Option<Texture> {
+        let map = map.as_ref()?;
+        if let Some(image) = &map.image {
+            let label = map.path.to_string_lossy();
+            let (width, height) = (image.width, image.height);
+            let label = Some(label.as_ref());
+            let rgba = &image.rgba;
+            return Some(Texture::from_rgba8(device, queue, rgba, width, height, format, label));
+        }
+        Self::load_map_file(source, device, queue, &map.path, format)
+    }
+    #[cfg(feature = "image")]
+    fn load_map_file(
+        source: &dyn AssetSource,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        format: wgpu::TextureFormat,
+    ) -> Option<Texture> {
+        use crate::texture::Error;
+        let load = || -> Result<Texture, Error> {
+            let bytes = source.read(path).map_err(|e| Error::IO(path.to_owned(), e))?;
+            let image = image::load_from_memory(&bytes)
+                .map_err(|e| Error::Image(Some(path.to_owned()), e))?;
+            let label = path.to_string_lossy();
+            Ok(Texture::from_image(device, queue, &image, format, Some(&label)))
+        };
+        load().map_err(|e| log::warn!("{}", e)).ok()
+    }
+    #[cfg(not(feature = "image"))]
+    fn load_map_file(
+        _source: &dyn AssetSource,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        path: &Path,
+        _format: wgpu::TextureFormat,
+    ) -> Option<Texture> {
+        log::warn!("can't load '{}' without the image feature", path.display());
+        None
+    }
+    /// Uploads the material, loads its maps and creates its bind group for `layout`.
+    pub fn bind(
+        self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        fallback: &FallbackTextures,
+    ) -> BoundMaterial {
+        self.bind_from(&FileSystem, device, queue, layout, fallback)
+    }
+    /// `bind` with the maps read through `source`.
+    pub fn bind_from(
+        self,
+        source: &dyn AssetSource,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        fallback: &FallbackTextures,
+    ) -> BoundMaterial {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} material buffer", self.name)),
+            contents: bytemuck::cast_slice(&[self.to_uniform()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let (srgb, linear) = (Texture::FORMAT, Texture::LINEAR_FORMAT);
+        let load = |map, format| Self::load_map(source, device, queue, map, format);
+        let diffuse = load(&self.diffuse_map, srgb);
+        let ambient = load(&self.ambient_map, srgb);
+        let emissive = load(&self.emissive_map, srgb);
+        let normal = load(&self.normal_map, linear);
+        let roughness = load(&self.roughness_map, linear);
+        let sampler_label = format!("{} material sampler", self.name);
+        let sampler =
+            self.sampler.create_sampler(device, fallback.downlevel_flags, Some(&sampler_label));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} material bind group", self.name)),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(
+                        &diffuse.as_ref().unwrap_or(&fallback.white).view,
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(
+                        &ambient.as_ref().unwrap_or(&fallback.white).view,
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(
+                        &emissive.as_ref().unwrap_or(&fallback.black).view,
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(
+                        &normal.as_ref().unwrap_or(&fallback.normal).view,
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(
+                        &roughness.as_ref().unwrap_or(&fallback.white).view,
+                    ),
+                },
+            ],
+        });
+        BoundMaterial {
+            material: self,
+            buffer,
+            bind_group,
+            sampler,
+            textures: [diffuse, ambient, emissive, normal, roughness]
+                .into_iter()
+                .flatten()
+                .collect(),
+        }
+    }
+}
+
+/// 1×1 textures bound in place of missing maps. White for maps multiplied with a color, black
+/// for maps added to it and a flat tangent space normal for normal maps.
+pub struct FallbackTextures {
+    pub white: Texture,
+    pub black: Texture,
+    pub normal: Texture,
+    /// What the adapter supports, for creating the material samplers. Empty until it's set,
+    /// which turns anisotropic filtering off.
+    pub downlevel_flags: wgpu::DownlevelFlags,
+}
+impl FallbackTextures {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let flat = [128, 128, 255, 255];
+        FallbackTextures {
+            white: Texture::white(device, queue),
+            black: Texture::black(device, queue),
+            normal: Texture::from_rgba8(
+                device,
+                queue,
+                &flat,
+                1,
+                1,
+                Texture::LINEAR_FORMAT,
+                Some("Flat Normal Texture"),
+            ),
+            downlevel_flags: wgpu::DownlevelFlags::empty(),
+        }
+    }
+}
+
+/// A material uploaded to the GPU. Share it between entities with an `Rc` so they share the
+/// bind group too.
+pub struct BoundMaterial {
+    pub material: Material,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    /// Made from `material.sampler`.
+    pub sampler: wgpu::Sampler,
+    /// The maps that were loaded.
+    pub textures: Vec<Texture>,
+}
+impl BoundMaterial {
+    pub fn is_transparent(&self) -> bool {
+        self.material.is_transparent()
+    }
+    /// Re-uploads the uniform after `material` was changed.
+    pub fn update(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[self.material.to_uniform()]),
+        );
+    }
+}
+
+/// Binds materials for a device, each at most once. Materials are keyed by their `Arc` so
+/// objects sharing an MTL file through `MtlCache` share the bind groups too.
+pub struct MaterialCache {
+    pub layout: wgpu::BindGroupLayout,
+    pub fallback_textures: FallbackTextures,
+    fallback: Rc<BoundMaterial>,
+    /// The `Arc` is kept so its address isn't reused while it's a key.
+    bound: HashMap<*const Material, (Arc<Material>, Rc<BoundMaterial>)>,
+    /// Where maps are read from.
+    source: Rc<dyn AssetSource>,
+}
+impl MaterialCache {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self::with_source(device, queue, Rc::new(FileSystem))
+    }
+    /// Reads maps through `source` instead of the filesystem.
+    pub fn with_source(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: Rc<dyn AssetSource>,
+    ) -> Self {
+        let layout = Material::bind_group_layout(device);
+        let fallback_textures = FallbackTextures::new(device, queue);
+        let fallback = Material::fallback().bind(device, queue, &layout, &fallback_textures);
+        let fallback = Rc::new(fallback);
+        MaterialCache {
+            layout,
+            fallback_textures,
+            fallback,
+            bound: HashMap::new(),
+            source,
+        }
+    }
+    /// The bound `Material::fallback`.
+    pub fn fallback(&self) -> Rc<BoundMaterial> {
+        self.fallback.clone()
+    }
+    pub fn bind(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material: &Arc<Material>,
+    ) -> Rc<BoundMaterial> {
+        let (layout, fallback) = (&self.layout, &self.fallback_textures);
+        let source = self.source.as_ref();
+        self.bound
+            .entry(Arc::as_ptr(material))
+            .or_insert_with(|| {
+                let material_copy = material.as_ref().clone();
+                let bound = material_copy.bind_from(source, device, queue, layout, fallback);
+                let bound = Rc::new(bound);
+                (material.clone(), bound)
+            })
+            .1
+            .clone()
+    }
+    /// Binds `library[name]`, or returns the fallback if the library doesn't define it.
+    pub fn get(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        library: &HashMap<String, Arc<Material>>,
+        name: &str,
+    ) -> Rc<BoundMaterial> {
+        match library.get(name) {
+            Some(material) => self.bind(device, queue, material),
+            None => {
+                log::warn!("material {:?} isn't defined, using the default material", name);
+                self.fallback()
+            }
+        }
+    }
+    /// The material of every submesh of `object`, in order. Submeshes before the first
+    /// `usemtl` get the fallback.
+    pub fn resolve(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        library: &HashMap<String, Arc<Material>>,
+        object: &Object,
+    ) -> Vec<Rc<BoundMaterial>> {
+        object
+            .submeshes()
+            .iter()
+            .map(|submesh| match submesh.material {
+                Some(material) => {
+                    self.get(device, queue, library, &object.materials()[material])
+                }
+                None => self.fallback(),
+            })
+            .collect()
+    }
+    /// Forgets bind groups of materials nothing else holds anymore, like ones dropped from an
+    /// invalidated `MtlCache` entry.
+    pub fn evict_unused(&mut self) {
+        self.bound.retain(|_, (material, _)| Arc::strong_count(material) > 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::model::files::mtl::MtlCache;
+    use crate::entity::model::files::source::EmbeddedFiles;
+    use crate::testing::device;
+
+    #[test]
+    fn shared_libraries_share_bind_groups() {
+        let (device, queue) = match device() {
+            Some(device) => device,
+            None => return,
+        };
+        let source = EmbeddedFiles::new([("lib.mtl", &b"newmtl Red\nKd 1 0 0\n"[..])]);
+        let libraries = MtlCache::new();
+        let first = libraries.load_from(&source, "lib.mtl").unwrap();
+        let second = libraries.load_from(&source, "lib.mtl").unwrap();
+        let mut materials = MaterialCache::with_source(&device, &queue, Rc::new(source));
+        let a = materials.get(&device, &queue, &first, "Red");
+        let b = materials.get(&device, &queue, &second, "Red");
+        assert!(Rc::ptr_eq(&a, &b));
+        let missing = materials.get(&device, &queue, &first, "Blue");
+        assert!(Rc::ptr_eq(&missing, &materials.fallback()));
+    }
+}
@@ -1,12 +1,922 @@
+use crate::camera::Ray;
+use crate::entity::model::bounds::Aabb;
+use crate::entity::model::files::obj::ObjectBuilder;
+use crate::entity::model::object::Stats;
+use crate::entity::model::validate::InvalidMesh;
+use crate::entity::model::{
+    primitives, raycast, simplify, wireframe_indices, Faces, Hit, Object, ValidationIssue, Vertex,
+    VertexExt,
+};
+use cgmath::{EuclideanSpace, Matrix4, SquareMatrix};
+use memoffset::offset_of;
+use std::cell::Cell;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::rc::Rc;
+use wgpu::util::DeviceExt;
+
+/// `Vertex` in 20 bytes instead of 32. The normal is octahedral encoded into two snorm16s and
+/// the texture coordinates are unorm16s, so they're clamped to `0..1` and textures relying
+/// on repeating UVs need the full `Vertex`. Shaders get the normal as a `vec2<f32>` and decode
+/// it with:
+///
+/// ```wgsl
+/// fn oct_decode(e: vec2<f32>) -> vec3<f32> {
+///     var n = vec3<f32>(e, 1.0 - abs(e.x) - abs(e.y));
+///     if (n.z < 0.0) {
+///         let sign = select(vec2<f32>(-1.0), vec2<f32>(1.0), n.xy >= vec2<f32>(0.0));
+///         n = vec3<f32>((1.0 - abs(n.yx)) * sign, n.z);
+///     }
+///     return normalize(n);
+/// }
+/// ```
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CompressedVertex {
+    pub position: [f32; 3],
+    /// The normal's two snorm16s then the UV's two unorm16s, the first of each pair in the
+    /// low bits.
+    pub normal_uv: [u32; 2],
+}
+const _: () = assert!(std::mem::size_of::<CompressedVertex>() == 20);
+
+impl CompressedVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] = [
+        wgpu::VertexAttribute {
+            offset: offset_of!(CompressedVertex, position) as wgpu::BufferAddress,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: offset_of!(CompressedVertex, normal_uv) as wgpu::BufferAddress,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Snorm16x2,
+        },
+        wgpu::VertexAttribute {
+            offset: (offset_of!(CompressedVertex, normal_uv) + 4) as wgpu::BufferAddress,
+            shader_location: 2,
+            format: wgpu::VertexFormat::Unorm16x2,
+        },
+    ];
+    /// The same locations as `Vertex::LAYOUT`, with the encoded normal at 1.
+    pub const COMPRESSED_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<CompressedVertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &Self::ATTRIBUTES,
+    };
+    pub fn decompress(&self) -> Vertex {
+        let low = |x: u32| (x & 0xffff) as u16;
+        let high = |x: u32| (x >> 16) as u16;
+        let snorm = |x: u16| (x as i16 as f32 / i16::MAX as f32).max(-1.0);
+        let unorm = |x: u16| x as f32 / u16::MAX as f32;
+        let [normal, uv] = self.normal_uv;
+        Vertex {
+            position: self.position,
+            normal: oct_decode([snorm(low(normal)), snorm(high(normal))]),
+            texture_coords: [unorm(low(uv)), unorm(high(uv))],
+        }
+    }
+    pub fn as_bytes(vertices: &[CompressedVertex]) -> &[u8] {
+        bytemuck::cast_slice(vertices)
+    }
+}
+impl From<Vertex> for CompressedVertex {
+    fn from(vertex: Vertex) -> Self {
+        let snorm = |x: f32| (x.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16 as u16;
+        let unorm = |x: f32| (x.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16;
+        let pack = |low: u16, high: u16| u32::from(low) | u32::from(high) << 16;
+        let [x, y] = oct_encode(vertex.normal);
+        let [u, v] = vertex.texture_coords;
+        CompressedVertex {
+            position: vertex.position,
+            normal_uv: [pack(snorm(x), snorm(y)), pack(unorm(u), unorm(v))],
+        }
+    }
+}
+
+/// Maps a direction onto the octahedron `|x| + |y| + |z| = 1` and folds the lower half over
+/// the upper, giving a point in `-1..1` on both axes.
+fn oct_encode([x, y, z]: [f32; 3]) -> [f32; 2] {
+    let sum = x.abs() + y.abs() + z.abs();
+    if sum == 0.0 {
+        return [0.0, 0.0];
+    }
+    let (x, y) = (x / sum, y / sum);
+    let sign = |v: f32| if v >= 0.0 { 1.0 } else { -1.0 };
+    if z < 0.0 {
+        [(1.0 - y.abs()) * sign(x), (1.0 - x.abs()) * sign(y)]
+    } else {
+        [x, y]
+    }
+}
+
+fn oct_decode([x, y]: [f32; 2]) -> [f32; 3] {
+    let z = 1.0 - x.abs() - y.abs();
+    let sign = |v: f32| if v >= 0.0 { 1.0 } else { -1.0 };
+    let (x, y) = if z < 0.0 {
+        ((1.0 - y.abs()) * sign(x), (1.0 - x.abs()) * sign(y))
+    } else {
+        (x, y)
+    };
+    let length = (x * x + y * y + z * z).sqrt();
+    [x / length, y / length, z / length]
+}
+
+/// Which vertex struct a mesh's vertex buffer holds.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum VertexLayout {
+    /// `Vertex`
+    Base,
+    /// `VertexExt`
+    WithTangent,
+    /// `CompressedVertex`
+    Compressed,
+}
+impl VertexLayout {
+    /// The layout pipelines drawing the mesh need.
+    pub fn buffer_layout(self) -> wgpu::VertexBufferLayout<'static> {
+        match self {
+            VertexLayout::Base => Vertex::LAYOUT,
+            VertexLayout::WithTangent => VertexExt::LAYOUT,
+            VertexLayout::Compressed => CompressedVertex::COMPRESSED_LAYOUT,
+        }
+    }
+}
+
+/// Whether a mesh's vertices can change after it's uploaded.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum MeshUsage {
+    Static,
+    /// The vertex buffer is also `COPY_DST` so `Mesh::update_vertices` can write to it.
+    Dynamic,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum UpdateError {
+    /// The mesh was uploaded with `MeshUsage::Static`.
+    NotDynamic,
+    /// There are more vertices than the buffer was created for.
+    TooManyVertices { given: usize, capacity: u32 },
+}
+impl Display for UpdateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateError::NotDynamic => write!(f, "the mesh isn't dynamic"),
+            UpdateError::TooManyVertices { given, capacity } => write!(
+                f,
+                "{} vertices don't fit in a buffer of {}",
+                given, capacity
+            ),
+        }
+    }
+}
+impl std::error::Error for UpdateError {}
+
+/// A range of a mesh's indices drawn with one material, all sharing its buffers.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct SubMesh {
+    pub index_range: Range<u32>,
+    /// Index into the bind groups given to `Mesh::draw_all`, in the order the object names
+    /// its materials. Ranges without a material get the index after the last named one, so
+    /// the fallback material goes there.
+    pub material: usize,
+    /// The OBJ group or glTF mesh name, empty if there isn't one.
+    pub name: String,
+}
+
+/// The buffers are `Rc`s so entities can draw them without copying.
 pub struct Mesh {
-    vertex_buffer: wgpu::Buffer,
-    indices_buffer: wgpu::Buffer,
+    vertex_buffer: Rc<wgpu::Buffer>,
+    indices_buffer: Rc<wgpu::Buffer>,
+    /// Per vertex RGBA for vertex slot 1, only for objects with colors.
+    color_buffer: Option<Rc<wgpu::Buffer>>,
+    layout: VertexLayout,
+    /// `Uint16` when every index fits, which halves the index buffer.
+    index_format: wgpu::IndexFormat,
+    /// Indices of the full detail mesh, LODs come after them.
+    index_count: u32,
+    /// Vertices in use, fewer than `vertex_capacity` after a smaller `update_vertices`.
+    vertex_count: Cell<u32>,
+    vertex_capacity: u32,
+    usage: MeshUsage,
+    label: Option<String>,
+    /// Index range of each level of detail, the first is the whole object.
+    lods: Vec<Range<u32>>,
+    /// Ranges of the full detail indices by material, covering all of them.
+    submeshes: Vec<SubMesh>,
+    /// Around the vertices in use, in the mesh's space.
+    bounds: Cell<Aabb>,
+    /// Line list of the full detail mesh's edges in `index_format`, see `with_wireframe`.
+    wireframe: Option<(Rc<wgpu::Buffer>, u32)>,
+    /// Size of all the buffers above.
+    gpu_bytes: u64,
 }
 impl Mesh {
-    pub fn new(vertex_buffer: wgpu::Buffer, indices_buffer: wgpu::Buffer) -> Mesh {
+    /// Layout of the color buffer in vertex slot 1, at location 4 so it can follow either
+    /// `VertexLayout`.
+    pub const COLOR_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &[wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 4,
+            format: wgpu::VertexFormat::Float32x4,
+        }],
+    };
+    /// Uploads `VertexExt`s when the object has tangents and `Vertex`s otherwise.
+    pub fn new(device: &wgpu::Device, object: &Object, label: Option<&str>) -> Mesh {
+        let layout = match object.tangents() {
+            Some(_) => VertexLayout::WithTangent,
+            None => VertexLayout::Base,
+        };
+        Self::with_layout(device, object, label, layout)
+    }
+    /// Like `new` but in debug builds the object is validated first, and it's an error if
+    /// there's anything that would make the GPU read out of bounds or draw garbage. Problems
+    /// that only affect shading are logged. Release builds skip the check.
+    pub fn try_new(
+        device: &wgpu::Device,
+        object: &Object,
+        label: Option<&str>,
+    ) -> Result<Mesh, InvalidMesh> {
+        if cfg!(debug_assertions) {
+            Self::check(label, object.validate())?;
+        }
+        Ok(Self::new(device, object, label))
+    }
+    /// Uploads what `builder` has parsed so far as `Vertex`s, with its colors, without
+    /// building an `Object`. Vertices no face uses are uploaded too unless it was compacted.
+    /// A builder without any triangles is an error, and in debug builds so is anything else
+    /// that `try_new` rejects. `MeshUsage::Dynamic` allows `update_vertices`, e.g. to animate
+    /// the builder's vertices on the CPU.
+    pub fn from_builder(
+        device: &wgpu::Device,
+        builder: &ObjectBuilder,
+        label: Option<&str>,
+        usage: MeshUsage,
+    ) -> Result<Mesh, InvalidMesh> {
+        let issues = if cfg!(debug_assertions) {
+            builder.validate()
+        } else {
+            let mut issues = Vec::new();
+            if builder.mesh_vertices.is_empty() {
+                issues.push(ValidationIssue::NoVertices);
+            }
+            if builder.mesh_indices.is_empty() {
+                issues.push(ValidationIssue::NoIndices);
+            }
+            issues
+        };
+        Self::check(label, issues)?;
+        let colors = Some(&builder.mesh_colors[..]).filter(|colors| !colors.is_empty());
+        // Numbered by first use like `ObjectBuilder::build` does
+        let mut materials: Vec<&str> = Vec::new();
+        for name in builder
+            .submeshes
+            .iter()
+            .filter_map(|s| s.material.as_deref())
+        {
+            if !materials.contains(&name) {
+                materials.push(name);
+            }
+        }
+        let submeshes = builder.submeshes.iter().map(|submesh| SubMesh {
+            index_range: submesh.indices.clone(),
+            material: submesh
+                .material
+                .as_deref()
+                .and_then(|name| materials.iter().position(|&m| m == name))
+                .unwrap_or(materials.len()),
+            name: submesh.group.clone().unwrap_or_default(),
+        });
+        let index_count = builder.mesh_indices.len() as u32;
+        let mut mesh = Self::create(
+            device,
+            label,
+            VertexLayout::Base,
+            Vertex::as_bytes(&builder.mesh_vertices),
+            &builder.mesh_indices,
+            colors,
+            usage,
+        );
+        mesh.submeshes = Self::cover(submeshes.collect(), index_count, materials.len());
+        mesh.bounds.set(Self::vertex_bounds(&builder.mesh_vertices));
+        Ok(mesh)
+    }
+    /// `submeshes` without the empty ones, or the whole mesh if there are none left.
+    fn cover(submeshes: Vec<SubMesh>, index_count: u32, unnamed: usize) -> Vec<SubMesh> {
+        let mut submeshes: Vec<SubMesh> = submeshes
+            .into_iter()
+            .filter(|s| !s.index_range.is_empty())
+            .collect();
+        if submeshes.is_empty() && index_count > 0 {
+            submeshes.push(SubMesh {
+                index_range: 0..index_count,
+                material: unnamed,
+                name: String::new(),
+            });
+        }
+        submeshes
+    }
+    /// `primitives::cube`, for placeholders and prototyping.
+    pub fn cube(device: &wgpu::Device, size: f32) -> Mesh {
+        Self::primitive(device, primitives::cube(size), "Cube")
+    }
+    /// `primitives::uv_sphere`.
+    pub fn uv_sphere(device: &wgpu::Device, radius: f32, rings: u32, segments: u32) -> Mesh {
+        Self::primitive(
+            device,
+            primitives::uv_sphere(radius, rings, segments),
+            "UV Sphere",
+        )
+    }
+    /// `primitives::plane`.
+    pub fn plane(device: &wgpu::Device, width: f32, depth: f32, subdivisions: u32) -> Mesh {
+        Self::primitive(
+            device,
+            primitives::plane(width, depth, subdivisions),
+            "Plane",
+        )
+    }
+    /// `primitives::cylinder`.
+    pub fn cylinder(device: &wgpu::Device, radius: f32, height: f32, segments: u32) -> Mesh {
+        Self::primitive(
+            device,
+            primitives::cylinder(radius, height, segments),
+            "Cylinder",
+        )
+    }
+    fn primitive(
+        device: &wgpu::Device,
+        (vertices, indices): (Vec<Vertex>, Vec<u32>),
+        name: &str,
+    ) -> Mesh {
+        let stats = Stats {
+            vertices: vertices.len(),
+            triangles: indices.len() / 3,
+            ..Stats::default()
+        };
+        let name = Some(String::from(name));
+        let object = Object::new(
+            name.clone(),
+            vertices,
+            indices,
+            vec![],
+            vec![],
+            vec![],
+            stats,
+        );
+        Self::new(device, &object, name.as_deref())
+    }
+    /// `from_builder` with a second index buffer of the triangles' edges, see
+    /// `wireframe_indices`. Drawn with `draw_wireframe` and a `LineList` pipeline, which works
+    /// everywhere unlike `PolygonMode::Line`.
+    pub fn with_wireframe(
+        device: &wgpu::Device,
+        builder: &ObjectBuilder,
+        label: Option<&str>,
+    ) -> Result<Mesh, InvalidMesh> {
+        let mut mesh = Self::from_builder(device, builder, label, MeshUsage::Static)?;
+        let lines = wireframe_indices(&builder.mesh_indices);
+        let wireframe_label = label.map(|s| String::from(s) + " wireframe index buffer");
+        let buffer = Self::create_index_buffer(
+            device,
+            wireframe_label.as_deref(),
+            &lines,
+            mesh.index_format,
+        );
+        mesh.gpu_bytes += Self::index_bytes(lines.len(), mesh.index_format);
+        mesh.wireframe = Some((Rc::new(buffer), lines.len() as u32));
+        Ok(mesh)
+    }
+    /// Logs the issues that only affect shading, errors if there are others.
+    fn check(label: Option<&str>, issues: Vec<ValidationIssue>) -> Result<(), InvalidMesh> {
+        let (errors, warnings): (Vec<ValidationIssue>, _) =
+            issues.into_iter().partition(ValidationIssue::is_error);
+        for warning in warnings {
+            log::warn!("mesh {}: {}", label.unwrap_or("without a label"), warning);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidMesh {
+                label: label.map(String::from),
+                issues: errors,
+            })
+        }
+    }
+    /// Uploads the vertices as `layout`. Objects without tangents get
+    /// `tangents::fallback_tangent` for `WithTangent`, `Compressed` drops any tangents.
+    pub fn with_layout(
+        device: &wgpu::Device,
+        object: &Object,
+        label: Option<&str>,
+        layout: VertexLayout,
+    ) -> Mesh {
+        Self::upload(device, object, label, layout, object.indices(), Vec::new())
+    }
+    /// Also uploads simplified levels of detail with `ratios` of the object's triangles, e.g.
+    /// `[0.25, 0.05]`, after the full detail indices. They share the vertex buffer.
+    pub fn with_lods(
+        device: &wgpu::Device,
+        object: &Object,
+        label: Option<&str>,
+        ratios: &[f32],
+    ) -> Mesh {
+        let layout = match object.tangents() {
+            Some(_) => VertexLayout::WithTangent,
+            None => VertexLayout::Base,
+        };
+        let (lods, ranges) = simplify::lod_chain(object.vertices(), object.indices(), ratios);
+        let mut indices = object.indices().to_vec();
+        let offset = indices.len() as u32;
+        indices.extend_from_slice(&lods);
+        let ranges = ranges
+            .into_iter()
+            .map(|r| r.start + offset..r.end + offset)
+            .collect();
+        Self::upload(device, object, label, layout, &indices, ranges)
+    }
+    fn upload(
+        device: &wgpu::Device,
+        object: &Object,
+        label: Option<&str>,
+        layout: VertexLayout,
+        indices: &[u32],
+        simplified: Vec<Range<u32>>,
+    ) -> Mesh {
+        let vertices_ext;
+        let compressed: Vec<CompressedVertex>;
+        let contents = match layout {
+            VertexLayout::Base => Vertex::as_bytes(object.vertices()),
+            VertexLayout::WithTangent => {
+                vertices_ext = object.vertices_ext().unwrap_or_else(|| {
+                    object
+                        .vertices()
+                        .iter()
+                        .map(|&v| VertexExt::from(v))
+                        .collect()
+                });
+                VertexExt::as_bytes(&vertices_ext)
+            }
+            VertexLayout::Compressed => {
+                compressed = object.vertices().iter().map(Vertex::compress).collect();
+                CompressedVertex::as_bytes(&compressed)
+            }
+        };
+        let index_count = object.indices().len() as u32;
+        let lods = std::iter::once(0..index_count).chain(simplified).collect();
+        let unnamed = object.materials().len();
+        let submeshes = object.submeshes().iter().map(|submesh| SubMesh {
+            index_range: submesh.indices.clone(),
+            material: submesh.material.unwrap_or(unnamed),
+            name: submesh.name.clone().unwrap_or_default(),
+        });
+        let colors = object.colors();
+        let usage = MeshUsage::Static;
+        let mut mesh = Self::create(device, label, layout, contents, indices, colors, usage);
+        mesh.index_count = index_count;
+        mesh.lods = lods;
+        mesh.submeshes = Self::cover(submeshes.collect(), index_count, unnamed);
+        mesh.bounds.set(*object.bounds());
+        mesh
+    }
+    fn vertex_bounds(vertices: &[Vertex]) -> Aabb {
+        Aabb::from_points(vertices.iter().map(|v| v.position.into()))
+    }
+    fn create_index_buffer(
+        device: &wgpu::Device,
+        label: Option<&str>,
+        indices: &[u32],
+        format: wgpu::IndexFormat,
+    ) -> wgpu::Buffer {
+        let short_indices: Vec<u16>;
+        let contents = match format {
+            wgpu::IndexFormat::Uint16 => {
+                short_indices = indices.iter().map(|&i| i as u16).collect();
+                bytemuck::cast_slice(&short_indices)
+            }
+            wgpu::IndexFormat::Uint32 => bytemuck::cast_slice(indices),
+        };
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label,
+            contents,
+            usage: wgpu::BufferUsages::INDEX,
+        })
+    }
+    fn index_bytes(count: usize, format: wgpu::IndexFormat) -> u64 {
+        let size = match format {
+            wgpu::IndexFormat::Uint16 => std::mem::size_of::<u16>(),
+            wgpu::IndexFormat::Uint32 => std::mem::size_of::<u32>(),
+        };
+        (count * size) as u64
+    }
+    /// The mesh has all of `indices` as its one LOD and no submeshes, the callers fill those
+    /// in.
+    fn create(
+        device: &wgpu::Device,
+        label: Option<&str>,
+        layout: VertexLayout,
+        vertices: &[u8],
+        indices: &[u32],
+        colors: Option<&[[f32; 4]]>,
+        usage: MeshUsage,
+    ) -> Mesh {
+        let vertex_count = vertices.len() as u64 / layout.buffer_layout().array_stride;
+        let vertex_label_name = label.map(|s| (String::from(s) + " vertex buffer"));
+        let indices_label_name = label.map(|s| (String::from(s) + " index buffer"));
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: vertex_label_name.as_deref(),
+            contents: vertices,
+            usage: match usage {
+                MeshUsage::Static => wgpu::BufferUsages::VERTEX,
+                MeshUsage::Dynamic => wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            },
+        });
+        let index_format = if indices.iter().all(|&i| i <= u16::MAX as u32) {
+            wgpu::IndexFormat::Uint16
+        } else {
+            wgpu::IndexFormat::Uint32
+        };
+        let indices_buffer =
+            Self::create_index_buffer(device, indices_label_name.as_deref(), indices, index_format);
+        let color_buffer = colors.map(|colors| {
+            let color_label_name = label.map(|s| (String::from(s) + " color buffer"));
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: color_label_name.as_deref(),
+                contents: bytemuck::cast_slice(colors),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+            Rc::new(buffer)
+        });
         Mesh {
-            vertex_buffer,
-            indices_buffer,
+            vertex_buffer: Rc::new(vertex_buffer),
+            indices_buffer: Rc::new(indices_buffer),
+            color_buffer,
+            layout,
+            index_format,
+            index_count: indices.len() as u32,
+            vertex_count: Cell::new(vertex_count as u32),
+            vertex_capacity: vertex_count as u32,
+            usage,
+            label: label.map(String::from),
+            lods: vec![0..indices.len() as u32],
+            submeshes: Vec::new(),
+            bounds: Cell::new(Aabb::empty()),
+            wireframe: None,
+            gpu_bytes: vertices.len() as u64
+                + Self::index_bytes(indices.len(), index_format)
+                + colors.map_or(0, |colors| std::mem::size_of_val(colors) as u64),
+        }
+    }
+    pub fn layout(&self) -> VertexLayout {
+        self.layout
+    }
+    pub fn index_format(&self) -> wgpu::IndexFormat {
+        self.index_format
+    }
+    /// Indices of the full detail mesh.
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+    /// Vertices in use, see `update_vertices`.
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count.get()
+    }
+    /// Around the vertices, kept up to date by `update_vertices`. See `Entity::world_bounds`
+    /// for world space.
+    pub fn bounds(&self) -> Aabb {
+        self.bounds.get()
+    }
+    /// `raycast::intersect_ray` on this mesh's `vertices` and `indices`, which the mesh doesn't
+    /// keep on the CPU, skipping them when the ray misses `bounds`.
+    pub fn intersect_ray(
+        &self,
+        ray: &Ray,
+        vertices: &[Vertex],
+        indices: &[u32],
+        transform: &Matrix4<f32>,
+        faces: Faces,
+    ) -> Option<Hit> {
+        let local = ray.transform(transform.invert()?);
+        local.intersect_aabb(&self.bounds())?;
+        let origin = ray.origin.to_vec();
+        raycast::intersect_ray(origin, ray.direction, vertices, indices, transform, faces)
+    }
+    /// Vertices the buffer has room for.
+    pub fn vertex_capacity(&self) -> u32 {
+        self.vertex_capacity
+    }
+    pub fn usage(&self) -> MeshUsage {
+        self.usage
+    }
+    /// Writes `vertices` over the start of the vertex buffer in place, so entities sharing
+    /// the mesh see the change without new buffers, and refits `bounds`. Converts them to the
+    /// mesh's layout first, `WithTangent` gets fallback tangents. Fewer vertices than before
+    /// leave the rest of the buffer unused, draws only bind the updated part so indices past
+    /// it fail validation. The indices aren't changed.
+    pub fn update_vertices(
+        &self,
+        queue: &wgpu::Queue,
+        vertices: &[Vertex],
+    ) -> Result<(), UpdateError> {
+        if self.usage != MeshUsage::Dynamic {
+            return Err(UpdateError::NotDynamic);
+        }
+        if vertices.len() > self.vertex_capacity as usize {
+            return Err(UpdateError::TooManyVertices {
+                given: vertices.len(),
+                capacity: self.vertex_capacity,
+            });
+        }
+        match self.layout {
+            VertexLayout::Base => {
+                queue.write_buffer(&self.vertex_buffer, 0, Vertex::as_bytes(vertices));
+            }
+            VertexLayout::WithTangent => {
+                let vertices: Vec<VertexExt> = vertices.iter().map(|&v| v.into()).collect();
+                queue.write_buffer(&self.vertex_buffer, 0, VertexExt::as_bytes(&vertices));
+            }
+            VertexLayout::Compressed => {
+                let vertices: Vec<CompressedVertex> =
+                    vertices.iter().map(Vertex::compress).collect();
+                let bytes = CompressedVertex::as_bytes(&vertices);
+                queue.write_buffer(&self.vertex_buffer, 0, bytes);
+            }
+        }
+        self.vertex_count.set(vertices.len() as u32);
+        self.bounds.set(Self::vertex_bounds(vertices));
+        Ok(())
+    }
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+    /// Memory of the vertex, index, color and wireframe buffers on the GPU, LODs included.
+    pub fn gpu_bytes(&self) -> u64 {
+        self.gpu_bytes
+    }
+    /// Levels of detail, at least the full one.
+    pub fn lod_count(&self) -> usize {
+        self.lods.len()
+    }
+    /// Indices of LOD `level`, 0 being full detail. Levels past the last give the last.
+    pub fn lod(&self, level: usize) -> Range<u32> {
+        self.lods[level.min(self.lods.len() - 1)].clone()
+    }
+    /// Never empty unless the mesh has no indices.
+    pub fn submeshes(&self) -> &[SubMesh] {
+        &self.submeshes
+    }
+    pub fn vertex_buffer(&self) -> &Rc<wgpu::Buffer> {
+        &self.vertex_buffer
+    }
+    pub fn index_buffer(&self) -> &Rc<wgpu::Buffer> {
+        &self.indices_buffer
+    }
+    /// Pipelines drawing meshes with colors need `COLOR_LAYOUT` in slot 1.
+    pub fn color_buffer(&self) -> Option<&Rc<wgpu::Buffer>> {
+        self.color_buffer.as_ref()
+    }
+    /// Binds the vertex buffer to slot 0, the colors if there are any to slot 1 and the index
+    /// buffer in its format. The pass borrows the buffers, which works through an `Rc` as
+    /// long as whatever holds it outlives the pass, e.g. `entity.mesh.as_deref()`.
+    pub fn set_buffers<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        let stride = self.layout.buffer_layout().array_stride;
+        let used = self.vertex_count.get() as wgpu::BufferAddress * stride;
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..used));
+        if let Some(colors) = &self.color_buffer {
+            pass.set_vertex_buffer(1, colors.slice(..));
+        }
+        pass.set_index_buffer(self.indices_buffer.slice(..), self.index_format);
+    }
+    /// Whether there's an edge index buffer, see `with_wireframe`.
+    pub fn has_wireframe(&self) -> bool {
+        self.wireframe.is_some()
+    }
+    /// Binds the vertex buffer and the edge index buffer and draws the edges, the pipeline has
+    /// to use `PrimitiveTopology::LineList`. Does nothing without `with_wireframe`.
+    pub fn draw_wireframe<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, instances: Range<u32>) {
+        if let Some((buffer, count)) = &self.wireframe {
+            self.set_buffers(pass);
+            pass.set_index_buffer(buffer.slice(..), self.index_format);
+            pass.draw_indexed(0..*count, 0, instances);
         }
     }
+    /// Binds the buffers and draws the full detail mesh. The pipeline and bind groups have to
+    /// be set already.
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, instances: Range<u32>) {
+        self.draw_submesh(pass, 0..self.index_count, instances);
+    }
+    /// Binds the buffers and draws `indices`, e.g. the range of one material or a `lod`.
+    pub fn draw_submesh<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        indices: Range<u32>,
+        instances: Range<u32>,
+    ) {
+        self.set_buffers(pass);
+        pass.draw_indexed(indices, 0, instances);
+    }
+    /// Binds the buffers and draws each submesh with `materials[submesh.material]` bound to
+    /// group `material_group`. Adjacent submeshes with the same material are drawn together,
+    /// so splits from OBJ groups don't cost a draw each.
+    pub fn draw_all<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        material_group: u32,
+        materials: &'a [wgpu::BindGroup],
+    ) {
+        self.set_buffers(pass);
+        let mut bound = None;
+        for (material, indices) in self.draw_ranges() {
+            Self::draw_range(
+                pass,
+                material_group,
+                materials,
+                &mut bound,
+                material,
+                indices,
+            );
+        }
+    }
+    /// The draws of `draw_all`: the non-empty submeshes, with adjacent ones of the same
+    /// material merged.
+    fn draw_ranges(&self) -> Vec<(usize, Range<u32>)> {
+        let mut ranges: Vec<(usize, Range<u32>)> = Vec::new();
+        let submeshes = self.submeshes.iter().filter(|s| !s.index_range.is_empty());
+        for submesh in submeshes {
+            let range = &submesh.index_range;
+            match ranges.last_mut() {
+                Some((material, last))
+                    if *material == submesh.material && last.end == range.start =>
+                {
+                    last.end = range.end;
+                }
+                _ => ranges.push((submesh.material, range.clone())),
+            }
+        }
+        ranges
+    }
+    fn draw_range<'a>(
+        pass: &mut wgpu::RenderPass<'a>,
+        material_group: u32,
+        materials: &'a [wgpu::BindGroup],
+        bound: &mut Option<usize>,
+        material: usize,
+        indices: Range<u32>,
+    ) {
+        if *bound != Some(material) {
+            pass.set_bind_group(material_group, &materials[material], &[]);
+            *bound = Some(material);
+        }
+        pass.draw_indexed(indices, 0, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nf 1 3 4\n";
+
+    #[test]
+    fn from_builder_uploads_short_indices() {
+        let (device, _queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut builder = ObjectBuilder::new();
+        builder.read_lines(QUAD.as_bytes()).unwrap();
+        let mesh = Mesh::from_builder(&device, &builder, Some("Quad"), MeshUsage::Static).unwrap();
+        assert_eq!(mesh.index_format(), wgpu::IndexFormat::Uint16);
+        assert_eq!(mesh.index_count(), 6);
+        assert_eq!(mesh.vertex_count(), builder.mesh_vertices.len() as u32);
+        let vertex_bytes = std::mem::size_of_val(&builder.mesh_vertices[..]) as u64;
+        assert_eq!(mesh.gpu_bytes(), vertex_bytes + 6 * 2);
+        assert_eq!(mesh.label(), Some("Quad"));
+        assert_eq!(mesh.bounds().max, cgmath::Point3::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh.submeshes().len(), 1);
+        assert_eq!(mesh.submeshes()[0].index_range, 0..6);
+    }
+
+    #[test]
+    fn submeshes_and_merged_draws() {
+        let (device, _queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let source = format!(
+            "{}g A\nusemtl Red\nf 1 2 3\ng B\nusemtl Red\nf 1 3 4\nusemtl Blue\nf 1 2 4\n",
+            &QUAD[..QUAD.find('f').unwrap()]
+        );
+        let mut builder = ObjectBuilder::new();
+        builder.read_lines(source.as_bytes()).unwrap();
+        let mut mesh = Mesh::from_builder(&device, &builder, None, MeshUsage::Static).unwrap();
+        let submeshes: Vec<_> = mesh
+            .submeshes()
+            .iter()
+            .map(|s| (s.name.as_str(), s.material, s.index_range.clone()))
+            .collect();
+        assert_eq!(submeshes, [("A", 0, 0..3), ("B", 0, 3..6), ("B", 1, 6..9)]);
+        // The groups share a material, so they're one draw
+        assert_eq!(mesh.draw_ranges(), [(0, 0..6), (1, 6..9)]);
+
+        let submesh = |material, index_range| SubMesh {
+            index_range,
+            material,
+            name: String::new(),
+        };
+        mesh.submeshes = vec![
+            submesh(0, 0..3),
+            submesh(1, 3..3),
+            submesh(0, 3..6),
+            submesh(0, 9..12),
+        ];
+        // Empty ranges are skipped, ranges with a gap between them aren't merged
+        assert_eq!(mesh.draw_ranges(), [(0, 0..6), (0, 9..12)]);
+    }
+
+    #[test]
+    fn empty_builders_are_errors() {
+        let (device, _queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let builder = ObjectBuilder::new();
+        let error = Mesh::from_builder(&device, &builder, Some("Empty"), MeshUsage::Static)
+            .err()
+            .unwrap();
+        assert_eq!(error.label.as_deref(), Some("Empty"));
+        assert!(error.issues.contains(&ValidationIssue::NoIndices));
+    }
+
+    #[test]
+    fn long_index_buffers() {
+        let (device, _queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        // 257 by 257 vertices, more than a u16 can index
+        let mesh = Mesh::plane(&device, 1.0, 1.0, 256);
+        assert_eq!(mesh.vertex_count(), 257 * 257);
+        assert_eq!(mesh.index_format(), wgpu::IndexFormat::Uint32);
+        assert_eq!(mesh.index_count(), 256 * 256 * 6);
+    }
+
+    #[test]
+    fn wireframes_count_towards_gpu_bytes() {
+        let (device, _queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut builder = ObjectBuilder::new();
+        builder.read_lines(QUAD.as_bytes()).unwrap();
+        let mesh = Mesh::from_builder(&device, &builder, None, MeshUsage::Static).unwrap();
+        let wireframe = Mesh::with_wireframe(&device, &builder, None).unwrap();
+        // Four sides and the diagonal, two u16s each
+        assert_eq!(wireframe.gpu_bytes(), mesh.gpu_bytes() + 5 * 2 * 2);
+    }
+
+    #[test]
+    fn dynamic_vertex_updates() {
+        let (device, queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut builder = ObjectBuilder::new();
+        builder.read_lines(QUAD.as_bytes()).unwrap();
+        let mesh = Mesh::from_builder(&device, &builder, None, MeshUsage::Dynamic).unwrap();
+        let capacity = mesh.vertex_capacity();
+        assert_eq!(capacity, builder.mesh_vertices.len() as u32);
+
+        let vertex = |x| Vertex {
+            position: [x, 2.0, 0.0],
+            normal: [0.0, 0.0, 1.0],
+            texture_coords: [0.0, 0.0],
+        };
+        let prefix = [vertex(-1.0), vertex(3.0)];
+        mesh.update_vertices(&queue, &prefix).unwrap();
+        assert_eq!(mesh.vertex_count(), 2);
+        assert_eq!(mesh.vertex_capacity(), capacity);
+        assert_eq!(mesh.bounds().min, cgmath::Point3::new(-1.0, 2.0, 0.0));
+        assert_eq!(mesh.bounds().max, cgmath::Point3::new(3.0, 2.0, 0.0));
+
+        let too_many = vec![vertex(0.0); capacity as usize + 1];
+        assert_eq!(
+            mesh.update_vertices(&queue, &too_many),
+            Err(UpdateError::TooManyVertices {
+                given: capacity as usize + 1,
+                capacity,
+            })
+        );
+        // A failed update leaves the mesh as it was
+        assert_eq!(mesh.vertex_count(), 2);
+
+        let fixed = Mesh::from_builder(&device, &builder, None, MeshUsage::Static).unwrap();
+        assert_eq!(
+            fixed.update_vertices(&queue, &prefix),
+            Err(UpdateError::NotDynamic)
+        );
+        assert_eq!(fixed.vertex_count(), capacity);
+    }
 }
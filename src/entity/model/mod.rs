@@ -1,5 +1,30 @@
+pub mod bounds;
+pub mod cache;
 pub mod files;
+pub mod loader;
+pub mod material;
 pub mod mesh;
+pub mod object;
+pub mod optimize;
+pub mod primitives;
+pub mod raycast;
+pub mod simplify;
+pub mod tangents;
+pub mod validate;
+pub mod wireframe;
+
+pub use loader::{
+    load, load_from_bytes, load_in_background, LoadOptions, LoadedModel, Model, ModelData,
+    ModelLoadHandle,
+};
+pub use material::Material;
+pub use object::Object;
+pub use raycast::{intersect_ray, Faces, Hit};
+pub use validate::{validate, ValidationIssue};
+pub use wireframe::wireframe_indices;
+
+use memoffset::offset_of;
+use mesh::CompressedVertex;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, PartialOrd, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
@@ -8,31 +33,153 @@ pub struct Vertex {
     pub normal: [f32; 3],
     pub texture_coords: [f32; 2],
 }
+// Vertex buffers are uploaded as is, `LAYOUT` depends on there being no padding
+const _: () = assert!(std::mem::size_of::<Vertex>() == 8 * std::mem::size_of::<f32>());
+
 impl Vertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] = [
+        wgpu::VertexAttribute {
+            offset: offset_of!(Vertex, position) as wgpu::BufferAddress,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: offset_of!(Vertex, normal) as wgpu::BufferAddress,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: offset_of!(Vertex, texture_coords) as wgpu::BufferAddress,
+            shader_location: 2,
+            format: wgpu::VertexFormat::Float32x2,
+        },
+    ];
+    /// Position at location 0, normal at 1 and texture coordinates at 2. The offsets come from
+    /// the struct so they can't drift from it.
+    pub const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &Self::ATTRIBUTES,
+    };
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                // Position
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                // Normal
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                // Texture Coords
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3 + 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-            ],
+        Self::LAYOUT
+    }
+    /// The bytes of `vertices`, for creating and writing vertex buffers.
+    pub fn as_bytes(vertices: &[Vertex]) -> &[u8] {
+        bytemuck::cast_slice(vertices)
+    }
+    /// See `CompressedVertex` for what's lost.
+    pub fn compress(&self) -> CompressedVertex {
+        CompressedVertex::from(*self)
+    }
+}
+
+/// `Vertex` with a tangent for normal mapping, `xyz` along increasing U and the handedness of
+/// the bitangent in `w`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialOrd, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct VertexExt {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub texture_coords: [f32; 2],
+    pub tangent: [f32; 4],
+}
+const _: () = assert!(std::mem::size_of::<VertexExt>() == 12 * std::mem::size_of::<f32>());
+
+impl VertexExt {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] = [
+        wgpu::VertexAttribute {
+            offset: offset_of!(VertexExt, position) as wgpu::BufferAddress,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: offset_of!(VertexExt, normal) as wgpu::BufferAddress,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: offset_of!(VertexExt, texture_coords) as wgpu::BufferAddress,
+            shader_location: 2,
+            format: wgpu::VertexFormat::Float32x2,
+        },
+        wgpu::VertexAttribute {
+            offset: offset_of!(VertexExt, tangent) as wgpu::BufferAddress,
+            shader_location: 3,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+    ];
+    /// `Vertex::LAYOUT` plus the tangent at location 3, so shaders written for `Vertex` work
+    /// with either.
+    pub const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<VertexExt>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &Self::ATTRIBUTES,
+    };
+    pub fn new(vertex: Vertex, tangent: [f32; 4]) -> Self {
+        VertexExt {
+            position: vertex.position,
+            normal: vertex.normal,
+            texture_coords: vertex.texture_coords,
+            tangent,
         }
     }
+    pub fn as_bytes(vertices: &[VertexExt]) -> &[u8] {
+        bytemuck::cast_slice(vertices)
+    }
+}
+impl From<Vertex> for VertexExt {
+    /// Uses `tangents::fallback_tangent`, a vertex alone has no UV gradient to derive one from.
+    fn from(vertex: Vertex) -> Self {
+        VertexExt::new(vertex, tangents::fallback_tangent(vertex.normal.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Offset and location of every attribute, and whether they tile the stride exactly.
+    fn check_layout(layout: &wgpu::VertexBufferLayout, expected_offsets: &[u64]) {
+        let offsets: Vec<u64> = layout.attributes.iter().map(|a| a.offset).collect();
+        assert_eq!(offsets, expected_offsets);
+        for (location, attribute) in layout.attributes.iter().enumerate() {
+            assert_eq!(attribute.shader_location, location as u32);
+        }
+        let last = layout.attributes.last().unwrap();
+        assert_eq!(last.offset + last.format.size(), layout.array_stride);
+    }
+
+    #[test]
+    fn vertex_layouts_match_the_structs() {
+        assert_eq!(std::mem::size_of::<Vertex>(), 32);
+        assert_eq!(Vertex::LAYOUT.array_stride, 32);
+        check_layout(&Vertex::LAYOUT, &[0, 12, 24]);
+        assert_eq!(Vertex::desc().array_stride, Vertex::LAYOUT.array_stride);
+
+        assert_eq!(std::mem::size_of::<VertexExt>(), 48);
+        assert_eq!(VertexExt::LAYOUT.array_stride, 48);
+        check_layout(&VertexExt::LAYOUT, &[0, 12, 24, 32]);
+        // Shaders written for `Vertex` read the same attributes from either
+        assert_eq!(VertexExt::LAYOUT.attributes[..3], Vertex::LAYOUT.attributes[..]);
+    }
+
+    #[test]
+    fn bytes_are_the_fields_in_order() {
+        let vertex = Vertex {
+            position: [1.0, 2.0, 3.0],
+            normal: [0.0, 1.0, 0.0],
+            texture_coords: [0.25, 0.75],
+        };
+        let vertices = [vertex, vertex];
+        let bytes = Vertex::as_bytes(&vertices);
+        assert_eq!(bytes.len(), 64);
+        let floats: &[f32] = bytemuck::cast_slice(&bytes[32..]);
+        assert_eq!(floats, [1.0, 2.0, 3.0, 0.0, 1.0, 0.0, 0.25, 0.75]);
+
+        let ext = [VertexExt::new(vertex, [1.0, 0.0, 0.0, -1.0])];
+        let floats: &[f32] = bytemuck::cast_slice(VertexExt::as_bytes(&ext));
+        assert_eq!(floats[..8], *bytemuck::cast_slice::<Vertex, f32>(&[vertex]));
+        assert_eq!(floats[8..], [1.0, 0.0, 0.0, -1.0]);
+    }
 }
@@ -0,0 +1,130 @@
+use crate::entity::model::bounds::Aabb;
+use crate::entity::model::{tangents, validate};
+use crate::entity::model::{ValidationIssue, Vertex, VertexExt};
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// A range of the object's indices drawn with one material.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Hash)]
+pub struct SubMesh {
+    pub name: Option<String>,
+    /// Index into `Object::materials`.
+    pub material: Option<usize>,
+    pub indices: Range<u32>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct Stats {
+    pub positions: usize,
+    pub normals: usize,
+    pub texture_coords: usize,
+    pub vertices: usize,
+    pub triangles: usize,
+    /// Faces dropped because they repeated an earlier face.
+    pub duplicate_faces: usize,
+    /// Vertices dropped because no face used them.
+    pub removed_vertices: usize,
+}
+
+/// A finished model produced by a builder. The vertex and index data can't be changed anymore.
+#[derive(Clone, Debug)]
+pub struct Object {
+    name: Option<String>,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    submeshes: Vec<SubMesh>,
+    materials: Vec<String>,
+    material_libraries: Vec<PathBuf>,
+    /// Per vertex RGBA, parallel to `vertices`.
+    colors: Option<Vec<[f32; 4]>>,
+    /// Per vertex tangents, parallel to `vertices`.
+    tangents: Option<Vec<[f32; 4]>>,
+    bounds: Aabb,
+    stats: Stats,
+}
+impl Object {
+    pub fn new(
+        name: Option<String>,
+        mut vertices: Vec<Vertex>,
+        mut indices: Vec<u32>,
+        submeshes: Vec<SubMesh>,
+        materials: Vec<String>,
+        material_libraries: Vec<PathBuf>,
+        stats: Stats,
+    ) -> Object {
+        vertices.shrink_to_fit();
+        indices.shrink_to_fit();
+        let bounds = Aabb::from_points(vertices.iter().map(|v| v.position.into()));
+        Object {
+            name,
+            vertices,
+            indices,
+            submeshes,
+            materials,
+            material_libraries,
+            colors: None,
+            tangents: None,
+            bounds,
+            stats,
+        }
+    }
+    /// Attaches per vertex colors, there must be one for each vertex.
+    pub fn with_colors(mut self, mut colors: Vec<[f32; 4]>) -> Object {
+        assert_eq!(colors.len(), self.vertices.len(), "one color per vertex");
+        colors.shrink_to_fit();
+        self.colors = Some(colors);
+        self
+    }
+    /// Generates tangents from the texture coordinates, see `tangents::generate_tangents`.
+    pub fn with_tangents(mut self) -> Object {
+        let tangents = tangents::generate_tangents(&self.vertices, &self.indices);
+        self.tangents = Some(tangents);
+        self
+    }
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+    pub fn submeshes(&self) -> &[SubMesh] {
+        &self.submeshes
+    }
+    pub fn materials(&self) -> &[String] {
+        &self.materials
+    }
+    /// MTL files the materials are looked up in, in `mtllib` order.
+    pub fn material_libraries(&self) -> &[PathBuf] {
+        &self.material_libraries
+    }
+    pub fn colors(&self) -> Option<&[[f32; 4]]> {
+        self.colors.as_deref()
+    }
+    pub fn tangents(&self) -> Option<&[[f32; 4]]> {
+        self.tangents.as_deref()
+    }
+    /// The vertices with their tangents, `None` without tangents.
+    pub fn vertices_ext(&self) -> Option<Vec<VertexExt>> {
+        let tangents = self.tangents.as_ref()?;
+        Some(
+            self.vertices
+                .iter()
+                .zip(tangents)
+                .map(|(vertex, tangent)| VertexExt::new(*vertex, *tangent))
+                .collect(),
+        )
+    }
+    pub fn bounds(&self) -> &Aabb {
+        &self.bounds
+    }
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+    /// See `validate::validate`.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        validate::validate(&self.vertices, &self.indices)
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+
+/// Vertices the scoring assumes the post-transform cache holds.
+const CACHE_SIZE: usize = 32;
+const CACHE_DECAY_POWER: f32 = 1.5;
+/// The last triangle's vertices score lower than the ones before them, so the next triangle
+/// doesn't just zigzag along a strip.
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+/// How much drawing a triangle using the vertex now is worth, higher for vertices recently
+/// used and for ones with few triangles left so they aren't left stranded.
+fn vertex_score(cache_position: Option<usize>, remaining: usize) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache = match cache_position {
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) if position < CACHE_SIZE => {
+            let scale = 1.0 / (CACHE_SIZE - 3) as f32;
+            (1.0 - (position - 3) as f32 * scale).powf(CACHE_DECAY_POWER)
+        }
+        _ => 0.0,
+    };
+    cache + VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+/// Reorders the triangles of a triangle list so vertices are reused while they're still in
+/// the GPU's post-transform cache, with Tom Forsyth's linear-speed algorithm. Only the order
+/// of the triangles changes, each keeps its vertices and winding so the mesh renders the
+/// same. Indices past the last whole triangle are left alone, and so is the whole list if an
+/// index isn't below `vertex_count`.
+pub fn optimize_indices(indices: &mut [u32], vertex_count: usize) {
+    let triangle_count = indices.len() / 3;
+    if triangle_count < 2 {
+        return;
+    }
+    let triangles = &indices[..triangle_count * 3];
+    if triangles.iter().any(|&i| i as usize >= vertex_count) {
+        log::warn!("index out of range, not optimizing the vertex cache");
+        return;
+    }
+    // Triangles using each vertex, the live ones of `v` are the first `remaining[v]` of
+    // `adjacency[offsets[v]..offsets[v + 1]]`
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for &i in triangles {
+        offsets[i as usize + 1] += 1;
+    }
+    for v in 0..vertex_count {
+        offsets[v + 1] += offsets[v];
+    }
+    let mut adjacency = vec![0u32; triangles.len()];
+    let mut remaining = vec![0usize; vertex_count];
+    for (t, triangle) in triangles.chunks_exact(3).enumerate() {
+        for &i in triangle {
+            let v = i as usize;
+            adjacency[offsets[v] + remaining[v]] = t as u32;
+            remaining[v] += 1;
+        }
+    }
+    let mut scores: Vec<f32> = remaining.iter().map(|&r| vertex_score(None, r)).collect();
+    let triangle_score = |t: usize, scores: &[f32]| -> f32 {
+        triangles[t * 3..t * 3 + 3].iter().map(|&i| scores[i as usize]).sum()
+    };
+
+    let mut emitted = vec![false; triangle_count];
+    let mut order = Vec::with_capacity(triangles.len());
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut next_cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    // Where to look for a triangle when none touch the cache
+    let mut next_unemitted = 0;
+    let mut best = (0..triangle_count).max_by(|&a, &b| {
+        let (a, b) = (triangle_score(a, &scores), triangle_score(b, &scores));
+        a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    while let Some(t) = best {
+        emitted[t] = true;
+        let triangle = [triangles[t * 3], triangles[t * 3 + 1], triangles[t * 3 + 2]];
+        order.extend_from_slice(&triangle);
+        for &i in &triangle {
+            let v = i as usize;
+            let live = &mut adjacency[offsets[v]..offsets[v] + remaining[v]];
+            if let Some(position) = live.iter().position(|&other| other as usize == t) {
+                live.swap(position, live.len() - 1);
+                remaining[v] -= 1;
+            }
+        }
+
+        // The triangle's vertices move to the front of the cache, pushing the rest back
+        next_cache.clear();
+        next_cache.extend_from_slice(&triangle);
+        next_cache.extend(cache.iter().filter(|&&v| !triangle.contains(&v)));
+        for (position, &v) in next_cache.iter().enumerate() {
+            let v = v as usize;
+            let position = Some(position).filter(|&p| p < CACHE_SIZE);
+            scores[v] = vertex_score(position, remaining[v]);
+        }
+        // Only triangles around the cache changed score, the best of them goes next.
+        // Evicted vertices are included since their score dropped
+        best = None;
+        let mut best_score = f32::NEG_INFINITY;
+        for &v in &next_cache {
+            let v = v as usize;
+            for &other in &adjacency[offsets[v]..offsets[v] + remaining[v]] {
+                let score = triangle_score(other as usize, &scores);
+                if score > best_score {
+                    best = Some(other as usize);
+                    best_score = score;
+                }
+            }
+        }
+        next_cache.truncate(CACHE_SIZE);
+        std::mem::swap(&mut cache, &mut next_cache);
+
+        if best.is_none() {
+            while next_unemitted < triangle_count && emitted[next_unemitted] {
+                next_unemitted += 1;
+            }
+            if next_unemitted < triangle_count {
+                best = Some(next_unemitted);
+            }
+        }
+    }
+    indices[..order.len()].copy_from_slice(&order);
+}
+
+/// Reorders `vertices` by when the indices first use them, so the vertex fetch reads memory
+/// mostly in order after `optimize_indices`, and rewrites `indices` to match. Vertices no
+/// index uses are dropped.
+pub fn optimize_vertex_fetch<T: Copy>(vertices: &mut Vec<T>, indices: &mut [u32]) {
+    const UNUSED: u32 = u32::MAX;
+    let mut remap = vec![UNUSED; vertices.len()];
+    let mut reordered = Vec::with_capacity(vertices.len());
+    for index in indices.iter_mut() {
+        let new_index = &mut remap[*index as usize];
+        if *new_index == UNUSED {
+            *new_index = reordered.len() as u32;
+            reordered.push(vertices[*index as usize]);
+        }
+        *index = *new_index;
+    }
+    *vertices = reordered;
+}
+
+/// Average cache miss ratio, the vertex shader invocations per triangle with a FIFO
+/// post-transform cache of `cache_size` vertices. 3 is the worst possible, a regular grid can
+/// get close to 0.5. For comparing index orders, e.g. before and after `optimize_indices`.
+pub fn acmr(indices: &[u32], cache_size: usize) -> f32 {
+    let triangles = indices.len() / 3;
+    if triangles == 0 {
+        return 0.0;
+    }
+    let mut cache: VecDeque<u32> = VecDeque::with_capacity(cache_size + 1);
+    let mut misses = 0;
+    for &index in &indices[..triangles * 3] {
+        if !cache.contains(&index) {
+            misses += 1;
+            cache.push_back(index);
+            if cache.len() > cache_size {
+                cache.pop_front();
+            }
+        }
+    }
+    misses as f32 / triangles as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::model::primitives;
+
+    fn triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> =
+            indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    /// The triangles of `indices` in a fixed pseudo-random order, the worst case for the cache.
+    fn shuffled(indices: &[u32]) -> Vec<u32> {
+        let mut triangles: Vec<&[u32]> = indices.chunks_exact(3).collect();
+        let mut state = 12345u32;
+        for i in (1..triangles.len()).rev() {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            triangles.swap(i, (state >> 8) as usize % (i + 1));
+        }
+        triangles.concat()
+    }
+
+    #[test]
+    fn improves_acmr() {
+        let (vertices, in_rows) = primitives::plane(1.0, 1.0, 32);
+        let mut random = shuffled(&in_rows);
+        let before = acmr(&random, CACHE_SIZE);
+        optimize_indices(&mut random, vertices.len());
+        let after = acmr(&random, CACHE_SIZE);
+        // Rows are a vertex longer than the cache, so file order misses on almost every vertex
+        let rows = acmr(&in_rows, CACHE_SIZE);
+        assert!(after < 0.8 * rows && rows < before, "{} {} {}", before, rows, after);
+        assert_eq!(triangles(&random), triangles(&in_rows));
+    }
+
+    #[test]
+    fn keeps_winding_and_trailing_indices() {
+        let (vertices, mut indices) = primitives::uv_sphere(1.0, 8, 12);
+        let original = indices.clone();
+        indices.extend([0, 1]);
+        optimize_indices(&mut indices, vertices.len());
+        // Each triangle's corners are kept in order, so it faces the same way
+        assert_eq!(triangles(&indices[..original.len()]), triangles(&original));
+        assert_eq!(indices[original.len()..], [0, 1]);
+    }
+
+    #[test]
+    fn leaves_invalid_indices_alone() {
+        let mut indices = vec![0, 1, 2, 2, 1, 3];
+        optimize_indices(&mut indices, 3);
+        assert_eq!(indices, [0, 1, 2, 2, 1, 3]);
+    }
+
+    #[test]
+    fn vertex_fetch_follows_first_use() {
+        let vertices = vec!['a', 'b', 'c', 'd', 'e'];
+        let mut reordered = vertices.clone();
+        let mut indices = vec![3, 1, 4, 4, 1, 0];
+        optimize_vertex_fetch(&mut reordered, &mut indices);
+        assert_eq!(indices, [0, 1, 2, 2, 1, 3]);
+        // 'c' isn't used
+        assert_eq!(reordered, ['d', 'b', 'e', 'a']);
+    }
+
+    #[test]
+    fn acmr_bounds() {
+        assert_eq!(acmr(&[], 16), 0.0);
+        assert_eq!(acmr(&[0, 1, 2, 3, 4, 5], 16), 3.0);
+        // A strip reuses two vertices of every triangle
+        assert_eq!(acmr(&[0, 1, 2, 2, 1, 3, 2, 3, 4, 4, 3, 5], 16), 1.5);
+    }
+}
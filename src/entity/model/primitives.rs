@@ -0,0 +1,259 @@
+use crate::entity::model::Vertex;
+use std::f32::consts::PI;
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+fn scale(a: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+/// A grid of `subdivisions` by `subdivisions` quads centered on `center`, spanning `u` to the
+/// right and `v` up as seen from the front. `u × v` has to point the way of `normal`, which
+/// makes the triangles counterclockwise from the front like the pipelines expect.
+fn quad_grid(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    center: [f32; 3],
+    u: [f32; 3],
+    v: [f32; 3],
+    normal: [f32; 3],
+    subdivisions: u32,
+) {
+    let subdivisions = subdivisions.max(1);
+    let start = vertices.len() as u32;
+    for j in 0..=subdivisions {
+        for i in 0..=subdivisions {
+            let (s, t) = (i as f32 / subdivisions as f32, j as f32 / subdivisions as f32);
+            let position = add(center, add(scale(u, s - 0.5), scale(v, t - 0.5)));
+            vertices.push(Vertex {
+                position,
+                normal,
+                // Textures are upright, with V going down from the top edge
+                texture_coords: [s, 1.0 - t],
+            });
+        }
+    }
+    let row = subdivisions + 1;
+    for j in 0..subdivisions {
+        for i in 0..subdivisions {
+            let a = start + j * row + i;
+            let (b, c, d) = (a + 1, a + row + 1, a + row);
+            indices.extend_from_slice(&[a, b, c, a, c, d]);
+        }
+    }
+}
+
+/// A cube of side `size` centered on the origin. Each face has its own four vertices so the
+/// normals are flat, and the whole texture.
+pub fn cube(size: f32) -> (Vec<Vertex>, Vec<u32>) {
+    let half = size * 0.5;
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    // Normal, right and up of each face seen from outside
+    let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+        ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ];
+    for (normal, u, v) in faces {
+        let center = scale(normal, half);
+        quad_grid(&mut vertices, &mut indices, center, scale(u, size), scale(v, size), normal, 1);
+    }
+    (vertices, indices)
+}
+
+/// A `width` by `depth` plane on the XZ plane facing up, split into `subdivisions` quads
+/// along each side so it can be displaced or lit per vertex.
+pub fn plane(width: f32, depth: f32, subdivisions: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let subdivisions = subdivisions.max(1);
+    let count = (subdivisions + 1) as usize;
+    let mut vertices = Vec::with_capacity(count * count);
+    let mut indices = Vec::with_capacity(subdivisions as usize * subdivisions as usize * 6);
+    let (u, v) = ([width, 0.0, 0.0], [0.0, 0.0, -depth]);
+    quad_grid(&mut vertices, &mut indices, [0.0; 3], u, v, [0.0, 1.0, 0.0], subdivisions);
+    (vertices, indices)
+}
+
+/// Point on the unit circle in the XZ plane, going from +X towards -Z so the U coordinate
+/// increases to the right when seen from outside.
+fn around(angle: f32) -> [f32; 3] {
+    [angle.cos(), 0.0, -angle.sin()]
+}
+
+/// A sphere centered on the origin, `rings` bands from pole to pole of `segments` quads each,
+/// at least 2 and 3. The bands touching the poles are triangles, one per segment, rather than
+/// quads with two corners on the pole. Each pole gets a vertex per segment with U at the
+/// middle of it, which keeps the texture from twisting there.
+pub fn uv_sphere(radius: f32, rings: u32, segments: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let (rings, segments) = (rings.max(2), segments.max(3));
+    let row = segments + 1;
+    let mut vertices = Vec::with_capacity(((rings + 1) * row) as usize);
+    for r in 0..=rings {
+        let theta = PI * r as f32 / rings as f32;
+        let pole = r == 0 || r == rings;
+        for s in 0..=segments {
+            // The seam's vertices are doubled so U can go from 0 to 1
+            let u = if pole && s < segments {
+                (s as f32 + 0.5) / segments as f32
+            } else {
+                s as f32 / segments as f32
+            };
+            let [x, _, z] = around(2.0 * PI * s as f32 / segments as f32);
+            let normal = [x * theta.sin(), theta.cos(), z * theta.sin()];
+            vertices.push(Vertex {
+                position: scale(normal, radius),
+                normal,
+                texture_coords: [u, r as f32 / rings as f32],
+            });
+        }
+    }
+    let mut indices = Vec::with_capacity((rings - 1) as usize * segments as usize * 6);
+    for r in 0..rings {
+        for s in 0..segments {
+            // `a` and `b` are on ring `r`, `d` and `c` below them
+            let a = r * row + s;
+            let (b, c, d) = (a + 1, a + row + 1, a + row);
+            if r != 0 {
+                indices.extend_from_slice(&[a, c, b]);
+            }
+            if r != rings - 1 {
+                indices.extend_from_slice(&[a, d, c]);
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+/// A closed cylinder along Y centered on the origin, with `segments` sides, at least 3. The
+/// sides have smooth normals and the caps flat ones, so the edges between them are split.
+/// The side is textured all the way around and each cap with a disc from the texture's middle.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let segments = segments.max(3);
+    let row = segments + 1;
+    let half = height * 0.5;
+    let mut vertices = Vec::with_capacity((row * 2 + (segments + 1) * 2) as usize);
+    let mut indices = Vec::with_capacity(segments as usize * 12);
+    for (y, v) in [(half, 0.0), (-half, 1.0)] {
+        for s in 0..=segments {
+            let normal = around(2.0 * PI * s as f32 / segments as f32);
+            vertices.push(Vertex {
+                position: add(scale(normal, radius), [0.0, y, 0.0]),
+                normal,
+                texture_coords: [s as f32 / segments as f32, v],
+            });
+        }
+    }
+    for s in 0..segments {
+        let (a, d) = (s, s + row);
+        indices.extend_from_slice(&[a, d, d + 1, a, d + 1, a + 1]);
+    }
+    for (y, up) in [(half, 1.0), (-half, -1.0)] {
+        let normal = [0.0, up, 0.0];
+        let center = vertices.len() as u32;
+        vertices.push(Vertex {
+            position: [0.0, y, 0.0],
+            normal,
+            texture_coords: [0.5, 0.5],
+        });
+        for s in 0..segments {
+            let [x, _, z] = around(2.0 * PI * s as f32 / segments as f32);
+            vertices.push(Vertex {
+                position: [x * radius, y, z * radius],
+                normal,
+                texture_coords: [0.5 + 0.5 * x, 0.5 + 0.5 * z * up],
+            });
+        }
+        for s in 0..segments {
+            let (this, next) = (center + 1 + s, center + 1 + (s + 1) % segments);
+            // Counterclockwise from above for the top and from below for the bottom
+            if up > 0.0 {
+                indices.extend_from_slice(&[center, this, next]);
+            } else {
+                indices.extend_from_slice(&[center, next, this]);
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{InnerSpace, Vector3};
+
+    fn all() -> Vec<(&'static str, (Vec<Vertex>, Vec<u32>))> {
+        vec![
+            ("cube", cube(2.0)),
+            ("plane", plane(2.0, 3.0, 4)),
+            ("uv_sphere", uv_sphere(1.5, 8, 12)),
+            ("cylinder", cylinder(1.0, 2.0, 12)),
+        ]
+    }
+
+    /// Enclosed volume, positive when the triangles face outwards.
+    fn signed_volume(vertices: &[Vertex], indices: &[u32]) -> f32 {
+        let position = |i: u32| Vector3::from(vertices[i as usize].position);
+        let volume: f32 = indices
+            .chunks_exact(3)
+            .map(|t| position(t[0]).dot(position(t[1]).cross(position(t[2]))))
+            .sum();
+        volume / 6.0
+    }
+
+    #[test]
+    fn unit_normals_and_uvs_in_range() {
+        for (name, (vertices, _)) in all() {
+            for vertex in &vertices {
+                let length = Vector3::from(vertex.normal).magnitude();
+                assert!((length - 1.0).abs() < 1e-5, "{} {:?}", name, vertex);
+                let uv = vertex.texture_coords;
+                assert!(uv.iter().all(|c| (0.0..=1.0).contains(c)), "{} {:?}", name, vertex);
+            }
+        }
+    }
+
+    #[test]
+    fn counterclockwise_from_the_front() {
+        for (name, (vertices, indices)) in all() {
+            assert_eq!(indices.len() % 3, 0);
+            for triangle in indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+                let [pa, pb, pc] = [a, b, c].map(|v| Vector3::from(v.position));
+                let face = (pb - pa).cross(pc - pa);
+                // No degenerate triangles, not even at the sphere's poles
+                assert!(face.magnitude() > 1e-4, "{} {:?}", name, triangle);
+                let normal: Vector3<f32> = [a, b, c].iter().map(|v| Vector3::from(v.normal)).sum();
+                assert!(face.dot(normal) > 0.0, "{} {:?} faces inwards", name, triangle);
+            }
+        }
+    }
+
+    #[test]
+    fn closed_meshes_enclose_their_volume() {
+        let (vertices, indices) = cube(2.0);
+        assert!((signed_volume(&vertices, &indices) - 8.0).abs() < 1e-4);
+        // Polygons are a bit smaller than the round shapes they approximate
+        let (vertices, indices) = uv_sphere(1.0, 32, 64);
+        let volume = signed_volume(&vertices, &indices);
+        assert!(volume < 4.0 / 3.0 * PI && volume > 0.98 * 4.0 / 3.0 * PI, "{}", volume);
+        let (vertices, indices) = cylinder(1.0, 2.0, 64);
+        let volume = signed_volume(&vertices, &indices);
+        assert!(volume < 2.0 * PI && volume > 0.99 * 2.0 * PI, "{}", volume);
+    }
+
+    #[test]
+    fn primitive_sizes() {
+        let (vertices, indices) = plane(2.0, 3.0, 4);
+        assert_eq!((vertices.len(), indices.len()), (25, 4 * 4 * 6));
+        let (vertices, indices) = cube(1.0);
+        assert_eq!((vertices.len(), indices.len()), (24, 36));
+        assert!(vertices.iter().all(|v| v.position.iter().all(|c| c.abs() == 0.5)));
+        // One triangle per segment in the bands touching the poles
+        let (_, indices) = uv_sphere(1.0, 8, 12);
+        assert_eq!(indices.len(), (2 * 12 + 6 * 12 * 2) * 3);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::entity::model::Vertex;
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+
+/// Which triangles a ray can hit.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Faces {
+    /// Only ones the ray sees counterclockwise, like the back face culled pipelines draw.
+    Front,
+    Both,
+}
+impl Default for Faces {
+    fn default() -> Self {
+        Faces::Front
+    }
+}
+
+/// Where a ray hits a triangle list.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Hit {
+    /// Along the ray, in lengths of its direction.
+    pub distance: f32,
+    /// The hit triangle is `indices[3 * triangle_index..][..3]`.
+    pub triangle_index: usize,
+    /// Weights of the triangle's three vertices at the hit, for interpolating attributes.
+    pub barycentric: [f32; 3],
+}
+
+/// Möller-Trumbore. `None` when the ray is parallel to the triangle, misses it, starts past
+/// it or only sees its back and `faces` is `Front`.
+fn hit_triangle(
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    [a, b, c]: [Vector3<f32>; 3],
+    faces: Faces,
+) -> Option<(f32, f32, f32)> {
+    let (e1, e2) = (b - a, c - a);
+    let p = direction.cross(e2);
+    // Positive when the ray sees the triangle counterclockwise
+    let det = e1.dot(p);
+    let parallel = det.abs() < 1e-12;
+    if parallel || (faces == Faces::Front && det < 0.0) {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = e2.dot(q) * inv_det;
+    if distance > 0.0 {
+        Some((distance, u, v))
+    } else {
+        None
+    }
+}
+
+/// The nearest hit of the ray from `origin` along `direction` in world space on the triangle
+/// list `vertices` and `indices` placed by `transform`, e.g. an entity's `mx_world`. The ray is
+/// moved into the mesh's space rather than the vertices into the world's, and the distance is
+/// the same in both. Indices past the vertices are skipped. See `Mesh::intersect_ray` for an
+/// early out with the mesh's bounds.
+pub fn intersect_ray(
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    vertices: &[Vertex],
+    indices: &[u32],
+    transform: &Matrix4<f32>,
+    faces: Faces,
+) -> Option<Hit> {
+    let to_local = transform.invert()?;
+    let local_origin = (to_local * origin.extend(1.0)).truncate();
+    let local_direction = (to_local * direction.extend(0.0)).truncate();
+    let mut nearest: Option<Hit> = None;
+    for (triangle_index, triangle) in indices.chunks_exact(3).enumerate() {
+        let corner = |i: usize| vertices.get(triangle[i] as usize).map(|v| v.position.into());
+        let corners = match (corner(0), corner(1), corner(2)) {
+            (Some(a), Some(b), Some(c)) => [a, b, c],
+            _ => continue,
+        };
+        let (distance, u, v) = match hit_triangle(local_origin, local_direction, corners, faces)
+        {
+            Some(hit) => hit,
+            None => continue,
+        };
+        if nearest.map_or(true, |nearest| distance < nearest.distance) {
+            nearest = Some(Hit {
+                distance,
+                triangle_index,
+                barycentric: [1.0 - u - v, u, v],
+            });
+        }
+    }
+    nearest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A triangle on z = 0 counterclockwise from +Z, and another behind it on z = -1.
+    fn triangles() -> (Vec<Vertex>, Vec<u32>) {
+        let vertex = |x, y, z| Vertex {
+            position: [x, y, z],
+            normal: [0.0, 0.0, 1.0],
+            texture_coords: [0.0; 2],
+        };
+        let vertices = vec![
+            vertex(0.0, 0.0, 0.0),
+            vertex(1.0, 0.0, 0.0),
+            vertex(0.0, 1.0, 0.0),
+            vertex(0.0, 0.0, -1.0),
+            vertex(1.0, 0.0, -1.0),
+            vertex(0.0, 1.0, -1.0),
+        ];
+        (vertices, vec![0, 1, 2, 3, 4, 5])
+    }
+
+    fn cast(origin: [f32; 3], direction: [f32; 3], faces: Faces) -> Option<Hit> {
+        let (vertices, indices) = triangles();
+        let identity = Matrix4::identity();
+        intersect_ray(origin.into(), direction.into(), &vertices, &indices, &identity, faces)
+    }
+
+    #[test]
+    fn nearest_front_face() {
+        let hit = cast([0.25, 0.25, 1.0], [0.0, 0.0, -1.0], Faces::Front).unwrap();
+        assert_eq!(hit.triangle_index, 0);
+        assert!((hit.distance - 1.0).abs() < 1e-6);
+        let expected = [0.5, 0.25, 0.25];
+        for (weight, expected) in hit.barycentric.into_iter().zip(expected) {
+            assert!((weight - expected).abs() < 1e-6);
+        }
+        // In lengths of the direction
+        let hit = cast([0.25, 0.25, 1.0], [0.0, 0.0, -2.0], Faces::Front).unwrap();
+        assert!((hit.distance - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn back_faces_only_when_asked() {
+        let origin = [0.25, 0.25, -2.0];
+        assert_eq!(cast(origin, [0.0, 0.0, 1.0], Faces::Front), None);
+        let hit = cast(origin, [0.0, 0.0, 1.0], Faces::Both).unwrap();
+        assert_eq!(hit.triangle_index, 1);
+        assert!((hit.distance - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn parallel_rays_miss() {
+        assert_eq!(cast([0.25, 0.25, 1.0], [1.0, 0.0, 0.0], Faces::Both), None);
+        // Even along the triangle's own plane
+        assert_eq!(cast([-1.0, 0.25, 0.0], [1.0, 0.0, 0.0], Faces::Both), None);
+    }
+
+    #[test]
+    fn triangles_behind_the_origin_miss() {
+        assert_eq!(cast([0.25, 0.25, 1.0], [0.0, 0.0, 1.0], Faces::Both), None);
+        // Between the two, only the one ahead is hit
+        let hit = cast([0.25, 0.25, -0.5], [0.0, 0.0, -1.0], Faces::Front).unwrap();
+        assert_eq!(hit.triangle_index, 1);
+        assert!((hit.distance - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn misses_beside_the_triangle() {
+        assert_eq!(cast([0.75, 0.75, 1.0], [0.0, 0.0, -1.0], Faces::Both), None);
+        assert_eq!(cast([-0.1, 0.5, 1.0], [0.0, 0.0, -1.0], Faces::Both), None);
+    }
+
+    #[test]
+    fn transformed_meshes() {
+        let (vertices, indices) = triangles();
+        let translation = Matrix4::from_translation(Vector3::new(0.0, 0.0, 5.0));
+        let transform = translation * Matrix4::from_scale(2.0);
+        let (origin, direction) = (Vector3::new(0.5, 0.5, 10.0), Vector3::new(0.0, 0.0, -1.0));
+        let hit = intersect_ray(origin, direction, &vertices, &indices, &transform, Faces::Front);
+        assert!((hit.unwrap().distance - 5.0).abs() < 1e-5);
+        // Flattened meshes can't be hit
+        let flat = Matrix4::from_nonuniform_scale(1.0, 1.0, 0.0);
+        assert_eq!(intersect_ray(origin, direction, &vertices, &indices, &flat, Faces::Both), None);
+        // Indices past the vertices are skipped
+        let origin = Vector3::new(0.25, 0.25, 10.0);
+        let indices = [0, 1, 9, 0, 1, 2];
+        let identity = Matrix4::identity();
+        let hit = intersect_ray(origin, direction, &vertices, &indices, &identity, Faces::Front);
+        assert_eq!(hit.unwrap().triangle_index, 1);
+    }
+}
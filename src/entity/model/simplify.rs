@@ -0,0 +1,303 @@
+use crate::entity::model::Vertex;
+use cgmath::{InnerSpace, Vector3};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// The squared distance to a set of planes, summed. Stored as the upper triangle of the
+/// symmetric 4x4 matrix.
+#[derive(Copy, Clone, Default)]
+struct Quadric([f64; 10]);
+impl Quadric {
+    /// The plane through a triangle, weighted by its area so slivers count for little.
+    fn from_triangle(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Quadric {
+        let cross = (b - a).cross(c - a);
+        let double_area = cross.magnitude();
+        if double_area <= f32::EPSILON {
+            return Quadric::default();
+        }
+        let n = (cross / double_area).cast::<f64>().unwrap();
+        let d = -n.dot(a.cast::<f64>().unwrap());
+        let w = double_area as f64 * 0.5;
+        Quadric([
+            w * n.x * n.x,
+            w * n.x * n.y,
+            w * n.x * n.z,
+            w * n.x * d,
+            w * n.y * n.y,
+            w * n.y * n.z,
+            w * n.y * d,
+            w * n.z * n.z,
+            w * n.z * d,
+            w * d * d,
+        ])
+    }
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.0.iter_mut().zip(other.0) {
+            *a += b;
+        }
+    }
+    fn error(&self, p: Vector3<f32>) -> f64 {
+        let [a, b, c, d, e, f, g, h, i, j] = self.0;
+        let (x, y, z) = (p.x as f64, p.y as f64, p.z as f64);
+        x * x * a + 2.0 * x * y * b + 2.0 * x * z * c + 2.0 * x * d
+            + y * y * e + 2.0 * y * z * f + 2.0 * y * g
+            + z * z * h + 2.0 * z * i
+            + j
+    }
+}
+
+/// Vertices that mustn't move: ones on an edge used by a single triangle, which includes
+/// open borders and UV or normal seams since the vertices there are split, and ones sharing
+/// their position with another vertex.
+fn locked_vertices(vertices: &[Vertex], indices: &[u32]) -> Vec<bool> {
+    let mut locked = vec![false; vertices.len()];
+    let mut edges: HashMap<(u32, u32), u32> = HashMap::with_capacity(indices.len());
+    for triangle in indices.chunks_exact(3) {
+        for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+            let (a, b) = (triangle[a], triangle[b]);
+            *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+    }
+    for ((a, b), count) in edges {
+        if count == 1 {
+            locked[a as usize] = true;
+            locked[b as usize] = true;
+        }
+    }
+    let mut positions: HashMap<[u32; 3], usize> = HashMap::with_capacity(vertices.len());
+    for (i, vertex) in vertices.iter().enumerate() {
+        let key = vertex.position.map(|x| (x + 0.0).to_bits());
+        if let Some(&other) = positions.get(&key) {
+            locked[i] = true;
+            locked[other] = true;
+        } else {
+            positions.insert(key, i);
+        }
+    }
+    locked
+}
+
+fn normal(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Vector3<f32> {
+    (b - a).cross(c - a)
+}
+
+/// Reduces `indices` to about `target_ratio` of its triangles by collapsing edges with
+/// quadric error metrics. Vertices only ever collapse onto other vertices, so the result
+/// indexes the same `vertices` and every LOD of a mesh can share its vertex buffer. Border and
+/// seam vertices are kept in place so textures don't swim, which can stop the reduction
+/// short of the target. Degenerate triangles are dropped and winding is kept.
+pub fn simplify_indices(vertices: &[Vertex], indices: &[u32], target_ratio: f32) -> Vec<u32> {
+    let mut indices: Vec<u32> = indices
+        .chunks_exact(3)
+        .filter(|t| t.iter().all(|&i| (i as usize) < vertices.len()))
+        .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0])
+        .flatten()
+        .copied()
+        .collect();
+    let target = ((indices.len() / 3) as f32 * target_ratio.clamp(0.0, 1.0)).round() as usize;
+    let position = |i: u32| Vector3::from(vertices[i as usize].position);
+    let locked = locked_vertices(vertices, &indices);
+    let mut quadrics = vec![Quadric::default(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let quadric = Quadric::from_triangle(
+            position(triangle[0]),
+            position(triangle[1]),
+            position(triangle[2]),
+        );
+        for &i in triangle {
+            quadrics[i as usize].add(&quadric);
+        }
+    }
+    // Passes collapse the cheapest edges that don't touch each other, until the target is
+    // reached or nothing can collapse
+    while indices.len() / 3 > target {
+        let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+        for (t, triangle) in indices.chunks_exact(3).enumerate() {
+            for &i in triangle {
+                adjacency[i as usize].push(t);
+            }
+        }
+        let mut collapses: Vec<(f64, u32, u32)> = Vec::with_capacity(indices.len() * 2);
+        for triangle in indices.chunks_exact(3) {
+            for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+                for (from, to) in [(triangle[a], triangle[b]), (triangle[b], triangle[a])] {
+                    if locked[from as usize] {
+                        continue;
+                    }
+                    let mut quadric = quadrics[from as usize];
+                    quadric.add(&quadrics[to as usize]);
+                    collapses.push((quadric.error(position(to)), from, to));
+                }
+            }
+        }
+        collapses.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+        let mut remap: Vec<u32> = (0..vertices.len() as u32).collect();
+        let mut touched = vec![false; vertices.len()];
+        let mut removed = 0;
+        let excess = indices.len() / 3 - target;
+        for (_, from, to) in collapses {
+            if removed >= excess {
+                break;
+            }
+            let (from_index, to_index) = (from as usize, to as usize);
+            if touched[from_index] || touched[to_index] {
+                continue;
+            }
+            let triangles = &adjacency[from_index];
+            let mut collapsed = 0;
+            let flips = triangles.iter().any(|&t| {
+                let triangle = &indices[t * 3..t * 3 + 3];
+                if triangle.contains(&to) {
+                    collapsed += 1;
+                    return false;
+                }
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+                let moved = |i: u32| if i == from { position(to) } else { position(i) };
+                let before = normal(position(a), position(b), position(c));
+                let after = normal(moved(a), moved(b), moved(c));
+                let degenerate = after.magnitude2() <= f32::EPSILON * before.magnitude2();
+                degenerate || before.dot(after) <= 0.0
+            });
+            if flips || collapsed == 0 {
+                continue;
+            }
+            remap[from_index] = to;
+            // Everything around `from` changes, so it's left alone for the rest of the pass
+            for &t in triangles {
+                for &i in &indices[t * 3..t * 3 + 3] {
+                    touched[i as usize] = true;
+                }
+            }
+            let quadric = quadrics[from_index];
+            quadrics[to_index].add(&quadric);
+            removed += collapsed;
+        }
+        if removed == 0 {
+            break;
+        }
+        for index in indices.iter_mut() {
+            *index = remap[*index as usize];
+        }
+        indices = indices
+            .chunks_exact(3)
+            .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0])
+            .flatten()
+            .copied()
+            .collect();
+    }
+    indices
+}
+
+/// `simplify_indices` with the unused vertices dropped, for a mesh of its own.
+pub fn simplify(
+    vertices: &[Vertex],
+    indices: &[u32],
+    target_ratio: f32,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let mut indices = simplify_indices(vertices, indices, target_ratio);
+    let mut remap = vec![u32::MAX; vertices.len()];
+    let mut kept = Vec::new();
+    for index in indices.iter_mut() {
+        let new_index = &mut remap[*index as usize];
+        if *new_index == u32::MAX {
+            *new_index = kept.len() as u32;
+            kept.push(vertices[*index as usize]);
+        }
+        *index = *new_index;
+    }
+    (kept, indices)
+}
+
+/// LODs for `ratios` of the triangles, e.g. `[1.0, 0.25, 0.05]`, concatenated into one index
+/// buffer over `vertices` with the range of each level. Each level is simplified from the one
+/// before, which is much faster than starting over.
+pub fn lod_chain(
+    vertices: &[Vertex],
+    indices: &[u32],
+    ratios: &[f32],
+) -> (Vec<u32>, Vec<Range<u32>>) {
+    let original = (indices.len() / 3).max(1) as f32;
+    let mut chain = Vec::new();
+    let mut ranges = Vec::with_capacity(ratios.len());
+    let mut level = indices.to_vec();
+    for &ratio in ratios {
+        let current = (level.len() / 3).max(1) as f32;
+        level = simplify_indices(vertices, &level, ratio * original / current);
+        let start = chain.len() as u32;
+        chain.extend_from_slice(&level);
+        ranges.push(start..chain.len() as u32);
+    }
+    (chain, ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::model::primitives;
+
+    /// Two 16 by 16 grids side by side, each with its own vertices where they meet like at a
+    /// UV seam.
+    fn seamed_plane() -> (Vec<Vertex>, Vec<u32>) {
+        let (mut vertices, mut indices) = primitives::plane(1.0, 1.0, 16);
+        let (right, right_indices) = primitives::plane(1.0, 1.0, 16);
+        let offset = vertices.len() as u32;
+        vertices.extend(right.into_iter().map(|v| Vertex {
+            position: [v.position[0] + 1.0, v.position[1], v.position[2]],
+            ..v
+        }));
+        indices.extend(right_indices.into_iter().map(|i| i + offset));
+        (vertices, indices)
+    }
+
+    fn up_facing(vertices: &[Vertex], indices: &[u32]) -> bool {
+        let position = |i: u32| Vector3::from(vertices[i as usize].position);
+        indices
+            .chunks_exact(3)
+            .all(|t| normal(position(t[0]), position(t[1]), position(t[2])).y > 0.0)
+    }
+
+    #[test]
+    fn lod_chain_reaches_its_ratios() {
+        let (vertices, indices) = seamed_plane();
+        let (chain, ranges) = lod_chain(&vertices, &indices, &[1.0, 0.5, 0.25, 0.1]);
+        let triangles: Vec<usize> = ranges.iter().map(|r| r.len() / 3).collect();
+        assert_eq!(triangles[0], 1024);
+        for (&count, target) in triangles.iter().zip([1024.0, 512.0, 256.0]) {
+            let close = (count as f32 - target).abs() <= 0.05 * target;
+            assert!(close, "{:?}", triangles);
+        }
+        // The locked borders stop the last level short of its 102 triangles
+        let last = triangles[3];
+        assert!(last > 102 && last < triangles[2], "{:?}", triangles);
+        for range in ranges {
+            let level = &chain[range.start as usize..range.end as usize];
+            assert!(up_facing(&vertices, level));
+        }
+    }
+
+    #[test]
+    fn seam_vertices_stay() {
+        let (vertices, indices) = seamed_plane();
+        let seam: Vec<u32> = (0..vertices.len() as u32)
+            .filter(|&i| vertices[i as usize].position[0] == 0.5)
+            .collect();
+        assert_eq!(seam.len(), 34);
+        let locked = locked_vertices(&vertices, &indices);
+        assert!(seam.iter().all(|&i| locked[i as usize]));
+        let simplified = simplify_indices(&vertices, &indices, 0.25);
+        assert!(seam.iter().all(|i| simplified.contains(i)));
+    }
+
+    #[test]
+    fn degenerate_and_invalid_triangles_are_dropped() {
+        let (vertices, quad) = primitives::plane(1.0, 1.0, 1);
+        let mut indices = quad.clone();
+        indices.extend_from_slice(&[1, 1, 2, 0, 1, 99, 0, 1]);
+        assert_eq!(simplify_indices(&vertices, &indices, 1.0), quad);
+        let (kept, simplified) = simplify(&vertices, &indices, 1.0);
+        assert_eq!(kept.len(), 4);
+        assert_eq!(simplified.len(), 6);
+    }
+}
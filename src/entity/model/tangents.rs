@@ -0,0 +1,69 @@
+use crate::entity::model::Vertex;
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+/// A unit vector perpendicular to `normal`, for vertices whose texture coordinates don't give
+/// a tangent. Always the same for the same normal.
+pub fn fallback_tangent(normal: Vector3<f32>) -> [f32; 4] {
+    let normal = if normal.magnitude2() > 0.0 {
+        normal.normalize()
+    } else {
+        Vector3::unit_z()
+    };
+    // Projecting an axis far from the normal onto its plane is never degenerate
+    let axis = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let tangent = (axis - normal * normal.dot(axis)).normalize();
+    [tangent.x, tangent.y, tangent.z, 1.0]
+}
+
+/// Per vertex tangents of a triangle list, pointing along increasing U in `xyz` with the
+/// handedness of the UV mapping in `w`, so `bitangent = cross(normal, tangent) * w`. Vertices
+/// without usable texture coordinates get `fallback_tangent`.
+pub fn generate_tangents(vertices: &[Vertex], indices: &[u32]) -> Vec<[f32; 4]> {
+    let mut tangents = vec![Vector3::new(0.0f32, 0.0, 0.0); vertices.len()];
+    let mut bitangents = tangents.clone();
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+        if a >= vertices.len() || b >= vertices.len() || c >= vertices.len() {
+            continue;
+        }
+        let position = |i: usize| Vector3::from(vertices[i].position);
+        let uv = |i: usize| Vector2::from(vertices[i].texture_coords);
+        let (edge1, edge2) = (position(b) - position(a), position(c) - position(a));
+        let (duv1, duv2) = (uv(b) - uv(a), uv(c) - uv(a));
+        let determinant = duv1.x * duv2.y - duv2.x * duv1.y;
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / determinant;
+        // Weighted by the UV area, larger triangles contribute more
+        let tangent = (edge1 * duv2.y - edge2 * duv1.y) * r;
+        let bitangent = (edge2 * duv1.x - edge1 * duv2.x) * r;
+        for i in [a, b, c] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+    vertices
+        .iter()
+        .zip(tangents.into_iter().zip(bitangents))
+        .map(|(vertex, (tangent, bitangent))| {
+            let normal = Vector3::from(vertex.normal);
+            // Gram-Schmidt, the tangent has to be perpendicular to the normal
+            let orthogonal = tangent - normal * normal.dot(tangent);
+            if !orthogonal.magnitude2().is_normal() {
+                return fallback_tangent(normal);
+            }
+            let orthogonal = orthogonal.normalize();
+            let handedness = if normal.cross(orthogonal).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            [orthogonal.x, orthogonal.y, orthogonal.z, handedness]
+        })
+        .collect()
+}
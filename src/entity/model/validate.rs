@@ -0,0 +1,279 @@
+use crate::entity::model::Vertex;
+use std::fmt::{Display, Formatter};
+
+/// How far a normal's length may be from 1 before it's reported.
+pub const NORMAL_TOLERANCE: f32 = 1e-3;
+/// Texture coordinates further from the origin than this are almost certainly garbage, even
+/// for tiling textures.
+pub const UV_LIMIT: f32 = 10.0;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum Attribute {
+    Position,
+    Normal,
+    TextureCoords,
+}
+
+/// A problem with mesh data, found by `validate`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ValidationIssue {
+    NoVertices,
+    NoIndices,
+    /// The index count isn't a multiple of 3, so the last triangle is incomplete.
+    PartialTriangle { index_count: usize },
+    /// `indices[index]` isn't below the vertex count.
+    IndexOutOfRange {
+        index: usize,
+        value: u32,
+        vertex_count: usize,
+    },
+    /// An attribute of `vertices[vertex]` is NaN or infinite.
+    NonFinite { vertex: usize, attribute: Attribute },
+    /// The normal of `vertices[vertex]` is off unit length by more than `NORMAL_TOLERANCE`.
+    UnnormalizedNormal { vertex: usize, length: f32 },
+    /// The texture coordinates of `vertices[vertex]` are outside `-UV_LIMIT..=UV_LIMIT`.
+    UvOutOfRange { vertex: usize, uv: [f32; 2] },
+}
+impl ValidationIssue {
+    /// Whether the data would make the GPU read out of bounds or draw garbage. The others only
+    /// make shading look wrong.
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            self,
+            ValidationIssue::UnnormalizedNormal { .. } | ValidationIssue::UvOutOfRange { .. }
+        )
+    }
+}
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationIssue::NoVertices => write!(f, "there are no vertices"),
+            ValidationIssue::NoIndices => write!(f, "there are no indices"),
+            ValidationIssue::PartialTriangle { index_count } => {
+                write!(f, "{} indices aren't a whole number of triangles", index_count)
+            }
+            ValidationIssue::IndexOutOfRange {
+                index,
+                value,
+                vertex_count,
+            } => write!(
+                f,
+                "index {} is {} but there are {} vertices",
+                index, value, vertex_count
+            ),
+            ValidationIssue::NonFinite { vertex, attribute } => {
+                write!(f, "vertex {} has a non-finite {:?}", vertex, attribute)
+            }
+            ValidationIssue::UnnormalizedNormal { vertex, length } => {
+                write!(f, "vertex {} has a normal of length {}", vertex, length)
+            }
+            ValidationIssue::UvOutOfRange { vertex, uv } => {
+                write!(f, "vertex {} has texture coordinates {:?}", vertex, uv)
+            }
+        }
+    }
+}
+
+/// Mesh data that failed validation, with every problem found.
+#[derive(Clone, PartialEq, Debug)]
+pub struct InvalidMesh {
+    pub label: Option<String>,
+    pub issues: Vec<ValidationIssue>,
+}
+impl Display for InvalidMesh {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid mesh {}:", self.label.as_deref().unwrap_or("without a label"))?;
+        for issue in &self.issues {
+            write!(f, "\n    {}", issue)?;
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for InvalidMesh {}
+
+/// Checks a triangle list for data that breaks rendering, before it gets to the GPU. Issues
+/// are in order: the buffers as a whole, then the indices, then the vertices.
+pub fn validate(vertices: &[Vertex], indices: &[u32]) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    if vertices.is_empty() {
+        issues.push(ValidationIssue::NoVertices);
+    }
+    if indices.is_empty() {
+        issues.push(ValidationIssue::NoIndices);
+    }
+    if indices.len() % 3 != 0 {
+        issues.push(ValidationIssue::PartialTriangle {
+            index_count: indices.len(),
+        });
+    }
+    for (index, &value) in indices.iter().enumerate() {
+        if value as usize >= vertices.len() {
+            issues.push(ValidationIssue::IndexOutOfRange {
+                index,
+                value,
+                vertex_count: vertices.len(),
+            });
+        }
+    }
+    for (i, v) in vertices.iter().enumerate() {
+        let attributes: [(Attribute, &[f32]); 3] = [
+            (Attribute::Position, &v.position),
+            (Attribute::Normal, &v.normal),
+            (Attribute::TextureCoords, &v.texture_coords),
+        ];
+        let mut finite = true;
+        for (attribute, values) in attributes {
+            if !values.iter().all(|x| x.is_finite()) {
+                issues.push(ValidationIssue::NonFinite {
+                    vertex: i,
+                    attribute,
+                });
+                finite = false;
+            }
+        }
+        if !finite {
+            continue;
+        }
+        let length = v.normal.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if (length - 1.0).abs() > NORMAL_TOLERANCE {
+            issues.push(ValidationIssue::UnnormalizedNormal { vertex: i, length });
+        }
+        if v.texture_coords.iter().any(|x| x.abs() > UV_LIMIT) {
+            issues.push(ValidationIssue::UvOutOfRange {
+                vertex: i,
+                uv: v.texture_coords,
+            });
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3]) -> Vertex {
+        Vertex {
+            position,
+            normal: [0.0, 0.0, 1.0],
+            texture_coords: [0.5, 0.5],
+        }
+    }
+
+    fn triangle() -> Vec<Vertex> {
+        vec![
+            vertex([0.0, 0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0]),
+            vertex([0.0, 1.0, 0.0]),
+        ]
+    }
+
+    #[test]
+    fn valid_meshes_have_no_issues() {
+        assert!(validate(&triangle(), &[0, 1, 2]).is_empty());
+        // Slightly off normals and tiling UVs are fine
+        let mut vertices = triangle();
+        vertices[0].normal = [0.0, 0.0, 1.0005];
+        vertices[1].texture_coords = [-10.0, 10.0];
+        assert!(validate(&vertices, &[0, 1, 2]).is_empty());
+    }
+
+    #[test]
+    fn empty_buffers() {
+        let issues = validate(&[], &[]);
+        assert_eq!(issues, [ValidationIssue::NoVertices, ValidationIssue::NoIndices]);
+        assert!(issues.iter().all(ValidationIssue::is_error));
+    }
+
+    #[test]
+    fn partial_triangles() {
+        let issues = validate(&triangle(), &[0, 1, 2, 0]);
+        assert_eq!(issues, [ValidationIssue::PartialTriangle { index_count: 4 }]);
+        assert!(issues[0].is_error());
+    }
+
+    #[test]
+    fn indices_out_of_range() {
+        let issues = validate(&triangle(), &[0, 1, 3]);
+        let expected = ValidationIssue::IndexOutOfRange {
+            index: 2,
+            value: 3,
+            vertex_count: 3,
+        };
+        assert_eq!(issues, [expected]);
+        assert!(issues[0].is_error());
+    }
+
+    #[test]
+    fn non_finite_attributes() {
+        let mut vertices = triangle();
+        vertices[1].position[2] = f32::NAN;
+        vertices[2].normal[0] = f32::INFINITY;
+        vertices[2].texture_coords[1] = f32::NEG_INFINITY;
+        let issues = validate(&vertices, &[0, 1, 2]);
+        let non_finite = |vertex, attribute| ValidationIssue::NonFinite { vertex, attribute };
+        // Other checks are skipped for the vertex, the infinite normal isn't also unnormalized
+        let expected = [
+            non_finite(1, Attribute::Position),
+            non_finite(2, Attribute::Normal),
+            non_finite(2, Attribute::TextureCoords),
+        ];
+        assert_eq!(issues, expected);
+        assert!(issues.iter().all(ValidationIssue::is_error));
+    }
+
+    #[test]
+    fn unnormalized_normals() {
+        let mut vertices = triangle();
+        vertices[0].normal = [0.0, 0.0, 2.0];
+        vertices[2].normal = [0.0; 3];
+        let issues = validate(&vertices, &[0, 1, 2]);
+        let expected = [
+            ValidationIssue::UnnormalizedNormal {
+                vertex: 0,
+                length: 2.0,
+            },
+            ValidationIssue::UnnormalizedNormal {
+                vertex: 2,
+                length: 0.0,
+            },
+        ];
+        assert_eq!(issues, expected);
+        assert!(!issues.iter().any(ValidationIssue::is_error));
+    }
+
+    #[test]
+    fn uvs_out_of_range() {
+        let mut vertices = triangle();
+        vertices[1].texture_coords = [0.0, -10.5];
+        let issues = validate(&vertices, &[0, 1, 2]);
+        let expected = ValidationIssue::UvOutOfRange {
+            vertex: 1,
+            uv: [0.0, -10.5],
+        };
+        assert_eq!(issues, [expected]);
+        assert!(!issues[0].is_error());
+    }
+
+    #[test]
+    fn issues_are_ordered_buffers_indices_vertices() {
+        let mut vertices = triangle();
+        vertices[0].texture_coords = [11.0, 0.0];
+        let issues = validate(&vertices, &[0, 5, 2, 1]);
+        assert!(matches!(
+            issues[..],
+            [
+                ValidationIssue::PartialTriangle { .. },
+                ValidationIssue::IndexOutOfRange { index: 1, .. },
+                ValidationIssue::UvOutOfRange { vertex: 0, .. },
+            ]
+        ));
+        let invalid = InvalidMesh {
+            label: Some("Crate".to_string()),
+            issues,
+        };
+        let message = invalid.to_string();
+        assert!(message.starts_with("invalid mesh Crate:\n    "), "{}", message);
+        assert_eq!(message.lines().count(), 4);
+    }
+}
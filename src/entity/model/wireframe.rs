@@ -0,0 +1,70 @@
+use std::collections::HashSet;
+
+/// A line list with each edge of the triangle list `indices` once, in the order they're first
+/// used, for drawing wireframes without `PolygonMode::Line`, which needs a device feature. A
+/// closed mesh has about 1.5 edges per triangle, since most edges are shared by two. Indices
+/// past the last whole triangle and edges of degenerate triangles that join a vertex to
+/// itself are left out.
+pub fn wireframe_indices(indices: &[u32]) -> Vec<u32> {
+    let triangles = indices.len() / 3;
+    let mut seen: HashSet<(u32, u32)> = HashSet::with_capacity(triangles * 3 / 2);
+    let mut lines = Vec::with_capacity(triangles * 3);
+    for triangle in indices.chunks_exact(3) {
+        for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+            let (a, b) = (triangle[a], triangle[b]);
+            if a != b && seen.insert((a.min(b), a.max(b))) {
+                lines.extend_from_slice(&[a, b]);
+            }
+        }
+    }
+    lines.shrink_to_fit();
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_cube_has_18_edges() {
+        // Corner `i` is at `(i & 1, i >> 1 & 1, i >> 2 & 1)`
+        let quads = [
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+        ];
+        let indices: Vec<u32> = quads
+            .iter()
+            .flat_map(|&[a, b, c, d]| [a, b, c, a, c, d])
+            .collect();
+        let lines = wireframe_indices(&indices);
+        // The 12 sides of the cube and a diagonal across each face
+        assert_eq!(lines.len(), 18 * 2);
+        let edges: HashSet<_> = lines
+            .chunks(2)
+            .map(|l| (l[0].min(l[1]), l[0].max(l[1])))
+            .collect();
+        assert_eq!(edges.len(), 18);
+    }
+
+    #[test]
+    fn shared_edges_are_emitted_once() {
+        assert_eq!(
+            wireframe_indices(&[0, 1, 2, 2, 1, 3]),
+            [0, 1, 1, 2, 2, 0, 1, 3, 3, 2]
+        );
+        assert_eq!(
+            wireframe_indices(&[0, 1, 2, 1, 2, 3]),
+            [0, 1, 1, 2, 2, 0, 2, 3, 3, 1]
+        );
+    }
+
+    #[test]
+    fn degenerate_triangles_and_trailing_indices_are_skipped() {
+        assert_eq!(wireframe_indices(&[0, 0, 1, 5, 6]), [0, 1]);
+        assert!(wireframe_indices(&[3, 3, 3, 1, 2]).is_empty());
+    }
+}
@@ -0,0 +1,28 @@
+use cgmath::{Matrix4, One, Quaternion, Vector3};
+
+/// Translation, rotation and scale of an entity, applied in reverse order.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
@@ -0,0 +1,407 @@
+use derive_more::{Display, Error};
+use wgpu::util::DeviceExt;
+
+#[derive(Debug, Display, Error)]
+pub enum EnvMapError {
+    Image(image::ImageError),
+}
+impl From<image::ImageError> for EnvMapError {
+    fn from(e: image::ImageError) -> Self {
+        EnvMapError::Image(e)
+    }
+}
+
+/// Format of the cube maps, float storage textures need a format that's both filterable and
+/// usable as a storage texture.
+const CUBE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const WORKGROUP_SIZE: u32 = 8;
+const PREFILTER_SAMPLES: u32 = 1024;
+const IRRADIANCE_SAMPLES: u32 = 2048;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct ConvolutionParams {
+    roughness: f32,
+    sample_count: u32,
+    _padding: [u32; 2],
+}
+
+/// An equirectangular HDR environment kept on the CPU until it's projected to a cube map.
+pub struct EnvironmentMap {
+    width: u32,
+    height: u32,
+    /// RGBA32F texels, row major.
+    pixels: Vec<f32>,
+}
+
+pub struct CubeMap {
+    pub texture: wgpu::Texture,
+    /// Cube view over every mip level.
+    pub view: wgpu::TextureView,
+    pub resolution: u32,
+    pub mip_levels: u32,
+}
+
+impl EnvironmentMap {
+    /// Decodes a Radiance `.hdr` or OpenEXR `.exr` image, the format is detected from the bytes.
+    pub fn from_hdr_bytes(bytes: &[u8]) -> Result<EnvironmentMap, EnvMapError> {
+        let image = image::load_from_memory(bytes)?.into_rgba32f();
+        let (width, height) = image.dimensions();
+        Ok(EnvironmentMap {
+            width,
+            height,
+            pixels: image.into_raw(),
+        })
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    /// Projects the environment onto a `resolution` sized cube map on the GPU.
+    pub fn to_cube_map(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resolution: u32,
+    ) -> CubeMap {
+        let size = wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        };
+        let source = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Equirectangular Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &source,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&self.pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(16 * self.width),
+                rows_per_image: std::num::NonZeroU32::new(self.height),
+            },
+            size,
+        );
+        let source_view = source.create_view(&wgpu::TextureViewDescriptor::default());
+        let cube = CubeMap::new(device, "Environment Cube Map", resolution, 1);
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Equirectangular To Cube Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                storage_entry(1),
+            ],
+        });
+        let dst_view = cube.storage_view(0);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Equirectangular To Cube Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&dst_view),
+                },
+            ],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Equirectangular To Cube Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../equirect_to_cube.wgsl").into()),
+        });
+        let pipeline = compute_pipeline(device, &bind_group_layout, &shader, "main");
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Equirectangular To Cube Encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Equirectangular To Cube Pass"),
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let groups = (resolution + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+            pass.dispatch(groups, groups, 6);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        cube
+    }
+    /// Cube faces with about as many texels per angle as the image, which spans four of them
+    /// horizontally.
+    fn cube_resolution(&self) -> u32 {
+        (self.width / 4).max(1)
+    }
+    /// `CubeMap::prefilter` of the environment projected with `to_cube_map`. Project it once
+    /// and call `CubeMap`'s methods instead to also make an irradiance map.
+    pub fn prefilter(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mip_levels: u32,
+    ) -> CubeMap {
+        let cube = self.to_cube_map(device, queue, self.cube_resolution());
+        cube.prefilter(device, queue, mip_levels)
+    }
+    /// `CubeMap::irradiance_map` of the environment projected with `to_cube_map`.
+    pub fn irradiance_map(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resolution: u32,
+    ) -> CubeMap {
+        let cube = self.to_cube_map(device, queue, self.cube_resolution());
+        cube.irradiance_map(device, queue, resolution)
+    }
+}
+
+impl CubeMap {
+    fn new(device: &wgpu::Device, label: &str, resolution: u32, mip_levels: u32) -> CubeMap {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: mip_levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: CUBE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        CubeMap {
+            texture,
+            view,
+            resolution,
+            mip_levels,
+        }
+    }
+    /// Writable view over the six faces of one mip level.
+    fn storage_view(&self, mip_level: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            base_mip_level: mip_level,
+            mip_level_count: std::num::NonZeroU32::new(1),
+            ..Default::default()
+        })
+    }
+    /// Specular IBL: mip `i` of the result is the environment convolved with the GGX lobe for
+    /// roughness `i / (mip_levels - 1)`.
+    pub fn prefilter(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mip_levels: u32,
+    ) -> CubeMap {
+        let mip_levels = mip_levels.max(1);
+        let prefiltered = CubeMap::new(device, "Prefiltered Cube Map", self.resolution, mip_levels);
+        let passes = (0..mip_levels)
+            .map(|mip| {
+                let roughness = if mip_levels > 1 {
+                    mip as f32 / (mip_levels - 1) as f32
+                } else {
+                    0.0
+                };
+                (mip, roughness)
+            })
+            .collect::<Vec<_>>();
+        self.convolve(device, queue, &prefiltered, "prefilter", PREFILTER_SAMPLES, &passes);
+        prefiltered
+    }
+    /// Diffuse IBL: the cosine weighted irradiance arriving from every direction.
+    pub fn irradiance_map(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resolution: u32,
+    ) -> CubeMap {
+        let irradiance = CubeMap::new(device, "Irradiance Cube Map", resolution, 1);
+        self.convolve(device, queue, &irradiance, "irradiance", IRRADIANCE_SAMPLES, &[(0, 1.0)]);
+        irradiance
+    }
+    /// Runs `entry_point` of the convolution shader once per `(mip level, roughness)` of `dst`.
+    fn convolve(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        dst: &CubeMap,
+        entry_point: &str,
+        sample_count: u32,
+        passes: &[(u32, f32)],
+    ) {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Convolution Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Environment Convolution Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(3),
+            ],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Environment Convolution Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../env_convolution.wgsl").into()),
+        });
+        let pipeline = compute_pipeline(device, &bind_group_layout, &shader, entry_point);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Environment Convolution Encoder"),
+        });
+        for &(mip, roughness) in passes {
+            let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Environment Convolution Params"),
+                contents: bytemuck::cast_slice(&[ConvolutionParams {
+                    roughness,
+                    sample_count,
+                    _padding: [0; 2],
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let dst_view = dst.storage_view(mip);
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Environment Convolution Bind Group"),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&self.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&dst_view),
+                    },
+                ],
+            });
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Environment Convolution Pass"),
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let mip_size = (dst.resolution >> mip).max(1);
+            let groups = (mip_size + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+            pass.dispatch(groups, groups, 6);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+fn storage_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: CUBE_FORMAT,
+            view_dimension: wgpu::TextureViewDimension::D2Array,
+        },
+        count: None,
+    }
+}
+
+fn compute_pipeline(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    shader: &wgpu::ShaderModule,
+    entry_point: &str,
+) -> wgpu::ComputePipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Environment Map Pipeline Layout"),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Environment Map Pipeline"),
+        layout: Some(&layout),
+        module: shader,
+        entry_point,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convolutions_from_the_environment() {
+        let (device, queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let (width, height) = (32, 16);
+        let environment = EnvironmentMap {
+            width,
+            height,
+            pixels: vec![0.5; (4 * width * height) as usize],
+        };
+        let prefiltered = environment.prefilter(&device, &queue, 4);
+        assert_eq!((prefiltered.resolution, prefiltered.mip_levels), (8, 4));
+        let irradiance = environment.irradiance_map(&device, &queue, 4);
+        assert_eq!((irradiance.resolution, irradiance.mip_levels), (4, 1));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::assets::Assets;
+use crate::camera::Camera;
+use crate::entity::model::bounds::Aabb;
+use crate::entity::model::{self, LoadOptions, ModelLoadHandle};
+use crate::scene::Scene;
+use std::path::PathBuf;
+use winit::event::WindowEvent;
+
+/// What finished drops do to the scene.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DropBehavior {
+    /// Adds the models next to what's there.
+    Add,
+    /// Clears the scene when the first model of a drop arrives. The rest of the same drop is
+    /// added to it.
+    Replace,
+}
+impl Default for DropBehavior {
+    fn default() -> Self {
+        DropBehavior::Add
+    }
+}
+
+struct DroppedLoad {
+    handle: ModelLoadHandle,
+    /// Files dropped together share a batch.
+    batch: u64,
+}
+
+/// Loads model files dragged onto the window. Every file is parsed on its own thread, and
+/// once it's done the model becomes an entity and the camera is framed on everything loaded
+/// from the same drop. Files that fail to load are logged and skipped.
+pub struct FileDrop {
+    pub behavior: DropBehavior,
+    loads: Vec<DroppedLoad>,
+    /// Files being dragged over the window, for highlighting it.
+    hovered: Vec<PathBuf>,
+    batch: u64,
+    /// Whether the next `DroppedFile` starts a new batch.
+    new_batch: bool,
+    /// The batch the scene was last cleared for and the bounds loaded from it so far.
+    current: Option<(u64, Aabb)>,
+}
+impl FileDrop {
+    pub fn new(behavior: DropBehavior) -> Self {
+        FileDrop {
+            behavior,
+            loads: Vec::new(),
+            hovered: Vec::new(),
+            batch: 0,
+            new_batch: true,
+            current: None,
+        }
+    }
+    /// The files being dragged over the window, empty when nothing is.
+    pub fn hovered(&self) -> &[PathBuf] {
+        &self.hovered
+    }
+    /// How many dropped files are still loading.
+    pub fn pending(&self) -> usize {
+        self.loads.len()
+    }
+    /// Starts loading dropped files. Returns whether the event was a drag and drop one.
+    pub fn on_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::HoveredFile(path) => {
+                self.hovered.push(path.clone());
+                self.new_batch = true;
+            }
+            WindowEvent::HoveredFileCancelled => self.hovered.clear(),
+            WindowEvent::DroppedFile(path) => {
+                self.hovered.clear();
+                if self.new_batch {
+                    self.batch += 1;
+                    self.new_batch = false;
+                }
+                log::info!("loading dropped file '{}'", path.display());
+                self.loads.push(DroppedLoad {
+                    handle: model::load_in_background(path.clone(), LoadOptions::default()),
+                    batch: self.batch,
+                });
+            }
+            _ => return false,
+        }
+        true
+    }
+    /// Adds the models that finished loading to `scene`. Returns how many were added.
+    pub fn poll(&mut self, assets: &mut Assets, scene: &mut Scene, camera: &mut Camera) -> usize {
+        let mut added = 0;
+        let mut i = 0;
+        while i < self.loads.len() {
+            let result = match self.loads[i].handle.try_take() {
+                Some(result) => result,
+                None => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let load = self.loads.remove(i);
+            let path = load.handle.path();
+            let loaded = result
+                .map_err(Into::into)
+                .and_then(|data| assets.insert_model(path, data));
+            let entity = match loaded {
+                Ok(entity) => entity,
+                Err(e) => {
+                    log::error!("can't load dropped file '{}': {}", path.display(), e);
+                    continue;
+                }
+            };
+            let bounds = match self.current {
+                Some((batch, bounds)) if batch == load.batch => bounds,
+                _ => {
+                    if self.behavior == DropBehavior::Replace {
+                        scene.entities.clear();
+                    }
+                    Aabb::empty()
+                }
+            };
+            let bounds = bounds.union(&entity.bounds);
+            self.current = Some((load.batch, bounds));
+            camera.frame(&bounds);
+            scene.add(entity);
+            added += 1;
+        }
+        added
+    }
+}
+impl Default for FileDrop {
+    fn default() -> Self {
+        Self::new(DropBehavior::default())
+    }
+}
@@ -0,0 +1,209 @@
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct FxaaUniform {
+    texel_size: [f32; 2],
+    subpixel: f32,
+    edge_threshold: f32,
+    edge_threshold_min: f32,
+    search_steps: u32,
+    _padding: [u32; 2],
+}
+
+/// FXAA presets, higher ones find fainter edges and search further along them.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum FxaaQuality {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+impl FxaaQuality {
+    /// `(subpixel, edge_threshold, edge_threshold_min, search_steps)`, from the FXAA 3.11
+    /// quality settings.
+    fn parameters(self) -> (f32, f32, f32, u32) {
+        match self {
+            FxaaQuality::Low => (0.25, 0.250, 0.0833, 4),
+            FxaaQuality::Medium => (0.50, 0.166, 0.0833, 8),
+            FxaaQuality::High => (0.75, 0.125, 0.0625, 12),
+            FxaaQuality::Ultra => (1.00, 0.063, 0.0312, 12),
+        }
+    }
+}
+impl Default for FxaaQuality {
+    fn default() -> Self {
+        FxaaQuality::High
+    }
+}
+
+/// Fast approximate anti-aliasing, FXAA 3.11 quality. Runs on the final LDR image after tone
+/// mapping, its luma thresholds are meant for display colors.
+pub struct FxaaPass {
+    quality: FxaaQuality,
+    uniform: FxaaUniform,
+    uniform_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+impl FxaaPass {
+    /// Writes to targets of the surface's format and size.
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let quality = FxaaQuality::default();
+        let mut uniform = FxaaUniform {
+            texel_size: texel_size(config),
+            ..Default::default()
+        };
+        set_parameters(&mut uniform, quality);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("FXAA Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("FXAA Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("FXAA Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("FXAA Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../fxaa.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("FXAA Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("FXAA Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[config.format.into()],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        });
+        FxaaPass {
+            quality,
+            uniform,
+            uniform_buffer,
+            sampler,
+            layout,
+            pipeline,
+        }
+    }
+    pub fn resize(&mut self, queue: &wgpu::Queue, config: &wgpu::SurfaceConfiguration) {
+        self.uniform.texel_size = texel_size(config);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+    pub fn quality(&self) -> FxaaQuality {
+        self.quality
+    }
+    pub fn set_quality(&mut self, queue: &wgpu::Queue, quality: FxaaQuality) {
+        self.quality = quality;
+        set_parameters(&mut self.uniform, quality);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+    /// Anti-aliases `ldr_view`, e.g. the tone mapped frame, into `target`. They can't be the
+    /// same texture.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        ldr_view: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+    ) {
+        // The input is recreated on resize, so the bind group is made per frame
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("FXAA Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(ldr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("FXAA Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn texel_size(config: &wgpu::SurfaceConfiguration) -> [f32; 2] {
+    [1.0 / config.width.max(1) as f32, 1.0 / config.height.max(1) as f32]
+}
+
+fn set_parameters(uniform: &mut FxaaUniform, quality: FxaaQuality) {
+    let (subpixel, edge_threshold, edge_threshold_min, search_steps) = quality.parameters();
+    uniform.subpixel = subpixel;
+    uniform.edge_threshold = edge_threshold;
+    uniform.edge_threshold_min = edge_threshold_min;
+    uniform.search_steps = search_steps;
+}
@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+/// Fixed timestep loop for logic updates with a variable rate for rendering. Real time is
+/// accumulated every frame and drained in `timestep` sized steps, the leftover fraction of a step
+/// is handed to the renderer to interpolate between the last two logic states.
+pub struct GameLoop {
+    timestep: Duration,
+    accumulator: Duration,
+    last_tick: Option<Instant>,
+    /// Caps the real time accounted for a single frame so a long stall (window drag, breakpoint)
+    /// doesn't turn into hundreds of catch up updates.
+    pub max_frame_time: Duration,
+}
+impl GameLoop {
+    pub const DEFAULT_RATE: u32 = 60;
+    pub fn new() -> Self {
+        Self::with_rate(Self::DEFAULT_RATE)
+    }
+    /// Runs logic updates `hz` times a second.
+    pub fn with_rate(hz: u32) -> Self {
+        Self::with_timestep(Duration::from_secs_f64(1.0 / f64::from(hz.max(1))))
+    }
+    pub fn with_timestep(timestep: Duration) -> Self {
+        GameLoop {
+            timestep,
+            accumulator: Duration::ZERO,
+            last_tick: None,
+            max_frame_time: Duration::from_millis(250),
+        }
+    }
+    pub fn timestep(&self) -> Duration {
+        self.timestep
+    }
+    /// Forgets the accumulated time, e.g. after the loop was paused.
+    pub fn reset(&mut self) {
+        self.accumulator = Duration::ZERO;
+        self.last_tick = None;
+    }
+    /// Accounts the real time since the last call, calls `update` once per whole timestep and
+    /// returns the render alpha in `[0, 1)`.
+    pub fn tick(&mut self, update: impl FnMut(Duration)) -> f32 {
+        let now = Instant::now();
+        let frame_time = match self.last_tick.replace(now) {
+            Some(last) => now - last,
+            None => Duration::ZERO,
+        };
+        self.advance(frame_time, update)
+    }
+    /// Like `tick` but with an explicit frame time instead of the wall clock.
+    pub fn advance(&mut self, frame_time: Duration, mut update: impl FnMut(Duration)) -> f32 {
+        self.accumulator += frame_time.min(self.max_frame_time);
+        while self.accumulator >= self.timestep {
+            update(self.timestep);
+            self.accumulator -= self.timestep;
+        }
+        self.alpha()
+    }
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f64() / self.timestep.as_secs_f64()) as f32
+    }
+}
+impl Default for GameLoop {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Duration = Duration::from_millis(10);
+
+    fn steps(game_loop: &mut GameLoop, frame_time: Duration) -> (u32, f32) {
+        let mut steps = 0;
+        let alpha = game_loop.advance(frame_time, |dt| {
+            assert_eq!(dt, STEP);
+            steps += 1;
+        });
+        (steps, alpha)
+    }
+
+    #[test]
+    fn long_frames_run_several_steps() {
+        let mut game_loop = GameLoop::with_timestep(STEP);
+        assert_eq!(steps(&mut game_loop, Duration::from_millis(35)), (3, 0.5));
+        // The leftover 5ms carry over into the next frame
+        assert_eq!(steps(&mut game_loop, Duration::from_millis(7)), (1, 0.2));
+        assert_eq!(steps(&mut game_loop, Duration::from_millis(1)), (0, 0.3));
+    }
+
+    #[test]
+    fn stalls_are_capped() {
+        let mut game_loop = GameLoop::with_timestep(STEP);
+        assert_eq!(steps(&mut game_loop, Duration::from_secs(5)), (25, 0.0));
+        game_loop.max_frame_time = Duration::from_millis(45);
+        assert_eq!(steps(&mut game_loop, Duration::from_secs(5)), (4, 0.5));
+    }
+
+    #[test]
+    fn reset_drops_the_accumulated_time() {
+        let mut game_loop = GameLoop::with_timestep(STEP);
+        steps(&mut game_loop, Duration::from_millis(15));
+        game_loop.reset();
+        assert_eq!(game_loop.alpha(), 0.0);
+    }
+
+    #[test]
+    fn defaults_to_60_hz() {
+        let timestep = GameLoop::default().timestep();
+        assert!((timestep.as_secs_f64() - 1.0 / 60.0).abs() < 1e-9);
+        assert_eq!(GameLoop::with_rate(0).timestep(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,328 @@
+use crate::entity::model::mesh::Mesh;
+use crate::entity::model::Vertex;
+use cgmath::SquareMatrix;
+use wgpu::util::DeviceExt;
+
+pub const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgb10a2Unorm;
+pub const MATERIAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg8Unorm;
+pub const VELOCITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CameraUniform {
+    view_proj: [[f32; 4]; 4],
+    /// `view_proj` without the TAA jitter, so the velocity doesn't include it.
+    unjittered_view_proj: [[f32; 4]; 4],
+    previous_view_proj: [[f32; 4]; 4],
+    position: [f32; 4],
+}
+
+/// The G-buffer textures, for binding in the lighting pass.
+pub struct GBufferViews<'a> {
+    /// rgb albedo, a roughness.
+    pub albedo: &'a wgpu::TextureView,
+    /// World space normal mapped to `[0, 1]`.
+    pub normal: &'a wgpu::TextureView,
+    /// r metallic, g ambient occlusion.
+    pub material: &'a wgpu::TextureView,
+    /// Screen space motion since the last frame in UV units, for `TaaPass`.
+    pub velocity: &'a wgpu::TextureView,
+    pub depth: &'a wgpu::TextureView,
+}
+
+struct Targets {
+    albedo: wgpu::TextureView,
+    normal: wgpu::TextureView,
+    material: wgpu::TextureView,
+    velocity: wgpu::TextureView,
+    depth: wgpu::TextureView,
+}
+fn create_target(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    label: &str,
+) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+impl Targets {
+    fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        Targets {
+            albedo: create_target(device, width, height, ALBEDO_FORMAT, "G-Buffer Albedo"),
+            normal: create_target(device, width, height, NORMAL_FORMAT, "G-Buffer Normal"),
+            material: create_target(device, width, height, MATERIAL_FORMAT, "G-Buffer Material"),
+            velocity: create_target(device, width, height, VELOCITY_FORMAT, "G-Buffer Velocity"),
+            depth: create_target(device, width, height, DEPTH_FORMAT, "G-Buffer Depth"),
+        }
+    }
+}
+
+/// Rasterizes the geometry once into albedo, normal, material, velocity and depth targets so
+/// lighting can be computed per pixel afterwards. Materials are `PbrMaterial` uniforms bound to
+/// group 1.
+pub struct GBufferPass {
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    material_layout: wgpu::BindGroupLayout,
+    read_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    /// For meshes with per vertex colors, which tint the material's albedo.
+    colored_pipeline: wgpu::RenderPipeline,
+    targets: Targets,
+}
+impl GBufferPass {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> GBufferPass {
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("G-Buffer Camera Buffer"),
+            contents: bytemuck::cast_slice(&[CameraUniform {
+                view_proj: cgmath::Matrix4::<f32>::identity().into(),
+                unjittered_view_proj: cgmath::Matrix4::<f32>::identity().into(),
+                previous_view_proj: cgmath::Matrix4::<f32>::identity().into(),
+                position: [0.0; 4],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_layout = |label| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            })
+        };
+        let camera_layout = uniform_layout("G-Buffer Camera Bind Group Layout");
+        let material_layout = uniform_layout("G-Buffer Material Bind Group Layout");
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("G-Buffer Camera Bind Group"),
+            layout: &camera_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        // Read with textureLoad, the lighting pass covers the targets pixel for pixel
+        let color = wgpu::TextureSampleType::Float { filterable: false };
+        let read_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("G-Buffer Read Bind Group Layout"),
+            entries: &[
+                texture_entry(0, color),
+                texture_entry(1, color),
+                texture_entry(2, color),
+                texture_entry(3, wgpu::TextureSampleType::Depth),
+            ],
+        });
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("G-Buffer Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../gbuffer.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("G-Buffer Pipeline Layout"),
+            bind_group_layouts: &[&camera_layout, &material_layout],
+            push_constant_ranges: &[],
+        });
+        let target = |format| wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        };
+        let create_pipeline = |label, entry_point, buffers: &[wgpu::VertexBufferLayout]| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point,
+                    buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[
+                        target(ALBEDO_FORMAT),
+                        target(NORMAL_FORMAT),
+                        target(MATERIAL_FORMAT),
+                        target(VELOCITY_FORMAT),
+                    ],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    clamp_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+            })
+        };
+        let pipeline = create_pipeline("G-Buffer Pipeline", "vs_main", &[Vertex::desc()]);
+        let colored_pipeline = create_pipeline(
+            "G-Buffer Colored Pipeline",
+            "vs_colored",
+            &[Vertex::desc(), Mesh::COLOR_LAYOUT],
+        );
+        GBufferPass {
+            camera_buffer,
+            camera_bind_group,
+            material_layout,
+            read_layout,
+            pipeline,
+            colored_pipeline,
+            targets: Targets::new(device, width, height),
+        }
+    }
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.targets = Targets::new(device, width, height);
+    }
+    /// Sets a still camera, the velocity target is left zero.
+    pub fn update_camera(
+        &self,
+        queue: &wgpu::Queue,
+        view_proj: cgmath::Matrix4<f32>,
+        position: cgmath::Point3<f32>,
+    ) {
+        self.update_camera_motion(queue, view_proj, view_proj, view_proj, position)
+    }
+    /// Sets the camera for a TAA frame. `jittered_view_proj` is drawn with, the velocity is
+    /// the motion between `previous_view_proj` and `view_proj`, both unjittered.
+    pub fn update_camera_motion(
+        &self,
+        queue: &wgpu::Queue,
+        jittered_view_proj: cgmath::Matrix4<f32>,
+        view_proj: cgmath::Matrix4<f32>,
+        previous_view_proj: cgmath::Matrix4<f32>,
+        position: cgmath::Point3<f32>,
+    ) {
+        let uniform = CameraUniform {
+            view_proj: jittered_view_proj.into(),
+            unjittered_view_proj: view_proj.into(),
+            previous_view_proj: previous_view_proj.into(),
+            position: position.to_homogeneous().into(),
+        };
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+    /// Layout of the `PbrMaterial` uniform bind groups drawn with.
+    pub fn material_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.material_layout
+    }
+    pub fn views(&self) -> GBufferViews {
+        GBufferViews {
+            albedo: &self.targets.albedo,
+            normal: &self.targets.normal,
+            material: &self.targets.material,
+            velocity: &self.targets.velocity,
+            depth: &self.targets.depth,
+        }
+    }
+    /// Layout with the albedo, normal, material and depth views at bindings 0 to 3.
+    pub fn read_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.read_layout
+    }
+    /// Bind group of `read_layout` for the lighting pass, recreate it after `resize`.
+    pub fn read_bind_group(&self, device: &wgpu::Device) -> wgpu::BindGroup {
+        let views = self.views();
+        let views = [views.albedo, views.normal, views.material, views.depth];
+        let entries: Vec<wgpu::BindGroupEntry> = views
+            .into_iter()
+            .enumerate()
+            .map(|(binding, view)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: wgpu::BindingResource::TextureView(view),
+            })
+            .collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("G-Buffer Read Bind Group"),
+            layout: &self.read_layout,
+            entries: &entries,
+        })
+    }
+    /// The pipeline for meshes with or without a `color_buffer`. `render` starts with the one
+    /// without.
+    pub fn pipeline(&self, colored: bool) -> &wgpu::RenderPipeline {
+        if colored {
+            &self.colored_pipeline
+        } else {
+            &self.pipeline
+        }
+    }
+    /// Clears the targets and calls `draw` with the pipeline and camera set. `draw` binds a
+    /// material to group 1 and the vertex and index buffers for each mesh, switching to
+    /// `pipeline(true)` for meshes with colors.
+    pub fn render<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        draw: impl FnOnce(&mut wgpu::RenderPass<'a>),
+    ) {
+        let clear = |view| wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: true,
+            },
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("G-Buffer Pass"),
+            color_attachments: &[
+                clear(&self.targets.albedo),
+                clear(&self.targets.normal),
+                clear(&self.targets.material),
+                clear(&self.targets.velocity),
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.targets.depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        draw(&mut pass);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::entity::model::{Object, Vertex};
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector4};
+use wgpu::util::DeviceExt;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// The arguments of one indexed draw, laid out like wgpu expects them in an indirect buffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawIndexedIndirect {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    /// Index into the instance buffer, so shaders can look up the draw's transform.
+    pub first_instance: u32,
+}
+
+/// Per draw data read by the culling shader and by the vertex shader through the instance
+/// index.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct Instance {
+    model: [[f32; 4]; 4],
+    bounds_min: [f32; 4],
+    bounds_max: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullParams {
+    planes: [[f32; 4]; 6],
+    draw_count: u32,
+    _padding: [u32; 3],
+}
+
+/// The six inward facing planes of the frustum of `view_proj`, normalized. Depth is `0..1`
+/// like wgpu's clip space.
+pub fn frustum_planes(view_proj: Matrix4<f32>) -> [[f32; 4]; 6] {
+    let row = |i: usize| {
+        Vector4::new(view_proj.x[i], view_proj.y[i], view_proj.z[i], view_proj.w[i])
+    };
+    let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+    let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2];
+    let mut normalized = [[0.0; 4]; 6];
+    for (out, plane) in normalized.iter_mut().zip(planes) {
+        let length = plane.truncate().magnitude();
+        *out = (plane / length).into();
+    }
+    normalized
+}
+
+/// Many objects packed into one vertex and index buffer and drawn with a single
+/// `multi_draw_indexed_indirect`, one draw per object. `cull` writes the draws of objects
+/// outside the frustum with no instances so the GPU skips them.
+pub struct IndirectDrawBatch {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    /// One `Instance` per draw, the model matrix first. Bind it to the vertex shader and index
+    /// it with the instance index.
+    pub instance_buffer: wgpu::Buffer,
+    /// Every draw, as packed.
+    draw_buffer: wgpu::Buffer,
+    /// The draws after culling, what `draw` uses.
+    culled_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+    instances: Vec<Instance>,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+impl IndirectDrawBatch {
+    /// `multi_draw_indexed_indirect` isn't core wgpu.
+    pub const REQUIRED_FEATURES: wgpu::Features = wgpu::Features::MULTI_DRAW_INDIRECT;
+
+    pub fn new(device: &wgpu::Device, objects: &[&Object]) -> IndirectDrawBatch {
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        let mut draws = Vec::with_capacity(objects.len());
+        let mut instances = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            draws.push(DrawIndexedIndirect {
+                index_count: object.indices().len() as u32,
+                instance_count: 1,
+                first_index: indices.len() as u32,
+                base_vertex: vertices.len() as i32,
+                first_instance: i as u32,
+            });
+            let bounds = object.bounds();
+            instances.push(Instance {
+                model: Matrix4::<f32>::identity().into(),
+                bounds_min: bounds.min.to_homogeneous().into(),
+                bounds_max: bounds.max.to_homogeneous().into(),
+            });
+            vertices.extend_from_slice(object.vertices());
+            indices.extend_from_slice(object.indices());
+        }
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Indirect Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Indirect Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Indirect Instance Buffer"),
+            contents: bytemuck::cast_slice(&instances),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let draw_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Indirect Draw Buffer"),
+            contents: bytemuck::cast_slice(&draws),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        // Starts out with every draw visible
+        let culled_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Indirect Culled Draw Buffer"),
+            contents: bytemuck::cast_slice(&draws),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Indirect Cull Params Buffer"),
+            size: std::mem::size_of::<CullParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cull Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+            ],
+        });
+        let buffers = [&params_buffer, &instance_buffer, &draw_buffer, &culled_buffer];
+        let entries: Vec<wgpu::BindGroupEntry> = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cull Bind Group"),
+            layout: &bind_group_layout,
+            entries: &entries,
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Cull Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../cull.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cull Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Cull Pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "main",
+        });
+        IndirectDrawBatch {
+            vertex_buffer,
+            index_buffer,
+            instance_buffer,
+            draw_buffer,
+            culled_buffer,
+            params_buffer,
+            instances,
+            bind_group,
+            pipeline,
+        }
+    }
+    pub fn draw_count(&self) -> u32 {
+        self.instances.len() as u32
+    }
+    /// Uploads the world matrices, one per object in the order given to `new`. Usually the
+    /// entities' `mx_world`.
+    pub fn set_transforms(&mut self, queue: &wgpu::Queue, transforms: &[Matrix4<f32>]) {
+        debug_assert_eq!(transforms.len(), self.instances.len());
+        for (instance, transform) in self.instances.iter_mut().zip(transforms) {
+            instance.model = (*transform).into();
+        }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&self.instances));
+    }
+    /// Records the culling dispatch against the frustum of `view_proj`, must come before the
+    /// pass calling `draw`.
+    pub fn cull(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view_proj: Matrix4<f32>,
+    ) {
+        let params = CullParams {
+            planes: frustum_planes(view_proj),
+            draw_count: self.draw_count(),
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Pass"),
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch((self.draw_count() + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE, 1, 1);
+    }
+    /// Binds the packed buffers and issues every draw, the pipeline and bind groups have to be
+    /// set already.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.instances.is_empty() {
+            return;
+        }
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.multi_draw_indexed_indirect(&self.culled_buffer, 0, self.draw_count());
+    }
+}
@@ -0,0 +1,45 @@
+pub mod assets;
+pub mod bloom;
+pub mod bvh;
+pub mod camera;
+pub mod debug_draw;
+#[cfg(feature = "ecs")]
+pub mod ecs;
+#[cfg(feature = "dev-ui")]
+pub mod egui_integration;
+pub mod entity;
+#[cfg(feature = "image")]
+pub mod environment_map;
+pub mod file_drop;
+pub mod fullscreen;
+pub mod fxaa;
+pub mod game_loop;
+pub mod gbuffer;
+pub mod indirect;
+pub mod light;
+pub mod lod;
+pub mod material;
+pub mod msaa;
+pub mod particles;
+pub mod profiler;
+pub mod readback;
+pub mod render_graph;
+pub mod scene;
+pub mod skinned_mesh;
+#[cfg(feature = "image")]
+pub mod skybox;
+pub mod spline;
+#[cfg(feature = "image")]
+pub mod sprite;
+pub mod ssao;
+pub mod state;
+pub mod taa;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "image")]
+pub mod terrain;
+pub mod texture;
+#[cfg(feature = "image")]
+pub mod texture_atlas;
+pub mod timing;
+pub mod tone_map;
@@ -0,0 +1,38 @@
+use crate::entity::model::mesh::Mesh;
+
+/// Versions of a mesh at decreasing detail, picked by distance to the camera.
+pub struct LodMesh {
+    /// Highest detail first, each with the farthest camera distance it's used at.
+    levels: Vec<(f32, Mesh)>,
+}
+impl LodMesh {
+    /// `meshes` are ordered from the highest detail down and `distances` are their maximum
+    /// camera distances, which must be increasing.
+    pub fn from_mesh_sequence(
+        meshes: impl IntoIterator<Item = Mesh>,
+        distances: impl IntoIterator<Item = f32>,
+    ) -> LodMesh {
+        let levels: Vec<(f32, Mesh)> = distances.into_iter().zip(meshes).collect();
+        assert!(!levels.is_empty(), "a LodMesh needs at least one level");
+        assert!(
+            levels.windows(2).all(|w| w[0].0 <= w[1].0),
+            "LOD distances must be increasing"
+        );
+        LodMesh { levels }
+    }
+    pub fn levels(&self) -> &[(f32, Mesh)] {
+        &self.levels
+    }
+    /// The highest detail mesh whose distance isn't exceeded, past the last threshold the
+    /// lowest detail mesh is still used.
+    pub fn select_lod(&self, distance: f32) -> &Mesh {
+        &self.levels[self.select_level(distance)].1
+    }
+    /// Index in `levels` of the mesh `select_lod` picks.
+    pub fn select_level(&self, distance: f32) -> usize {
+        self.levels
+            .iter()
+            .position(|(max_distance, _)| distance <= *max_distance)
+            .unwrap_or(self.levels.len() - 1)
+    }
+}
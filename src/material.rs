@@ -0,0 +1,187 @@
+use cgmath::{InnerSpace, Vector3};
+use std::f32::consts::PI;
+use std::path::PathBuf;
+
+/// Metallic-roughness material, rendered by `pbr.wgsl`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PbrMaterial {
+    pub albedo: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub ao: f32,
+
+    pub albedo_texture: Option<PathBuf>,
+    pub metallic_texture: Option<PathBuf>,
+    pub roughness_texture: Option<PathBuf>,
+    pub ao_texture: Option<PathBuf>,
+}
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        PbrMaterial {
+            albedo: [1.0; 4],
+            metallic: 0.0,
+            roughness: 0.5,
+            ao: 1.0,
+            albedo_texture: None,
+            metallic_texture: None,
+            roughness_texture: None,
+            ao_texture: None,
+        }
+    }
+}
+impl PbrMaterial {
+    pub const ALBEDO_TEXTURE: u32 = 1 << 0;
+    pub const METALLIC_TEXTURE: u32 = 1 << 1;
+    pub const ROUGHNESS_TEXTURE: u32 = 1 << 2;
+    pub const AO_TEXTURE: u32 = 1 << 3;
+
+    pub fn texture_flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.albedo_texture.is_some() {
+            flags |= Self::ALBEDO_TEXTURE;
+        }
+        if self.metallic_texture.is_some() {
+            flags |= Self::METALLIC_TEXTURE;
+        }
+        if self.roughness_texture.is_some() {
+            flags |= Self::ROUGHNESS_TEXTURE;
+        }
+        if self.ao_texture.is_some() {
+            flags |= Self::AO_TEXTURE;
+        }
+        flags
+    }
+    pub fn to_uniform(&self) -> MaterialUniform {
+        MaterialUniform {
+            albedo: self.albedo,
+            metallic: self.metallic,
+            // GGX breaks down for perfectly smooth surfaces
+            roughness: self.roughness.clamp(0.04, 1.0),
+            ao: self.ao,
+            texture_flags: self.texture_flags(),
+        }
+    }
+}
+
+/// CPU copies of the terms in `pbr.wgsl`, keep them in step with the shader.
+pub mod brdf {
+    use std::f32::consts::PI;
+
+    /// Trowbridge-Reitz GGX normal distribution.
+    pub fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+        let a = roughness * roughness;
+        let a2 = a * a;
+        let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+        a2 / (PI * d * d)
+    }
+    fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+        let r = roughness + 1.0;
+        let k = r * r / 8.0;
+        n_dot_x / (n_dot_x * (1.0 - k) + k)
+    }
+    /// Smith's method, shadowing from the light and masking towards the viewer.
+    pub fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+        geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness)
+    }
+    pub fn fresnel_schlick(cos_theta: f32, f0: [f32; 3]) -> [f32; 3] {
+        let weight = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5);
+        f0.map(|f0| f0 + (1.0 - f0) * weight)
+    }
+}
+
+impl PbrMaterial {
+    /// What `pbr.wgsl` reflects towards `v` of light from `l` on a surface facing `n`, all unit
+    /// length, before the `n·l` factor. Textures aren't sampled.
+    pub fn brdf(&self, n: Vector3<f32>, v: Vector3<f32>, l: Vector3<f32>) -> [f32; 3] {
+        let uniform = self.to_uniform();
+        let (metallic, roughness) = (uniform.metallic, uniform.roughness);
+        let h = (v + l).normalize();
+        let n_dot_v = n.dot(v).max(0.0);
+        let n_dot_l = n.dot(l).max(0.0);
+        let mut f0 = [0.0; 3];
+        for (f0, albedo) in f0.iter_mut().zip(self.albedo) {
+            *f0 = 0.04 + (albedo - 0.04) * metallic;
+        }
+        let f = brdf::fresnel_schlick(h.dot(v).max(0.0), f0);
+        let ndf = brdf::distribution_ggx(n.dot(h).max(0.0), roughness);
+        let g = brdf::geometry_smith(n_dot_v, n_dot_l, roughness);
+        let mut reflected = [0.0; 3];
+        for i in 0..3 {
+            let specular = ndf * g * f[i] / (4.0 * n_dot_v * n_dot_l + 0.0001);
+            let k_d = (1.0 - f[i]) * (1.0 - metallic);
+            reflected[i] = k_d * self.albedo[i] / PI + specular;
+        }
+        reflected
+    }
+}
+
+/// `PbrMaterial` laid out like the `Material` uniform in `pbr.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub albedo: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub ao: f32,
+    pub texture_flags: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `f` over the hemisphere around +Z in polar coordinates, with the midpoint rule.
+    fn integrate_hemisphere(f: impl Fn(Vector3<f32>) -> f32) -> f32 {
+        const STEPS: usize = 200;
+        let (d_theta, d_phi) = (PI / 2.0 / STEPS as f32, 2.0 * PI / (2 * STEPS) as f32);
+        let mut sum = 0.0;
+        for i in 0..STEPS {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..2 * STEPS {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let (sin, cos) = theta.sin_cos();
+                let direction = Vector3::new(sin * phi.cos(), sin * phi.sin(), cos);
+                sum += f(direction) * sin * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn ggx_is_normalized() {
+        for roughness in [0.2, 0.5, 1.0] {
+            let projected = integrate_hemisphere(|h| brdf::distribution_ggx(h.z, roughness) * h.z);
+            assert!((projected - 1.0).abs() < 0.01, "{}: {}", roughness, projected);
+        }
+    }
+
+    #[test]
+    fn white_dielectric_reflects_almost_everything() {
+        let n = Vector3::unit_z();
+        let v = Vector3::new(0.3f32.sin(), 0.0, 0.3f32.cos());
+        for roughness in [0.1, 0.5, 1.0] {
+            let material = PbrMaterial {
+                roughness,
+                ..PbrMaterial::default()
+            };
+            let reflected = integrate_hemisphere(|l| material.brdf(n, v, l)[0] * l.z);
+            // Fresnel and shadowing lose a little, nothing is created
+            assert!(reflected > 0.95 && reflected < 1.001, "{}: {}", roughness, reflected);
+        }
+    }
+
+    #[test]
+    fn metals_have_no_diffuse_lobe() {
+        let material = PbrMaterial {
+            albedo: [1.0, 0.5, 0.0, 1.0],
+            metallic: 1.0,
+            roughness: 1.0,
+            ..PbrMaterial::default()
+        };
+        // Far from the mirror direction the specular lobe is all that's left, tinted by albedo
+        let n = Vector3::unit_z();
+        let reflected = material.brdf(n, n, Vector3::new(0.8, 0.0, 0.6));
+        assert!(reflected[0] > reflected[1] && reflected[1] > reflected[2]);
+        assert!(reflected[2] < 1e-4);
+    }
+}
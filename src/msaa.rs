@@ -0,0 +1,55 @@
+use std::fmt::{Display, Formatter};
+
+/// The sample count isn't 1, 2, 4 or 8.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct InvalidSampleCount(pub u32);
+impl Display for InvalidSampleCount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid MSAA sample count {}, expected 1, 2, 4 or 8", self.0)
+    }
+}
+
+impl std::error::Error for InvalidSampleCount {}
+
+/// Multi-sample anti-aliasing of the main pass. A `sample_count` of 1 disables it.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct MsaaConfig {
+    sample_count: u32,
+}
+impl MsaaConfig {
+    pub const DISABLED: MsaaConfig = MsaaConfig { sample_count: 1 };
+    pub fn new(sample_count: u32) -> Result<Self, InvalidSampleCount> {
+        match sample_count {
+            1 | 2 | 4 | 8 => Ok(MsaaConfig { sample_count }),
+            _ => Err(InvalidSampleCount(sample_count)),
+        }
+    }
+    pub fn sample_count(self) -> u32 {
+        self.sample_count
+    }
+    pub fn is_enabled(self) -> bool {
+        self.sample_count > 1
+    }
+    /// Features the device needs for this sample count. Every adapter supports 4 samples,
+    /// other counts need adapter specific format features.
+    pub fn required_features(self) -> wgpu::Features {
+        match self.sample_count {
+            1 | 4 => wgpu::Features::empty(),
+            _ => wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+        }
+    }
+    /// The config a device with `features` can render with, falling back to 1 sample.
+    pub fn supported(self, features: wgpu::Features) -> MsaaConfig {
+        if features.contains(self.required_features()) {
+            self
+        } else {
+            log::warn!("{}x MSAA isn't supported, disabling it", self.sample_count);
+            Self::DISABLED
+        }
+    }
+}
+impl Default for MsaaConfig {
+    fn default() -> Self {
+        MsaaConfig { sample_count: 4 }
+    }
+}
@@ -0,0 +1,269 @@
+use cgmath::{Matrix4, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Particle {
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+    /// Seconds left, the particle is recycled at the emitter once it reaches 0.
+    pub lifetime: f32,
+    pub max_lifetime: f32,
+    pub color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct SimParams {
+    emitter: [f32; 3],
+    dt: f32,
+    gravity: [f32; 3],
+    particle_count: u32,
+    color: [f32; 4],
+    speed: f32,
+    seed: u32,
+    _padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct RenderParams {
+    view_proj: [[f32; 4]; 4],
+    /// `w` is the particle size.
+    right: [f32; 4],
+    up: [f32; 4],
+}
+
+/// Particles simulated by a compute shader and drawn as camera facing quads. They leave the
+/// emitter in random upward directions, fall with `gravity` and are recycled at the emitter
+/// when their lifetime runs out.
+pub struct ParticleSystem {
+    pub emitter: Point3<f32>,
+    pub gravity: Vector3<f32>,
+    /// Launch speed of recycled particles.
+    pub speed: f32,
+    /// Half the width of a particle's quad in world units.
+    pub size: f32,
+    /// Color of recycled particles, faded out over their lifetime.
+    pub color: [f32; 4],
+    pub particle_buffer: wgpu::Buffer,
+    particle_count: u32,
+    frame: u32,
+    sim_buffer: wgpu::Buffer,
+    render_buffer: wgpu::Buffer,
+    update_bind_group: wgpu::BindGroup,
+    render_bind_group: wgpu::BindGroup,
+    update_pipeline: wgpu::ComputePipeline,
+    render_pipeline: wgpu::RenderPipeline,
+}
+impl ParticleSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        particle_count: u32,
+        max_lifetime: f32,
+        emitter: Point3<f32>,
+    ) -> ParticleSystem {
+        let color = [1.0; 4];
+        // Staggered so they don't all leave the emitter on the first frame
+        let particles: Vec<Particle> = (0..particle_count)
+            .map(|i| Particle {
+                position: emitter.into(),
+                velocity: [0.0; 3],
+                lifetime: max_lifetime * i as f32 / particle_count as f32,
+                max_lifetime,
+                color,
+            })
+            .collect();
+        let particle_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Particle Buffer"),
+            contents: bytemuck::cast_slice(&particles),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let sim_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle Sim Params Buffer"),
+            size: std::mem::size_of::<SimParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let render_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle Render Params Buffer"),
+            size: std::mem::size_of::<RenderParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = |label, visibility, read_only| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            })
+        };
+        let update_layout = layout(
+            "Particle Update Bind Group Layout",
+            wgpu::ShaderStages::COMPUTE,
+            false,
+        );
+        let render_layout = layout(
+            "Particle Render Bind Group Layout",
+            wgpu::ShaderStages::VERTEX,
+            true,
+        );
+        let bind_group = |label, layout, params: &wgpu::Buffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: particle_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let update_bind_group =
+            bind_group("Particle Update Bind Group", &update_layout, &sim_buffer);
+        let render_bind_group =
+            bind_group("Particle Render Bind Group", &render_layout, &render_buffer);
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../particles.wgsl").into()),
+        });
+        let update_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Particle Update Pipeline Layout"),
+                bind_group_layouts: &[&update_layout],
+                push_constant_ranges: &[],
+            });
+        let update_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Particle Update Pipeline"),
+            layout: Some(&update_pipeline_layout),
+            module: &shader,
+            entry_point: "update",
+        });
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Particle Render Pipeline Layout"),
+                bind_group_layouts: &[&render_layout],
+                push_constant_ranges: &[],
+            });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Particle Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Tested against the scene but not written, particles are blended in any order
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+        });
+        ParticleSystem {
+            emitter,
+            gravity: Vector3::new(0.0, -9.81, 0.0),
+            speed: 5.0,
+            size: 0.05,
+            color,
+            particle_buffer,
+            particle_count,
+            frame: 0,
+            sim_buffer,
+            render_buffer,
+            update_bind_group,
+            render_bind_group,
+            update_pipeline,
+            render_pipeline,
+        }
+    }
+    pub fn particle_count(&self) -> u32 {
+        self.particle_count
+    }
+    /// Records the simulation step of `dt` seconds, must come before the pass calling `render`.
+    pub fn update(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, dt: f32) {
+        self.frame = self.frame.wrapping_add(1);
+        let params = SimParams {
+            emitter: self.emitter.into(),
+            dt,
+            gravity: self.gravity.into(),
+            particle_count: self.particle_count,
+            color: self.color,
+            speed: self.speed,
+            // Different random directions every frame
+            seed: self.frame.wrapping_mul(0x9e37_79b9),
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.sim_buffer, 0, bytemuck::cast_slice(&[params]));
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Particle Update Pass"),
+        });
+        pass.set_pipeline(&self.update_pipeline);
+        pass.set_bind_group(0, &self.update_bind_group, &[]);
+        pass.dispatch((self.particle_count + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE, 1, 1);
+    }
+    /// Camera used by the next `render`. `right` and `up` are the camera's world space axes the
+    /// quads are spanned by.
+    pub fn set_camera(
+        &self,
+        queue: &wgpu::Queue,
+        view_proj: Matrix4<f32>,
+        right: Vector3<f32>,
+        up: Vector3<f32>,
+    ) {
+        let params = RenderParams {
+            view_proj: view_proj.into(),
+            right: right.extend(self.size).into(),
+            up: up.extend(0.0).into(),
+        };
+        queue.write_buffer(&self.render_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+    /// Six vertices per particle, generated in the vertex shader from the vertex index.
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.render_bind_group, &[]);
+        render_pass.draw(0..6, 0..self.particle_count);
+    }
+}
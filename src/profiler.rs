@@ -0,0 +1,175 @@
+/// How long a span of GPU work took.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct GpuSpan {
+    pub label: String,
+    pub duration_ns: u64,
+}
+
+struct Span {
+    label: String,
+    /// Index of the begin timestamp, the end one is right after it.
+    query: u32,
+}
+
+struct Queries {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+}
+
+/// Times GPU work with timestamp queries. Spans are recorded with `begin` and `end` while
+/// encoding, copied out with `resolve` and read after the frame is submitted with `read`:
+///
+/// ```ignore
+/// profiler.begin(&mut encoder, "Shadows");
+/// // ...
+/// profiler.end(&mut encoder);
+/// profiler.resolve(&mut encoder);
+/// queue.submit(std::iter::once(encoder.finish()));
+/// for span in profiler.read(&device).await? { ... }
+/// ```
+///
+/// Without `Features::TIMESTAMP_QUERY`, which has to be requested through
+/// `StateConfig::request_features`, every call does nothing and `read` returns no spans.
+pub struct GpuProfiler {
+    queries: Option<Queries>,
+    capacity: u32,
+    spans: Vec<Span>,
+    /// Spans begun but not ended, `None` for ones over the capacity.
+    open: Vec<Option<usize>>,
+}
+impl GpuProfiler {
+    /// Records up to `max_spans` spans a frame, the rest are dropped.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        features: wgpu::Features,
+        max_spans: u32,
+    ) -> Self {
+        let queries = if features.contains(wgpu::Features::TIMESTAMP_QUERY) && max_spans > 0 {
+            let count = max_spans * 2;
+            let size = count as u64 * std::mem::size_of::<u64>() as u64;
+            Some(Queries {
+                query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("Profiler Query Set"),
+                    ty: wgpu::QueryType::Timestamp,
+                    count,
+                }),
+                resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler Resolve Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                staging: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler Staging Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                period: queue.get_timestamp_period(),
+            })
+        } else {
+            log::info!("timestamp queries aren't available, GPU profiling is off");
+            None
+        };
+        GpuProfiler {
+            queries,
+            capacity: max_spans,
+            spans: Vec::new(),
+            open: Vec::new(),
+        }
+    }
+    /// Whether timestamps are actually written.
+    pub fn enabled(&self) -> bool {
+        self.queries.is_some()
+    }
+    /// Starts a span, spans can nest.
+    pub fn begin(&mut self, encoder: &mut wgpu::CommandEncoder, label: &str) {
+        let queries = match &self.queries {
+            Some(queries) => queries,
+            None => return,
+        };
+        if self.spans.len() as u32 >= self.capacity {
+            self.open.push(None);
+            return;
+        }
+        let query = self.spans.len() as u32 * 2;
+        encoder.write_timestamp(&queries.query_set, query);
+        self.open.push(Some(self.spans.len()));
+        self.spans.push(Span {
+            label: label.to_string(),
+            query,
+        });
+    }
+    /// Ends the innermost span that's still open.
+    pub fn end(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let queries = match &self.queries {
+            Some(queries) => queries,
+            None => return,
+        };
+        match self.open.pop() {
+            Some(Some(span)) => {
+                encoder.write_timestamp(&queries.query_set, self.spans[span].query + 1)
+            }
+            Some(None) => {}
+            None => log::warn!("GpuProfiler::end without a matching begin"),
+        }
+    }
+    /// Copies the timestamps written so far to be read. Goes at the end of the frame's last
+    /// encoder, after every span has ended.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let queries = match &self.queries {
+            Some(queries) => queries,
+            None => return,
+        };
+        if !self.open.is_empty() {
+            log::warn!("{} GPU spans weren't ended", self.open.len());
+            self.open.clear();
+        }
+        let count = self.spans.len() as u32 * 2;
+        if count == 0 {
+            return;
+        }
+        encoder.resolve_query_set(&queries.query_set, 0..count, &queries.resolve_buffer, 0);
+        let size = count as u64 * std::mem::size_of::<u64>() as u64;
+        encoder.copy_buffer_to_buffer(&queries.resolve_buffer, 0, &queries.staging, 0, size);
+    }
+    /// Waits for the resolved timestamps and returns the spans in the order they began. Starts
+    /// the next frame's spans.
+    pub async fn read(
+        &mut self,
+        device: &wgpu::Device,
+    ) -> Result<Vec<GpuSpan>, wgpu::BufferAsyncError> {
+        let spans = std::mem::take(&mut self.spans);
+        self.open.clear();
+        let queries = match &self.queries {
+            Some(queries) if !spans.is_empty() => queries,
+            _ => return Ok(Vec::new()),
+        };
+        let size = spans.len() as u64 * 2 * std::mem::size_of::<u64>() as u64;
+        let slice = queries.staging.slice(..size);
+        let mapped = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        mapped.await?;
+        // Copied out because the mapped bytes may not be aligned for `u64`
+        let mut timestamps = vec![0u64; spans.len() * 2];
+        bytemuck::cast_slice_mut(&mut timestamps).copy_from_slice(&slice.get_mapped_range());
+        queries.staging.unmap();
+        Ok(spans
+            .into_iter()
+            .map(|span| {
+                let begin = timestamps[span.query as usize];
+                let end = timestamps[span.query as usize + 1];
+                // Some drivers report timestamps out of order across passes
+                let ticks = end.saturating_sub(begin);
+                GpuSpan {
+                    label: span.label,
+                    duration_ns: (ticks as f64 * queries.period as f64) as u64,
+                }
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,75 @@
+/// Copies GPU buffers back to the CPU, e.g. to check what a compute shader wrote.
+pub struct BufferReadback {
+    staging: wgpu::Buffer,
+    size: u64,
+}
+impl BufferReadback {
+    /// `size` is in bytes and has to be a multiple of `wgpu::COPY_BUFFER_ALIGNMENT`.
+    pub fn new(device: &wgpu::Device, size: u64) -> Self {
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Staging Buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        BufferReadback { staging, size }
+    }
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+    /// Copies the first `size` bytes of `src`, which needs `BufferUsages::COPY_SRC`, and
+    /// reinterprets them as `T`s. Work submitted to `queue` before this is finished first.
+    pub async fn read<T: bytemuck::Pod>(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        src: &wgpu::Buffer,
+    ) -> Result<Vec<T>, wgpu::BufferAsyncError> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(src, 0, &self.staging, 0, self.size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = self.staging.slice(..);
+        let mapped = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        mapped.await?;
+        // Copied into a `Vec<T>` because the mapped bytes may not be aligned for `T`
+        let mut values = vec![T::zeroed(); self.size as usize / std::mem::size_of::<T>()];
+        {
+            let bytes = slice.get_mapped_range();
+            let len = values.len() * std::mem::size_of::<T>();
+            bytemuck::cast_slice_mut(&mut values).copy_from_slice(&bytes[..len]);
+        }
+        self.staging.unmap();
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::util::DeviceExt;
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let (device, queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let values: Vec<u32> = (0..64).map(|i| i * i).collect();
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&values),
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+        let readback = BufferReadback::new(&device, 4 * values.len() as u64);
+        let read: Vec<u32> = pollster::block_on(readback.read(&device, &queue, &buffer)).unwrap();
+        assert_eq!(read, values);
+        // The staging buffer is unmapped again, so it can be read from twice
+        let halves: Vec<[u16; 2]> =
+            pollster::block_on(readback.read(&device, &queue, &buffer)).unwrap();
+        assert_eq!(halves[3], [9, 0]);
+    }
+}
@@ -0,0 +1,464 @@
+use crate::scene::Scene;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Logical name of a texture shared between passes.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct ResourceId(pub &'static str);
+impl ResourceId {
+    /// The view passed to `RenderGraph::execute`, always available.
+    pub const OUTPUT: ResourceId = ResourceId("output");
+}
+impl Display for ResourceId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl From<&'static str> for ResourceId {
+    fn from(name: &'static str) -> Self {
+        ResourceId(name)
+    }
+}
+
+/// Size of a pooled texture.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TextureSize {
+    /// Follows the screen, `scale` times its resolution. Recreated by `ResourcePool::resize_all`.
+    ScreenRelative { scale: f32 },
+    Fixed { width: u32, height: u32 },
+}
+impl TextureSize {
+    pub const SCREEN_RELATIVE: TextureSize = TextureSize::ScreenRelative { scale: 1.0 };
+    fn extent(self, screen_width: u32, screen_height: u32) -> wgpu::Extent3d {
+        let (width, height) = match self {
+            TextureSize::ScreenRelative { scale } => (
+                (screen_width as f32 * scale).round() as u32,
+                (screen_height as f32 * scale).round() as u32,
+            ),
+            TextureSize::Fixed { width, height } => (width, height),
+        };
+        wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        }
+    }
+}
+
+/// How a pooled texture is created, a 2D `wgpu::TextureDescriptor` without the label.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ResourceDesc {
+    pub size: TextureSize,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+    pub sample_count: u32,
+}
+impl ResourceDesc {
+    /// A single sampled, screen sized texture.
+    pub fn screen(format: wgpu::TextureFormat, usage: wgpu::TextureUsages) -> Self {
+        ResourceDesc {
+            size: TextureSize::SCREEN_RELATIVE,
+            format,
+            usage,
+            sample_count: 1,
+        }
+    }
+}
+
+struct PooledTexture {
+    /// `None` for textures added with `insert`, which the pool can't recreate.
+    desc: Option<ResourceDesc>,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+/// Textures passes read from and write to, by `ResourceId`. Textures are created on first use
+/// and screen relative ones follow the screen size.
+pub struct ResourcePool {
+    textures: HashMap<ResourceId, PooledTexture>,
+    width: u32,
+    height: u32,
+}
+impl ResourcePool {
+    pub fn new(width: u32, height: u32) -> Self {
+        ResourcePool {
+            textures: HashMap::new(),
+            width,
+            height,
+        }
+    }
+    fn create(&self, device: &wgpu::Device, id: ResourceId, desc: ResourceDesc) -> PooledTexture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(id.0),
+            size: desc.size.extent(self.width, self.height),
+            mip_level_count: 1,
+            sample_count: desc.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: desc.format,
+            usage: desc.usage,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        PooledTexture {
+            desc: Some(desc),
+            texture,
+            view,
+        }
+    }
+    /// The view of `id`, creating the texture if it doesn't exist yet or was created with a
+    /// different descriptor.
+    pub fn get_or_create(
+        &mut self,
+        device: &wgpu::Device,
+        id: impl Into<ResourceId>,
+        desc: ResourceDesc,
+    ) -> &wgpu::TextureView {
+        let id = id.into();
+        if self.textures.get(&id).map_or(true, |pooled| pooled.desc != Some(desc)) {
+            let pooled = self.create(device, id, desc);
+            self.textures.insert(id, pooled);
+        }
+        &self.textures[&id].view
+    }
+    /// Recreates every screen relative texture for the new screen size.
+    pub fn resize_all(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+        let screen_relative: Vec<(ResourceId, ResourceDesc)> = self
+            .textures
+            .iter()
+            .filter_map(|(&id, pooled)| match pooled.desc {
+                Some(desc @ ResourceDesc {
+                    size: TextureSize::ScreenRelative { .. },
+                    ..
+                }) => Some((id, desc)),
+                _ => None,
+            })
+            .collect();
+        for (id, desc) in screen_relative {
+            let pooled = self.create(device, id, desc);
+            self.textures.insert(id, pooled);
+        }
+    }
+    /// Adds a texture created elsewhere under `id`, replacing the previous one. It's left alone
+    /// by `resize_all`.
+    pub fn insert(&mut self, id: ResourceId, texture: wgpu::Texture) {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.textures.insert(
+            id,
+            PooledTexture {
+                desc: None,
+                texture,
+                view,
+            },
+        );
+    }
+    pub fn remove(&mut self, id: ResourceId) -> Option<wgpu::Texture> {
+        self.textures.remove(&id).map(|pooled| pooled.texture)
+    }
+    pub fn contains(&self, id: ResourceId) -> bool {
+        self.textures.contains_key(&id)
+    }
+    pub fn texture(&self, id: ResourceId) -> Option<&wgpu::Texture> {
+        self.textures.get(&id).map(|pooled| &pooled.texture)
+    }
+    pub fn view(&self, id: ResourceId) -> Option<&wgpu::TextureView> {
+        self.textures.get(&id).map(|pooled| &pooled.view)
+    }
+}
+
+/// A node of the `RenderGraph`.
+pub trait RenderPass {
+    /// Used in errors and as the debug label.
+    fn name(&self) -> &str;
+    /// Resources that must be written by earlier passes, or be in the pool, before this one.
+    fn reads(&self) -> &[ResourceId] {
+        &[]
+    }
+    fn writes(&self) -> &[ResourceId] {
+        &[]
+    }
+    /// Called once per frame before any pass is recorded, for uploading uniforms.
+    fn prepare(&mut self, _queue: &wgpu::Queue, _scene: &Scene) {}
+    fn record(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &ResourcePool,
+        output: &wgpu::TextureView,
+    );
+}
+
+#[derive(Clone, Debug)]
+pub enum Error {
+    /// The pass reads a resource no pass writes and the pool doesn't have.
+    MissingResource { pass: String, resource: ResourceId },
+    /// The passes depend on each other.
+    Cycle(Vec<String>),
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Records passes in the order their reads and writes require instead of the order they're
+/// added in. Passes writing the same resource keep the order they were added in.
+pub struct RenderGraph {
+    pub resources: ResourcePool,
+    passes: Vec<Box<dyn RenderPass>>,
+    /// Indices into `passes`, `None` when passes changed since the last `compile`.
+    order: Option<Vec<usize>>,
+}
+impl RenderGraph {
+    /// `width` and `height` are the screen size screen relative resources start out with.
+    pub fn new(width: u32, height: u32) -> Self {
+        RenderGraph {
+            resources: ResourcePool::new(width, height),
+            passes: Vec::new(),
+            order: None,
+        }
+    }
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.resources.resize_all(device, width, height);
+    }
+    pub fn add_pass(&mut self, pass: impl RenderPass + 'static) {
+        self.passes.push(Box::new(pass));
+        self.order = None;
+    }
+    /// Passes that have to be recorded before `passes[index]`.
+    fn dependencies(&self, index: usize) -> Result<Vec<usize>, Error> {
+        let pass = &self.passes[index];
+        let mut dependencies = Vec::new();
+        for &resource in pass.reads() {
+            let writers: Vec<usize> = (0..self.passes.len())
+                .filter(|&other| other != index && self.passes[other].writes().contains(&resource))
+                .collect();
+            if writers.is_empty()
+                && resource != ResourceId::OUTPUT
+                && !self.resources.contains(resource)
+            {
+                return Err(Error::MissingResource {
+                    pass: pass.name().to_string(),
+                    resource,
+                });
+            }
+            dependencies.extend(writers);
+        }
+        for &resource in pass.writes() {
+            dependencies.extend(
+                (0..index).filter(|&other| self.passes[other].writes().contains(&resource)),
+            );
+        }
+        Ok(dependencies)
+    }
+    /// Validates the dependencies and sorts the passes. `execute` does this when needed.
+    pub fn compile(&mut self) -> Result<(), Error> {
+        let dependencies = (0..self.passes.len())
+            .map(|index| self.dependencies(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Kahn's algorithm, picking the earliest added pass when there's a choice
+        let mut remaining: Vec<usize> = dependencies.iter().map(Vec::len).collect();
+        let mut done = vec![false; self.passes.len()];
+        let mut order = Vec::with_capacity(self.passes.len());
+        while let Some(next) = (0..self.passes.len()).find(|&i| !done[i] && remaining[i] == 0) {
+            done[next] = true;
+            order.push(next);
+            for (pass, dependencies) in dependencies.iter().enumerate() {
+                remaining[pass] -= dependencies.iter().filter(|&&d| d == next).count();
+            }
+        }
+        if order.len() != self.passes.len() {
+            return Err(Error::Cycle(
+                (0..self.passes.len())
+                    .filter(|&i| !done[i])
+                    .map(|i| self.passes[i].name().to_string())
+                    .collect(),
+            ));
+        }
+        self.order = Some(order);
+        Ok(())
+    }
+    pub fn prepare(&mut self, queue: &wgpu::Queue, scene: &Scene) {
+        for pass in &mut self.passes {
+            pass.prepare(queue, scene);
+        }
+    }
+    /// Records every pass into `encoder`, `output` is usually the surface texture.
+    pub fn execute(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+    ) -> Result<(), Error> {
+        if self.order.is_none() {
+            self.compile()?;
+        }
+        for &index in self.order.iter().flatten() {
+            let pass = &self.passes[index];
+            encoder.push_debug_group(pass.name());
+            pass.record(encoder, &self.resources, output);
+            encoder.pop_debug_group();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records nothing, only its reads and writes matter for the order.
+    struct Stub {
+        name: &'static str,
+        reads: Vec<ResourceId>,
+        writes: Vec<ResourceId>,
+    }
+    impl RenderPass for Stub {
+        fn name(&self) -> &str {
+            self.name
+        }
+        fn reads(&self) -> &[ResourceId] {
+            &self.reads
+        }
+        fn writes(&self) -> &[ResourceId] {
+            &self.writes
+        }
+        fn record(&self, _: &mut wgpu::CommandEncoder, _: &ResourcePool, _: &wgpu::TextureView) {}
+    }
+
+    fn stub(name: &'static str, reads: &[&'static str], writes: &[&'static str]) -> Stub {
+        Stub {
+            name,
+            reads: reads.iter().map(|&r| ResourceId(r)).collect(),
+            writes: writes.iter().map(|&w| ResourceId(w)).collect(),
+        }
+    }
+
+    fn order(graph: &mut RenderGraph) -> Vec<&str> {
+        graph.compile().unwrap();
+        let order = graph.order.as_ref().unwrap();
+        order.iter().map(|&i| graph.passes[i].name()).collect()
+    }
+
+    #[test]
+    fn writers_come_before_readers() {
+        let mut graph = RenderGraph::new(640, 480);
+        graph.add_pass(stub("Tone Map", &["hdr", "bloom"], &["output"]));
+        graph.add_pass(stub("Bloom", &["hdr"], &["bloom"]));
+        graph.add_pass(stub("Forward", &[], &["hdr"]));
+        assert_eq!(order(&mut graph), ["Forward", "Bloom", "Tone Map"]);
+    }
+
+    #[test]
+    fn independent_passes_keep_their_order() {
+        let mut graph = RenderGraph::new(640, 480);
+        graph.add_pass(stub("Shadows", &[], &["shadow"]));
+        graph.add_pass(stub("Depth", &[], &["depth"]));
+        graph.add_pass(stub("Forward", &["shadow", "depth"], &["hdr"]));
+        graph.add_pass(stub("Overlay", &[], &["hdr"]));
+        // Passes writing the same resource stay in the order they were added in
+        assert_eq!(
+            order(&mut graph),
+            ["Shadows", "Depth", "Forward", "Overlay"]
+        );
+
+        // The order is recomputed when a pass is added
+        graph.add_pass(stub("Sky", &[], &["sky"]));
+        assert_eq!(graph.order, None);
+        assert_eq!(order(&mut graph).last(), Some(&"Sky"));
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let mut graph = RenderGraph::new(640, 480);
+        graph.add_pass(stub("Sky", &[], &["sky"]));
+        graph.add_pass(stub("A", &["b"], &["a"]));
+        graph.add_pass(stub("B", &["a"], &["b"]));
+        match graph.compile() {
+            Err(Error::Cycle(passes)) => assert_eq!(passes, ["A", "B"]),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_need_a_writer() {
+        let mut graph = RenderGraph::new(640, 480);
+        // The output is always there
+        graph.add_pass(stub("Blit", &["output"], &[]));
+        assert!(graph.compile().is_ok());
+        graph.add_pass(stub("Forward", &["shadow"], &["hdr"]));
+        match graph.compile() {
+            Err(Error::MissingResource { pass, resource }) => {
+                assert_eq!(pass, "Forward");
+                assert_eq!(resource, ResourceId("shadow"));
+            }
+            other => panic!("expected a missing resource, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn screen_relative_extents_round_and_stay_nonzero() {
+        let extent = |scale, width, height| {
+            let extent = TextureSize::ScreenRelative { scale }.extent(width, height);
+            (extent.width, extent.height)
+        };
+        // An odd window size, halves round away from zero
+        assert_eq!(extent(0.25, 801, 601), (200, 150));
+        assert_eq!(extent(0.5, 801, 601), (401, 301));
+        assert_eq!(extent(0.75, 801, 601), (601, 451));
+        assert_eq!(extent(1.0, 801, 601), (801, 601));
+        // A minimized window still gets a texture
+        assert_eq!(extent(0.25, 3, 1), (1, 1));
+        assert_eq!(extent(0.5, 0, 0), (1, 1));
+        let fixed = TextureSize::Fixed {
+            width: 256,
+            height: 128,
+        };
+        assert_eq!(fixed.extent(801, 601).width, 256);
+    }
+
+    #[test]
+    fn pooled_textures_are_created_lazily_and_follow_the_screen() {
+        let (device, _queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut pool = ResourcePool::new(64, 48);
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+        let hdr = ResourceDesc::screen(wgpu::TextureFormat::Rgba16Float, usage);
+        let shadow = ResourceDesc {
+            size: TextureSize::Fixed {
+                width: 256,
+                height: 256,
+            },
+            ..ResourceDesc::screen(wgpu::TextureFormat::Depth32Float, usage)
+        };
+        // wgpu 0.11 textures have no size or identity to compare, but their debug output has
+        // the ID, which is different for a recreated texture
+        let id = |pool: &ResourcePool, name| format!("{:?}", pool.texture(ResourceId(name)));
+
+        assert!(!pool.contains(ResourceId("hdr")));
+        pool.get_or_create(&device, "hdr", hdr);
+        pool.get_or_create(&device, "shadow", shadow);
+        assert!(pool.contains(ResourceId("hdr")));
+        let (first_hdr, first_shadow) = (id(&pool, "hdr"), id(&pool, "shadow"));
+        pool.get_or_create(&device, "hdr", hdr);
+        assert_eq!(id(&pool, "hdr"), first_hdr);
+
+        pool.resize_all(&device, 64, 48);
+        assert_eq!(id(&pool, "hdr"), first_hdr);
+        pool.resize_all(&device, 128, 96);
+        assert_ne!(id(&pool, "hdr"), first_hdr);
+        assert_eq!(id(&pool, "shadow"), first_shadow);
+
+        // A different descriptor replaces the texture
+        let resized_hdr = id(&pool, "hdr");
+        let ldr = ResourceDesc::screen(wgpu::TextureFormat::Rgba8Unorm, usage);
+        pool.get_or_create(&device, "hdr", ldr);
+        assert_ne!(id(&pool, "hdr"), resized_hdr);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::camera::Ray;
+use crate::entity::model::mesh::Mesh;
+use crate::entity::Entity;
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix};
+use std::cmp::Ordering;
+
+/// Index of an entity in `Scene::entities`.
+pub type EntityId = usize;
+
+/// Everything drawn in a frame.
+pub struct Scene {
+    /// Clear color of the main render pass.
+    pub background_color: wgpu::Color,
+    pub entities: Vec<Entity>,
+    /// Changed since the last `clear_dirty`, see `is_dirty`.
+    dirty: bool,
+    /// `entities.len()` at the last `clear_dirty`.
+    clean_len: usize,
+}
+impl Scene {
+    pub const DEFAULT_BACKGROUND: wgpu::Color = wgpu::Color {
+        r: 0.01,
+        g: 0.01,
+        b: 0.01,
+        a: 1.0,
+    };
+    pub fn new() -> Self {
+        Scene {
+            background_color: Self::DEFAULT_BACKGROUND,
+            entities: Vec::new(),
+            dirty: true,
+            clean_len: 0,
+        }
+    }
+}
+impl Scene {
+    /// The entity whose bounds `ray` enters first. The ray is moved into each entity's local
+    /// space, where its bounds are, so rotated entities are tested against their own box.
+    pub fn pick(&self, ray: &Ray) -> Option<EntityId> {
+        self.entities
+            .iter()
+            .enumerate()
+            .filter_map(|(id, entity)| {
+                let to_local = entity.mx_world.invert()?;
+                Some((id, ray.transform(to_local).intersect_aabb(&entity.bounds)?))
+            })
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|(id, _)| id)
+    }
+    pub fn add(&mut self, entity: Entity) -> EntityId {
+        self.entities.push(entity);
+        self.dirty = true;
+        self.entities.len() - 1
+    }
+    /// Removes the entity by moving the last one into its place. Parents of the moved entity's
+    /// children and of the removed one's aren't fixed up.
+    pub fn remove(&mut self, id: EntityId) -> Entity {
+        self.dirty = true;
+        self.entities.swap_remove(id)
+    }
+    /// Whether the scene has to be drawn again: entities were added or removed, or moved in
+    /// `update`, since the last `clear_dirty`. Other changes, like to materials or colors,
+    /// need a `mark_dirty`.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty || self.entities.len() != self.clean_len
+    }
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+    /// Called once the scene is drawn.
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
+        self.clean_len = self.entities.len();
+    }
+    /// Updates every entity and then applies the parents' world matrices to their children.
+    /// Marks the scene dirty when a world matrix changes.
+    pub fn update(&mut self, dt: f32) {
+        let previous: Vec<Matrix4<f32>> = self.entities.iter().map(|e| e.mx_world).collect();
+        for entity in &mut self.entities {
+            entity.update(dt);
+        }
+        let locals: Vec<Matrix4<f32>> = self.entities.iter().map(|e| e.mx_world).collect();
+        for i in 0..self.entities.len() {
+            let mut world = locals[i];
+            let mut parent = self.entities[i].parent;
+            // Bounded so a cycle can't hang the update
+            for _ in 0..self.entities.len() {
+                let p = match parent {
+                    Some(p) if p < self.entities.len() => p,
+                    _ => break,
+                };
+                world = locals[p] * world;
+                parent = self.entities[p].parent;
+            }
+            self.entities[i].mx_world = world;
+        }
+        if self.entities.iter().zip(&previous).any(|(e, &previous)| e.mx_world != previous) {
+            self.dirty = true;
+        }
+    }
+    /// Indices of the entities with an opaque or no material, in scene order.
+    pub fn opaque_entities(&self) -> Vec<usize> {
+        (0..self.entities.len())
+            .filter(|&i| !self.entities[i].is_transparent())
+            .collect()
+    }
+    /// Indices of the entities that need blending, farthest from `eye` first.
+    pub fn transparent_entities(&self, eye: Point3<f32>) -> Vec<usize> {
+        let mut transparent: Vec<(usize, f32)> = (0..self.entities.len())
+            .filter(|&i| self.entities[i].is_transparent())
+            .map(|i| (i, (self.entities[i].position() - eye).magnitude2()))
+            .collect();
+        transparent.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+        transparent.into_iter().map(|(i, _)| i).collect()
+    }
+}
+impl Scene {
+    /// The mesh each entity with a `lod` should be drawn with this frame, by entity index.
+    pub fn select_lods(&self, eye: Point3<f32>) -> Vec<(usize, &Mesh)> {
+        self.entities
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entity)| entity.select_lod(eye).map(|mesh| (i, mesh)))
+            .collect()
+    }
+    /// `select_lods` as indices into each `LodMesh::levels`, for keeping the choice past the
+    /// borrow of the scene.
+    pub fn select_lod_levels(&self, eye: Point3<f32>) -> Vec<(usize, usize)> {
+        self.entities
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entity)| entity.select_lod_level(eye).map(|level| (i, level)))
+            .collect()
+    }
+}
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,240 @@
+use crate::entity::model::Vertex;
+use crate::readback::BufferReadback;
+use cgmath::Matrix4;
+use wgpu::util::DeviceExt;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkinningParams {
+    vertex_count: u32,
+    bone_count: u32,
+    _padding: [u32; 2],
+}
+
+fn matrices(m: &[Matrix4<f32>]) -> Vec<[[f32; 4]; 4]> {
+    m.iter().map(|&m| m.into()).collect()
+}
+
+/// A mesh deformed by bones on the GPU. `skin` writes the posed vertices to
+/// `posed_vertex_buffer`, which is laid out like `model::Vertex` and can be drawn directly.
+pub struct SkinnedMesh {
+    /// Inverse bind matrices, from model space to each bone's space.
+    pub bind_pose: Vec<Matrix4<f32>>,
+    /// Four `u8` bone indices per vertex.
+    pub bone_indices: wgpu::Buffer,
+    /// Four `f32` weights per vertex, summing to 1.
+    pub bone_weights: wgpu::Buffer,
+    pub posed_vertex_buffer: wgpu::Buffer,
+    rest_vertex_buffer: wgpu::Buffer,
+    bind_pose_buffer: wgpu::Buffer,
+    pose_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+impl SkinnedMesh {
+    pub fn new(
+        device: &wgpu::Device,
+        vertices: &[Vertex],
+        bone_indices: &[[u8; 4]],
+        bone_weights: &[[f32; 4]],
+        bind_pose: Vec<Matrix4<f32>>,
+    ) -> SkinnedMesh {
+        debug_assert_eq!(vertices.len(), bone_indices.len());
+        debug_assert_eq!(vertices.len(), bone_weights.len());
+        let vertex_count = vertices.len() as u32;
+        let bone_count = bind_pose.len() as u32;
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skinning Params Buffer"),
+            contents: bytemuck::cast_slice(&[SkinningParams {
+                vertex_count,
+                bone_count,
+                _padding: [0; 2],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let rest_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skinning Rest Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let bone_indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skinning Bone Index Buffer"),
+            contents: bytemuck::cast_slice(bone_indices),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let bone_weights = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skinning Bone Weight Buffer"),
+            contents: bytemuck::cast_slice(bone_weights),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let bind_pose_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skinning Bind Pose Buffer"),
+            contents: bytemuck::cast_slice(&matrices(&bind_pose)),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        // Starts out in the bind pose
+        let rest_pose: Vec<Matrix4<f32>> = bind_pose
+            .iter()
+            .map(|m| cgmath::SquareMatrix::invert(m).unwrap_or(*m))
+            .collect();
+        let pose_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skinning Pose Buffer"),
+            contents: bytemuck::cast_slice(&matrices(&rest_pose)),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let posed_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skinning Posed Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Skinning Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, true),
+                storage(4, true),
+                storage(5, true),
+                storage(6, false),
+            ],
+        });
+        let buffers = [
+            &params_buffer,
+            &rest_vertex_buffer,
+            &bone_indices,
+            &bone_weights,
+            &bind_pose_buffer,
+            &pose_buffer,
+            &posed_vertex_buffer,
+        ];
+        let entries: Vec<wgpu::BindGroupEntry> = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Skinning Bind Group"),
+            layout: &bind_group_layout,
+            entries: &entries,
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Skinning Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../skinning.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Skinning Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Skinning Pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "main",
+        });
+        SkinnedMesh {
+            bind_pose,
+            bone_indices,
+            bone_weights,
+            posed_vertex_buffer,
+            rest_vertex_buffer,
+            bind_pose_buffer,
+            pose_buffer,
+            params_buffer,
+            vertex_count,
+            bind_group,
+            pipeline,
+        }
+    }
+    /// Uploads the current bone to model space matrices, one per bone of `bind_pose`.
+    pub fn set_pose(&self, queue: &wgpu::Queue, pose: &[Matrix4<f32>]) {
+        debug_assert_eq!(pose.len(), self.bind_pose.len());
+        queue.write_buffer(&self.pose_buffer, 0, bytemuck::cast_slice(&matrices(pose)));
+    }
+    /// Reads the posed vertices back, for checking the skinning shader.
+    pub async fn read_posed_vertices(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<Vertex>, wgpu::BufferAsyncError> {
+        let size = self.vertex_count as u64 * std::mem::size_of::<Vertex>() as u64;
+        BufferReadback::new(device, size)
+            .read(device, queue, &self.posed_vertex_buffer)
+            .await
+    }
+    /// Records the skinning dispatch, must come before the pass drawing `posed_vertex_buffer`.
+    pub fn skin(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Skinning Pass"),
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch((self.vertex_count + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE, 1, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{SquareMatrix, Vector3};
+
+    #[test]
+    fn skinning_blends_bones() {
+        let (device, queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let vertex = |x| Vertex {
+            position: [x, 0.0, 0.0],
+            normal: [0.0, 0.0, 1.0],
+            texture_coords: [0.5, 0.5],
+        };
+        let vertices = [vertex(0.0), vertex(1.0)];
+        let bone_indices = [[0, 0, 0, 0], [0, 1, 0, 0]];
+        let bone_weights = [[1.0, 0.0, 0.0, 0.0], [0.5, 0.5, 0.0, 0.0]];
+        let bind_pose = vec![Matrix4::identity(); 2];
+        let mesh = SkinnedMesh::new(&device, &vertices, &bone_indices, &bone_weights, bind_pose);
+        let pose = [
+            Matrix4::identity(),
+            Matrix4::from_translation(Vector3::new(0.0, 2.0, 0.0)),
+        ];
+        mesh.set_pose(&queue, &pose);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        mesh.skin(&mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
+        let posed = pollster::block_on(mesh.read_posed_vertices(&device, &queue)).unwrap();
+        assert_eq!(posed[0], vertices[0]);
+        assert_eq!(posed[1].position, [1.0, 1.0, 0.0]);
+        assert_eq!(posed[1].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(posed[1].texture_coords, [0.5, 0.5]);
+    }
+}
@@ -0,0 +1,211 @@
+use cgmath::{EuclideanSpace, InnerSpace, MetricSpace, Point3, Vector3};
+
+/// A piecewise cubic Bézier curve, for camera paths and animation. Catmull-Rom splines are
+/// converted to the same form.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CubicSpline<P> {
+    /// Four control points per segment, consecutive segments share their end points.
+    segments: Vec<[P; 4]>,
+}
+impl<P> CubicSpline<P>
+where
+    P: EuclideanSpace<Scalar = f32> + MetricSpace<Metric = f32> + Clone,
+{
+    /// Passes through every control point, the first and last only steer the ends. Needs at
+    /// least four points.
+    pub fn catmull_rom(control_points: Vec<P>) -> Self {
+        let segments = control_points
+            .windows(4)
+            .map(|w| {
+                let (p0, p1, p2, p3) = (w[0].to_vec(), w[1].to_vec(), w[2].to_vec(), w[3].to_vec());
+                [
+                    w[1],
+                    P::from_vec(p1 + (p2 - p0) / 6.0),
+                    P::from_vec(p2 - (p3 - p1) / 6.0),
+                    w[2],
+                ]
+            })
+            .collect();
+        CubicSpline { segments }
+    }
+    /// Segments of four points where each segment starts at the end of the previous one, so
+    /// `3n + 1` points make `n` segments. Left over points are ignored.
+    pub fn bezier(control_points: Vec<P>) -> Self {
+        let segments = (0..control_points.len().saturating_sub(1) / 3)
+            .map(|i| {
+                let p = &control_points[i * 3..];
+                [p[0], p[1], p[2], p[3]]
+            })
+            .collect();
+        CubicSpline { segments }
+    }
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+    /// The point at `t` in `0..=1` over the whole curve, each segment getting an equal share.
+    /// Panics if the spline has no segments.
+    pub fn evaluate(&self, t: f32) -> P {
+        let n = self.segments.len();
+        assert!(n > 0, "evaluating an empty spline");
+        let scaled = t.max(0.0).min(1.0) * n as f32;
+        let segment = (scaled as usize).min(n - 1);
+        let t = scaled - segment as f32;
+        let [p0, p1, p2, p3] = self.segments[segment];
+        let u = 1.0 - t;
+        P::from_vec(
+            p0.to_vec() * (u * u * u)
+                + p1.to_vec() * (3.0 * u * u * t)
+                + p2.to_vec() * (3.0 * u * t * t)
+                + p3.to_vec() * (t * t * t),
+        )
+    }
+    /// Samples the curve at `steps` evenly spaced `t`s to map distances along it back to `t`.
+    pub fn arc_length_reparametrize(&self, steps: usize) -> ArcLengthSpline<P> {
+        let steps = steps.max(1);
+        let mut lengths = Vec::with_capacity(steps + 1);
+        let mut length = 0.0;
+        let mut previous = self.evaluate(0.0);
+        lengths.push(0.0);
+        for i in 1..=steps {
+            let point = self.evaluate(i as f32 / steps as f32);
+            length += previous.distance(point);
+            lengths.push(length);
+            previous = point;
+        }
+        ArcLengthSpline {
+            spline: self.clone(),
+            lengths,
+        }
+    }
+}
+
+/// A `CubicSpline` evaluated by distance along it, for moving at a constant speed.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ArcLengthSpline<P> {
+    pub spline: CubicSpline<P>,
+    /// Length of the curve up to each of the evenly spaced samples.
+    lengths: Vec<f32>,
+}
+impl<P> ArcLengthSpline<P>
+where
+    P: EuclideanSpace<Scalar = f32> + MetricSpace<Metric = f32> + Clone,
+{
+    pub fn length(&self) -> f32 {
+        self.lengths.last().copied().unwrap_or(0.0)
+    }
+    /// The `t` of the spline `distance` along it, clamped to the ends.
+    pub fn t_at(&self, distance: f32) -> f32 {
+        let steps = self.lengths.len() - 1;
+        let i = self.lengths.partition_point(|&length| length < distance);
+        if i == 0 {
+            return 0.0;
+        }
+        if i > steps {
+            return 1.0;
+        }
+        let (before, after) = (self.lengths[i - 1], self.lengths[i]);
+        let fraction = if after > before {
+            (distance - before) / (after - before)
+        } else {
+            0.0
+        };
+        (i as f32 - 1.0 + fraction) / steps as f32
+    }
+    /// The point `distance` along the curve.
+    pub fn evaluate(&self, distance: f32) -> P {
+        self.spline.evaluate(self.t_at(distance))
+    }
+}
+
+/// Moves a camera along a path at a constant speed.
+pub struct SplineCameraController {
+    pub path: ArcLengthSpline<Point3<f32>>,
+    /// How far along the path the camera is.
+    pub distance: f32,
+    /// Start over at the end instead of stopping.
+    pub looping: bool,
+}
+impl SplineCameraController {
+    /// Samples used to reparametrize the path per segment.
+    const STEPS_PER_SEGMENT: usize = 32;
+
+    pub fn new(path: &CubicSpline<Point3<f32>>) -> Self {
+        let steps = path.segment_count() * Self::STEPS_PER_SEGMENT;
+        SplineCameraController {
+            path: path.arc_length_reparametrize(steps),
+            distance: 0.0,
+            looping: false,
+        }
+    }
+    /// Moves `speed * dt` further and returns the new eye position.
+    pub fn advance(&mut self, dt: f32, speed: f32) -> Point3<f32> {
+        let length = self.path.length();
+        self.distance += speed * dt;
+        self.distance = if self.looping && length > 0.0 {
+            self.distance.rem_euclid(length)
+        } else {
+            self.distance.max(0.0).min(length)
+        };
+        self.position()
+    }
+    pub fn position(&self) -> Point3<f32> {
+        self.path.evaluate(self.distance)
+    }
+    /// Direction of travel, for the camera to look along.
+    pub fn direction(&self) -> Vector3<f32> {
+        let step = (self.path.length() * 0.001).max(f32::EPSILON);
+        let ahead = self.path.evaluate(self.distance + step);
+        let behind = self.path.evaluate(self.distance - step);
+        let direction = ahead - behind;
+        if direction.magnitude2() > 0.0 {
+            direction.normalize()
+        } else {
+            Vector3::unit_z()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catmull_rom_passes_through_its_control_points() {
+        let points = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(2.0, 1.0, 0.0),
+            Point3::new(3.0, 0.0, -1.0),
+            Point3::new(4.0, 2.0, 0.0),
+        ];
+        let spline = CubicSpline::catmull_rom(points.clone());
+        assert_eq!(spline.segment_count(), 2);
+        for (t, point) in [(0.0, points[1]), (0.5, points[2]), (1.0, points[3])] {
+            assert!(spline.evaluate(t).distance(point) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn equal_distances_for_equal_steps() {
+        // A straight line with the control points bunched up at the start, so equal steps in
+        // `t` are far from equal steps along it
+        let spline = CubicSpline::bezier(vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.1, 0.0, 0.0),
+            Point3::new(0.2, 0.0, 0.0),
+            Point3::new(10.0, 0.0, 0.0),
+        ]);
+        let uneven = spline.evaluate(0.1).distance(spline.evaluate(0.0));
+        assert!(uneven < 0.5, "{}", uneven);
+        let path = spline.arc_length_reparametrize(256);
+        assert!((path.length() - 10.0).abs() < 1e-3);
+        let mut previous = path.evaluate(0.0);
+        for i in 1..=10 {
+            let point = path.evaluate(i as f32);
+            assert!((point.distance(previous) - 1.0).abs() < 1e-2, "step {}", i);
+            previous = point;
+        }
+        assert_eq!(path.t_at(-1.0), 0.0);
+        assert_eq!(path.t_at(11.0), 1.0);
+    }
+}
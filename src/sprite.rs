@@ -0,0 +1,199 @@
+use crate::texture::Texture;
+use crate::texture_atlas::{AtlasHandle, TextureAtlas};
+use std::collections::HashMap;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpriteVertex {
+    /// Clip space.
+    position: [f32; 2],
+    texture_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+/// A sprite waiting for `flush`, in pixels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Sprite {
+    uv: [f32; 4],
+    position: [f32; 2],
+    size: [f32; 2],
+    color: [f32; 4],
+    z_order: f32,
+}
+
+/// Collects screen space sprites from one `TextureAtlas` and draws them with a single draw call,
+/// for HUD elements. Positions and sizes are in pixels with the origin at the top left.
+pub struct SpriteBatch {
+    sprites: Vec<Sprite>,
+    uv_rects: HashMap<AtlasHandle, [f32; 4]>,
+    screen_size: [f32; 2],
+    /// Layer of the sprites drawn next, higher layers are drawn on top.
+    pub z_order: f32,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    buffer: Option<wgpu::Buffer>,
+}
+impl SpriteBatch {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        atlas: &TextureAtlas,
+        width: u32,
+        height: u32,
+    ) -> SpriteBatch {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Sprite Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../sprite.wgsl").into()),
+        });
+        let bind_group_layout = Texture::bind_group_layout(device);
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sprite Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sprite Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<SpriteVertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &[
+                        // Position
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            shader_location: 0,
+                            format: wgpu::VertexFormat::Float32x2,
+                        },
+                        // Texture Coords
+                        wgpu::VertexAttribute {
+                            offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                            shader_location: 1,
+                            format: wgpu::VertexFormat::Float32x2,
+                        },
+                        // Color
+                        wgpu::VertexAttribute {
+                            offset: std::mem::size_of::<[f32; 2 + 2]>() as wgpu::BufferAddress,
+                            shader_location: 2,
+                            format: wgpu::VertexFormat::Float32x4,
+                        },
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        });
+        SpriteBatch {
+            sprites: Vec::new(),
+            uv_rects: atlas.handles().map(|handle| (handle, atlas.uv_rect(handle))).collect(),
+            screen_size: [width as f32, height as f32],
+            z_order: 0.0,
+            pipeline,
+            bind_group_layout,
+            buffer: None,
+        }
+    }
+    /// For the bind group of the atlas texture passed to `flush`.
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.screen_size = [width as f32, height as f32];
+    }
+    /// Queues a sprite at the current `z_order`. `color` multiplies the atlas image.
+    pub fn draw_sprite(
+        &mut self,
+        atlas_handle: AtlasHandle,
+        position: [f32; 2],
+        size: [f32; 2],
+        color: [f32; 4],
+    ) {
+        let uv = match self.uv_rects.get(&atlas_handle) {
+            Some(&uv) => uv,
+            None => {
+                log::warn!("sprite from another atlas: {:?}", atlas_handle);
+                return;
+            }
+        };
+        self.sprites.push(Sprite {
+            uv,
+            position,
+            size,
+            color,
+            z_order: self.z_order,
+        });
+    }
+    /// Draws every sprite queued since the last flush, lowest `z_order` first, and clears them.
+    /// The vertex buffer is kept alive until the next flush because the pass borrows it.
+    pub fn flush<'a>(
+        &'a mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        atlas_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.sprites.is_empty() {
+            self.buffer = None;
+            return;
+        }
+        // Stable, so sprites on the same layer keep their draw order
+        self.sprites.sort_by(|a, b| {
+            a.z_order.partial_cmp(&b.z_order).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let [width, height] = self.screen_size;
+        let to_clip = |x: f32, y: f32| [x / width * 2.0 - 1.0, 1.0 - y / height * 2.0];
+        let mut vertices = Vec::with_capacity(self.sprites.len() * 6);
+        for sprite in &self.sprites {
+            let [x, y] = sprite.position;
+            let [w, h] = sprite.size;
+            let [u_min, v_min, u_max, v_max] = sprite.uv;
+            let corner = |right: bool, bottom: bool| SpriteVertex {
+                position: to_clip(if right { x + w } else { x }, if bottom { y + h } else { y }),
+                texture_coords: [
+                    if right { u_max } else { u_min },
+                    if bottom { v_max } else { v_min },
+                ],
+                color: sprite.color,
+            };
+            let (top_left, top_right) = (corner(false, false), corner(true, false));
+            let (bottom_left, bottom_right) = (corner(false, true), corner(true, true));
+            vertices.extend_from_slice(&[
+                top_left,
+                bottom_left,
+                bottom_right,
+                top_left,
+                bottom_right,
+                top_right,
+            ]);
+        }
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Vertex Buffer"),
+            size: (vertices.len() * std::mem::size_of::<SpriteVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&buffer, 0, bytemuck::cast_slice(&vertices));
+        self.sprites.clear();
+        self.buffer = Some(buffer);
+
+        let this: &'a SpriteBatch = self;
+        if let Some(buffer) = &this.buffer {
+            render_pass.set_pipeline(&this.pipeline);
+            render_pass.set_bind_group(0, atlas_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            render_pass.draw(0..vertices.len() as u32, 0..1);
+        }
+    }
+}
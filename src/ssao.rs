@@ -0,0 +1,481 @@
+use cgmath::SquareMatrix;
+use wgpu::util::DeviceExt;
+
+pub const MAX_SAMPLES: usize = 64;
+const AO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+const NOISE_SIZE: u32 = 4;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SsaoSettings {
+    /// Hemisphere samples per pixel, at most `MAX_SAMPLES`.
+    pub sample_count: u32,
+    /// View space radius of the sampled hemisphere.
+    pub radius: f32,
+    /// Depth offset that keeps flat surfaces from occluding themselves.
+    pub bias: f32,
+}
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        SsaoSettings {
+            sample_count: 16,
+            radius: 0.5,
+            bias: 0.025,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    projection: [[f32; 4]; 4],
+    inverse_projection: [[f32; 4]; 4],
+    kernel: [[f32; 4]; MAX_SAMPLES],
+    sample_count: u32,
+    radius: f32,
+    bias: f32,
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlurUniform {
+    direction: [f32; 2],
+    _padding: [f32; 2],
+}
+
+/// Small xorshift generator so the kernel and noise are the same every run.
+struct XorShift(u32);
+impl XorShift {
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32
+    }
+}
+
+/// Points uniformly distributed over the +Z hemisphere, scaled so more of them land close to
+/// the origin.
+fn hemisphere_kernel(rng: &mut XorShift) -> [[f32; 4]; MAX_SAMPLES] {
+    let mut kernel = [[0.0; 4]; MAX_SAMPLES];
+    for (i, sample) in kernel.iter_mut().enumerate() {
+        let z = rng.next_f32();
+        let phi = 2.0 * std::f32::consts::PI * rng.next_f32();
+        let r = (1.0 - z * z).sqrt();
+        let t = i as f32 / MAX_SAMPLES as f32;
+        let scale = rng.next_f32() * (0.1 + 0.9 * t * t);
+        *sample = [r * phi.cos() * scale, r * phi.sin() * scale, z * scale, 0.0];
+    }
+    kernel
+}
+
+/// Textures and bind groups that depend on the surface size.
+struct Targets {
+    /// Final, blurred occlusion. Also holds the raw occlusion between the passes.
+    ao_view: wgpu::TextureView,
+    ssao_bind_group: wgpu::BindGroup,
+    blur_horizontal_bind_group: wgpu::BindGroup,
+    blur_vertical_bind_group: wgpu::BindGroup,
+    temp_view: wgpu::TextureView,
+}
+
+/// Half resolution SSAO computed from a depth buffer and blurred with a separable Gaussian.
+pub struct SsaoPass {
+    uniform: SsaoUniform,
+    uniform_buffer: wgpu::Buffer,
+    noise_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    horizontal_buffer: wgpu::Buffer,
+    vertical_buffer: wgpu::Buffer,
+    ssao_layout: wgpu::BindGroupLayout,
+    blur_layout: wgpu::BindGroupLayout,
+    ssao_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    targets: Targets,
+}
+impl SsaoPass {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        depth_view: &wgpu::TextureView,
+        settings: SsaoSettings,
+    ) -> SsaoPass {
+        let mut rng = XorShift(0x9E37_79B9);
+        let identity: [[f32; 4]; 4] = cgmath::Matrix4::<f32>::identity().into();
+        let uniform = SsaoUniform {
+            projection: identity,
+            inverse_projection: identity,
+            kernel: hemisphere_kernel(&mut rng),
+            sample_count: settings.sample_count.clamp(1, MAX_SAMPLES as u32),
+            radius: settings.radius,
+            bias: settings.bias,
+            _padding: 0,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("SSAO Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // Random rotations around the normal, tiled over the screen
+        let noise: Vec<u8> = (0..NOISE_SIZE * NOISE_SIZE)
+            .flat_map(|_| {
+                let x = (rng.next_f32() * 255.0) as u8;
+                let y = (rng.next_f32() * 255.0) as u8;
+                [x, y, 128, 255]
+            })
+            .collect();
+        let noise_size = wgpu::Extent3d {
+            width: NOISE_SIZE,
+            height: NOISE_SIZE,
+            depth_or_array_layers: 1,
+        };
+        let noise_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("SSAO Noise Texture"),
+            size: noise_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &noise_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &noise,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4 * NOISE_SIZE),
+                rows_per_image: std::num::NonZeroU32::new(NOISE_SIZE),
+            },
+            noise_size,
+        );
+        let noise_view = noise_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("SSAO Blur Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let blur_buffer = |label, direction| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(&[BlurUniform {
+                    direction,
+                    _padding: [0.0; 2],
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            })
+        };
+        let horizontal_buffer = blur_buffer("SSAO Horizontal Blur Buffer", [1.0, 0.0]);
+        let vertical_buffer = blur_buffer("SSAO Vertical Blur Buffer", [0.0, 1.0]);
+
+        let ssao_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Bind Group Layout"),
+            entries: &[
+                uniform_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                texture_entry(2, false),
+            ],
+        });
+        let blur_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Blur Bind Group Layout"),
+            entries: &[
+                texture_entry(0, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                uniform_entry(2),
+            ],
+        });
+        let ssao_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("SSAO Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../ssao.wgsl").into()),
+        });
+        let blur_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("SSAO Blur Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../ssao_blur.wgsl").into()),
+        });
+        let ssao_pipeline = fullscreen_pipeline(device, "SSAO Pipeline", &ssao_layout, &ssao_shader);
+        let blur_pipeline =
+            fullscreen_pipeline(device, "SSAO Blur Pipeline", &blur_layout, &blur_shader);
+
+        let targets = create_targets(
+            device,
+            config,
+            depth_view,
+            &TargetResources {
+                uniform_buffer: &uniform_buffer,
+                noise_view: &noise_view,
+                sampler: &sampler,
+                horizontal_buffer: &horizontal_buffer,
+                vertical_buffer: &vertical_buffer,
+                ssao_layout: &ssao_layout,
+                blur_layout: &blur_layout,
+            },
+        );
+        SsaoPass {
+            uniform,
+            uniform_buffer,
+            noise_view,
+            sampler,
+            horizontal_buffer,
+            vertical_buffer,
+            ssao_layout,
+            blur_layout,
+            ssao_pipeline,
+            blur_pipeline,
+            targets,
+        }
+    }
+    /// Recreates the half resolution targets, `depth_view` is the resized depth buffer.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_view: &wgpu::TextureView,
+    ) {
+        self.targets = create_targets(
+            device,
+            config,
+            depth_view,
+            &TargetResources {
+                uniform_buffer: &self.uniform_buffer,
+                noise_view: &self.noise_view,
+                sampler: &self.sampler,
+                horizontal_buffer: &self.horizontal_buffer,
+                vertical_buffer: &self.vertical_buffer,
+                ssao_layout: &self.ssao_layout,
+                blur_layout: &self.blur_layout,
+            },
+        );
+    }
+    /// Must be called whenever the camera projection changes.
+    pub fn update_projection(&mut self, queue: &wgpu::Queue, projection: cgmath::Matrix4<f32>) {
+        self.uniform.projection = projection.into();
+        self.uniform.inverse_projection = projection
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity)
+            .into();
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+    pub fn update_settings(&mut self, queue: &wgpu::Queue, settings: SsaoSettings) {
+        self.uniform.sample_count = settings.sample_count.clamp(1, MAX_SAMPLES as u32);
+        self.uniform.radius = settings.radius;
+        self.uniform.bias = settings.bias;
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+    /// Blurred ambient occlusion, 1 is unoccluded.
+    pub fn ao_view(&self) -> &wgpu::TextureView {
+        &self.targets.ao_view
+    }
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        let passes = [
+            ("SSAO Pass", &self.ssao_pipeline, &self.targets.ssao_bind_group, &self.targets.ao_view),
+            (
+                "SSAO Horizontal Blur Pass",
+                &self.blur_pipeline,
+                &self.targets.blur_horizontal_bind_group,
+                &self.targets.temp_view,
+            ),
+            (
+                "SSAO Vertical Blur Pass",
+                &self.blur_pipeline,
+                &self.targets.blur_vertical_bind_group,
+                &self.targets.ao_view,
+            ),
+        ];
+        for (label, pipeline, bind_group, target) in passes {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(label),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+/// The size independent resources the targets' bind groups refer to.
+struct TargetResources<'a> {
+    uniform_buffer: &'a wgpu::Buffer,
+    noise_view: &'a wgpu::TextureView,
+    sampler: &'a wgpu::Sampler,
+    horizontal_buffer: &'a wgpu::Buffer,
+    vertical_buffer: &'a wgpu::Buffer,
+    ssao_layout: &'a wgpu::BindGroupLayout,
+    blur_layout: &'a wgpu::BindGroupLayout,
+}
+
+fn create_targets(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    depth_view: &wgpu::TextureView,
+    resources: &TargetResources,
+) -> Targets {
+    let size = wgpu::Extent3d {
+        width: (config.width / 2).max(1),
+        height: (config.height / 2).max(1),
+        depth_or_array_layers: 1,
+    };
+    let target = |label| {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: AO_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    };
+    let ao_view = target("SSAO Texture");
+    let temp_view = target("SSAO Blur Texture");
+    let ssao_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("SSAO Bind Group"),
+        layout: resources.ssao_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: resources.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(depth_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(resources.noise_view),
+            },
+        ],
+    });
+    let blur_bind_group = |label: &str, src: &wgpu::TextureView, direction: &wgpu::Buffer| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: resources.blur_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(src),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(resources.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: direction.as_entire_binding(),
+                },
+            ],
+        })
+    };
+    let blur_horizontal_bind_group = blur_bind_group(
+        "SSAO Horizontal Blur Bind Group",
+        &ao_view,
+        resources.horizontal_buffer,
+    );
+    let blur_vertical_bind_group = blur_bind_group(
+        "SSAO Vertical Blur Bind Group",
+        &temp_view,
+        resources.vertical_buffer,
+    );
+    Targets {
+        ao_view,
+        ssao_bind_group,
+        blur_horizontal_bind_group,
+        blur_vertical_bind_group,
+        temp_view,
+    }
+}
+
+fn uniform_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn texture_entry(binding: u32, filterable: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable },
+        },
+        count: None,
+    }
+}
+
+fn fullscreen_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[wgpu::ColorTargetState {
+                format: AO_FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+    })
+}
@@ -1,28 +1,93 @@
 use derive_more::{Display, Error};
 use wgpu::util::DeviceExt;
 
-use crate::entity::model::mesh::Mesh;
-use crate::entity::model::Vertex;
-use winit::window::Window;
+use crate::assets::Assets;
+use crate::camera::Camera;
+#[cfg(feature = "dev-ui")]
+use crate::egui_integration::EguiRenderer;
+use crate::entity::model::material::BoundMaterial;
+use crate::entity::model::mesh::{Mesh, VertexLayout};
+use crate::entity::model::{Material, Model, Object};
+use crate::entity::Entity;
+use crate::file_drop::FileDrop;
+use crate::game_loop::GameLoop;
+use crate::lod::LodMesh;
+use crate::msaa::MsaaConfig;
+use crate::fullscreen::FullscreenPass;
+use crate::render_graph::{
+    RenderGraph, RenderPass, ResourceDesc, ResourceId, ResourcePool, TextureSize,
+};
+use crate::scene::{EntityId, Scene};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use winit::{
+    event::*,
+    event_loop::{ControlFlow, EventLoop},
+    window::Window,
+};
 
 pub struct State {
     surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
+    device: Rc<wgpu::Device>,
+    queue: Rc<wgpu::Queue>,
     config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
+    graph: RenderGraph,
+    msaa: MsaaConfig,
+    resolution_scale: ResolutionScale,
+    device_info: DeviceInfo,
+    game_loop: GameLoop,
+    redraw_policy: RedrawPolicy,
+    redraw: RedrawTracker,
+    pub scene: Scene,
+    pub camera: Camera,
+    /// `camera` as of the last `render`, read by `ForwardPass::prepare`.
+    forward_camera: Rc<Cell<Camera>>,
+    pub assets: Assets,
+    /// Loads model files dropped onto the window into the scene.
+    pub file_drop: FileDrop,
+    #[cfg(feature = "dev-ui")]
+    pub egui: EguiRenderer,
+    /// Builds the developer UI, called every frame with `egui`'s context.
+    #[cfg(feature = "dev-ui")]
+    pub ui: Option<Box<dyn FnMut(&egui::CtxRef)>>,
 }
+/// Errors creating a `State`. `Send + Sync` so it can be returned from a spawned task or boxed
+/// into an `anyhow`-style error.
 #[derive(Debug, Display, Error)]
 pub enum Error {
+    #[display(fmt = "no graphics adapter can present to the window")]
     NoGraphicAdapter,
+    #[display(fmt = "can't get a device from the adapter: {}", _0)]
     RequestDeviceError(wgpu::RequestDeviceError),
-    WGpu(wgpu::Error),
+    /// wgpu's errors box their source without `Sync`, so only the message is kept.
+    #[display(fmt = "wgpu error: {}", _0)]
+    WGpu(#[error(not(source))] String),
+    #[display(fmt = "can't create the window: {}", _0)]
     WinIt(winit::error::OsError),
+    #[display(fmt = "invalid render graph: {}", _0)]
+    RenderGraph(crate::render_graph::Error),
+}
+fn _assert_send_sync()
+where
+    Error: Send + Sync,
+{
 }
 impl From<wgpu::Error> for Error {
     fn from(e: wgpu::Error) -> Self {
-        Error::WGpu(e)
+        Error::WGpu(e.to_string())
+    }
+}
+impl From<winit::error::OsError> for Error {
+    fn from(e: winit::error::OsError) -> Self {
+        Error::WinIt(e)
+    }
+}
+impl From<crate::render_graph::Error> for Error {
+    fn from(e: crate::render_graph::Error) -> Self {
+        Error::RenderGraph(e)
     }
 }
 impl From<wgpu::RequestDeviceError> for Error {
@@ -30,102 +95,301 @@ impl From<wgpu::RequestDeviceError> for Error {
         Error::RequestDeviceError(e)
     }
 }
+/// The adapter `State` picked and what it supports.
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    pub adapter_name: String,
+    pub backend: wgpu::Backend,
+    pub max_texture_dimension: u32,
+    pub supported_features: wgpu::Features,
+    /// The requested features the device was created with, see `StateConfig::request_features`.
+    pub granted_features: wgpu::Features,
+    /// Capabilities WebGL and older APIs lack, anisotropic filtering among them.
+    pub downlevel_flags: wgpu::DownlevelFlags,
+}
+impl DeviceInfo {
+    pub fn new(adapter: &wgpu::Adapter) -> Self {
+        let info = adapter.get_info();
+        DeviceInfo {
+            adapter_name: info.name,
+            backend: info.backend,
+            max_texture_dimension: adapter.limits().max_texture_dimension_2d,
+            supported_features: adapter.features(),
+            granted_features: wgpu::Features::empty(),
+            downlevel_flags: adapter.get_downlevel_properties().flags,
+        }
+    }
+}
+
+/// Renders the scene at `factor` times the window resolution and scales it to the window
+/// with a bilinear filter. Lower factors trade sharpness for speed on slow GPUs.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ResolutionScale {
+    pub factor: f32,
+}
+impl ResolutionScale {
+    pub const FULL: ResolutionScale = ResolutionScale { factor: 1.0 };
+    /// Smaller factors would round small windows down to empty textures.
+    pub const MIN_FACTOR: f32 = 0.25;
+    /// Larger factors would go over the texture size limit on 4K screens.
+    pub const MAX_FACTOR: f32 = 2.0;
+    /// `factor` clamped to `MIN_FACTOR..=MAX_FACTOR`, or full resolution if it isn't a number.
+    pub fn new(factor: f32) -> Self {
+        if factor.is_nan() {
+            log::warn!("resolution scale is NaN, rendering at full resolution");
+            return Self::FULL;
+        }
+        let clamped = factor.clamp(Self::MIN_FACTOR, Self::MAX_FACTOR);
+        if clamped != factor {
+            log::warn!("resolution scale {} clamped to {}", factor, clamped);
+        }
+        ResolutionScale { factor: clamped }
+    }
+}
+impl Default for ResolutionScale {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// When `State::run` renders.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum RedrawPolicy {
+    /// Every time the event loop is idle, as fast as the present mode allows.
+    Continuous,
+    /// Only after `State::request_redraw`, when the scene is dirty or when the window system
+    /// asks, e.g. after the window was uncovered. The event loop sleeps in between, waking up
+    /// every timestep while entities have animators or dropped files are loading. Changes to
+    /// the camera need a `request_redraw`.
+    OnDemand,
+}
+impl Default for RedrawPolicy {
+    fn default() -> Self {
+        RedrawPolicy::Continuous
+    }
+}
+
+/// Whether `RedrawPolicy::OnDemand` has to render, apart from `State` so it works without a
+/// device.
+#[derive(Debug)]
+struct RedrawTracker {
+    /// Set by `request`, cleared by `drawn`.
+    requested: bool,
+}
+impl RedrawTracker {
+    /// Requested, the first frame always renders.
+    fn new() -> Self {
+        RedrawTracker { requested: true }
+    }
+    fn request(&mut self) {
+        self.requested = true;
+    }
+    fn needs_redraw(&self, scene: &Scene) -> bool {
+        self.requested || scene.is_dirty()
+    }
+    /// Called once `scene` is drawn.
+    fn drawn(&mut self, scene: &mut Scene) {
+        self.requested = false;
+        scene.clear_dirty();
+    }
+}
+
+/// How `State` picks its adapter and presents.
+#[derive(Copy, Clone, Debug)]
+pub struct StateConfig {
+    pub power_preference: wgpu::PowerPreference,
+    /// `Backends::GL` runs on software rasterizers, e.g. in CI.
+    pub backends: wgpu::Backends,
+    pub present_mode: wgpu::PresentMode,
+    pub msaa: MsaaConfig,
+    /// Optional features to enable. Ones the adapter lacks are logged and left out, check
+    /// `DeviceInfo::granted_features` before relying on them.
+    pub request_features: wgpu::Features,
+    pub resolution_scale: ResolutionScale,
+    pub redraw_policy: RedrawPolicy,
+}
+impl StateConfig {
+    pub fn new() -> Self {
+        StateConfig {
+            power_preference: wgpu::PowerPreference::default(),
+            backends: wgpu::Backends::all(),
+            // VSync
+            present_mode: wgpu::PresentMode::Fifo,
+            msaa: MsaaConfig::default(),
+            request_features: wgpu::Features::empty(),
+            resolution_scale: ResolutionScale::FULL,
+            redraw_policy: RedrawPolicy::Continuous,
+        }
+    }
+    pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+    pub fn backends(mut self, backends: wgpu::Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+    pub fn present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+    pub fn msaa(mut self, msaa: MsaaConfig) -> Self {
+        self.msaa = msaa;
+        self
+    }
+    pub fn request_features(mut self, features: wgpu::Features) -> Self {
+        self.request_features = features;
+        self
+    }
+    /// See `ResolutionScale::new` for the factors allowed.
+    pub fn resolution_scale(mut self, factor: f32) -> Self {
+        self.resolution_scale = ResolutionScale::new(factor);
+        self
+    }
+    pub fn redraw_policy(mut self, redraw_policy: RedrawPolicy) -> Self {
+        self.redraw_policy = redraw_policy;
+        self
+    }
+}
+impl Default for StateConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const MSAA_COLOR: ResourceId = ResourceId("msaa color");
+const DEPTH: ResourceId = ResourceId("depth");
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// The scene at the render resolution, blitted to the surface by `FullscreenPass`.
+const SCENE_COLOR: ResourceId = ResourceId("scene color");
+const SCENE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Creates the textures the forward pass renders to at `scale` times the screen size,
+/// replacing ones of another scale.
+fn create_scene_targets(
+    device: &wgpu::Device,
+    resources: &mut ResourcePool,
+    msaa: MsaaConfig,
+    scale: ResolutionScale,
+) {
+    let attachment = wgpu::TextureUsages::RENDER_ATTACHMENT;
+    let size = TextureSize::ScreenRelative {
+        scale: scale.factor,
+    };
+    let depth_desc = ResourceDesc {
+        size,
+        sample_count: msaa.sample_count(),
+        ..ResourceDesc::screen(DEPTH_FORMAT, attachment)
+    };
+    resources.get_or_create(device, DEPTH, depth_desc);
+    if msaa.is_enabled() {
+        let color_desc = ResourceDesc {
+            size,
+            sample_count: msaa.sample_count(),
+            ..ResourceDesc::screen(SCENE_FORMAT, attachment)
+        };
+        resources.get_or_create(device, MSAA_COLOR, color_desc);
+    }
+    let scene_desc = ResourceDesc {
+        size,
+        ..ResourceDesc::screen(SCENE_FORMAT, attachment | wgpu::TextureUsages::TEXTURE_BINDING)
+    };
+    resources.get_or_create(device, SCENE_COLOR, scene_desc);
+}
+
 impl State {
-    // Creating some of the wgpu types requires async code
     pub async fn new(window: &Window) -> Result<Self, Error> {
+        Self::with_config(window, StateConfig::default()).await
+    }
+    // Creating some of the wgpu types requires async code
+    pub async fn with_config(window: &Window, state_config: StateConfig) -> Result<Self, Error> {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::Backends::DX12);
+        let instance = wgpu::Instance::new(state_config.backends);
         let surface = unsafe { instance.create_surface(window) };
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
+                power_preference: state_config.power_preference,
                 compatible_surface: Some(&surface),
                 force_fallback_adapter: false,
             })
             .await
             .ok_or(Error::NoGraphicAdapter)?;
 
-        println!("Using Adapter: {}", &adapter.get_info().name);
+        let mut device_info = DeviceInfo::new(&adapter);
+        println!("Using Adapter: {}", &device_info.adapter_name);
+        let requested = state_config.request_features | state_config.msaa.required_features();
+        device_info.granted_features = requested & device_info.supported_features;
+        if device_info.granted_features != requested {
+            log::warn!(
+                "adapter doesn't support requested features {:?}",
+                requested - device_info.granted_features
+            );
+        }
 
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
+                    features: device_info.granted_features,
                     limits: wgpu::Limits::default(),
                     label: None,
                 },
                 None, // Trace path
             )
             .await?;
+        let (device, queue) = (Rc::new(device), Rc::new(queue));
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface.get_preferred_format(&adapter).unwrap(),
             width: size.width,
             height: size.height,
-            // VSync
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: state_config.present_mode,
         };
         surface.configure(&device, &config);
-        /* SHADER START */
-        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shader.wgsl").into()),
-        });
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[],
-                push_constant_ranges: &[],
-            });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",     // 1.
-                buffers: &[Vertex::desc()], // 2.
-            },
-            fragment: Some(wgpu::FragmentState {
-                // 3.
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[wgpu::ColorTargetState {
-                    // 4.
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, // 2.
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLAMPING
-                clamp_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1,                         // 2.
-                mask: !0,                         // 3.
-                alpha_to_coverage_enabled: false, // 4.
-            },
-        });
-        /* SHADER END */
+        let msaa = state_config.msaa.supported(device_info.granted_features);
+        let mut assets = Assets::new(device.clone(), queue.clone());
+        assets.set_downlevel_flags(device_info.downlevel_flags);
+        let camera = Camera::new(size.width as f32 / size.height.max(1) as f32);
+        let forward_camera = Rc::new(Cell::new(camera));
+        let mut graph = RenderGraph::new(size.width, size.height);
+        // The field is public, so it may not have gone through `ResolutionScale::new`
+        let resolution_scale = ResolutionScale::new(state_config.resolution_scale.factor);
+        create_scene_targets(&device, &mut graph.resources, msaa, resolution_scale);
+        graph.add_pass(ForwardPass::new(
+            device.clone(),
+            assets.material_layout(),
+            assets.fallback_material(),
+            forward_camera.clone(),
+            msaa,
+        ));
+        graph.add_pass(FullscreenPass::blit(device.clone(), SCENE_COLOR, config.format));
+        graph.compile()?;
+        #[cfg(feature = "dev-ui")]
+        let egui = EguiRenderer::new(device.clone(), queue.clone(), &config, window);
         Ok(Self {
             surface,
             device,
             queue,
             config,
             size,
-            render_pipeline,
+            graph,
+            msaa,
+            resolution_scale,
+            device_info,
+            game_loop: GameLoop::new(),
+            redraw_policy: state_config.redraw_policy,
+            redraw: RedrawTracker::new(),
+            scene: Scene::new(),
+            camera,
+            forward_camera,
+            assets,
+            file_drop: FileDrop::default(),
+            #[cfg(feature = "dev-ui")]
+            egui,
+            #[cfg(feature = "dev-ui")]
+            ui: None,
         })
     }
 
@@ -136,17 +400,108 @@ impl State {
             self.config.height = new_size.height;
             // Resize window
             self.surface.configure(&self.device, &self.config);
+            self.graph.resize(&self.device, new_size.width, new_size.height);
+            self.camera.aspect = new_size.width as f32 / new_size.height as f32;
+            #[cfg(feature = "dev-ui")]
+            self.egui.resize(new_size.width, new_size.height);
+            self.request_redraw();
         }
     }
 
-    pub fn input(&mut self, _event: &winit::event::WindowEvent) -> bool {
-        false
+    pub fn redraw_policy(&self) -> RedrawPolicy {
+        self.redraw_policy
+    }
+
+    pub fn set_redraw_policy(&mut self, redraw_policy: RedrawPolicy) {
+        self.redraw_policy = redraw_policy;
+        self.request_redraw();
+    }
+
+    /// Has `RedrawPolicy::OnDemand` render the next frame. Does nothing more with
+    /// `Continuous`.
+    pub fn request_redraw(&mut self) {
+        self.redraw.request();
+    }
+
+    /// Whether `OnDemand` would render: a redraw was requested or the scene is dirty.
+    pub fn needs_redraw(&self) -> bool {
+        self.redraw.needs_redraw(&self.scene)
     }
 
-    pub fn update(&mut self) {}
+    /// Whether `OnDemand` has to keep running logic updates while nothing happens.
+    fn keeps_updating(&self) -> bool {
+        self.file_drop.pending() > 0 || self.scene.entities.iter().any(|e| !e.animators.is_empty())
+    }
+
+    /// The entity under a window position, e.g. from `WindowEvent::CursorMoved`, by its
+    /// bounds.
+    pub fn pick(&self, screen_pos: winit::dpi::PhysicalPosition<f64>) -> Option<EntityId> {
+        let ndc = cgmath::Vector2::new(
+            (2.0 * screen_pos.x / self.size.width as f64 - 1.0) as f32,
+            (1.0 - 2.0 * screen_pos.y / self.size.height as f64) as f32,
+        );
+        self.scene.pick(&self.camera.screen_to_ray(ndc))
+    }
+
+    /// The MSAA config in use, which may have fallen back from the requested one.
+    pub fn msaa(&self) -> MsaaConfig {
+        self.msaa
+    }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+    pub fn resolution_scale(&self) -> ResolutionScale {
+        self.resolution_scale
+    }
+
+    /// Recreates the scene textures at `factor` times the window size, clamped by
+    /// `ResolutionScale::new`, and redraws.
+    pub fn set_resolution_scale(&mut self, factor: f32) {
+        self.resolution_scale = ResolutionScale::new(factor);
+        let (msaa, scale) = (self.msaa, self.resolution_scale);
+        create_scene_targets(&self.device, &mut self.graph.resources, msaa, scale);
+        self.request_redraw();
+    }
+
+    /// The adapter in use, for bug reports and for enabling optional code paths.
+    pub fn device_info(&self) -> DeviceInfo {
+        self.device_info.clone()
+    }
+
+    /// Returns whether the event was used up. The developer UI gets events first so typing
+    /// into it doesn't also drive the scene.
+    pub fn input(&mut self, event: &winit::event::WindowEvent) -> bool {
+        #[cfg(feature = "dev-ui")]
+        if self.egui.on_event(event) {
+            self.request_redraw();
+            return true;
+        }
+        self.file_drop.on_event(event)
+    }
+
+    /// Fixed timestep logic update, called `GameLoop::timestep` apart in simulated time.
+    pub fn update(&mut self, dt: Duration) {
+        #[cfg(feature = "hot-reload")]
+        self.assets.poll_reloads(&mut self.scene);
+        self.file_drop.poll(&mut self.assets, &mut self.scene, &mut self.camera);
+        self.scene.update(dt.as_secs_f32());
+    }
+
+    /// The next surface texture. A lost or outdated surface is reconfigured and acquired once
+    /// more, a second error is returned. Running out of memory is returned right away.
+    fn acquire_frame(&mut self) -> Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
+        match self.surface.get_current_texture() {
+            Err(e @ (wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated)) => {
+                log::info!("surface {:?}, reconfiguring", e);
+                self.surface.configure(&self.device, &self.config);
+                self.surface.get_current_texture()
+            }
+            result => result,
+        }
+    }
+
+    /// `_alpha` is how far between the last and the next logic update this frame is. Errors
+    /// are ones `acquire_frame` couldn't recover from.
+    pub fn render(&mut self, _alpha: f32) -> Result<(), wgpu::SurfaceError> {
+        let output = self.acquire_frame()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -155,34 +510,111 @@ impl State {
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
+        self.forward_camera.set(self.camera);
+        self.graph.prepare(&self.queue, &self.scene);
+        if let Err(e) = self.graph.execute(&mut encoder, &view) {
+            log::error!("render graph: {}", e);
+        }
+        #[cfg(feature = "dev-ui")]
         {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: None,
-            });
-            render_pass.set_pipeline(&self.render_pipeline); // 2.
-            render_pass.draw(0..3, 0..1); // 3.
+            let pixels_per_point = self.egui.pixels_per_point();
+            self.egui.end_frame(&mut encoder, &view, pixels_per_point);
         }
 
         // submit will accept anything that implements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
+        self.redraw.drawn(&mut self.scene);
 
         Ok(())
     }
+    /// Takes over the event loop, driving `update` with the fixed timestep `GameLoop` and
+    /// rendering once per redraw. How often that is depends on the `RedrawPolicy`, with
+    /// `OnDemand` the updates run as events arrive instead of before each frame.
+    pub fn run(mut self, window: Window, event_loop: EventLoop<()>) -> ! {
+        event_loop.run(move |event, _, control_flow| match event {
+            Event::WindowEvent {
+                ref event,
+                window_id,
+            } if window_id == window.id() && !self.input(event) => match event {
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        },
+                    ..
+                } => *control_flow = ControlFlow::Exit,
+                WindowEvent::Resized(physical_size) => {
+                    self.resize(*physical_size);
+                }
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    // new_inner_size is &&mut so we have to dereference it twice
+                    self.resize(**new_inner_size);
+                }
+
+                _ => {}
+            },
+            Event::RedrawRequested(_) => {
+                let alpha = match self.redraw_policy {
+                    RedrawPolicy::Continuous => self.tick(),
+                    RedrawPolicy::OnDemand => self.game_loop.alpha(),
+                };
+                #[cfg(feature = "dev-ui")]
+                {
+                    let context = self.egui.begin_frame(&window);
+                    if let Some(ui) = &mut self.ui {
+                        ui(&context);
+                    }
+                }
+                match self.render(alpha) {
+                    Ok(_) => {}
+                    // The system is out of memory, we should probably quit
+                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                    // Timeouts and surfaces still lost after reconfiguring get another try
+                    // next frame
+                    Err(e) => log::warn!("skipped a frame: {:?}", e),
+                }
+            }
+            Event::MainEventsCleared => match self.redraw_policy {
+                RedrawPolicy::Continuous => {
+                    // RedrawRequested will only trigger once, unless we manually
+                    // request it.
+                    window.request_redraw();
+                    // Undoes the Wait an earlier OnDemand left behind
+                    if *control_flow != ControlFlow::Exit {
+                        *control_flow = ControlFlow::Poll;
+                    }
+                }
+                RedrawPolicy::OnDemand => {
+                    self.tick();
+                    if self.needs_redraw() {
+                        window.request_redraw();
+                    }
+                    if *control_flow == ControlFlow::Exit {
+                        return;
+                    }
+                    *control_flow = if self.keeps_updating() {
+                        ControlFlow::WaitUntil(Instant::now() + self.game_loop.timestep())
+                    } else {
+                        // Otherwise the time asleep would be caught up on waking
+                        self.game_loop.reset();
+                        ControlFlow::Wait
+                    };
+                }
+            },
+            _ => {}
+        })
+    }
+    /// Runs the logic updates due since the last tick and returns the render alpha.
+    fn tick(&mut self) -> f32 {
+        let mut game_loop = std::mem::take(&mut self.game_loop);
+        let alpha = game_loop.tick(|dt| self.update(dt));
+        self.game_loop = game_loop;
+        alpha
+    }
     pub fn register_buffer(
         &self,
         usage: wgpu::BufferUsages,
@@ -196,19 +628,420 @@ impl State {
                 usage,
             })
     }
-    pub fn load_mesh(&self, vertices: &[Vertex], indices: &[u32], label: Option<&str>) -> Mesh {
-        let vertex_label_name = label.map(|s| (String::from(s) + " vertex buffer"));
-        let indices_label_name = label.map(|s| (String::from(s) + " index buffer"));
-        let vertices = self.register_buffer(
-            wgpu::BufferUsages::VERTEX,
-            bytemuck::cast_slice(vertices),
-            vertex_label_name.as_deref(),
+    pub fn load_mesh(&self, object: &Object, label: Option<&str>) -> Mesh {
+        Mesh::new(&self.device, object, label)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CameraUniform {
+    view_proj: [[f32; 4]; 4],
+    position: [f32; 4],
+}
+
+/// The `Entity` uniform of `shader.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EntityUniform {
+    model: [[f32; 4]; 4],
+    tint: [f32; 4],
+    /// `w` is 1 for entities drawn unlit in this color.
+    emissive: [f32; 4],
+}
+impl EntityUniform {
+    fn new(entity: &Entity) -> Self {
+        let rgba = |c: wgpu::Color| [c.r as f32, c.g as f32, c.b as f32, c.a as f32];
+        EntityUniform {
+            model: entity.mx_world.into(),
+            tint: rgba(entity.color),
+            emissive: entity.emissive.map_or([0.0; 4], |c| rgba(wgpu::Color { a: 1.0, ..c })),
+        }
+    }
+}
+/// Dynamic offsets have to be multiples of `Limits::min_uniform_buffer_offset_alignment`,
+/// which is 256 by default.
+const ENTITY_UNIFORM_STRIDE: usize = 256;
+
+/// What an entity is drawn with.
+enum Geometry {
+    Mesh(Rc<Mesh>),
+    /// The level `Scene::select_lod_levels` picked for the camera.
+    Lod(Rc<LodMesh>, usize),
+    Model(Rc<Model>),
+}
+
+/// An entity to draw this frame.
+struct Draw {
+    geometry: Geometry,
+    /// Unused for models, which bind their own.
+    material: Rc<BoundMaterial>,
+    /// Of the entity's uniform in `entity_buffer`.
+    offset: wgpu::DynamicOffset,
+}
+
+/// Clears `SCENE_COLOR` to the scene background and draws the scene's entities, opaque ones
+/// first and blended ones back to front, though without blending. Entities with a `lod` are
+/// drawn at the level for their distance to the camera. With MSAA it renders to the
+/// multi-sampled `MSAA_COLOR` and resolves into `SCENE_COLOR`. Back faces are culled unless the
+/// material is double sided. Meshes with the `Compressed` vertex layout are skipped,
+/// `shader.wgsl` reads full precision attributes.
+struct ForwardPass {
+    device: Rc<wgpu::Device>,
+    /// By vertex layout, whether the mesh has a color buffer and the material's
+    /// `Material::cull_mode`.
+    pipelines: HashMap<(VertexLayout, bool, Option<wgpu::Face>), wgpu::RenderPipeline>,
+    camera: Rc<Cell<Camera>>,
+    camera_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Room for `entity_capacity` entity uniforms, `ENTITY_UNIFORM_STRIDE` apart.
+    entity_buffer: wgpu::Buffer,
+    entity_capacity: usize,
+    /// The camera at binding 0 and the entity uniforms at 1, group 0.
+    bind_group: wgpu::BindGroup,
+    /// Bound to group 1 for entities without a material.
+    fallback_material: Rc<BoundMaterial>,
+    draws: Vec<Draw>,
+    clear_color: wgpu::Color,
+    reads: Vec<ResourceId>,
+}
+impl ForwardPass {
+    fn new(
+        device: Rc<wgpu::Device>,
+        material_layout: &wgpu::BindGroupLayout,
+        fallback_material: Rc<BoundMaterial>,
+        camera: Rc<Cell<Camera>>,
+        msaa: MsaaConfig,
+    ) -> Self {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shader.wgsl").into()),
+        });
+        let uniform = |binding, has_dynamic_offset, min_binding_size| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset,
+                min_binding_size,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Render Bind Group Layout"),
+            entries: &[uniform(0, false, None), uniform(1, true, Self::entity_size())],
+        });
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout, material_layout],
+                push_constant_ranges: &[],
+            });
+        let mut pipelines = HashMap::new();
+        for layout in [VertexLayout::Base, VertexLayout::WithTangent] {
+            for colored in [false, true] {
+                // Double sided materials get the pipeline without culling
+                for cull_mode in [Some(wgpu::Face::Back), None] {
+                    let buffers = [layout.buffer_layout(), Mesh::COLOR_LAYOUT];
+                    let (entry_point, buffers) = if colored {
+                        ("vs_colored", &buffers[..])
+                    } else {
+                        ("vs_main", &buffers[..1])
+                    };
+                    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: Some("Render Pipeline"),
+                        layout: Some(&render_pipeline_layout),
+                        vertex: wgpu::VertexState {
+                            module: &shader,
+                            entry_point,
+                            buffers,
+                        },
+                        fragment: Some(wgpu::FragmentState {
+                            module: &shader,
+                            entry_point: "fs_main",
+                            targets: &[wgpu::ColorTargetState {
+                                format: SCENE_FORMAT,
+                                blend: Some(wgpu::BlendState::REPLACE),
+                                write_mask: wgpu::ColorWrites::ALL,
+                            }],
+                        }),
+                        primitive: wgpu::PrimitiveState {
+                            topology: wgpu::PrimitiveTopology::TriangleList,
+                            strip_index_format: None,
+                            front_face: wgpu::FrontFace::Ccw,
+                            cull_mode,
+                            polygon_mode: wgpu::PolygonMode::Fill,
+                            clamp_depth: false,
+                            conservative: false,
+                        },
+                        depth_stencil: Some(wgpu::DepthStencilState {
+                            format: DEPTH_FORMAT,
+                            depth_write_enabled: true,
+                            depth_compare: wgpu::CompareFunction::Less,
+                            stencil: wgpu::StencilState::default(),
+                            bias: wgpu::DepthBiasState::default(),
+                        }),
+                        multisample: wgpu::MultisampleState {
+                            count: msaa.sample_count(),
+                            mask: !0,
+                            alpha_to_coverage_enabled: false,
+                        },
+                    });
+                    pipelines.insert((layout, colored, cull_mode), pipeline);
+                }
+            }
+        }
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Camera Buffer"),
+            size: std::mem::size_of::<CameraUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let entity_capacity = 64;
+        let (entity_buffer, bind_group) = Self::create_entity_buffer(
+            &device,
+            &bind_group_layout,
+            &camera_buffer,
+            entity_capacity,
         );
-        let indices = self.register_buffer(
-            wgpu::BufferUsages::INDEX,
-            bytemuck::cast_slice(indices),
-            indices_label_name.as_deref(),
+        ForwardPass {
+            device,
+            pipelines,
+            camera,
+            camera_buffer,
+            bind_group_layout,
+            entity_buffer,
+            entity_capacity,
+            bind_group,
+            fallback_material,
+            draws: Vec::new(),
+            clear_color: Scene::DEFAULT_BACKGROUND,
+            reads: if msaa.is_enabled() {
+                vec![DEPTH, MSAA_COLOR]
+            } else {
+                vec![DEPTH]
+            },
+        }
+    }
+    fn entity_size() -> Option<wgpu::BufferSize> {
+        wgpu::BufferSize::new(std::mem::size_of::<EntityUniform>() as u64)
+    }
+    fn create_entity_buffer(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        camera_buffer: &wgpu::Buffer,
+        capacity: usize,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let entity_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Entity Uniform Buffer"),
+            size: (capacity * ENTITY_UNIFORM_STRIDE) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Render Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &entity_buffer,
+                        offset: 0,
+                        size: Self::entity_size(),
+                    }),
+                },
+            ],
+        });
+        (entity_buffer, bind_group)
+    }
+    fn draw_mesh<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        mesh: &'a Mesh,
+        material: &'a BoundMaterial,
+    ) {
+        if self.set_pipeline(pass, mesh, &material.material) {
+            pass.set_bind_group(1, &material.bind_group, &[]);
+            mesh.draw(pass, 0..1);
+        }
+    }
+    /// Sets the pipeline for `mesh`'s buffers and `material`'s culling. `false` for meshes no
+    /// pipeline can draw.
+    fn set_pipeline<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        mesh: &Mesh,
+        material: &Material,
+    ) -> bool {
+        let key = (
+            mesh.layout(),
+            mesh.color_buffer().is_some(),
+            material.cull_mode(),
         );
-        Mesh::new(vertices, indices)
+        match self.pipelines.get(&key) {
+            Some(pipeline) => {
+                pass.set_pipeline(pipeline);
+                true
+            }
+            None => false,
+        }
+    }
+}
+impl RenderPass for ForwardPass {
+    fn name(&self) -> &str {
+        "Render Pass"
+    }
+    fn reads(&self) -> &[ResourceId] {
+        &self.reads
+    }
+    fn writes(&self) -> &[ResourceId] {
+        &[SCENE_COLOR]
+    }
+    /// Uploads the camera and a uniform per entity to draw, growing the buffer when there
+    /// are more entities than fit.
+    fn prepare(&mut self, queue: &wgpu::Queue, scene: &Scene) {
+        self.clear_color = scene.background_color;
+        let camera = self.camera.get();
+        let camera_uniform = CameraUniform {
+            view_proj: camera.view_proj().into(),
+            position: camera.eye.to_homogeneous().into(),
+        };
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera_uniform]));
+        self.draws.clear();
+        let mut uniforms: Vec<u8> = Vec::new();
+        let lod_levels: HashMap<usize, usize> =
+            scene.select_lod_levels(camera.eye).into_iter().collect();
+        let transparent = scene.transparent_entities(camera.eye);
+        for i in scene.opaque_entities().into_iter().chain(transparent) {
+            let entity = &scene.entities[i];
+            let geometry = match (&entity.model, &entity.lod, &entity.mesh) {
+                (Some(model), _, _) => Geometry::Model(model.clone()),
+                (None, Some(lod), _) => Geometry::Lod(lod.clone(), lod_levels[&i]),
+                (None, None, Some(mesh)) => Geometry::Mesh(mesh.clone()),
+                (None, None, None) => continue,
+            };
+            let offset = uniforms.len();
+            uniforms.resize(offset + ENTITY_UNIFORM_STRIDE, 0);
+            let uniform = EntityUniform::new(entity);
+            uniforms[offset..][..std::mem::size_of::<EntityUniform>()]
+                .copy_from_slice(bytemuck::bytes_of(&uniform));
+            let material = entity.material.as_ref().unwrap_or(&self.fallback_material);
+            self.draws.push(Draw {
+                geometry,
+                material: material.clone(),
+                offset: offset as wgpu::DynamicOffset,
+            });
+        }
+        if self.draws.len() > self.entity_capacity {
+            self.entity_capacity = self.draws.len().next_power_of_two();
+            let (entity_buffer, bind_group) = Self::create_entity_buffer(
+                &self.device,
+                &self.bind_group_layout,
+                &self.camera_buffer,
+                self.entity_capacity,
+            );
+            self.entity_buffer = entity_buffer;
+            self.bind_group = bind_group;
+        }
+        if !uniforms.is_empty() {
+            queue.write_buffer(&self.entity_buffer, 0, &uniforms);
+        }
+    }
+    fn record(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &ResourcePool,
+        _output: &wgpu::TextureView,
+    ) {
+        let scene = resources.view(SCENE_COLOR).expect("scene color is created in State::new");
+        let (view, resolve_target) = match resources.view(MSAA_COLOR) {
+            Some(msaa) => (msaa, Some(scene)),
+            None => (scene, None),
+        };
+        let depth = resources.view(DEPTH).expect("depth is created in State::new");
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(self.name()),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        for draw in &self.draws {
+            render_pass.set_bind_group(0, &self.bind_group, &[draw.offset]);
+            match &draw.geometry {
+                Geometry::Mesh(mesh) => self.draw_mesh(&mut render_pass, mesh, &draw.material),
+                Geometry::Lod(lod, level) => {
+                    let mesh = &lod.levels()[*level].1;
+                    self.draw_mesh(&mut render_pass, mesh, &draw.material);
+                }
+                Geometry::Model(model) => {
+                    for submesh in model.submeshes.iter().filter(|s| !s.indices.is_empty()) {
+                        let mesh = &model.meshes[submesh.mesh];
+                        let material = &model.materials[submesh.material];
+                        if self.set_pipeline(&mut render_pass, mesh, &material.material) {
+                            render_pass.set_bind_group(1, &material.bind_group, &[]);
+                            mesh.draw_submesh(&mut render_pass, submesh.indices.clone(), 0..1);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redraw_until_drawn() {
+        let mut scene = Scene::new();
+        let mut redraw = RedrawTracker::new();
+        assert!(redraw.needs_redraw(&scene));
+        redraw.drawn(&mut scene);
+        assert!(!redraw.needs_redraw(&scene));
+        redraw.request();
+        assert!(redraw.needs_redraw(&scene));
+        redraw.drawn(&mut scene);
+        assert!(!redraw.needs_redraw(&scene));
+    }
+
+    #[test]
+    fn dirty_scene_needs_redraw() {
+        let mut scene = Scene::new();
+        let mut redraw = RedrawTracker::new();
+        redraw.drawn(&mut scene);
+        scene.mark_dirty();
+        assert!(redraw.needs_redraw(&scene));
+        redraw.drawn(&mut scene);
+        assert!(!scene.is_dirty());
+        assert!(!redraw.needs_redraw(&scene));
+    }
+
+    #[test]
+    fn resolution_scale_is_clamped() {
+        assert_eq!(ResolutionScale::new(0.5).factor, 0.5);
+        assert_eq!(ResolutionScale::new(0.0).factor, ResolutionScale::MIN_FACTOR);
+        assert_eq!(ResolutionScale::new(-1.0).factor, ResolutionScale::MIN_FACTOR);
+        assert_eq!(ResolutionScale::new(f32::INFINITY).factor, ResolutionScale::MAX_FACTOR);
+        assert_eq!(ResolutionScale::new(f32::NAN), ResolutionScale::FULL);
+        let config = StateConfig::new().resolution_scale(f32::NEG_INFINITY);
+        assert_eq!(config.resolution_scale.factor, ResolutionScale::MIN_FACTOR);
     }
 }
@@ -0,0 +1,349 @@
+use cgmath::{Matrix4, Vector2, Vector3};
+use wgpu::util::DeviceExt;
+
+/// Format of the accumulated history, and of the resolved frame.
+pub const HISTORY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// How many Halton points the jitter cycles through.
+const JITTER_PERIOD: u32 = 8;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TaaSettings {
+    /// Weight of the new frame, the history gets the rest.
+    pub blend: f32,
+    /// Relative luminance difference above which the history is thrown away as a disocclusion.
+    pub luminance_threshold: f32,
+}
+impl Default for TaaSettings {
+    fn default() -> Self {
+        TaaSettings {
+            blend: 0.1,
+            luminance_threshold: 0.5,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaUniform {
+    texel_size: [f32; 2],
+    blend: f32,
+    luminance_threshold: f32,
+    history_valid: u32,
+    _padding: [u32; 3],
+}
+
+/// The `index`th element of the Halton sequence in `base`, in `0..1`.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+struct History {
+    views: [wgpu::TextureView; 2],
+    width: u32,
+    height: u32,
+}
+impl History {
+    fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let create = || {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some("TAA History Texture"),
+                    size: wgpu::Extent3d {
+                        width: width.max(1),
+                        height: height.max(1),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: HISTORY_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        History {
+            views: [create(), create()],
+            width,
+            height,
+        }
+    }
+}
+
+/// Temporal anti-aliasing. The projection is offset by a sub-pixel Halton jitter every frame
+/// and the frames are accumulated in a history texture, reprojected with the velocity buffer
+/// the G-buffer pass writes. Call `begin_frame` before rendering the scene with
+/// `jitter_projection`, then `render`.
+pub struct TaaPass {
+    settings: TaaSettings,
+    uniform_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    history: History,
+    frame: u32,
+    /// Whether `history` holds a previous frame, false after creation and `resize`.
+    history_valid: bool,
+}
+impl TaaPass {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, settings: TaaSettings) -> Self {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("TAA Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[TaaUniform {
+                texel_size: [0.0; 2],
+                blend: settings.blend,
+                luminance_threshold: settings.luminance_threshold,
+                history_valid: 0,
+                _padding: [0; 3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("TAA Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("TAA Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                texture_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("TAA Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../taa.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("TAA Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("TAA Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[HISTORY_FORMAT.into()],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        });
+        TaaPass {
+            settings,
+            uniform_buffer,
+            sampler,
+            layout,
+            pipeline,
+            history: History::new(device, width, height),
+            frame: 0,
+            history_valid: false,
+        }
+    }
+    /// Recreates the history at the new size, the next frame starts accumulating from scratch.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.history = History::new(device, width, height);
+        self.history_valid = false;
+    }
+    /// Drops the accumulated frames, e.g. after the camera jumped.
+    pub fn reset(&mut self) {
+        self.history_valid = false;
+    }
+    pub fn settings(&self) -> TaaSettings {
+        self.settings
+    }
+    pub fn update_settings(&mut self, settings: TaaSettings) {
+        self.settings = settings;
+    }
+    /// Moves on to the next jitter offset.
+    pub fn begin_frame(&mut self) {
+        self.frame = self.frame.wrapping_add(1);
+    }
+    /// This frame's sub-pixel offset in NDC, within half a pixel of the center.
+    pub fn jitter(&self) -> Vector2<f32> {
+        // Halton indices start at 1, 0 would give no offset every period
+        let index = self.frame % JITTER_PERIOD + 1;
+        let pixel = Vector2::new(halton(index, 2) - 0.5, halton(index, 3) - 0.5);
+        Vector2::new(
+            2.0 * pixel.x / self.history.width.max(1) as f32,
+            2.0 * pixel.y / self.history.height.max(1) as f32,
+        )
+    }
+    /// `projection` offset by `jitter`. The translation is applied in clip space so it moves
+    /// every depth by the same amount on screen.
+    pub fn jitter_projection(&self, projection: Matrix4<f32>) -> Matrix4<f32> {
+        let jitter = self.jitter();
+        Matrix4::from_translation(Vector3::new(jitter.x, jitter.y, 0.0)) * projection
+    }
+    /// The last resolved frame, valid after `render`.
+    pub fn output_view(&self) -> &wgpu::TextureView {
+        &self.history.views[(self.frame % 2) as usize]
+    }
+    /// Resolves `color`, rendered with `jitter_projection`, against the history into
+    /// `output_view`. `velocity` is the G-buffer's velocity target of the same frame. Both
+    /// must have `TEXTURE_BINDING` usage and the size the pass was created with.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        velocity: &wgpu::TextureView,
+    ) {
+        let uniform = TaaUniform {
+            texel_size: [
+                1.0 / self.history.width.max(1) as f32,
+                1.0 / self.history.height.max(1) as f32,
+            ],
+            blend: self.settings.blend,
+            luminance_threshold: self.settings.luminance_threshold,
+            history_valid: self.history_valid as u32,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        let write = (self.frame % 2) as usize;
+        let (target, history) = (&self.history.views[write], &self.history.views[1 - write]);
+        // The inputs belong to the caller and may be recreated, so the bind group is made per
+        // frame
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("TAA Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(color),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(history),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(velocity),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("TAA Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        drop(render_pass);
+        self.history_valid = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{SquareMatrix, Vector4};
+
+    #[test]
+    fn halton_sequence() {
+        let base_2: Vec<f32> = (1..=4).map(|i| halton(i, 2)).collect();
+        assert_eq!(base_2, [0.5, 0.25, 0.75, 0.125]);
+        let base_3: Vec<f32> = (1..=4).map(|i| halton(i, 3)).collect();
+        let expected = [1.0 / 3.0, 2.0 / 3.0, 1.0 / 9.0, 4.0 / 9.0];
+        for (value, expected) in base_3.into_iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-6);
+        }
+        assert_eq!(halton(0, 2), 0.0);
+    }
+
+    #[test]
+    fn jitter_cycles_within_half_a_pixel() {
+        let (device, _queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        let (width, height) = (64, 32);
+        let mut pass = TaaPass::new(&device, width, height, TaaSettings::default());
+        let mut offsets = Vec::new();
+        for _ in 0..JITTER_PERIOD {
+            pass.begin_frame();
+            let jitter = pass.jitter();
+            // In pixels rather than NDC
+            let pixel = Vector2::new(jitter.x * width as f32, jitter.y * height as f32) / 2.0;
+            assert!(pixel.x.abs() <= 0.5 && pixel.y.abs() <= 0.5, "{:?}", pixel);
+            assert!(!offsets.contains(&jitter), "{:?} repeats within a period", jitter);
+            offsets.push(jitter);
+        }
+        pass.begin_frame();
+        assert_eq!(pass.jitter(), offsets[0]);
+
+        // Every depth moves by the same amount after the perspective divide
+        let jitter = pass.jitter();
+        let projection = pass.jitter_projection(Matrix4::identity());
+        for point in [Vector4::new(0.2, -0.3, 0.5, 1.0), Vector4::new(0.4, 0.6, 2.0, 2.0)] {
+            let jittered = projection * point;
+            assert!((jittered.x / jittered.w - point.x / point.w - jitter.x).abs() < 1e-6);
+            assert!((jittered.y / jittered.w - point.y / point.w - jitter.y).abs() < 1e-6);
+            assert_eq!(jittered.z, point.z);
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use crate::entity::model::Vertex;
+use cgmath::{InnerSpace, Matrix4, Vector3};
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct TerrainParams {
+    world_size: [f32; 4],
+}
+
+/// Uploads a single channel image, padding the rows to the copy alignment.
+fn upload_r8(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &image::GrayImage,
+    label: &str,
+) -> wgpu::Texture {
+    let (width, height) = image.dimensions();
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    });
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_bytes_per_row = (width + align - 1) / align * align;
+    let padding = (padded_bytes_per_row - width) as usize;
+    let data: Vec<u8> = image
+        .as_raw()
+        .chunks_exact(width as usize)
+        .flat_map(|row| row.iter().copied().chain(std::iter::repeat(0).take(padding)))
+        .collect();
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        &data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+            rows_per_image: std::num::NonZeroU32::new(height),
+        },
+        size,
+    );
+    texture
+}
+
+/// Central difference gradient of the heights at every texel packed into a world space normal
+/// map, y up. Texels past the edges repeat the edge.
+pub fn generate_normal_map(heights: &image::GrayImage, world_size: [f32; 3]) -> image::RgbImage {
+    let (width, height) = heights.dimensions();
+    let spacing_x = world_size[0] / (width.max(2) - 1) as f32;
+    let spacing_z = world_size[2] / (height.max(2) - 1) as f32;
+    let h = |x: i64, z: i64| {
+        let x = x.max(0).min(width as i64 - 1) as u32;
+        let z = z.max(0).min(height as i64 - 1) as u32;
+        heights.get_pixel(x, z).0[0] as f32 / 255.0 * world_size[1]
+    };
+    image::RgbImage::from_fn(width, height, |x, z| {
+        let (x, z) = (x as i64, z as i64);
+        let dx = (h(x + 1, z) - h(x - 1, z)) / (2.0 * spacing_x);
+        let dz = (h(x, z + 1) - h(x, z - 1)) / (2.0 * spacing_z);
+        let normal = Vector3::new(-dx, 1.0, -dz).normalize();
+        let pack = |c: f32| ((c * 0.5 + 0.5) * 255.0).round() as u8;
+        image::Rgb([pack(normal.x), pack(normal.y), pack(normal.z)])
+    })
+}
+
+/// Terrain from a grayscale heightmap. A flat grid with one vertex per texel is displaced by
+/// the height texture in the vertex shader, black is `y = 0` and white `y = world_size[1]`. The
+/// terrain spans `0..world_size[0]` on x and `0..world_size[2]` on z.
+pub struct Terrain {
+    pub world_size: [f32; 3],
+    heights: image::GrayImage,
+    pub height_texture: wgpu::Texture,
+    /// Baked by `bake_normals_gpu`.
+    pub normal_texture: Option<wgpu::Texture>,
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    index_count: u32,
+    camera_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+impl Terrain {
+    pub fn from_heightmap(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::GrayImage,
+        world_size: [f32; 3],
+    ) -> Terrain {
+        let (width, height) = image.dimensions();
+        let (columns, rows) = (width.max(2), height.max(2));
+        let mut vertices = Vec::with_capacity((columns * rows) as usize);
+        for z in 0..rows {
+            for x in 0..columns {
+                let u = x as f32 / (columns - 1) as f32;
+                let v = z as f32 / (rows - 1) as f32;
+                vertices.push(Vertex {
+                    position: [u * world_size[0], 0.0, v * world_size[2]],
+                    normal: [0.0, 1.0, 0.0],
+                    texture_coords: [u, v],
+                });
+            }
+        }
+        let mut indices = Vec::with_capacity(((columns - 1) * (rows - 1) * 6) as usize);
+        for z in 0..rows - 1 {
+            for x in 0..columns - 1 {
+                let i = z * columns + x;
+                // Counter clockwise seen from above
+                let below = i + columns;
+                indices.extend_from_slice(&[i, below, i + 1, i + 1, below, below + 1]);
+            }
+        }
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Terrain Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Terrain Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let height_texture = upload_r8(device, queue, image, "Terrain Height Texture");
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Terrain Camera Buffer"),
+            size: std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let [x, y, z] = world_size;
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Terrain Params Buffer"),
+            contents: bytemuck::cast_slice(&[TerrainParams {
+                world_size: [x, y, z, 0.0],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let uniform = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Terrain Bind Group Layout"),
+            entries: &[
+                uniform(0),
+                uniform(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let height_view = height_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Terrain Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&height_view),
+                },
+            ],
+        });
+        Terrain {
+            world_size,
+            heights: image.clone(),
+            height_texture,
+            normal_texture: None,
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            camera_buffer,
+            params_buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+    /// A pipeline drawing terrains into `format`, shared by every terrain of a device.
+    pub fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Terrain Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../terrain.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Terrain Pipeline Layout"),
+            bind_group_layouts: &[&self.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Terrain Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+        })
+    }
+    pub fn update_camera(&self, queue: &wgpu::Queue, view_proj: Matrix4<f32>) {
+        let view_proj: [[f32; 4]; 4] = view_proj.into();
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&view_proj));
+    }
+    /// Draws the terrain with a pipeline from `create_pipeline`.
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        pipeline: &'a wgpu::RenderPipeline,
+    ) {
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+    /// `generate_normal_map` on the GPU, for heightmaps too large to do on the CPU. The
+    /// normal map has the size of the heightmap and is kept in `normal_texture`.
+    pub fn bake_normals_gpu(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> &wgpu::Texture {
+        let (width, height) = self.heights.dimensions();
+        let normal_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Terrain Normal Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Terrain Normals Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let height_view = self.height_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let normal_view = normal_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Terrain Normals Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&height_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal_view),
+                },
+            ],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Terrain Normals Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../terrain_normals.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Terrain Normals Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Terrain Normals Pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "main",
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Terrain Normals Encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Terrain Normals Pass"),
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch((width + 7) / 8, (height + 7) / 8, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        self.normal_texture.insert(normal_texture)
+    }
+    /// Height of the surface at a world position, bilinearly interpolated between the texels
+    /// like the grid does between its vertices. Positions off the terrain are clamped to its
+    /// edge.
+    pub fn height_at(&self, world_x: f32, world_z: f32) -> f32 {
+        let (width, height) = self.heights.dimensions();
+        if width == 0 || height == 0 {
+            return 0.0;
+        }
+        let to_texel = |world: f32, size: f32, texels: u32| {
+            let last = (texels - 1) as f32;
+            (world / size * last).max(0.0).min(last)
+        };
+        let x = to_texel(world_x, self.world_size[0], width);
+        let z = to_texel(world_z, self.world_size[2], height);
+        let (x0, z0) = (x.floor() as u32, z.floor() as u32);
+        let (x1, z1) = ((x0 + 1).min(width - 1), (z0 + 1).min(height - 1));
+        let (tx, tz) = (x - x0 as f32, z - z0 as f32);
+        let h = |x, z| self.heights.get_pixel(x, z).0[0] as f32 / 255.0;
+        let top = h(x0, z0) * (1.0 - tx) + h(x1, z0) * tx;
+        let bottom = h(x0, z1) * (1.0 - tx) + h(x1, z1) * tx;
+        (top * (1.0 - tz) + bottom * tz) * self.world_size[1]
+    }
+}
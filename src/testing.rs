@@ -0,0 +1,7 @@
+/// A device without a surface for tests that need the GPU, `None` on machines without an
+/// adapter so those tests pass trivially there.
+pub fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = pollster::block_on(instance.request_adapter(&Default::default()))?;
+    pollster::block_on(adapter.request_device(&Default::default(), None)).ok()
+}
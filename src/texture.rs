@@ -0,0 +1,622 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    IO(PathBuf, std::io::Error),
+    #[cfg(feature = "image")]
+    Image(Option<PathBuf>, image::ImageError),
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IO(path, e) => write!(f, "can't read texture '{}': {}", path.display(), e),
+            #[cfg(feature = "image")]
+            Error::Image(Some(path), e) => {
+                write!(f, "can't decode texture '{}': {}", path.display(), e)
+            }
+            #[cfg(feature = "image")]
+            Error::Image(None, e) => write!(f, "can't decode texture: {}", e),
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IO(_, e) => Some(e),
+            #[cfg(feature = "image")]
+            Error::Image(_, e) => Some(e),
+        }
+    }
+}
+
+/// How a texture is sampled. The default repeats and filters linearly without anisotropy.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SamplerConfig {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub filter_min: wgpu::FilterMode,
+    pub filter_mag: wgpu::FilterMode,
+    pub filter_mip: wgpu::FilterMode,
+    /// Most samples taken for surfaces seen at an angle, 1 is off. Rounded down to a power of
+    /// two up to 16.
+    pub anisotropy: u8,
+}
+impl SamplerConfig {
+    /// `address_mode` in both directions.
+    pub fn with_address_mode(self, address_mode: wgpu::AddressMode) -> Self {
+        SamplerConfig {
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            ..self
+        }
+    }
+    /// Anisotropy needs `DownlevelFlags::ANISOTROPIC_FILTERING`, see
+    /// `DeviceInfo::downlevel_flags`. Without it a warning is logged and it's off.
+    pub fn create_sampler(
+        &self,
+        device: &wgpu::Device,
+        downlevel_flags: wgpu::DownlevelFlags,
+        label: Option<&str>,
+    ) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: self.address_mode_u,
+            mag_filter: self.filter_mag,
+            min_filter: self.filter_min,
+            mipmap_filter: self.filter_mip,
+            anisotropy_clamp: self.anisotropy_clamp(downlevel_flags, label),
+            ..Default::default()
+        })
+    }
+    /// `anisotropy` as wgpu takes it, `None` when it's off.
+    fn anisotropy_clamp(
+        &self,
+        downlevel_flags: wgpu::DownlevelFlags,
+        label: Option<&str>,
+    ) -> Option<std::num::NonZeroU8> {
+        let anisotropy = self.anisotropy.clamp(1, 16);
+        let supported = downlevel_flags.contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING);
+        if anisotropy > 1 && !supported {
+            log::warn!(
+                "anisotropic filtering isn't supported, sampling {:?} at 1x",
+                label
+            );
+            return None;
+        }
+        // The largest power of two that isn't more
+        let anisotropy = 1 << (7 - anisotropy.leading_zeros());
+        std::num::NonZeroU8::new(anisotropy).filter(|&a| a.get() > 1)
+    }
+}
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        SamplerConfig {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            filter_min: wgpu::FilterMode::Linear,
+            filter_mag: wgpu::FilterMode::Linear,
+            filter_mip: wgpu::FilterMode::Nearest,
+            anisotropy: 1,
+        }
+    }
+}
+
+/// A sampled RGBA8 texture with its view and sampler.
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub size: wgpu::Extent3d,
+    pub format: wgpu::TextureFormat,
+    /// 1 unless it was created with mipmaps, see `generate_mipmaps`.
+    pub mip_level_count: u32,
+}
+impl Texture {
+    /// For color data such as diffuse maps.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+    /// For data that isn't a color, e.g. normal and bump maps encode vectors and heights.
+    pub const LINEAR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    #[cfg(feature = "image")]
+    /// Loads and decodes an sRGB image file, PNG and JPEG are supported.
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+    ) -> Result<Texture, Error> {
+        Self::load_with_format(device, queue, path, Self::FORMAT)
+    }
+    #[cfg(feature = "image")]
+    /// Loads an image that isn't sRGB encoded, like normal and bump maps.
+    pub fn load_linear(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+    ) -> Result<Texture, Error> {
+        Self::load_with_format(device, queue, path, Self::LINEAR_FORMAT)
+    }
+    #[cfg(feature = "image")]
+    pub fn load_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+        format: wgpu::TextureFormat,
+    ) -> Result<Texture, Error> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| Error::IO(path.to_owned(), e))?;
+        let image =
+            image::load_from_memory(&bytes).map_err(|e| Error::Image(Some(path.to_owned()), e))?;
+        let label = path.to_string_lossy();
+        Ok(Self::from_image(
+            device,
+            queue,
+            &image,
+            format,
+            Some(&label),
+        ))
+    }
+    #[cfg(feature = "image")]
+    /// Loads `path`, or gives the white fallback texture for materials without that map.
+    pub fn load_or_white(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: Option<&Path>,
+    ) -> Result<Texture, Error> {
+        match path {
+            Some(path) => Self::load(device, queue, path),
+            None => Ok(Self::white(device, queue)),
+        }
+    }
+    #[cfg(feature = "image")]
+    pub fn from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: Option<&str>,
+    ) -> Result<Texture, Error> {
+        let image = image::load_from_memory(bytes).map_err(|e| Error::Image(None, e))?;
+        Ok(Self::from_image(device, queue, &image, Self::FORMAT, label))
+    }
+    #[cfg(feature = "image")]
+    /// `from_bytes` sampled with `sampler` instead of the default.
+    pub fn from_bytes_with_sampler(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        sampler: &SamplerConfig,
+        downlevel_flags: wgpu::DownlevelFlags,
+        label: Option<&str>,
+    ) -> Result<Texture, Error> {
+        let mut texture = Self::from_bytes(device, queue, bytes, label)?;
+        texture.sampler = sampler.create_sampler(device, downlevel_flags, label);
+        Ok(texture)
+    }
+    #[cfg(feature = "image")]
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::DynamicImage,
+        format: wgpu::TextureFormat,
+        label: Option<&str>,
+    ) -> Texture {
+        let rgba = image.to_rgba8();
+        let (width, height) = rgba.dimensions();
+        Self::from_rgba8(device, queue, &rgba, width, height, format, label)
+    }
+    /// 1×1 opaque white, lets materials without a texture use the textured pipeline.
+    pub fn white(device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+        Self::from_rgba8(
+            device,
+            queue,
+            &[255; 4],
+            1,
+            1,
+            Self::FORMAT,
+            Some("White Texture"),
+        )
+    }
+    /// 1×1 opaque black, the fallback for maps that add light like emissive maps.
+    pub fn black(device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+        let black = [0, 0, 0, 255];
+        Self::from_rgba8(
+            device,
+            queue,
+            &black,
+            1,
+            1,
+            Self::FORMAT,
+            Some("Black Texture"),
+        )
+    }
+    pub fn from_rgba8(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: Option<&str>,
+    ) -> Texture {
+        let usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        let descriptor = Self::descriptor(label, width, height, format, 1, usage);
+        Self::upload(device, queue, rgba, &descriptor)
+    }
+    /// `from_rgba8` with the full mip chain generated on the GPU, for textures seen from a
+    /// distance that would shimmer otherwise. The sampler only blends between levels if its
+    /// `filter_mip` is linear.
+    pub fn from_rgba8_mipmapped(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: Option<&str>,
+    ) -> Texture {
+        let usage = wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::RENDER_ATTACHMENT;
+        let mip_level_count = Self::mip_level_count(width, height);
+        let descriptor = Self::descriptor(label, width, height, format, mip_level_count, usage);
+        let texture = Self::upload(device, queue, rgba, &descriptor);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap Encoder"),
+        });
+        texture.generate_mipmaps(device, &mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
+        texture
+    }
+    /// Levels in a full mip chain, halving the larger side down to 1.
+    pub fn mip_level_count(width: u32, height: u32) -> u32 {
+        32 - width.max(height).max(1).leading_zeros()
+    }
+    fn descriptor(
+        label: Option<&str>,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        mip_level_count: u32,
+        usage: wgpu::TextureUsages,
+    ) -> wgpu::TextureDescriptor {
+        wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+        }
+    }
+    /// Creates the texture and writes `rgba` to its first mip level.
+    fn upload(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: &[u8],
+        descriptor: &wgpu::TextureDescriptor,
+    ) -> Texture {
+        let texture = device.create_texture(descriptor);
+        let (size, label) = (descriptor.size, descriptor.label);
+        let (width, height) = (size.width, size.height);
+        // Rows are padded to the copy alignment so the same data could go through a buffer copy
+        let bytes_per_row = 4 * width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (bytes_per_row + align - 1) / align * align;
+        let padded;
+        let data = if padded_bytes_per_row == bytes_per_row {
+            rgba
+        } else {
+            let padding = (padded_bytes_per_row - bytes_per_row) as usize;
+            padded = rgba
+                .chunks_exact(bytes_per_row as usize)
+                .flat_map(|row| {
+                    row.iter()
+                        .copied()
+                        .chain(std::iter::repeat(0).take(padding))
+                })
+                .collect::<Vec<u8>>();
+            &padded
+        };
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                rows_per_image: std::num::NonZeroU32::new(height),
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler =
+            SamplerConfig::default().create_sampler(device, wgpu::DownlevelFlags::empty(), label);
+        Texture {
+            texture,
+            view,
+            sampler,
+            size,
+            format: descriptor.format,
+            mip_level_count: descriptor.mip_level_count,
+        }
+    }
+    /// Fills mip levels 1 and up from level 0. wgpu has no blit command, so each level is a
+    /// render pass drawing the level before it with a bilinear sampler, which averages each
+    /// 2×2 block of texels. sRGB textures are averaged in linear space since the view decodes
+    /// and encodes them.
+    ///
+    /// The texture needs `TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING`
+    /// and a format that can be rendered to and filtered, like `FORMAT` and `LINEAR_FORMAT`.
+    /// The pipeline is created for each call, so batch textures into one encoder rather than
+    /// calling this per frame.
+    pub fn generate_mipmaps(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        if self.mip_level_count < 2 {
+            return;
+        }
+        let layout = Self::bind_group_layout(device);
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Mipmap Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../blit.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Mipmap Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Mipmap Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[self.format.into()],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Mipmap Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let views: Vec<wgpu::TextureView> = (0..self.mip_level_count)
+            .map(|mip| {
+                self.texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Mip View"),
+                    base_mip_level: mip,
+                    mip_level_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        for pair in views.windows(2) {
+            let (source, target) = (&pair[0], &pair[1]);
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Mipmap Bind Group"),
+                layout: &layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+            });
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+    /// Memory the texture takes on the GPU, four bytes per texel of every mip level.
+    pub fn gpu_bytes(&self) -> u64 {
+        let size = self.size;
+        (0..self.mip_level_count)
+            .map(|mip| {
+                let (width, height) = ((size.width >> mip).max(1), (size.height >> mip).max(1));
+                4 * width as u64 * height as u64 * size.depth_or_array_layers as u64
+            })
+            .sum()
+    }
+    /// Texture at binding 0 and its sampler at binding 1, visible to the fragment stage.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Texture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Texture Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anisotropy_rounds_down_to_powers_of_two() {
+        let supported = wgpu::DownlevelFlags::ANISOTROPIC_FILTERING;
+        let clamp = |anisotropy| {
+            let config = SamplerConfig {
+                anisotropy,
+                ..SamplerConfig::default()
+            };
+            config
+                .anisotropy_clamp(supported, None)
+                .map_or(1, |a| a.get())
+        };
+        let clamped: Vec<u8> = [0, 1, 2, 3, 7, 8, 15, 16, 255]
+            .into_iter()
+            .map(clamp)
+            .collect();
+        assert_eq!(clamped, [1, 1, 2, 2, 4, 8, 8, 16, 16]);
+    }
+
+    #[test]
+    fn anisotropy_falls_back_without_support() {
+        let config = SamplerConfig {
+            anisotropy: 16,
+            ..SamplerConfig::default()
+        };
+        assert_eq!(
+            config.anisotropy_clamp(wgpu::DownlevelFlags::empty(), None),
+            None
+        );
+    }
+
+    #[test]
+    fn address_mode_in_both_directions() {
+        let clamp = SamplerConfig::default().with_address_mode(wgpu::AddressMode::ClampToEdge);
+        assert_eq!(clamp.address_mode_u, wgpu::AddressMode::ClampToEdge);
+        assert_eq!(clamp.address_mode_v, wgpu::AddressMode::ClampToEdge);
+        assert_eq!(clamp.filter_min, SamplerConfig::default().filter_min);
+    }
+
+    #[test]
+    fn mip_chains_halve_the_larger_side() {
+        assert_eq!(Texture::mip_level_count(1, 1), 1);
+        assert_eq!(Texture::mip_level_count(0, 0), 1);
+        assert_eq!(Texture::mip_level_count(2, 1), 2);
+        assert_eq!(Texture::mip_level_count(256, 256), 9);
+        assert_eq!(Texture::mip_level_count(300, 100), 9);
+        assert_eq!(Texture::mip_level_count(1, 1024), 11);
+    }
+
+    #[test]
+    fn generated_mipmaps_average_the_base_level() {
+        let (device, queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        // A 4×4 red and blue checkerboard, so every mip level below it is purple
+        let rgba: Vec<u8> = (0..16)
+            .flat_map(|i| match (i % 4 + i / 4) % 2 {
+                0 => [255, 0, 0, 255],
+                _ => [0, 0, 255, 255],
+            })
+            .collect();
+        // `from_rgba8_mipmapped` without COPY_SRC can't be read back
+        let usage = wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::RENDER_ATTACHMENT;
+        let format = Texture::LINEAR_FORMAT;
+        let descriptor = Texture::descriptor(None, 4, 4, format, 3, usage);
+        let texture = Texture::upload(&device, &queue, &rgba, &descriptor);
+        assert_eq!(texture.gpu_bytes(), 4 * (16 + 4 + 1));
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        texture.generate_mipmaps(&device, &mut encoder);
+        // Rows of copies to buffers are aligned to 256 bytes
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture.texture,
+                mip_level: 2,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(256),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        let readback = crate::readback::BufferReadback::new(&device, 256);
+        let pixels: Vec<[u8; 4]> =
+            pollster::block_on(readback.read(&device, &queue, &buffer)).unwrap();
+        let [red, green, blue, alpha] = pixels[0];
+        assert!((126..=129).contains(&red), "red {}", red);
+        assert_eq!(green, 0);
+        assert!((126..=129).contains(&blue), "blue {}", blue);
+        assert_eq!(alpha, 255);
+    }
+}
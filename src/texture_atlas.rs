@@ -0,0 +1,157 @@
+use crate::texture::Texture;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Transparent pixels between entries so filtering doesn't bleed neighbours in.
+const PADDING: u32 = 1;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Error {
+    /// The images don't fit into `max_size`×`max_size`.
+    TooLarge { max_size: u32 },
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An image added to an `AtlasBuilder`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct AtlasHandle(usize);
+
+/// Pixel rectangle of an entry.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Places the sizes on shelves, tallest first so each shelf wastes little height. Returns
+/// `None` if they don't fit into `size`×`size`.
+fn shelf_pack(sizes: &[(u32, u32)], size: u32) -> Option<Vec<Rect>> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i].1));
+    let mut rects = vec![Rect::default(); sizes.len()];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    for i in order {
+        let (width, height) = (sizes[i].0 + PADDING, sizes[i].1 + PADDING);
+        if width > size {
+            return None;
+        }
+        if x + width > size {
+            // Next shelf
+            y += shelf_height;
+            x = 0;
+            shelf_height = 0;
+        }
+        if y + height > size {
+            return None;
+        }
+        rects[i] = Rect {
+            x,
+            y,
+            width: sizes[i].0,
+            height: sizes[i].1,
+        };
+        x += width;
+        shelf_height = shelf_height.max(height);
+    }
+    Some(rects)
+}
+
+/// Collects images for a `TextureAtlas`.
+pub struct AtlasBuilder {
+    max_size: u32,
+    images: Vec<(String, image::RgbaImage)>,
+}
+impl AtlasBuilder {
+    pub fn add(&mut self, label: &str, image: &image::DynamicImage) -> AtlasHandle {
+        self.images.push((label.to_string(), image.to_rgba8()));
+        AtlasHandle(self.images.len() - 1)
+    }
+    /// Packs the images into the smallest power of two square that fits them and uploads it.
+    pub fn build(self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<TextureAtlas, Error> {
+        let sizes: Vec<(u32, u32)> =
+            self.images.iter().map(|(_, image)| image.dimensions()).collect();
+        let mut size = 1;
+        let rects = loop {
+            if let Some(rects) = shelf_pack(&sizes, size) {
+                break rects;
+            }
+            if size >= self.max_size {
+                return Err(Error::TooLarge {
+                    max_size: self.max_size,
+                });
+            }
+            size = (size * 2).min(self.max_size);
+        };
+        let mut atlas = image::RgbaImage::new(size, size);
+        for ((_, image), rect) in self.images.iter().zip(&rects) {
+            image::imageops::replace(&mut atlas, image, rect.x as i64, rect.y as i64);
+        }
+        let texture = Texture::from_rgba8(
+            device,
+            queue,
+            &atlas,
+            size,
+            size,
+            Texture::FORMAT,
+            Some("Texture Atlas"),
+        );
+        Ok(TextureAtlas {
+            texture,
+            size,
+            rects,
+            labels: self
+                .images
+                .into_iter()
+                .enumerate()
+                .map(|(i, (label, _))| (label, AtlasHandle(i)))
+                .collect(),
+        })
+    }
+}
+
+/// Many images packed into one texture, so they can be drawn without switching bind groups.
+pub struct TextureAtlas {
+    pub texture: Texture,
+    /// Width and height in pixels.
+    size: u32,
+    rects: Vec<Rect>,
+    labels: HashMap<String, AtlasHandle>,
+}
+impl TextureAtlas {
+    pub fn builder(max_size: u32) -> AtlasBuilder {
+        AtlasBuilder {
+            max_size,
+            images: Vec::new(),
+        }
+    }
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+    /// The handle of the image added as `label`.
+    pub fn handle(&self, label: &str) -> Option<AtlasHandle> {
+        self.labels.get(label).copied()
+    }
+    /// Every image in the atlas, in the order they were added.
+    pub fn handles(&self) -> impl Iterator<Item = AtlasHandle> {
+        (0..self.rects.len()).map(AtlasHandle)
+    }
+    /// `[u_min, v_min, u_max, v_max]` of the image, for the texture coordinates of a quad.
+    pub fn uv_rect(&self, handle: AtlasHandle) -> [f32; 4] {
+        let rect = self.rects[handle.0];
+        let size = self.size as f32;
+        [
+            rect.x as f32 / size,
+            rect.y as f32 / size,
+            (rect.x + rect.width) as f32 / size,
+            (rect.y + rect.height) as f32 / size,
+        ]
+    }
+}
@@ -0,0 +1,196 @@
+use std::time::{Duration, Instant};
+
+/// Frames a `FrameHistogram` keeps, older ones are overwritten.
+pub const HISTORY: usize = 1024;
+
+/// Frames longer than the budget times this are hitches.
+pub const HITCH_FACTOR: f64 = 1.5;
+
+/// Frame time statistics over the last `HISTORY` frames.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct HistogramReport {
+    /// Frames the percentiles are over, at most `HISTORY`.
+    pub frames: usize,
+    pub min: Duration,
+    pub mean: Duration,
+    pub max: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    /// Frames over `budget * HITCH_FACTOR` among `frames`.
+    pub hitches: usize,
+    /// Hitches since the histogram was created or reset, including ones no longer in the
+    /// history.
+    pub total_hitches: usize,
+}
+
+/// Frame times in a ring buffer, for finding hitches an average FPS hides. Both buffers are
+/// allocated up front, recording and reporting don't allocate.
+pub struct FrameHistogram {
+    budget: Duration,
+    samples: Box<[Duration; HISTORY]>,
+    /// Sorted copy of the samples for the percentiles.
+    scratch: Box<[Duration; HISTORY]>,
+    /// Where the next sample goes.
+    next: usize,
+    len: usize,
+    total_hitches: usize,
+    last_frame: Option<Instant>,
+}
+impl FrameHistogram {
+    /// `budget` is the frame time aimed for, e.g. 16.6ms for 60 FPS.
+    pub fn new(budget: Duration) -> Self {
+        FrameHistogram {
+            budget,
+            samples: Box::new([Duration::ZERO; HISTORY]),
+            scratch: Box::new([Duration::ZERO; HISTORY]),
+            next: 0,
+            len: 0,
+            total_hitches: 0,
+            last_frame: None,
+        }
+    }
+    /// A budget of one frame at `fps`.
+    pub fn with_target_fps(fps: u32) -> Self {
+        Self::new(Duration::from_secs_f64(1.0 / f64::from(fps.max(1))))
+    }
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+    pub fn set_budget(&mut self, budget: Duration) {
+        self.budget = budget;
+    }
+    /// Records the wall clock time since the last call, once a frame. The first call only
+    /// starts the clock.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame.replace(now) {
+            self.record(now - last);
+        }
+    }
+    /// Like `tick` but with an explicit frame time.
+    pub fn record(&mut self, frame_time: Duration) {
+        if is_hitch(frame_time, self.budget) {
+            self.total_hitches += 1;
+        }
+        self.samples[self.next] = frame_time;
+        self.next = (self.next + 1) % HISTORY;
+        self.len = (self.len + 1).min(HISTORY);
+    }
+    /// Statistics of the recorded frames, all zero before any were.
+    pub fn report(&mut self) -> HistogramReport {
+        if self.len == 0 {
+            return HistogramReport {
+                total_hitches: self.total_hitches,
+                ..Default::default()
+            };
+        }
+        let budget = self.budget;
+        // While the buffer fills up the samples are at the start
+        let sorted = &mut self.scratch[..self.len];
+        sorted.copy_from_slice(&self.samples[..self.len]);
+        sorted.sort_unstable();
+        // Nearest rank
+        let percentile = |p: usize| sorted[((sorted.len() * p + 99) / 100).max(1) - 1];
+        let total: Duration = sorted.iter().sum();
+        let hitches = sorted.iter().filter(|&&t| is_hitch(t, budget)).count();
+        HistogramReport {
+            frames: sorted.len(),
+            min: sorted[0],
+            mean: total / sorted.len() as u32,
+            max: sorted[sorted.len() - 1],
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            hitches,
+            total_hitches: self.total_hitches,
+        }
+    }
+    /// Forgets every recorded frame, e.g. between the scenarios being profiled. The next
+    /// `tick` starts the clock again.
+    pub fn reset(&mut self) {
+        self.next = 0;
+        self.len = 0;
+        self.total_hitches = 0;
+        self.last_frame = None;
+    }
+}
+
+fn is_hitch(frame_time: Duration, budget: Duration) -> bool {
+    frame_time.as_secs_f64() > budget.as_secs_f64() * HITCH_FACTOR
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let mut histogram = FrameHistogram::new(ms(1000));
+        // Out of order so the report has to sort
+        for t in (1..=100).rev() {
+            histogram.record(ms(t));
+        }
+        let report = histogram.report();
+        assert_eq!(report.frames, 100);
+        assert_eq!((report.min, report.max), (ms(1), ms(100)));
+        assert_eq!(report.mean, Duration::from_micros(50_500));
+        assert_eq!(
+            (report.p50, report.p95, report.p99),
+            (ms(50), ms(95), ms(99))
+        );
+        assert_eq!(report.hitches, 0);
+    }
+
+    #[test]
+    fn hitches_are_over_one_and_a_half_budgets() {
+        let mut histogram = FrameHistogram::new(ms(10));
+        for t in [10, 14, 16, 30] {
+            histogram.record(ms(t));
+        }
+        let report = histogram.report();
+        assert_eq!((report.hitches, report.total_hitches), (2, 2));
+    }
+
+    #[test]
+    fn old_frames_are_overwritten() {
+        let mut histogram = FrameHistogram::new(ms(10));
+        for _ in 0..HISTORY {
+            histogram.record(ms(1));
+        }
+        for _ in 0..10 {
+            histogram.record(ms(100));
+        }
+        let report = histogram.report();
+        assert_eq!(report.frames, HISTORY);
+        assert_eq!(
+            (report.min, report.max, report.hitches),
+            (ms(1), ms(100), 10)
+        );
+        for _ in 0..HISTORY {
+            histogram.record(ms(2));
+        }
+        let report = histogram.report();
+        assert_eq!((report.min, report.max, report.hitches), (ms(2), ms(2), 0));
+        // Hitches that left the history still count towards the total
+        assert_eq!(report.total_hitches, 10);
+    }
+
+    #[test]
+    fn reset_forgets_every_frame() {
+        let mut histogram = FrameHistogram::new(ms(10));
+        histogram.record(ms(100));
+        histogram.reset();
+        assert_eq!(histogram.report(), HistogramReport::default());
+        histogram.record(ms(4));
+        let report = histogram.report();
+        assert_eq!(
+            (report.frames, report.p99, report.total_hitches),
+            (1, ms(4), 0)
+        );
+    }
+}
@@ -0,0 +1,345 @@
+use wgpu::util::DeviceExt;
+
+/// Format `ToneMappingPass::new` writes, sRGB so the curves can stay linear.
+pub const OUTPUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct ToneMappingUniform {
+    exposure: f32,
+    _padding: [f32; 3],
+}
+
+/// The curve HDR colors are mapped to `0..1` with.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ToneMapper {
+    /// `x / (1 + x)`, never clips but washes out bright colors.
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    ACES,
+    /// John Hable's filmic curve from Uncharted 2, with a white point of 11.2.
+    Uncharted2,
+}
+impl ToneMapper {
+    /// WGSL defining `tone_map(color: vec3<f32>) -> vec3<f32>`.
+    fn wgsl(self) -> &'static str {
+        match self {
+            ToneMapper::Reinhard => {
+                "fn tone_map(color: vec3<f32>) -> vec3<f32> {
+                    return color / (vec3<f32>(1.0) + color);
+                }"
+            }
+            ToneMapper::ACES => {
+                "fn tone_map(x: vec3<f32>) -> vec3<f32> {
+                    let mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+                    return clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+                }"
+            }
+            ToneMapper::Uncharted2 => {
+                "fn hable(x: vec3<f32>) -> vec3<f32> {
+                    let a = 0.15; let b = 0.50; let c = 0.10; let d = 0.20; let e = 0.02;
+                    let f = 0.30;
+                    return (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f;
+                }
+                fn tone_map(color: vec3<f32>) -> vec3<f32> {
+                    return hable(color * 2.0) / hable(vec3<f32>(11.2));
+                }"
+            }
+        }
+    }
+    /// The curve on the CPU for one channel, the same as the shader's.
+    pub fn apply(self, x: f32) -> f32 {
+        match self {
+            ToneMapper::Reinhard => x / (1.0 + x),
+            ToneMapper::ACES => {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+            ToneMapper::Uncharted2 => {
+                let hable = |x: f32| {
+                    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+                    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+                };
+                hable(x * 2.0) / hable(11.2)
+            }
+        }
+    }
+}
+impl Default for ToneMapper {
+    fn default() -> Self {
+        ToneMapper::ACES
+    }
+}
+
+/// Maps an HDR color buffer to LDR with a `ToneMapper`, after scaling it by the exposure.
+pub struct ToneMappingPass {
+    tone_mapper: ToneMapper,
+    uniform: ToneMappingUniform,
+    uniform_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+impl ToneMappingPass {
+    /// Writes `OUTPUT_FORMAT` targets.
+    pub fn new(device: &wgpu::Device, tone_mapper: ToneMapper) -> Self {
+        Self::with_output_format(device, tone_mapper, OUTPUT_FORMAT)
+    }
+    pub fn with_output_format(
+        device: &wgpu::Device,
+        tone_mapper: ToneMapper,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let uniform = ToneMappingUniform {
+            exposure: 1.0,
+            _padding: [0.0; 3],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tone Mapping Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Tone Mapping Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tone Mapping Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        // The curve comes first so `fs_main` can call it
+        let source = format!("{}\n{}", tone_mapper.wgsl(), include_str!("../tone_map.wgsl"));
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Tone Mapping Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tone Mapping Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Tone Mapping Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[output_format.into()],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        });
+        ToneMappingPass {
+            tone_mapper,
+            uniform,
+            uniform_buffer,
+            sampler,
+            layout,
+            pipeline,
+        }
+    }
+    pub fn tone_mapper(&self) -> ToneMapper {
+        self.tone_mapper
+    }
+    /// Exposure in EV, colors are scaled by `2^ev` before the curve. 0 leaves them as is.
+    pub fn set_exposure(&mut self, queue: &wgpu::Queue, ev: f32) {
+        self.uniform.exposure = ev.exp2();
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+    /// The exposure in EV.
+    pub fn exposure(&self) -> f32 {
+        self.uniform.exposure.log2()
+    }
+    /// Tone maps `hdr_view` into `target`, which has the output format.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr_view: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+    ) {
+        // The HDR buffer is recreated on resize, so the bind group is made per frame
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tone Mapping Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tone Mapping Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readback::BufferReadback;
+
+    #[test]
+    fn curves_stay_in_range() {
+        for tone_mapper in [ToneMapper::Reinhard, ToneMapper::ACES, ToneMapper::Uncharted2] {
+            assert!(tone_mapper.apply(0.0).abs() < 1e-3, "{:?}", tone_mapper);
+            let mut previous = tone_mapper.apply(0.0);
+            // Up to Uncharted 2's white point, past which it goes over 1
+            for i in 1..280 {
+                let mapped = tone_mapper.apply(i as f32 * 0.02);
+                assert!(mapped >= previous && mapped <= 1.0, "{:?} at {}", tone_mapper, i);
+                previous = mapped;
+            }
+        }
+        assert_eq!(ToneMapper::Reinhard.apply(1.0), 0.5);
+        assert!((ToneMapper::Uncharted2.apply(5.6) - 1.0).abs() < 1e-6);
+    }
+
+    /// Tone maps a single pixel of the half float `value` at `ev` on the GPU.
+    fn render_pixel(device: &wgpu::Device, queue: &wgpu::Queue, value: u16, ev: f32) -> u8 {
+        let size = wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        };
+        let texture = |format, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+            })
+        };
+        let copy = |texture| wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        };
+        let usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        let hdr = texture(wgpu::TextureFormat::Rgba16Float, usage);
+        queue.write_texture(
+            copy(&hdr),
+            bytemuck::cast_slice(&[value, value, value, 0x3c00]),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(8),
+                rows_per_image: None,
+            },
+            size,
+        );
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC;
+        let target = texture(wgpu::TextureFormat::Rgba8Unorm, usage);
+
+        let mut pass = ToneMappingPass::with_output_format(
+            device,
+            ToneMapper::Reinhard,
+            wgpu::TextureFormat::Rgba8Unorm,
+        );
+        pass.set_exposure(queue, ev);
+        let view = |texture: &wgpu::Texture| texture.create_view(&Default::default());
+        let mut encoder = device.create_command_encoder(&Default::default());
+        pass.render(device, &mut encoder, &view(&hdr), &view(&target));
+        // Rows of copies to buffers are aligned to 256 bytes
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            copy(&target),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(256),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        let readback = BufferReadback::new(device, 256);
+        let pixels: Vec<[u8; 4]> =
+            pollster::block_on(readback.read(device, queue, &buffer)).unwrap();
+        pixels[0][0]
+    }
+
+    #[test]
+    fn reinhard_maps_one_to_half() {
+        let (device, queue) = match crate::testing::device() {
+            Some(device) => device,
+            None => return,
+        };
+        // 1, 0.5 and 4 as half floats, all 1 after exposure. 0.5 is 127.5 in a unorm target.
+        for (value, ev) in [(0x3c00, 0.0), (0x3800, 1.0), (0x4400, -2.0)] {
+            let red = render_pixel(&device, &queue, value, ev);
+            assert!((127..=128).contains(&red), "{:#x} at EV {} gave {}", value, ev, red);
+        }
+    }
+}
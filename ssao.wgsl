@@ -0,0 +1,71 @@
+// Screen space ambient occlusion from a depth buffer
+
+[[block]]
+struct Params {
+    projection: mat4x4<f32>;
+    inverse_projection: mat4x4<f32>;
+    // Hemisphere samples around +Z, only the first sample_count are used
+    kernel: array<vec4<f32>, 64>;
+    sample_count: u32;
+    radius: f32;
+    bias: f32;
+    padding: u32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: Params;
+[[group(0), binding(1)]]
+var depth: texture_depth_2d;
+[[group(0), binding(2)]]
+var noise: texture_2d<f32>;
+
+// Vertex shader
+
+struct FullscreenOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+// One triangle covering the whole screen
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] in_vertex_index: u32) -> FullscreenOutput {
+    var out: FullscreenOutput;
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Fragment shader
+
+fn view_position(uv: vec2<f32>) -> vec3<f32> {
+    let size = textureDimensions(depth);
+    let coords = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0), size - vec2<i32>(1));
+    let z = textureLoad(depth, coords, 0);
+    let view = params.inverse_projection * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, z, 1.0);
+    return view.xyz / view.w;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let position = view_position(in.uv);
+    // Screen y points down so this faces the camera
+    let normal = normalize(cross(dpdy(position), dpdx(position)));
+    let noise_size = textureDimensions(noise);
+    let random = textureLoad(noise, vec2<i32>(in.position.xy) % noise_size, 0).xyz * 2.0 - 1.0;
+    let tangent = normalize(random - normal * dot(random, normal));
+    let bitangent = cross(normal, tangent);
+
+    var occlusion: f32 = 0.0;
+    for (var i: u32 = 0u; i < params.sample_count; i = i + 1u) {
+        let k = params.kernel[i].xyz;
+        let sample = position + (tangent * k.x + bitangent * k.y + normal * k.z) * params.radius;
+        let offset = params.projection * vec4<f32>(sample, 1.0);
+        let sample_uv = offset.xy / offset.w * vec2<f32>(0.5, -0.5) + 0.5;
+        let scene_z = view_position(sample_uv).z;
+        // Fade out occluders far outside the radius so silhouettes don't get halos
+        let range = smoothStep(0.0, 1.0, params.radius / abs(position.z - scene_z));
+        occlusion = occlusion + select(0.0, 1.0, scene_z >= sample.z + params.bias) * range;
+    }
+    return vec4<f32>(1.0 - occlusion / f32(params.sample_count), 0.0, 0.0, 1.0);
+}
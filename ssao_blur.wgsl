@@ -0,0 +1,47 @@
+// One direction of a separable 9 tap Gaussian blur
+
+[[block]]
+struct Blur {
+    direction: vec2<f32>;
+    padding: vec2<f32>;
+};
+
+[[group(0), binding(0)]]
+var src: texture_2d<f32>;
+[[group(0), binding(1)]]
+var src_sampler: sampler;
+[[group(0), binding(2)]]
+var<uniform> blur: Blur;
+
+// Vertex shader
+
+struct FullscreenOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] in_vertex_index: u32) -> FullscreenOutput {
+    var out: FullscreenOutput;
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Fragment shader
+
+fn tap(uv: vec2<f32>, offset: f32, weight: f32) -> f32 {
+    let step = blur.direction / vec2<f32>(textureDimensions(src)) * offset;
+    return (textureSample(src, src_sampler, uv + step).r + textureSample(src, src_sampler, uv - step).r) * weight;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    var result: f32 = textureSample(src, src_sampler, in.uv).r * 0.227027;
+    result = result + tap(in.uv, 1.0, 0.1945946);
+    result = result + tap(in.uv, 2.0, 0.1216216);
+    result = result + tap(in.uv, 3.0, 0.054054);
+    result = result + tap(in.uv, 4.0, 0.016216);
+    return vec4<f32>(result, 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,80 @@
+// Temporal anti-aliasing: blends the jittered frame into the reprojected history
+
+[[block]]
+struct Taa {
+    texel_size: vec2<f32>;
+    blend: f32;
+    luminance_threshold: f32;
+    history_valid: u32;
+    padding0: u32;
+    padding1: u32;
+    padding2: u32;
+};
+
+[[group(0), binding(0)]]
+var current: texture_2d<f32>;
+[[group(0), binding(1)]]
+var history: texture_2d<f32>;
+[[group(0), binding(2)]]
+var velocity: texture_2d<f32>;
+[[group(0), binding(3)]]
+var linear_sampler: sampler;
+[[group(0), binding(4)]]
+var<uniform> taa: Taa;
+
+struct FullscreenOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] in_vertex_index: u32) -> FullscreenOutput {
+    var out: FullscreenOutput;
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSampleLevel(current, linear_sampler, in.uv, 0.0);
+    if (taa.history_valid == 0u) {
+        return color;
+    }
+    // Velocity is the UV offset from the previous frame to this one
+    let motion = textureSampleLevel(velocity, linear_sampler, in.uv, 0.0).xy;
+    let previous_uv = in.uv - motion;
+    if (any(previous_uv < vec2<f32>(0.0)) || any(previous_uv > vec2<f32>(1.0))) {
+        return color;
+    }
+    var previous = textureSampleLevel(history, linear_sampler, previous_uv, 0.0);
+
+    // Clamp the history to the 3x3 neighbourhood so stale colors can't survive
+    var low = color.rgb;
+    var high = color.rgb;
+    for (var y: i32 = -1; y <= 1; y = y + 1) {
+        for (var x: i32 = -1; x <= 1; x = x + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * taa.texel_size;
+            let neighbour = textureSampleLevel(current, linear_sampler, in.uv + offset, 0.0).rgb;
+            low = min(low, neighbour);
+            high = max(high, neighbour);
+        }
+    }
+    let clamped = clamp(previous.rgb, low, high);
+
+    // Disocclusions show up as a large luminance change, drop the history there
+    let current_luminance = luminance(color.rgb);
+    let history_luminance = luminance(previous.rgb);
+    let difference = abs(current_luminance - history_luminance)
+        / max(max(current_luminance, history_luminance), 0.2);
+    if (difference > taa.luminance_threshold) {
+        return color;
+    }
+    previous = vec4<f32>(clamped, previous.a);
+    return mix(previous, color, taa.blend);
+}
@@ -0,0 +1,62 @@
+// Heightmap terrain, the flat grid is displaced by the height texture in the vertex shader
+
+[[block]]
+struct Camera {
+    view_proj: mat4x4<f32>;
+};
+[[block]]
+struct TerrainParams {
+    // World size of the terrain, y is the height of a white texel
+    world_size: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+[[group(0), binding(1)]]
+var<uniform> params: TerrainParams;
+[[group(0), binding(2)]]
+var t_height: texture_2d<f32>;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] normal: vec3<f32>;
+    [[location(1)]] height: f32;
+};
+
+fn height(texel: vec2<i32>) -> f32 {
+    let last = textureDimensions(t_height) - vec2<i32>(1, 1);
+    let clamped = clamp(texel, vec2<i32>(0, 0), last);
+    return textureLoad(t_height, clamped, 0).r * params.world_size.y;
+}
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] normal: vec3<f32>,
+    [[location(2)]] texture_coords: vec2<f32>,
+) -> VertexOutput {
+    let dimensions = textureDimensions(t_height);
+    // Grid vertices sit exactly on texels, so load instead of filtering
+    let texel = vec2<i32>(round(texture_coords * vec2<f32>(dimensions - vec2<i32>(1, 1))));
+    let y = height(texel);
+    // Central differences over the grid spacing
+    let cells = max(dimensions - vec2<i32>(1, 1), vec2<i32>(1, 1));
+    let spacing = params.world_size.xz / vec2<f32>(cells);
+    let dx = height(texel + vec2<i32>(1, 0)) - height(texel - vec2<i32>(1, 0));
+    let dz = height(texel + vec2<i32>(0, 1)) - height(texel - vec2<i32>(0, 1));
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(position.x, y, position.z, 1.0);
+    out.normal = normalize(vec3<f32>(-dx / (2.0 * spacing.x), 1.0, -dz / (2.0 * spacing.y)));
+    out.height = y / max(params.world_size.y, 0.0001);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let light = normalize(vec3<f32>(0.3, 1.0, 0.2));
+    let diffuse = max(dot(normalize(in.normal), light), 0.0);
+    let low = vec3<f32>(0.2, 0.35, 0.15);
+    let high = vec3<f32>(0.55, 0.5, 0.45);
+    let albedo = mix(low, high, clamp(in.height, 0.0, 1.0));
+    return vec4<f32>(albedo * (0.2 + 0.8 * diffuse), 1.0);
+}
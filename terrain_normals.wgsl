@@ -0,0 +1,36 @@
+// Bakes a world space normal map (y up) from the terrain heightmap with central differences
+
+[[block]]
+struct TerrainParams {
+    world_size: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: TerrainParams;
+[[group(0), binding(1)]]
+var t_height: texture_2d<f32>;
+[[group(0), binding(2)]]
+var t_normals: texture_storage_2d<rgba8unorm, write>;
+
+fn height(texel: vec2<i32>) -> f32 {
+    let last = textureDimensions(t_height) - vec2<i32>(1, 1);
+    let clamped = clamp(texel, vec2<i32>(0, 0), last);
+    return textureLoad(t_height, clamped, 0).r * params.world_size.y;
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let dimensions = textureDimensions(t_height);
+    let texel = vec2<i32>(id.xy);
+    if (texel.x >= dimensions.x || texel.y >= dimensions.y) {
+        return;
+    }
+    let cells = max(dimensions - vec2<i32>(1, 1), vec2<i32>(1, 1));
+    let spacing = params.world_size.xz / vec2<f32>(cells);
+    let x = vec2<i32>(1, 0);
+    let z = vec2<i32>(0, 1);
+    let dx = (height(texel + x) - height(texel - x)) / (2.0 * spacing.x);
+    let dz = (height(texel + z) - height(texel - z)) / (2.0 * spacing.y);
+    let normal = normalize(vec3<f32>(-dx, 1.0, -dz));
+    textureStore(t_normals, texel, vec4<f32>(normal * 0.5 + 0.5, 1.0));
+}
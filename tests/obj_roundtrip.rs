@@ -0,0 +1,75 @@
+//! `write_obj` output read back by `ObjectBuilder` has to give the same mesh. Parse errors are
+//! returned as failures rather than panics so proptest shrinks them to the smallest input.
+
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+use soyuz::entity::model::files::obj::{write_obj, ObjectBuilder};
+use soyuz::entity::model::Vertex;
+use std::collections::HashMap;
+
+/// Any finite float, including subnormals and both zeros.
+fn finite() -> impl Strategy<Value = f32> {
+    use proptest::num::f32::{NEGATIVE, NORMAL, POSITIVE, SUBNORMAL, ZERO};
+    prop_oneof![POSITIVE | NEGATIVE | NORMAL | SUBNORMAL | ZERO, -10.0f32..10.0]
+}
+
+fn vertex() -> impl Strategy<Value = Vertex> {
+    ([finite(), finite(), finite()], [finite(), finite(), finite()], [finite(), finite()])
+        .prop_map(|(position, normal, texture_coords)| Vertex {
+            position,
+            normal,
+            texture_coords,
+        })
+}
+
+/// Vertices and a triangle list indexing them.
+fn mesh() -> impl Strategy<Value = (Vec<Vertex>, Vec<u32>)> {
+    prop::collection::vec(vertex(), 1..32).prop_flat_map(|vertices| {
+        let count = vertices.len() as u32;
+        let triangles = prop::collection::vec([0..count, 0..count, 0..count], 1..32);
+        (Just(vertices), triangles.prop_map(|t| t.concat()))
+    })
+}
+
+/// The vertex's floats with `-0.0` as `0.0`, which the builder merges.
+fn bits(v: &Vertex) -> Vec<u32> {
+    let floats = v.position.iter().chain(&v.normal).chain(&v.texture_coords);
+    floats.map(|x| (x + 0.0).to_bits()).collect()
+}
+
+/// What the builder makes of the mesh: vertices are numbered in order of first use and equal
+/// ones merged.
+fn expected(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut lookup = HashMap::new();
+    let mut unique = Vec::new();
+    let indices = indices
+        .iter()
+        .map(|&i| {
+            let vertex = vertices[i as usize];
+            *lookup.entry(bits(&vertex)).or_insert_with(|| {
+                unique.push(vertex);
+                unique.len() as u32 - 1
+            })
+        })
+        .collect();
+    (unique, indices)
+}
+
+proptest! {
+    #[test]
+    fn write_then_read((vertices, indices) in mesh()) {
+        let mut obj = Vec::new();
+        write_obj(&mut obj, &vertices, &indices, &[], None).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let mut builder = ObjectBuilder::new();
+        builder
+            .read_lines(obj.as_bytes())
+            .map_err(|e| TestCaseError::fail(format!("{} reading\n{}", e, obj)))?;
+        let (expected_vertices, expected_indices) = expected(&vertices, &indices);
+        prop_assert_eq!(&builder.mesh_indices, &expected_indices);
+        prop_assert_eq!(builder.mesh_vertices.len(), expected_vertices.len());
+        for (read, written) in builder.mesh_vertices.iter().zip(&expected_vertices) {
+            prop_assert_eq!(bits(read), bits(written), "{}", obj);
+        }
+    }
+}
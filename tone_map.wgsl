@@ -0,0 +1,37 @@
+// Maps HDR color to LDR, `tone_map` is defined by the chosen `ToneMapper`
+
+[[block]]
+struct ToneMapping {
+    // exp2 of the exposure in EV
+    exposure: f32;
+    padding0: f32;
+    padding1: f32;
+    padding2: f32;
+};
+
+[[group(0), binding(0)]]
+var hdr: texture_2d<f32>;
+[[group(0), binding(1)]]
+var hdr_sampler: sampler;
+[[group(0), binding(2)]]
+var<uniform> tone_mapping: ToneMapping;
+
+struct FullscreenOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] in_vertex_index: u32) -> FullscreenOutput {
+    var out: FullscreenOutput;
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(hdr, hdr_sampler, in.uv);
+    return vec4<f32>(tone_map(color.rgb * tone_mapping.exposure), color.a);
+}